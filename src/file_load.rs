@@ -289,20 +289,7 @@ impl FcsSampleStub {
     /// # Errors
     /// Will return `Err` if the `Keyword` is not found in the `metadata` or if the `Keyword` cannot be converted to a `&str`
     pub fn get_keyword_string_value(&self, keyword: &str) -> Result<Cow<'_, str>> {
-        // TODO: This should be a match statement
-        if let Ok(keyword) = self.metadata.get_string_keyword(keyword) {
-            Ok(keyword.get_str())
-        } else if let Ok(keyword) = self.metadata.get_integer_keyword(keyword) {
-            Ok(keyword.get_str())
-        } else if let Ok(keyword) = self.metadata.get_float_keyword(keyword) {
-            Ok(keyword.get_str())
-        } else if let Ok(keyword) = self.metadata.get_byte_keyword(keyword) {
-            Ok(keyword.get_str())
-        } else if let Ok(keyword) = self.metadata.get_mixed_keyword(keyword) {
-            Ok(keyword.get_str())
-        } else {
-            Err(anyhow!("Keyword not found: {}", keyword))
-        }
+        keyword_string_value(&self.metadata, keyword)
     }
     /// A convenience function to return the `GUID` keyword from the `metadata` as a `&str`
     /// # Errors
@@ -338,3 +325,23 @@ impl FcsSampleStub {
         self.metadata.get_number_of_parameters()
     }
 }
+
+/// Looks for a keyword among any `Metadata` - an open `Fcs` file's as well as a stub's
+/// # Errors
+/// Will return `Err` if the `Keyword` is not found in the `metadata` or if the `Keyword` cannot be converted to a `&str`
+pub fn keyword_string_value<'a>(metadata: &'a Metadata, keyword: &str) -> Result<Cow<'a, str>> {
+    // TODO: This should be a match statement
+    if let Ok(keyword) = metadata.get_string_keyword(keyword) {
+        Ok(keyword.get_str())
+    } else if let Ok(keyword) = metadata.get_integer_keyword(keyword) {
+        Ok(keyword.get_str())
+    } else if let Ok(keyword) = metadata.get_float_keyword(keyword) {
+        Ok(keyword.get_str())
+    } else if let Ok(keyword) = metadata.get_byte_keyword(keyword) {
+        Ok(keyword.get_str())
+    } else if let Ok(keyword) = metadata.get_mixed_keyword(keyword) {
+        Ok(keyword.get_str())
+    } else {
+        Err(anyhow!("Keyword not found: {}", keyword))
+    }
+}
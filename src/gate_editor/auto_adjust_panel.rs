@@ -0,0 +1,328 @@
+use std::path::PathBuf;
+
+use dioxus::prelude::*;
+use dioxus::stores::SyncStore;
+
+use crate::components::sheet::{Sheet, SheetContent, SheetHeader, SheetSide, SheetTitle};
+use crate::file_load::FcsFiles;
+use crate::gate_editor::compensation::CompensationStore;
+use crate::gate_editor::gates::{
+    GateId, GateState,
+    auto_adjust::{AutoAdjustResult, compute_gate_adjustments, group_adjustments},
+    gate_store::{FileId, GateSource, GateStateImplExt, GateStateStoreExt},
+};
+use crate::gate_editor::plots::axis_store::{AxisStore, AxisStoreStoreExt};
+use crate::gate_editor::stats_panel::collect_stats_files;
+use crate::omiq::metadata::{MetaDataParameter, MetaDataStore, MetaDataStoreStoreExt};
+
+#[derive(Clone, PartialEq)]
+struct PreviewRow {
+    file_id: FileId,
+    file_name: String,
+    drift: String,
+    dx: f32,
+    dy: f32,
+    width_ratio_x: f64,
+    width_ratio_y: f64,
+}
+
+// moves the gate onto each sample by its drift from a reference sample, previewed before
+// anything is written. Open while `gate_id` is set.
+#[component]
+pub fn AutoAdjustPanel(
+    gate_id: Signal<Option<GateId>>,
+    files: ReadSignal<Option<FcsFiles>>,
+) -> Element {
+    let mut gate_store = use_context::<SyncStore<GateState>>();
+    let axis_store = use_context::<SyncStore<AxisStore>>();
+    let metadata_store = use_context::<SyncStore<MetaDataStore>>();
+    let comp_store = use_context::<SyncStore<CompensationStore>>();
+
+    let mut reference: Signal<Option<PathBuf>> = use_signal(|| None);
+    // None writes one override per sample
+    let mut group_parameter: Signal<Option<MetaDataParameter>> = use_signal(|| None);
+    let mut result: Signal<Option<AutoAdjustResult>> = use_signal(|| None);
+    let mut accepted: Signal<Vec<FileId>> = use_signal(Vec::new);
+    let mut running = use_signal(|| false);
+    let mut message = use_signal(|| None::<String>);
+
+    // a preview is only valid for the gate it was made for
+    use_effect(move || {
+        let _ = gate_id.read();
+        result.set(None);
+        accepted.set(vec![]);
+        message.set(None);
+    });
+
+    let gate_name = gate_id
+        .read()
+        .as_ref()
+        .and_then(|id| gate_store.get_gate_name(id.clone()))
+        .unwrap_or_default();
+
+    let samples: Vec<(PathBuf, String)> = files
+        .read()
+        .as_ref()
+        .map(|f| {
+            f.file_list()
+                .iter()
+                .map(|stub| {
+                    let path = stub.get_filepath().to_path_buf();
+                    let name = stub
+                        .get_fil_keyword()
+                        .map(|n| n.to_string())
+                        .unwrap_or_else(|_| path.display().to_string());
+                    (path, name)
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let parameters: Vec<MetaDataParameter> = {
+        let mut p: Vec<MetaDataParameter> = metadata_store
+            .metadata()
+            .read()
+            .values()
+            .flat_map(|groups| groups.keys().cloned())
+            .collect();
+        p.sort();
+        p.dedup();
+        p
+    };
+
+    let rows: Vec<PreviewRow> = result
+        .read()
+        .as_ref()
+        .map(|r| {
+            r.adjustments
+                .iter()
+                .map(|a| PreviewRow {
+                    file_id: a.file_id.clone(),
+                    file_name: a.file_name.clone(),
+                    drift: a.drift_type.to_string(),
+                    dx: a.dx,
+                    dy: a.dy,
+                    width_ratio_x: a.width_ratio_x,
+                    width_ratio_y: a.width_ratio_y,
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let analyse = move |_| {
+        let Some(target) = gate_id.peek().clone() else {
+            return;
+        };
+        let Some(fcs_files) = files.peek().clone() else {
+            message.set(Some("No FCS directory loaded".to_string()));
+            return;
+        };
+        let Some(reference_path) = reference.peek().clone() else {
+            message.set(Some("Choose a reference sample".to_string()));
+            return;
+        };
+
+        let (mut stats_files, mut warnings) =
+            collect_stats_files(&fcs_files, gate_store, metadata_store, comp_store);
+        let Some(i) = stats_files.iter().position(|f| f.path == reference_path) else {
+            warnings.insert(0, "Reference sample could not be loaded".to_string());
+            message.set(Some(warnings.join("\n")));
+            return;
+        };
+        let reference_file = stats_files.swap_remove(i);
+        let hierarchy = gate_store.hierarchy().peek().clone();
+        let axis_settings = axis_store.settings().peek().clone();
+
+        running.set(true);
+        message.set(None);
+        spawn(async move {
+            let computed = tokio::task::spawn_blocking(move || {
+                compute_gate_adjustments(
+                    &target,
+                    &reference_file,
+                    &stats_files,
+                    &hierarchy,
+                    &axis_settings,
+                )
+            })
+            .await;
+            match computed {
+                Ok(Ok(mut r)) => {
+                    warnings.append(&mut r.errors);
+                    r.errors = warnings;
+                    accepted.set(
+                        r.adjustments
+                            .iter()
+                            .filter(|a| a.recommended())
+                            .map(|a| a.file_id.clone())
+                            .collect(),
+                    );
+                    result.set(Some(r));
+                }
+                Ok(Err(e)) => message.set(Some(format!("Auto-adjust failed: {e}"))),
+                Err(e) => message.set(Some(format!("Auto-adjust failed: {e}"))),
+            }
+            running.set(false);
+        });
+    };
+
+    let apply = move |_| {
+        let overrides = {
+            let result = result.read();
+            let Some(r) = &*result else {
+                return;
+            };
+            let accepted = accepted.peek();
+            match &*group_parameter.peek() {
+                None => Ok(r
+                    .adjustments
+                    .iter()
+                    .filter(|a| accepted.contains(&a.file_id))
+                    .map(|a| {
+                        (
+                            GateSource::Sample((a.gate.get_id(), a.file_id.clone())),
+                            a.gate.clone(),
+                        )
+                    })
+                    .collect::<Vec<_>>()),
+                Some(parameter) => {
+                    let metadata = metadata_store.metadata().peek().clone();
+                    group_adjustments(r, &accepted, &metadata, parameter).map(|groups| {
+                        groups
+                            .into_iter()
+                            .map(|(key, gate)| (GateSource::Group((gate.get_id(), key)), gate))
+                            .collect()
+                    })
+                }
+            }
+        };
+        match overrides.and_then(|o| gate_store.insert_position_overrides(o)) {
+            Ok(_) => gate_id.set(None),
+            Err(e) => message.set(Some(format!("Failed to apply adjustments: {e}"))),
+        }
+    };
+
+    rsx! {
+        Sheet {
+            open: gate_id.read().is_some(),
+            on_open_change: move |v: bool| {
+                if !v {
+                    gate_id.set(None);
+                }
+            },
+            SheetContent { side: SheetSide::Right, class: "stats-panel",
+                SheetHeader {
+                    SheetTitle { "Auto-adjust {gate_name}" }
+                }
+                div { class: "input-unit",
+                    label { "Reference sample" }
+                    select {
+                        onchange: move |e| reference.set(Some(PathBuf::from(e.value()))),
+                        option { value: "", disabled: true, selected: reference.read().is_none(),
+                            "Choose..."
+                        }
+                        for (path , name) in samples {
+                            option {
+                                key: "{path.display()}",
+                                value: "{path.display()}",
+                                selected: reference.read().as_ref() == Some(&path),
+                                "{name}"
+                            }
+                        }
+                    }
+                }
+                div { class: "input-unit",
+                    label { "Write to" }
+                    select {
+                        onchange: move |e| {
+                            let value = e.value();
+                            group_parameter
+                                .set(if value.is_empty() { None } else { Some(value.as_str().into()) });
+                        },
+                        option { value: "", selected: group_parameter.read().is_none(),
+                            "Each sample"
+                        }
+                        for parameter in parameters {
+                            option {
+                                key: "{parameter}",
+                                value: "{parameter}",
+                                selected: group_parameter.read().as_ref() == Some(&parameter),
+                                "Groups by {parameter} (mean shift)"
+                            }
+                        }
+                    }
+                }
+                div { class: "stats-panel_actions",
+                    button { disabled: running(), onclick: analyse,
+                        if running() {
+                            "Analysing..."
+                        } else {
+                            "Analyse"
+                        }
+                    }
+                    button {
+                        disabled: accepted.read().is_empty(),
+                        onclick: apply,
+                        "Apply selected"
+                    }
+                }
+                if let Some(m) = message() {
+                    div { class: "stats-panel_message", "{m}" }
+                }
+                if let Some(r) = &*result.read() {
+                    for e in r.errors.iter() {
+                        div { class: "error-message", "{e}" }
+                    }
+                }
+                if !rows.is_empty() {
+                    div { class: "stats-panel_table",
+                        table {
+                            thead {
+                                tr {
+                                    th { "" }
+                                    th { "Sample" }
+                                    th { "Drift" }
+                                    th { "dx" }
+                                    th { "dy" }
+                                    th { "Width x" }
+                                    th { "Width y" }
+                                }
+                            }
+                            tbody {
+                                for row in rows {
+                                    tr { key: "{row.file_id}",
+                                        td {
+                                            input {
+                                                r#type: "checkbox",
+                                                checked: accepted.read().contains(&row.file_id),
+                                                onchange: {
+                                                    let file_id = row.file_id.clone();
+                                                    move |e: FormEvent| {
+                                                        let mut accepted = accepted.write();
+                                                        if e.checked() {
+                                                            if !accepted.contains(&file_id) {
+                                                                accepted.push(file_id.clone());
+                                                            }
+                                                        } else {
+                                                            accepted.retain(|f| *f != file_id);
+                                                        }
+                                                    }
+                                                },
+                                            }
+                                        }
+                                        td { "{row.file_name}" }
+                                        td { "{row.drift}" }
+                                        td { "{row.dx:.3}" }
+                                        td { "{row.dy:.3}" }
+                                        td { "{row.width_ratio_x:.2}" }
+                                        td { "{row.width_ratio_y:.2}" }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
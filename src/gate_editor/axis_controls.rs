@@ -0,0 +1,541 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use dioxus::prelude::*;
+use dioxus::stores::SyncStore;
+use flow_fcs::TransformType;
+
+use crate::file_load::FcsFiles;
+use crate::gate_editor::{
+    AxisInfo,
+    axis_info::BiexParams,
+    compensation::{CompSource, CompTarget, CompensationImplExt, CompensationStore},
+    gates::gate_store::{FileId, GroupId},
+    plots::{
+        axis_store::{AxisStore, AxisStoreImplExt, Param},
+        histogram::{HistogramOptions, HistogramOverlay, HistogramScale, PlotMode},
+        plot_style::{PlotColourMap, PlotStyle, PlotStyleSettings},
+    },
+};
+use crate::omiq::metadata::{MetaDataKey, MetaDataParameter, MetaDataStore, MetaDataStoreStoreExt};
+
+// cofactor used when a channel is first switched to arcsinh
+const DEFAULT_COFACTOR: f32 = 6000.0;
+
+#[component]
+pub fn TransformSelect(axis: ReadSignal<AxisInfo>, on_change: EventHandler<TransformType>) -> Element {
+    let selected = use_memo(move || match axis.read().transform {
+        TransformType::Linear => "linear",
+        TransformType::Arcsinh { .. } => "arcsinh",
+        TransformType::Biexponential { .. } => "biex",
+    });
+
+    rsx! {
+        div { class: "input-unit",
+            label { "Transform" }
+            select {
+                value: "{selected}",
+                onchange: move |e| {
+                    let transform = match e.value().as_str() {
+                        "linear" => TransformType::Linear,
+                        "arcsinh" => TransformType::Arcsinh {
+                            cofactor: DEFAULT_COFACTOR,
+                        },
+                        "biex" => BiexParams::default().into_transform(),
+                        _ => return,
+                    };
+                    on_change.call(transform);
+                },
+                option { value: "linear", selected: selected() == "linear", "Linear" }
+                option { value: "arcsinh", selected: selected() == "arcsinh", "Arcsinh" }
+                option { value: "biex", selected: selected() == "biex", "Biex" }
+            }
+        }
+    }
+}
+
+// the width/T/M/A inputs shown in place of the cofactor for biexponential axes
+#[component]
+pub fn BiexControls(axis: ReadSignal<AxisInfo>, on_change: EventHandler<BiexParams>) -> Element {
+    let params = use_memo(move || axis.read().get_biex_params().unwrap_or_default());
+
+    let update = move |value: String, set: fn(&mut BiexParams, f32)| {
+        if let Ok(val) = value.parse::<f32>() {
+            let mut new_params = params();
+            set(&mut new_params, val);
+            on_change.call(new_params);
+        }
+    };
+
+    rsx! {
+        div { class: "input-unit biex-params",
+            label { "Biex (T / M / A / W)" }
+            div { class: "biex-params_inputs",
+                input {
+                    r#type: "number",
+                    title: "Top of scale (T)",
+                    value: "{params().top_of_scale}",
+                    oninput: move |e| update(e.value(), |p, v| p.top_of_scale = v),
+                    step: "any",
+                }
+                input {
+                    r#type: "number",
+                    title: "Positive decades (M)",
+                    value: "{params().positive_decades}",
+                    oninput: move |e| update(e.value(), |p, v| p.positive_decades = v),
+                    step: "any",
+                }
+                input {
+                    r#type: "number",
+                    title: "Negative decades (A)",
+                    value: "{params().negative_decades}",
+                    oninput: move |e| update(e.value(), |p, v| p.negative_decades = v),
+                    step: "any",
+                }
+                input {
+                    r#type: "number",
+                    title: "Width (W)",
+                    value: "{params().width}",
+                    oninput: move |e| update(e.value(), |p, v| p.width = v),
+                    step: "any",
+                }
+            }
+        }
+    }
+}
+
+// 2D density or a histogram of the x axis, with the histogram's display options
+#[component]
+pub fn PlotModeControls(mode: Signal<PlotMode>, options: Signal<HistogramOptions>) -> Element {
+    rsx! {
+        div { class: "input-unit",
+            label { "Plot" }
+            select {
+                onchange: move |e| {
+                    mode.set(
+                        if e.value() == "histogram" { PlotMode::Histogram } else { PlotMode::Density },
+                    )
+                },
+                option { value: "density", selected: mode() == PlotMode::Density, "2D Density" }
+                option { value: "histogram", selected: mode() == PlotMode::Histogram,
+                    "Histogram (X-Axis)"
+                }
+            }
+        }
+        if mode() == PlotMode::Histogram {
+            div { class: "input-unit",
+                label { "Y Scale" }
+                select {
+                    onchange: move |e| {
+                        options.write().scale = if e.value() == "percent" {
+                            HistogramScale::PercentMax
+                        } else {
+                            HistogramScale::Count
+                        };
+                    },
+                    option {
+                        value: "count",
+                        selected: options().scale == HistogramScale::Count,
+                        "Count"
+                    }
+                    option {
+                        value: "percent",
+                        selected: options().scale == HistogramScale::PercentMax,
+                        "% Max"
+                    }
+                }
+            }
+            div { class: "input-unit",
+                label { "Smooth" }
+                input {
+                    r#type: "checkbox",
+                    checked: options().smooth,
+                    onchange: move |e: FormEvent| options.write().smooth = e.checked(),
+                }
+            }
+        }
+    }
+}
+
+// how the current x/y pair is drawn - saved with the axes
+#[component]
+pub fn PlotStyleControls(x_axis: ReadSignal<Param>, y_axis: ReadSignal<Param>) -> Element {
+    let mut axis_store = use_context::<SyncStore<AxisStore>>();
+    let settings =
+        use_memo(move || axis_store.plot_style(&x_axis.read().fluoro, &y_axis.read().fluoro));
+
+    let mut update = move |change: &dyn Fn(&mut PlotStyleSettings)| {
+        let mut new_settings = settings();
+        change(&mut new_settings);
+        if new_settings != settings() {
+            axis_store.set_plot_style(
+                x_axis.peek().fluoro.clone(),
+                y_axis.peek().fluoro.clone(),
+                new_settings,
+            );
+        }
+    };
+
+    let levels = settings()
+        .contour_levels
+        .iter()
+        .map(|l| l.to_string())
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    rsx! {
+        div { class: "input-unit",
+            label { "Style" }
+            select {
+                onchange: move |e| {
+                    if let Some(style) = PlotStyle::ALL.into_iter().find(|s| s.label() == e.value()) {
+                        update(&|s| s.style = style);
+                    }
+                },
+                for style in PlotStyle::ALL {
+                    option {
+                        value: style.label(),
+                        selected: settings().style == style,
+                        {style.label()}
+                    }
+                }
+            }
+        }
+        if settings().style.has_contours() {
+            div { class: "input-unit",
+                label { "Levels (%)" }
+                input {
+                    r#type: "text",
+                    title: "Probability contours - the percentage of events outside each line",
+                    value: "{levels}",
+                    onchange: move |e| {
+                        let parsed: Result<Vec<f32>, _> = e
+                            .value()
+                            .split(',')
+                            .map(|l| l.trim())
+                            .filter(|l| !l.is_empty())
+                            .map(|l| l.parse::<f32>())
+                            .collect();
+                        if let Ok(mut levels) = parsed {
+                            levels.retain(|l| *l > 0.0 && *l < 100.0);
+                            levels.sort_by(f32::total_cmp);
+                            levels.dedup();
+                            update(&|s| s.contour_levels = levels.clone());
+                        }
+                    },
+                }
+            }
+        } else {
+            div { class: "input-unit",
+                label { "Colour Map" }
+                select {
+                    disabled: settings().style != PlotStyle::Density,
+                    onchange: move |e| {
+                        if let Some(map) = PlotColourMap::ALL
+                            .into_iter()
+                            .find(|m| m.label() == e.value())
+                        {
+                            update(&|s| s.colour_map = map);
+                        }
+                    },
+                    for map in PlotColourMap::ALL {
+                        option {
+                            value: map.label(),
+                            selected: settings().colour_map == map,
+                            {map.label()}
+                        }
+                    }
+                }
+            }
+        }
+        div { class: "input-unit",
+            label { "Point Size" }
+            input {
+                r#type: "number",
+                value: "{settings().point_size}",
+                step: "0.1",
+                oninput: move |e| {
+                    if let Ok(size) = e.value().parse::<f32>() {
+                        if size > 0.0 {
+                            update(&|s| s.point_size = size);
+                        }
+                    }
+                },
+            }
+        }
+        if settings().style == PlotStyle::Zebra {
+            div { class: "input-unit",
+                label { "Outliers (%)" }
+                input {
+                    r#type: "number",
+                    title: "Events in the sparsest areas, drawn as dots",
+                    value: "{settings().outlier_threshold}",
+                    step: "any",
+                    oninput: move |e| {
+                        if let Ok(threshold) = e.value().parse::<f32>() {
+                            if (0.0..100.0).contains(&threshold) {
+                                update(&|s| s.outlier_threshold = threshold);
+                            }
+                        }
+                    },
+                }
+            }
+        }
+    }
+}
+
+#[derive(Clone, PartialEq)]
+enum OverlayBy {
+    Samples,
+    Group(MetaDataParameter),
+}
+
+// other samples, or pooled metadata groups, drawn over the histogram
+#[component]
+pub fn OverlaySelect(
+    files: ReadSignal<Option<FcsFiles>>,
+    overlays: Signal<Vec<HistogramOverlay>>,
+) -> Element {
+    let metadata_store = use_context::<SyncStore<MetaDataStore>>();
+    let mut overlay_by: Signal<Option<OverlayBy>> = use_signal(|| None);
+
+    let parameters: Vec<MetaDataParameter> = {
+        let mut p: Vec<MetaDataParameter> = metadata_store
+            .metadata()
+            .read()
+            .values()
+            .flat_map(|groups| groups.keys().cloned())
+            .collect();
+        p.sort();
+        p.dedup();
+        p
+    };
+
+    let choices = use_memo(move || {
+        let files = files.read();
+        let Some(files) = files.as_ref() else {
+            return vec![];
+        };
+        match &*overlay_by.read() {
+            None => vec![],
+            Some(OverlayBy::Samples) => files
+                .file_list()
+                .iter()
+                .map(|stub| {
+                    let path = stub.get_filepath().to_path_buf();
+                    let name = stub
+                        .get_fil_keyword()
+                        .map(|n| n.to_string())
+                        .unwrap_or_else(|_| path.display().to_string());
+                    HistogramOverlay {
+                        name,
+                        paths: vec![path],
+                    }
+                })
+                .collect(),
+            Some(OverlayBy::Group(parameter)) => {
+                let name_to_id = metadata_store.file_name_to_gating_id().read();
+                let metadata = metadata_store.metadata().read();
+                let mut groups: BTreeMap<GroupId, Vec<PathBuf>> = BTreeMap::new();
+                for stub in files.file_list() {
+                    let path = stub.get_filepath();
+                    let group = path
+                        .file_name()
+                        .and_then(|n| n.to_str())
+                        .and_then(|n| name_to_id.get(n))
+                        .and_then(|id| metadata.get(id))
+                        .and_then(|file_groups| file_groups.get(parameter));
+                    if let Some(group) = group {
+                        groups
+                            .entry(group.clone())
+                            .or_default()
+                            .push(path.to_path_buf());
+                    }
+                }
+                groups
+                    .into_iter()
+                    .map(|(group, paths)| HistogramOverlay {
+                        name: format!("{parameter}: {group}"),
+                        paths,
+                    })
+                    .collect()
+            }
+        }
+    });
+
+    rsx! {
+        div { class: "input-unit",
+            label { "Overlay" }
+            select {
+                onchange: move |e| {
+                    overlays.set(vec![]);
+                    overlay_by
+                        .set(
+                            match e.value().as_str() {
+                                "" => None,
+                                "samples" => Some(OverlayBy::Samples),
+                                value => {
+                                    value
+                                        .strip_prefix("group:")
+                                        .map(|p| OverlayBy::Group(p.into()))
+                                }
+                            },
+                        );
+                },
+                option { value: "", selected: overlay_by.read().is_none(), "None" }
+                option {
+                    value: "samples",
+                    selected: overlay_by() == Some(OverlayBy::Samples),
+                    "Samples"
+                }
+                for parameter in parameters {
+                    option {
+                        value: "group:{parameter}",
+                        selected: overlay_by() == Some(OverlayBy::Group(parameter.clone())),
+                        "{parameter}"
+                    }
+                }
+            }
+        }
+        if !choices.read().is_empty() {
+            div { class: "overlay-choices",
+                for choice in choices() {
+                    label { key: "{choice.name}",
+                        input {
+                            r#type: "checkbox",
+                            checked: overlays.read().contains(&choice),
+                            onchange: {
+                                let choice = choice.clone();
+                                move |e: FormEvent| {
+                                    let mut selected = overlays.write();
+                                    if e.checked() {
+                                        if !selected.contains(&choice) {
+                                            selected.push(choice.clone());
+                                        }
+                                    } else {
+                                        selected.retain(|o| *o != choice);
+                                    }
+                                }
+                            },
+                        }
+                        "{choice.name}"
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[derive(Clone, PartialEq)]
+enum CompApplyTo {
+    Global,
+    Group(MetaDataParameter),
+    Sample,
+}
+
+// the matrix the current sample is compensated with, and which samples a change applies to
+#[component]
+pub fn CompensationControls(current_file: ReadSignal<Option<FileId>>) -> Element {
+    let mut comp_store = use_context::<SyncStore<CompensationStore>>();
+    let metadata_store = use_context::<SyncStore<MetaDataStore>>();
+    let mut apply_to = use_signal(|| CompApplyTo::Global);
+
+    let groups = use_memo(move || {
+        current_file()
+            .and_then(|id| metadata_store.metadata().read().get(&id).cloned())
+            .unwrap_or_default()
+    });
+    let current_source = use_memo(move || match current_file() {
+        Some(id) => comp_store.source_for(&id, &groups.read()),
+        None => CompSource::default(),
+    });
+    let mut parameters: Vec<MetaDataParameter> = groups.read().keys().cloned().collect();
+    parameters.sort();
+
+    let mut sources = vec![CompSource::Uncompensated, CompSource::Acquisition];
+    sources.extend(
+        comp_store
+            .matrix_names()
+            .into_iter()
+            .map(CompSource::Matrix),
+    );
+
+    rsx! {
+        div { class: "input-unit",
+            label { "Matrix" }
+            select {
+                disabled: current_file.read().is_none(),
+                onchange: move |e| {
+                    let Some(file_id) = current_file() else {
+                        return;
+                    };
+                    let source = match e.value().as_str() {
+                        "uncompensated" => CompSource::Uncompensated,
+                        "acquisition" => CompSource::Acquisition,
+                        v => match v.strip_prefix("matrix:") {
+                            Some(name) => CompSource::Matrix(name.into()),
+                            None => return,
+                        },
+                    };
+                    let target = match &*apply_to.read() {
+                        CompApplyTo::Global => CompTarget::Global,
+                        CompApplyTo::Sample => CompTarget::Sample(file_id),
+                        CompApplyTo::Group(parameter) => {
+                            let Some(group) = groups.read().get(parameter).cloned() else {
+                                return;
+                            };
+                            CompTarget::Group(MetaDataKey {
+                                parameter: parameter.clone(),
+                                group,
+                            })
+                        }
+                    };
+                    let metadata = metadata_store.metadata().peek().clone();
+                    comp_store.assign(target, source, &metadata);
+                },
+                for s in sources {
+                    option {
+                        value: match &s {
+                            CompSource::Uncompensated => "uncompensated".to_string(),
+                            CompSource::Acquisition => "acquisition".to_string(),
+                            CompSource::Matrix(name) => format!("matrix:{name}"),
+                        },
+                        selected: current_source() == s,
+                        {s.label()}
+                    }
+                }
+            }
+        }
+        div { class: "input-unit",
+            label { "Apply to" }
+            select {
+                onchange: move |e| {
+                    apply_to
+                        .set(
+                            match e.value().as_str() {
+                                "sample" => CompApplyTo::Sample,
+                                v => match v.strip_prefix("group:") {
+                                    Some(parameter) => CompApplyTo::Group(parameter.into()),
+                                    None => CompApplyTo::Global,
+                                },
+                            },
+                        );
+                },
+                option { value: "global", selected: apply_to() == CompApplyTo::Global,
+                    "All samples"
+                }
+                for parameter in parameters {
+                    option {
+                        key: "{parameter}",
+                        value: "group:{parameter}",
+                        selected: apply_to() == CompApplyTo::Group(parameter.clone()),
+                        "This sample's {parameter} group"
+                    }
+                }
+                option { value: "sample", selected: apply_to() == CompApplyTo::Sample,
+                    "This sample only"
+                }
+            }
+        }
+    }
+}
@@ -1,7 +1,8 @@
 use std::sync::Arc;
 
 use dioxus::prelude::*;
-use flow_fcs::TransformType;
+use flow_fcs::{TransformType, Transformable};
+use serde::{Deserialize, Serialize};
 
 use crate::gate_editor::plots::axis_store::Param;
 
@@ -34,6 +35,153 @@ pub fn asinh_to_asinh(value: f32, old_cofactor: f32, new_cofactor: f32) -> anyho
     asinh_transform_f32(untransformed, new_cofactor)
 }
 
+// Biexponential (logicle) parameters, named as in Parks et al:
+// T = top_of_scale, M = positive_decades, A = negative_decades, W = width
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BiexParams {
+    pub top_of_scale: f32,
+    pub positive_decades: f32,
+    pub negative_decades: f32,
+    pub width: f32,
+}
+
+impl Default for BiexParams {
+    fn default() -> Self {
+        Self {
+            top_of_scale: 262144_f32,
+            positive_decades: 4.5,
+            negative_decades: 0_f32,
+            width: 0.5,
+        }
+    }
+}
+
+impl BiexParams {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        let BiexParams {
+            top_of_scale: t,
+            positive_decades: m,
+            negative_decades: a,
+            width: w,
+        } = *self;
+        if !(t.is_finite() && m.is_finite() && a.is_finite() && w.is_finite()) {
+            return Err(anyhow::anyhow!("Biexponential parameters must be finite"));
+        }
+        if t <= 0_f32 {
+            return Err(anyhow::anyhow!("Top of scale (T) must be positive"));
+        }
+        if m <= 0_f32 {
+            return Err(anyhow::anyhow!("Positive decades (M) must be positive"));
+        }
+        if w < 0_f32 || w > m / 2_f32 {
+            return Err(anyhow::anyhow!("Width (W) must be between 0 and M/2"));
+        }
+        if a < -w || a > m - 2_f32 * w {
+            return Err(anyhow::anyhow!(
+                "Negative decades (A) must be between -W and M - 2W"
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn into_transform(self) -> TransformType {
+        TransformType::Biexponential {
+            top_of_scale: self.top_of_scale,
+            positive_decades: self.positive_decades,
+            negative_decades: self.negative_decades,
+            width: self.width,
+        }
+    }
+
+    pub fn from_transform(transform: &TransformType) -> Option<Self> {
+        match transform {
+            TransformType::Biexponential {
+                top_of_scale,
+                positive_decades,
+                negative_decades,
+                width,
+            } => Some(Self {
+                top_of_scale: *top_of_scale,
+                positive_decades: *positive_decades,
+                negative_decades: *negative_decades,
+                width: *width,
+            }),
+            _ => None,
+        }
+    }
+}
+
+// the biex maths lives in flow_fcs so gates are transformed exactly as the event data is
+pub fn biex_transform_f32(value: f32, params: BiexParams) -> anyhow::Result<f32> {
+    if value.is_nan() || value.is_infinite() {
+        return Err(anyhow::anyhow!(
+            "Value {value} cannot be biexponential transformed"
+        ));
+    }
+    params.validate()?;
+    let transformed = params.into_transform().transform(&value);
+    if transformed.is_finite() {
+        Ok(transformed)
+    } else {
+        Err(anyhow::anyhow!(
+            "Biexponential transform of {value} is not finite"
+        ))
+    }
+}
+
+pub fn biex_reverse_f32(transformed_value: f32, params: BiexParams) -> anyhow::Result<f32> {
+    if transformed_value.is_nan() || transformed_value.is_infinite() {
+        return Err(anyhow::anyhow!(
+            "Transformed value {transformed_value} is invalid"
+        ));
+    }
+    params.validate()?;
+    let raw = params.into_transform().inverse_transform(&transformed_value);
+    if raw.is_finite() {
+        Ok(raw)
+    } else {
+        Err(anyhow::anyhow!(
+            "Biexponential reverse of {transformed_value} is not finite"
+        ))
+    }
+}
+
+// transform a raw value into any scale
+pub fn transform_f32(value: f32, transform: &TransformType) -> anyhow::Result<f32> {
+    match transform {
+        TransformType::Linear => Ok(value),
+        TransformType::Arcsinh { cofactor } => asinh_transform_f32(value, *cofactor),
+        TransformType::Biexponential { .. } => {
+            let params = BiexParams::from_transform(transform)
+                .ok_or_else(|| anyhow::anyhow!("{transform:?} has no biexponential parameters"))?;
+            biex_transform_f32(value, params)
+        }
+    }
+}
+
+// take a value on any scale back to raw
+pub fn reverse_f32(transformed_value: f32, transform: &TransformType) -> anyhow::Result<f32> {
+    match transform {
+        TransformType::Linear => Ok(transformed_value),
+        TransformType::Arcsinh { cofactor } => asinh_reverse_f32(transformed_value, *cofactor),
+        TransformType::Biexponential { .. } => {
+            let params = BiexParams::from_transform(transform)
+                .ok_or_else(|| anyhow::anyhow!("{transform:?} has no biexponential parameters"))?;
+            biex_reverse_f32(transformed_value, params)
+        }
+    }
+}
+
+// move a value between scales, keeping its raw position
+pub fn transform_to_transform(
+    value: f32,
+    old: &TransformType,
+    new: &TransformType,
+) -> anyhow::Result<f32> {
+    let untransformed = reverse_f32(value, old)?;
+    transform_f32(untransformed, new)
+}
+
 #[derive(Debug, Clone, PartialEq, Props)]
 pub struct AxisInfo {
     pub param: Param,
@@ -92,15 +240,31 @@ impl AxisInfo {
                     transform,
                 }
             }
-            TransformType::Biexponential {
-                top_of_scale: _,
-                positive_decades: _,
-                negative_decades: _,
-                width: _,
-            } => todo!(),
+            TransformType::Biexponential { .. } => {
+                let lower = transform_f32(lower_raw, &transform).unwrap_or(0f32);
+                let upper = transform_f32(upper_raw, &transform).unwrap_or(f32::INFINITY);
+                Self {
+                    param,
+                    axis_lower: lower,
+                    axis_upper: upper,
+                    transform,
+                }
+            }
         }
     }
 
+    pub fn into_biexponential(&self, params: BiexParams) -> anyhow::Result<Self> {
+        params.validate()?;
+        // go back to raw first so the axis keeps its raw-space limits
+        let (lower_raw, upper_raw) = self.get_untransformed_bounds();
+        Ok(Self {
+            param: self.param.clone(),
+            axis_lower: biex_transform_f32(lower_raw, params)?,
+            axis_upper: biex_transform_f32(upper_raw, params)?,
+            transform: params.into_transform(),
+        })
+    }
+
     pub fn into_archsinh(&self, cofactor: f32) -> anyhow::Result<Self> {
         let old_lower = self.axis_lower;
         let old_upper = self.axis_upper;
@@ -125,8 +289,9 @@ impl AxisInfo {
                 }
             }
             _ => {
-                let lower = asinh_transform_f32(old_lower, cofactor)?;
-                let upper = asinh_transform_f32(old_upper, cofactor)?;
+                let (lower_raw, upper_raw) = self.get_untransformed_bounds();
+                let lower = asinh_transform_f32(lower_raw, cofactor)?;
+                let upper = asinh_transform_f32(upper_raw, cofactor)?;
                 // let data_lower = asinh_transform_f32(old_dl, cofactor)?;
                 // let data_upper = asinh_transform_f32(old_du, cofactor)?;
                 Self {
@@ -167,18 +332,33 @@ impl AxisInfo {
                     transform,
                 }
             }
-            TransformType::Biexponential { .. } => Self {
-                param: self.param.clone(),
-                axis_lower: old_lower,
-                axis_upper: old_upper,
-                // data_lower: old_dl,
-                // data_upper: old_du,
-                transform,
-            },
+            TransformType::Biexponential { .. } => {
+                let (lower_untransformed, upper_untransformed) = self.get_untransformed_bounds();
+                Self {
+                    param: self.param.clone(),
+                    axis_lower: lower_untransformed,
+                    axis_upper: upper_untransformed,
+                    transform,
+                }
+            }
         };
         Ok(new_self)
     }
 
+    // re-project the axis limits onto a new scale, keeping their raw positions
+    pub fn into_transform(&self, transform: &TransformType) -> anyhow::Result<Self> {
+        match transform {
+            TransformType::Linear => self.into_linear(),
+            TransformType::Arcsinh { cofactor } => self.into_archsinh(*cofactor),
+            TransformType::Biexponential { .. } => {
+                let params = BiexParams::from_transform(transform).ok_or_else(|| {
+                    anyhow::anyhow!("{transform:?} has no biexponential parameters")
+                })?;
+                self.into_biexponential(params)
+            }
+        }
+    }
+
     pub fn is_linear(&self) -> bool {
         matches!(self.transform, TransformType::Linear)
     }
@@ -187,32 +367,24 @@ impl AxisInfo {
         matches!(self.transform, TransformType::Arcsinh { .. })
     }
 
+    pub fn is_biexponential(&self) -> bool {
+        matches!(self.transform, TransformType::Biexponential { .. })
+    }
+
+    pub fn get_biex_params(&self) -> Option<BiexParams> {
+        BiexParams::from_transform(&self.transform)
+    }
+
     pub fn get_untransformed_bounds(&self) -> (f32, f32) {
-        match self.transform {
-            TransformType::Arcsinh { cofactor } => (
-                asinh_reverse_f32(self.axis_lower, cofactor).unwrap_or_default(),
-                asinh_reverse_f32(self.axis_upper, cofactor).unwrap_or_default(),
-            ),
-            _ => (self.axis_lower, self.axis_upper),
-        }
+        (self.get_untransformed_lower(), self.get_untransformed_upper())
     }
 
     pub fn get_untransformed_lower(&self) -> f32 {
-        match self.transform {
-            TransformType::Arcsinh { cofactor } => {
-                asinh_reverse_f32(self.axis_lower, cofactor).unwrap_or_default()
-            }
-            _ => self.axis_lower,
-        }
+        reverse_f32(self.axis_lower, &self.transform).unwrap_or_default()
     }
 
     pub fn get_untransformed_upper(&self) -> f32 {
-        match self.transform {
-            TransformType::Arcsinh { cofactor } => {
-                asinh_reverse_f32(self.axis_upper, cofactor).unwrap_or_default()
-            }
-            _ => self.axis_upper,
-        }
+        reverse_f32(self.axis_upper, &self.transform).unwrap_or_default()
     }
 
     pub fn into_new_lower(&self, lower_raw: f32) -> Self {
@@ -236,7 +408,16 @@ impl AxisInfo {
                     transform: self.transform.clone(),
                 }
             }
-            TransformType::Biexponential { .. } => todo!(),
+            TransformType::Biexponential { .. } => {
+                let new_lower =
+                    transform_f32(lower_raw, &self.transform).unwrap_or(self.axis_lower);
+                Self {
+                    param: self.param.clone(),
+                    axis_lower: new_lower,
+                    axis_upper: self.axis_upper,
+                    transform: self.transform.clone(),
+                }
+            }
         }
     }
 
@@ -261,7 +442,16 @@ impl AxisInfo {
                     transform: self.transform.clone(),
                 }
             }
-            TransformType::Biexponential { .. } => todo!(),
+            TransformType::Biexponential { .. } => {
+                let new_upper =
+                    transform_f32(upper_raw, &self.transform).unwrap_or(self.axis_upper);
+                Self {
+                    param: self.param.clone(),
+                    axis_lower: self.axis_lower,
+                    axis_upper: new_upper,
+                    transform: self.transform.clone(),
+                }
+            }
         }
     }
 
@@ -273,3 +463,180 @@ impl AxisInfo {
         }
     }
 }
+
+// flow_fcs transforms aren't serialisable - this mirrors TransformType for project files
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type")]
+pub enum TransformRecord {
+    Linear,
+    Arcsinh {
+        cofactor: f32,
+    },
+    Biexponential {
+        top_of_scale: f32,
+        positive_decades: f32,
+        negative_decades: f32,
+        width: f32,
+    },
+}
+
+impl From<&TransformType> for TransformRecord {
+    fn from(transform: &TransformType) -> Self {
+        match transform {
+            TransformType::Linear => TransformRecord::Linear,
+            TransformType::Arcsinh { cofactor } => TransformRecord::Arcsinh {
+                cofactor: *cofactor,
+            },
+            TransformType::Biexponential {
+                top_of_scale,
+                positive_decades,
+                negative_decades,
+                width,
+            } => TransformRecord::Biexponential {
+                top_of_scale: *top_of_scale,
+                positive_decades: *positive_decades,
+                negative_decades: *negative_decades,
+                width: *width,
+            },
+        }
+    }
+}
+
+impl From<TransformRecord> for TransformType {
+    fn from(record: TransformRecord) -> Self {
+        match record {
+            TransformRecord::Linear => TransformType::Linear,
+            TransformRecord::Arcsinh { cofactor } => TransformType::Arcsinh { cofactor },
+            TransformRecord::Biexponential {
+                top_of_scale,
+                positive_decades,
+                negative_decades,
+                width,
+            } => TransformType::Biexponential {
+                top_of_scale,
+                positive_decades,
+                negative_decades,
+                width,
+            },
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct AxisRecord {
+    pub param: Param,
+    pub axis_lower: f32,
+    pub axis_upper: f32,
+    pub transform: TransformRecord,
+}
+
+impl From<&AxisInfo> for AxisRecord {
+    fn from(axis: &AxisInfo) -> Self {
+        Self {
+            param: axis.param.clone(),
+            axis_lower: axis.axis_lower,
+            axis_upper: axis.axis_upper,
+            transform: (&axis.transform).into(),
+        }
+    }
+}
+
+impl From<AxisRecord> for AxisInfo {
+    fn from(record: AxisRecord) -> Self {
+        Self {
+            param: record.param,
+            axis_lower: record.axis_lower,
+            axis_upper: record.axis_upper,
+            transform: record.transform.into(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_raw_close(a: f32, b: f32) {
+        assert!((a - b).abs() <= 1e-3 * b.abs().max(10.0), "{a} != {b}");
+    }
+
+    #[test]
+    fn test_biex_round_trip() {
+        let params = BiexParams {
+            negative_decades: 1.0,
+            ..BiexParams::default()
+        };
+        for raw in [
+            -5000_f32, -500.0, -10.0, -0.5, 0.0, 0.5, 10.0, 500.0, 5000.0, 200000.0,
+        ] {
+            let scaled = biex_transform_f32(raw, params).unwrap();
+            assert_raw_close(biex_reverse_f32(scaled, params).unwrap(), raw);
+        }
+        // the scale still orders negatives below zero
+        assert!(
+            biex_transform_f32(-500.0, params).unwrap() < biex_transform_f32(0.0, params).unwrap()
+        );
+        assert!(biex_transform_f32(f32::NAN, params).is_err());
+    }
+
+    #[test]
+    fn test_transform_switch_keeps_raw_position() {
+        let arcsinh = TransformType::Arcsinh { cofactor: 150.0 };
+        let biex = BiexParams::default().into_transform();
+        for raw in [-300_f32, 0.0, 40.0, 1200.0, 90000.0] {
+            let on_arcsinh = transform_f32(raw, &arcsinh).unwrap();
+            let on_biex = transform_to_transform(on_arcsinh, &arcsinh, &biex).unwrap();
+            assert_raw_close(reverse_f32(on_biex, &biex).unwrap(), raw);
+
+            let back = transform_to_transform(on_biex, &biex, &arcsinh).unwrap();
+            assert_raw_close(reverse_f32(back, &arcsinh).unwrap(), raw);
+        }
+    }
+
+    #[test]
+    fn test_biex_params_validate() {
+        let default = BiexParams::default();
+        assert!(default.validate().is_ok());
+
+        let bad = [
+            BiexParams {
+                top_of_scale: 0.0,
+                ..default
+            },
+            BiexParams {
+                positive_decades: -1.0,
+                ..default
+            },
+            // W > M / 2
+            BiexParams {
+                width: 3.0,
+                ..default
+            },
+            // A < -W
+            BiexParams {
+                negative_decades: -1.0,
+                ..default
+            },
+            // A > M - 2W
+            BiexParams {
+                negative_decades: 4.0,
+                ..default
+            },
+            BiexParams {
+                width: f32::NAN,
+                ..default
+            },
+        ];
+        for params in bad {
+            assert!(params.validate().is_err(), "{params:?} should be rejected");
+            assert!(biex_transform_f32(1.0, params).is_err());
+
+            // a bad transform is an error, not silently drawn with the default parameters
+            let transform = params.into_transform();
+            assert!(transform_f32(1.0, &transform).is_err());
+            assert!(reverse_f32(1.0, &transform).is_err());
+            assert!(AxisInfo::default().into_transform(&transform).is_err());
+        }
+    }
+}
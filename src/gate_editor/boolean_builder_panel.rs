@@ -0,0 +1,417 @@
+use dioxus::prelude::*;
+use dioxus::stores::SyncStore;
+use flow_fcs::Fcs;
+
+use crate::components::sheet::{Sheet, SheetContent, SheetHeader, SheetSide, SheetTitle};
+use crate::file_load::FcsFiles;
+use crate::gate_editor::compensation::CompensationStore;
+use crate::gate_editor::gates::{
+    GateId, GateState,
+    boolean_expr::{BooleanExpr, quote_name},
+    gate_filtering::PopulationMasks,
+    gate_stats::PopulationCount,
+    gate_store::{FileId, GateStateImplExt, GateStateStoreExt, GateSubStoreStoreExt, ROOTGATE},
+};
+use crate::gate_editor::plots::axis_store::{AxisStore, AxisStoreStoreExt};
+use crate::gate_editor::plots::data_helpers::scale_fcs_data;
+use crate::gate_editor::stats_panel::collect_stats_files;
+use crate::omiq::metadata::MetaDataStore;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum BooleanTarget {
+    // a new boolean gate beside `gate_id`, starting from `text`
+    New { gate_id: GateId, text: String },
+    // an existing boolean gate
+    Edit(GateId),
+}
+
+impl BooleanTarget {
+    fn gate_id(&self) -> &GateId {
+        match self {
+            BooleanTarget::New { gate_id, .. } => gate_id,
+            BooleanTarget::Edit(gate_id) => gate_id,
+        }
+    }
+}
+
+#[derive(Clone, PartialEq)]
+struct PreviewRow {
+    label: String,
+    count: Result<PopulationCount, String>,
+}
+
+// combines the gates under one parent into a boolean gate, eg (A AND B) OR NOT C, with counts
+// for the current sample before it is created. Open while `target` is set.
+#[component]
+pub fn BooleanBuilderPanel(
+    target: Signal<Option<BooleanTarget>>,
+    files: ReadSignal<Option<FcsFiles>>,
+    current_file: ReadSignal<Option<FileId>>,
+) -> Element {
+    let mut gate_store = use_context::<SyncStore<GateState>>();
+    let axis_store = use_context::<SyncStore<AxisStore>>();
+    let metadata_store = use_context::<SyncStore<MetaDataStore>>();
+    let comp_store = use_context::<SyncStore<CompensationStore>>();
+
+    let mut text = use_signal(String::new);
+    let mut name = use_signal(String::new);
+    let mut selected: Signal<Vec<GateId>> = use_signal(Vec::new);
+    let mut preview: Signal<Option<Vec<PreviewRow>>> = use_signal(|| None);
+    let mut running = use_signal(|| false);
+    let mut message = use_signal(|| None::<String>);
+
+    // the gates under the target's parent, and for an edit any others its expression reads
+    let context = use_memo(move || {
+        let target = target.read().clone()?;
+        let editing = match &target {
+            BooleanTarget::Edit(id) => Some(id.clone()),
+            BooleanTarget::New { .. } => None,
+        };
+        let expr = editing
+            .as_ref()
+            .and_then(|id| gate_store.get_boolean_expression(id.clone()));
+        let hierarchy_binding = gate_store.hierarchy();
+        let hierarchy = hierarchy_binding.read();
+        let registry_binding = gate_store.gate_store().primary_and_subgate_registry();
+        let registry = registry_binding.read();
+        let parent = hierarchy
+            .get_parent(target.gate_id())
+            .cloned()
+            .unwrap_or_else(|| ROOTGATE.clone());
+        let mut gates: Vec<GateId> = hierarchy
+            .get_children(&parent)
+            .into_iter()
+            .filter(|id| Some(*id) != editing.as_ref())
+            .cloned()
+            .collect();
+        let siblings = gates.len();
+        if let Some(expr) = &expr {
+            for id in expr.gate_ids() {
+                if !gates.contains(&id) {
+                    gates.push(id);
+                }
+            }
+        }
+        let names: Vec<(GateId, String)> = gates
+            .into_iter()
+            .map(|id| {
+                let name = registry.display_name(&id).unwrap_or_else(|| id.to_string());
+                (id, name)
+            })
+            .collect();
+        let text = match (&target, &expr) {
+            (BooleanTarget::New { text, .. }, _) => text.clone(),
+            (BooleanTarget::Edit(_), Some(expr)) => expr.to_text(&|id: &GateId| {
+                registry.display_name(id).unwrap_or_else(|| id.to_string())
+            }),
+            (BooleanTarget::Edit(_), None) => String::new(),
+        };
+        let name = match &editing {
+            Some(id) => registry.display_name(id).unwrap_or_default(),
+            None => String::new(),
+        };
+        Some(BuilderContext {
+            parent,
+            editing,
+            names,
+            siblings,
+            text,
+            name,
+        })
+    });
+
+    use_effect(move || {
+        let context = context.read();
+        text.set(context.as_ref().map(|c| c.text.clone()).unwrap_or_default());
+        name.set(context.as_ref().map(|c| c.name.clone()).unwrap_or_default());
+        selected.set(vec![]);
+        preview.set(None);
+        message.set(None);
+    });
+
+    let Some(BuilderContext {
+        parent,
+        editing,
+        names,
+        siblings,
+        ..
+    }) = context()
+    else {
+        return rsx! {};
+    };
+
+    // puts the ticked gates into the expression, joined by `operation`
+    let mut combine = {
+        let names = names.clone();
+        move |operation: &str| {
+            let picked: Vec<String> = names
+                .iter()
+                .filter(|(id, _)| selected.peek().contains(id))
+                .map(|(_, n)| quote_name(n))
+                .collect();
+            if picked.is_empty() {
+                return;
+            }
+            let joined = picked.join(&format!(" {operation} "));
+            let current = text.peek().trim().to_string();
+            text.set(if current.is_empty() {
+                joined
+            } else if picked.len() == 1 {
+                format!("{current} {operation} {joined}")
+            } else {
+                format!("{current} {operation} ({joined})")
+            });
+            selected.set(vec![]);
+            preview.set(None);
+        }
+    };
+    let mut combine_or = combine.clone();
+
+    let parse = {
+        let names = names.clone();
+        move || BooleanExpr::parse(&text.peek(), &names)
+    };
+
+    let parse_for_preview = parse.clone();
+    let parent_for_preview = parent.clone();
+    let names_for_preview = names.clone();
+    let run_preview = move |_| {
+        let expr = match parse_for_preview() {
+            Ok(expr) => expr,
+            Err(e) => {
+                message.set(Some(e.to_string()));
+                return;
+            }
+        };
+        let Some(fcs_files) = files.peek().clone() else {
+            message.set(Some("No FCS directory loaded".to_string()));
+            return;
+        };
+        let (stats_files, _) =
+            collect_stats_files(&fcs_files, gate_store, metadata_store, comp_store);
+        let current = current_file.peek().clone();
+        let Some(file) = stats_files
+            .into_iter()
+            .find(|f| current.as_ref().is_none_or(|id| *id == f.file_id))
+        else {
+            message.set(Some("The current sample could not be loaded".to_string()));
+            return;
+        };
+        let hierarchy = gate_store.hierarchy().peek().clone();
+        let axis_settings = axis_store.settings().peek().clone();
+        let parent = parent_for_preview.clone();
+        let names = names_for_preview.clone();
+        let label = match name.peek().trim() {
+            "" => "New gate".to_string(),
+            n => n.to_string(),
+        };
+
+        running.set(true);
+        message.set(None);
+        spawn(async move {
+            let computed =
+                tokio::task::spawn_blocking(move || -> anyhow::Result<Vec<PreviewRow>> {
+                    let fcs = Fcs::open(file.path.to_str().unwrap_or_default())?;
+                    let scaled =
+                        scale_fcs_data(&fcs, file.compensation.as_deref(), &axis_settings)?;
+                    let mut populations = PopulationMasks::new(&scaled, &hierarchy, &file.resolver);
+                    let mut rows = vec![preview_row(label, &expr, &mut populations, &parent)];
+                    let mut seen = vec![];
+                    for id in expr.gate_ids() {
+                        if seen.contains(&id) {
+                            continue;
+                        }
+                        let label = names
+                            .iter()
+                            .find(|(gid, _)| *gid == id)
+                            .map(|(_, n)| n.clone())
+                            .unwrap_or_else(|| id.to_string());
+                        let gate = BooleanExpr::Gate(id.clone());
+                        rows.push(preview_row(label, &gate, &mut populations, &parent));
+                        seen.push(id);
+                    }
+                    Ok(rows)
+                })
+                .await;
+            match computed {
+                Ok(Ok(rows)) => preview.set(Some(rows)),
+                Ok(Err(e)) => message.set(Some(format!("Preview failed: {e}"))),
+                Err(e) => message.set(Some(format!("Preview failed: {e}"))),
+            }
+            running.set(false);
+        });
+    };
+
+    let editing_for_save = editing.clone();
+    let save = move |_| {
+        let expr = match parse() {
+            Ok(expr) => expr,
+            Err(e) => {
+                message.set(Some(e.to_string()));
+                return;
+            }
+        };
+        let new_name = name.peek().trim().to_string();
+        let new_name = (!new_name.is_empty()).then_some(new_name);
+        let result = match &editing_for_save {
+            Some(id) => gate_store.edit_boolean_gate(id.clone(), new_name, &expr),
+            None => gate_store
+                .add_boolean_expression(new_name, &expr, parent.clone())
+                .map(|_| ()),
+        };
+        match result {
+            Ok(_) => target.set(None),
+            Err(e) => message.set(Some(format!("Failed to save boolean gate: {e}"))),
+        }
+    };
+
+    let (title, action) = match &editing {
+        Some(_) => ("Edit boolean gate", "Save"),
+        None => ("New boolean gate", "Create"),
+    };
+    let gate_choices: Vec<(GateId, String)> = names.into_iter().take(siblings).collect();
+
+    rsx! {
+        Sheet {
+            open: true,
+            on_open_change: move |v: bool| {
+                if !v {
+                    target.set(None);
+                }
+            },
+            SheetContent { side: SheetSide::Right, class: "stats-panel",
+                SheetHeader {
+                    SheetTitle { "{title}" }
+                }
+                div { class: "stats-panel_channels",
+                    for (id , gate_name) in gate_choices {
+                        label { key: "{id}",
+                            input {
+                                r#type: "checkbox",
+                                checked: selected.read().contains(&id),
+                                onchange: {
+                                    let id = id.clone();
+                                    move |e: FormEvent| {
+                                        let mut selected = selected.write();
+                                        if e.checked() {
+                                            if !selected.contains(&id) {
+                                                selected.push(id.clone());
+                                            }
+                                        } else {
+                                            selected.retain(|s| *s != id);
+                                        }
+                                    }
+                                },
+                            }
+                            "{gate_name}"
+                        }
+                    }
+                }
+                div { class: "stats-panel_actions",
+                    button {
+                        disabled: selected.read().is_empty(),
+                        onclick: move |_| combine("AND"),
+                        "Add with AND"
+                    }
+                    button {
+                        disabled: selected.read().is_empty(),
+                        onclick: move |_| combine_or("OR"),
+                        "Add with OR"
+                    }
+                }
+                div { class: "input-unit",
+                    label { "Expression" }
+                    input {
+                        class: "boolean-builder_expression",
+                        value: "{text}",
+                        placeholder: "(A AND B) OR NOT C",
+                        oninput: move |e| {
+                            text.set(e.value());
+                            preview.set(None);
+                            message.set(None);
+                        },
+                    }
+                }
+                div { class: "input-unit",
+                    label { "Name" }
+                    input {
+                        value: "{name}",
+                        placeholder: "The expression",
+                        oninput: move |e| name.set(e.value()),
+                    }
+                }
+                div { class: "stats-panel_actions",
+                    button { disabled: running(), onclick: run_preview,
+                        if running() {
+                            "Counting..."
+                        } else {
+                            "Preview"
+                        }
+                    }
+                    button { onclick: save, "{action}" }
+                }
+                if let Some(m) = message() {
+                    div { class: "stats-panel_message", "{m}" }
+                }
+                if let Some(rows) = &*preview.read() {
+                    div { class: "stats-panel_table",
+                        table {
+                            thead {
+                                tr {
+                                    th { "Population" }
+                                    th { "Count" }
+                                    th { "%Parent" }
+                                }
+                            }
+                            tbody {
+                                for row in rows.iter() {
+                                    tr {
+                                        td { "{row.label}" }
+                                        {
+                                            match &row.count {
+                                                Ok(p) => rsx! {
+                                                    td { "{p.count}" }
+                                                    td { "{p.percent_parent:.2}" }
+                                                },
+                                                Err(e) => rsx! {
+                                                    td { class: "error-message", colspan: 2, "{e}" }
+                                                },
+                                            }
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+// the count of `expr` under `parent` - a gate that can't be evaluated shows its error
+fn preview_row(
+    label: String,
+    expr: &BooleanExpr,
+    populations: &mut PopulationMasks<'_>,
+    parent: &GateId,
+) -> PreviewRow {
+    PreviewRow {
+        label,
+        count: expr
+            .preview_count(populations, parent)
+            .map_err(|e| e.to_string()),
+    }
+}
+
+#[derive(Clone, PartialEq)]
+struct BuilderContext {
+    parent: GateId,
+    // the boolean gate being edited
+    editing: Option<GateId>,
+    // siblings first, then other gates an edited expression reads
+    names: Vec<(GateId, String)>,
+    siblings: usize,
+    text: String,
+    name: String,
+}
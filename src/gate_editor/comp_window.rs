@@ -0,0 +1,430 @@
+use std::sync::Arc;
+
+use dioxus::prelude::*;
+use dioxus::stores::SyncStore;
+use polars::frame::DataFrame;
+
+use crate::gate_editor::axis_controls::CompensationControls;
+use crate::gate_editor::compensation::{
+    CompMatrix, CompensationImplExt, CompensationStore, is_compensated_channel,
+};
+use crate::gate_editor::plots::axis_store::{AxisStore, AxisStoreStoreExt, Param};
+use crate::gate_editor::plots::data_helpers::{compensated_points, get_flow_data, raw_fcs_data};
+use crate::gate_editor::plots::draw_plot::PseudoColourPlot;
+use crate::gate_editor::session::Session;
+use crate::omiq::metadata::{MetaDataStore, MetaDataStoreStoreExt};
+
+static COMP_STYLE: Asset = asset!("assets/comp_window.css");
+
+// the preview sample is cut down to this many events so edits redraw straight away
+const PREVIEW_EVENTS: usize = 50_000;
+const PREVIEW_SIZE: (u32, u32) = (400, 400);
+
+#[derive(Clone, PartialEq)]
+enum StartFrom {
+    // whatever the current sample is compensated with
+    Sample,
+    Matrix(Arc<str>),
+    Identity,
+}
+
+// Edits a spillover matrix over the fluorescence channels, previewing the current sample
+// before and after the edit
+#[component]
+pub fn CompWindow() -> Element {
+    let session = use_context::<Session>();
+    let axis_store = use_context::<SyncStore<AxisStore>>();
+    let mut comp_store = use_context::<SyncStore<CompensationStore>>();
+    let metadata_store = use_context::<SyncStore<MetaDataStore>>();
+
+    let mut start_from = use_signal(|| StartFrom::Sample);
+    let mut x_choice: Signal<Option<Param>> = use_signal(|| None);
+    let mut y_choice: Signal<Option<Param>> = use_signal(|| None);
+    let mut save_name = use_signal(String::new);
+    let mut message = use_signal(|| None::<String>);
+    // the two matrices being compared - None is the matrix being edited
+    let mut compare: Signal<(Option<Arc<str>>, Option<Arc<str>>)> = use_signal(|| (None, None));
+
+    let channels = use_memo(move || {
+        axis_store
+            .sorted_settings()
+            .read()
+            .iter()
+            .filter(|p| is_compensated_channel(&p.fluoro))
+            .cloned()
+            .collect::<Vec<Param>>()
+    });
+
+    let sample = use_memo(move || {
+        let files = session.files.read();
+        files
+            .as_ref()?
+            .file_list()
+            .get((session.sample_index)())
+            .cloned()
+    });
+
+    // what the edit starts from, laid out over the channels being edited
+    let base = use_memo(move || {
+        let names: Vec<Arc<str>> = channels.read().iter().map(|p| p.fluoro.clone()).collect();
+        let matrix = match start_from() {
+            StartFrom::Identity => None,
+            StartFrom::Matrix(name) => comp_store.matrix(&name),
+            StartFrom::Sample => match (sample(), (session.current_file)()) {
+                (Some(stub), Some(file_id)) => {
+                    let groups = metadata_store
+                        .metadata()
+                        .read()
+                        .get(&file_id)
+                        .cloned()
+                        .unwrap_or_default();
+                    comp_store
+                        .active_matrix(&file_id, &groups, &stub.metadata)
+                        .inspect_err(|e| println!("{e}"))
+                        .ok()
+                        .flatten()
+                }
+                _ => None,
+            },
+        };
+        match matrix {
+            Some(m) => m.reindexed(&names),
+            None => CompMatrix::identity(names),
+        }
+    });
+
+    let mut draft = use_signal(|| base());
+    use_effect(move || draft.set(base()));
+
+    let raw_events = use_resource(move || async move {
+        let Some(stub) = sample() else {
+            return Err(anyhow::anyhow!("No sample loaded"));
+        };
+        let fcs = get_flow_data(stub.get_filepath().to_path_buf())
+            .await
+            .map_err(|e| anyhow::anyhow!("{e}"))?;
+        let raw = raw_fcs_data(&fcs, None)?;
+        Ok(Arc::new(raw.head(Some(PREVIEW_EVENTS))))
+    });
+
+    let x_axis = use_memo(move || x_choice().or_else(|| channels.read().first().cloned()));
+    let y_axis = use_memo(move || y_choice().or_else(|| channels.read().get(1).cloned()));
+
+    let matrix_names = comp_store.matrix_names();
+    let sample_name = sample
+        .read()
+        .as_ref()
+        .map(|s| {
+            s.get_fil_keyword()
+                .map(|n| n.to_string())
+                .unwrap_or_else(|_| s.get_filepath().display().to_string())
+        })
+        .unwrap_or_else(|| "No sample loaded".to_string());
+
+    let compared = move |name: &Option<Arc<str>>| match name {
+        Some(name) => comp_store.matrix(name).map(|m| (*m).clone()),
+        None => Some(draft()),
+    };
+    let changes = {
+        let (a, b) = compare();
+        match (compared(&a), compared(&b)) {
+            (Some(a), Some(b)) => a.diff(&b),
+            _ => vec![],
+        }
+    };
+    let marker_of = move |fluoro: &Arc<str>| {
+        channels
+            .read()
+            .iter()
+            .find(|p| p.fluoro == *fluoro)
+            .map(|p| p.to_string())
+            .unwrap_or_else(|| fluoro.to_string())
+    };
+
+    let save = move |_| {
+        let name = save_name.peek().trim().to_string();
+        if name.is_empty() {
+            message.set(Some("Name the matrix before saving it".to_string()));
+            return;
+        }
+        let metadata = metadata_store.metadata().peek().clone();
+        comp_store.set_matrix(name.as_str().into(), draft(), &metadata);
+        start_from.set(StartFrom::Matrix(name.as_str().into()));
+        message.set(Some(format!("Saved {name}")));
+    };
+
+    let channel_select = move |choice: Signal<Option<Param>>, current: Memo<Option<Param>>| {
+        let mut choice = choice;
+        rsx! {
+            select {
+                onchange: move |e| {
+                    let param = channels.read().iter().find(|p| *p.fluoro == *e.value()).cloned();
+                    choice.set(param);
+                },
+                for p in channels() {
+                    option {
+                        key: "{p.fluoro}",
+                        value: "{p.fluoro}",
+                        selected: current().as_ref() == Some(&p),
+                        "{p}"
+                    }
+                }
+            }
+        }
+    };
+
+    rsx! {
+        document::Stylesheet { href: COMP_STYLE }
+        div { class: "comp-window",
+            div { class: "comp-window_toolbar",
+                span { class: "comp-window_sample", "{sample_name}" }
+                div { class: "input-unit",
+                    label { "Start from" }
+                    select {
+                        onchange: move |e| {
+                            start_from
+                                .set(
+                                    match e.value().as_str() {
+                                        "sample" => StartFrom::Sample,
+                                        v => match v.strip_prefix("matrix:") {
+                                            Some(name) => StartFrom::Matrix(name.into()),
+                                            None => StartFrom::Identity,
+                                        },
+                                    },
+                                );
+                        },
+                        option { value: "sample", selected: start_from() == StartFrom::Sample,
+                            "This sample's matrix"
+                        }
+                        for name in matrix_names.clone() {
+                            option {
+                                key: "{name}",
+                                value: "matrix:{name}",
+                                selected: start_from() == StartFrom::Matrix(name.clone()),
+                                "{name}"
+                            }
+                        }
+                        option { value: "identity", selected: start_from() == StartFrom::Identity,
+                            "Uncompensated"
+                        }
+                    }
+                }
+                div { class: "input-unit",
+                    label { "X" }
+                    {channel_select(x_choice, x_axis)}
+                }
+                div { class: "input-unit",
+                    label { "Y" }
+                    {channel_select(y_choice, y_axis)}
+                }
+                button { onclick: move |_| draft.set(base()), "Revert" }
+            }
+
+            div { class: "comp-window_body",
+                div { class: "comp-grid-container",
+                    if channels.read().is_empty() {
+                        "Load scaling to choose the channels to compensate"
+                    } else {
+                        table { class: "comp-grid",
+                            thead {
+                                tr {
+                                    th { title: "Spillover from each row's channel into each column's, in %",
+                                        "%"
+                                    }
+                                    for p in channels() {
+                                        th { key: "{p.fluoro}", "{p.marker}" }
+                                    }
+                                }
+                            }
+                            tbody {
+                                for (i , from) in channels().into_iter().enumerate() {
+                                    tr { key: "{from.fluoro}",
+                                        th { "{from.marker}" }
+                                        for (j , to) in channels().into_iter().enumerate() {
+                                            td {
+                                                key: "{to.fluoro}",
+                                                class: if draft.read().spillover[i][j] != base.read().spillover[i][j] { "comp-cell_changed" },
+                                                input {
+                                                    r#type: "number",
+                                                    step: "0.1",
+                                                    title: "{from} into {to}",
+                                                    disabled: i == j,
+                                                    value: "{draft.read().spillover[i][j] * 100.0:.2}",
+                                                    onchange: move |e| {
+                                                        if let Ok(percent) = e.value().parse::<f32>() {
+                                                            draft.write().spillover[i][j] = percent / 100.0;
+                                                        }
+                                                    },
+                                                }
+                                            }
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+
+                if let (Some(x), Some(y)) = (x_axis(), y_axis()) {
+                    div { class: "comp-previews",
+                        CompPreview {
+                            title: "Before",
+                            matrix: base,
+                            raw_events,
+                            x_axis: x.clone(),
+                            y_axis: y.clone(),
+                        }
+                        CompPreview {
+                            title: "After",
+                            matrix: draft,
+                            raw_events,
+                            x_axis: x,
+                            y_axis: y,
+                        }
+                    }
+                }
+            }
+
+            div { class: "comp-window_footer",
+                div { class: "comp-window_section",
+                    h4 { "Save" }
+                    div { class: "input-unit",
+                        label { "Name" }
+                        input {
+                            r#type: "text",
+                            value: "{save_name}",
+                            oninput: move |e| save_name.set(e.value()),
+                        }
+                    }
+                    button { onclick: save, "Save matrix" }
+                    if let Some(m) = message() {
+                        span { class: "comp-window_message", "{m}" }
+                    }
+                }
+                div { class: "comp-window_section",
+                    h4 { "Assign" }
+                    CompensationControls { current_file: session.current_file }
+                }
+                div { class: "comp-window_section",
+                    h4 { "Compare" }
+                    for side in 0..2 {
+                        select {
+                            key: "{side}",
+                            onchange: move |e| {
+                                let name: Option<Arc<str>> = e.value().strip_prefix("matrix:").map(Arc::from);
+                                let mut pair = compare.write();
+                                if side == 0 {
+                                    pair.0 = name;
+                                } else {
+                                    pair.1 = name;
+                                }
+                            },
+                            option { value: "edit", "This edit" }
+                            for name in matrix_names.clone() {
+                                option {
+                                    key: "{name}",
+                                    value: "matrix:{name}",
+                                    selected: {
+                                        let pair = compare.read();
+                                        let chosen = if side == 0 { &pair.0 } else { &pair.1 };
+                                        chosen.as_ref() == Some(&name)
+                                    },
+                                    "{name}"
+                                }
+                            }
+                        }
+                    }
+                    if changes.is_empty() {
+                        span { class: "comp-window_message", "No differences" }
+                    } else {
+                        ul { class: "comp-diff",
+                            for change in changes {
+                                li {
+                                    "{marker_of(&change.from)} into {marker_of(&change.to)}: "
+                                    "{change.before * 100.0:.2}% → {change.after * 100.0:.2}%"
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+// one sample's events on the chosen pair of channels, compensated with `matrix`
+#[component]
+fn CompPreview(
+    title: String,
+    matrix: ReadSignal<CompMatrix>,
+    raw_events: Resource<anyhow::Result<Arc<DataFrame>>>,
+    x_axis: ReadSignal<Param>,
+    y_axis: ReadSignal<Param>,
+) -> Element {
+    let axis_store = use_context::<SyncStore<AxisStore>>();
+
+    let x_axis_info = use_memo(move || {
+        let param = x_axis.read();
+        axis_store
+            .settings()
+            .read()
+            .get(&param.fluoro)
+            .cloned()
+            .unwrap_or_default()
+    });
+    let y_axis_info = use_memo(move || {
+        let param = y_axis.read();
+        axis_store
+            .settings()
+            .read()
+            .get(&param.fluoro)
+            .cloned()
+            .unwrap_or_default()
+    });
+
+    let points = use_resource(move || {
+        let matrix = matrix();
+        let raw = raw_events
+            .read()
+            .as_ref()
+            .and_then(|r| r.as_ref().ok())
+            .cloned();
+        let x = x_axis.read().fluoro.clone();
+        let y = y_axis.read().fluoro.clone();
+        let axis_settings = axis_store.settings().read().clone();
+        async move {
+            let raw = raw.ok_or_else(|| anyhow::anyhow!("Loading events"))?;
+            tokio::task::spawn_blocking(move || {
+                compensated_points(&raw, &matrix, (&x, &y), &axis_settings)
+            })
+            .await?
+        }
+    });
+
+    rsx! {
+        div { class: "comp-preview",
+            h4 { "{title}" }
+            match &*points.read() {
+                Some(Ok(data)) if !data.is_empty() => rsx! {
+                    PseudoColourPlot {
+                        size: PREVIEW_SIZE,
+                        data: data.clone(),
+                        x_axis_info: x_axis_info(),
+                        y_axis_info: y_axis_info(),
+                        parental_gate_id: None,
+                        backgates: vec![],
+                        show_gates: false,
+                    }
+                },
+                Some(Err(e)) => rsx! {
+                    span { class: "error-message", "{e}" }
+                },
+                _ => rsx! {
+                    div { class: "spinner-container",
+                        div { class: "spinner" }
+                    }
+                },
+            }
+        }
+    }
+}
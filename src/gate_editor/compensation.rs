@@ -0,0 +1,742 @@
+use std::path::Path;
+use std::sync::Arc;
+
+use anyhow::anyhow;
+use dioxus::prelude::*;
+use flow_fcs::Metadata;
+use polars::prelude::*;
+use rayon::prelude::*;
+use rustc_hash::{FxHashMap, FxHashSet};
+use serde::{Deserialize, Serialize};
+
+use crate::file_load::keyword_string_value;
+use crate::gate_editor::gates::gate_store::{FileId, GroupId};
+use crate::omiq::metadata::{MetaDataFileMap, MetaDataKey, MetaDataParameter};
+
+// where acquisition software writes its spillover matrix - $COMP is the FCS 2.0 name
+const SPILLOVER_KEYWORDS: [&str; 3] = ["$SPILLOVER", "$SPILL", "$COMP"];
+// a pivot this small means two channels are indistinguishable
+const SINGULAR_TOLERANCE: f64 = 1e-12;
+// coefficients closer than this are the same value written out differently
+const DIFF_TOLERANCE: f32 = 1e-6;
+
+/// A spillover matrix - row i is how much of channel i's signal is seen in each channel,
+/// so the diagonal is 1. Events are compensated with its inverse.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CompMatrix {
+    pub channels: Vec<Arc<str>>,
+    pub spillover: Vec<Vec<f32>>,
+}
+
+impl CompMatrix {
+    pub fn new(channels: Vec<Arc<str>>, spillover: Vec<Vec<f32>>) -> anyhow::Result<Self> {
+        let n = channels.len();
+        if n == 0 {
+            return Err(anyhow!("A compensation matrix needs at least one channel"));
+        }
+        if spillover.len() != n || spillover.iter().any(|row| row.len() != n) {
+            return Err(anyhow!(
+                "A compensation matrix for {n} channels needs {n} x {n} values"
+            ));
+        }
+        if spillover.iter().flatten().any(|v| !v.is_finite()) {
+            return Err(anyhow!("Compensation matrix values must be numbers"));
+        }
+        Ok(Self {
+            channels,
+            spillover,
+        })
+    }
+
+    pub fn identity(channels: Vec<Arc<str>>) -> Self {
+        let n = channels.len();
+        let spillover = (0..n)
+            .map(|i| (0..n).map(|j| if i == j { 1.0 } else { 0.0 }).collect())
+            .collect();
+        Self {
+            channels,
+            spillover,
+        }
+    }
+
+    /// How much of `from`'s signal is seen in `to` - channels outside the matrix don't spill
+    pub fn coefficient(&self, from: &str, to: &str) -> f32 {
+        let index = |channel: &str| self.channels.iter().position(|c| &**c == channel);
+        match (index(from), index(to)) {
+            (Some(i), Some(j)) => self.spillover[i][j],
+            _ if from == to => 1.0,
+            _ => 0.0,
+        }
+    }
+
+    /// The same spillover laid out over `channels`, in their order
+    pub fn reindexed(&self, channels: &[Arc<str>]) -> Self {
+        let spillover = channels
+            .iter()
+            .map(|from| {
+                channels
+                    .iter()
+                    .map(|to| self.coefficient(from, to))
+                    .collect()
+            })
+            .collect();
+        Self {
+            channels: channels.to_vec(),
+            spillover,
+        }
+    }
+
+    /// Every coefficient that differs between the two matrices, over the channels of both
+    pub fn diff(&self, other: &Self) -> Vec<SpilloverChange> {
+        let mut channels = self.channels.clone();
+        channels.extend(
+            other
+                .channels
+                .iter()
+                .filter(|c| !self.channels.contains(c))
+                .cloned(),
+        );
+        let mut changes = vec![];
+        for from in &channels {
+            for to in &channels {
+                let before = self.coefficient(from, to);
+                let after = other.coefficient(from, to);
+                if (before - after).abs() > DIFF_TOLERANCE {
+                    changes.push(SpilloverChange {
+                        from: from.clone(),
+                        to: to.clone(),
+                        before,
+                        after,
+                    });
+                }
+            }
+        }
+        changes
+    }
+
+    /// The matrix the file was acquired with, if it has one
+    pub fn from_metadata(metadata: &Metadata) -> Option<anyhow::Result<Self>> {
+        SPILLOVER_KEYWORDS.iter().find_map(|keyword| {
+            let value = keyword_string_value(metadata, keyword).ok()?;
+            Some(
+                Self::from_spillover_keyword(&value)
+                    .map_err(|e| anyhow!("{keyword} could not be read: {e}")),
+            )
+        })
+    }
+
+    /// Parses `n,channel_1,...,channel_n,s_11,s_12,...,s_nn`
+    pub fn from_spillover_keyword(value: &str) -> anyhow::Result<Self> {
+        let mut tokens = value.split(',').map(str::trim);
+        let n: usize = tokens
+            .next()
+            .and_then(|t| t.parse().ok())
+            .ok_or_else(|| anyhow!("no channel count"))?;
+        let channels: Vec<Arc<str>> = tokens.by_ref().take(n).map(Arc::from).collect();
+        if channels.len() != n || channels.iter().any(|c| c.parse::<f32>().is_ok()) {
+            return Err(anyhow!("expected {n} channel names"));
+        }
+        let values = tokens
+            .map(|t| t.parse::<f32>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| anyhow!("{e}"))?;
+        if values.len() != n * n {
+            return Err(anyhow!("expected {} values, found {}", n * n, values.len()));
+        }
+        Self::new(channels, values.chunks(n).map(|r| r.to_vec()).collect())
+    }
+
+    pub fn from_omiq_csv(path: &Path) -> anyhow::Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| anyhow!("Failed to read {}: {}", path.display(), e))?;
+        Self::from_omiq_csv_text(&text)
+    }
+
+    /// A header row of channel names, then one row of spillover values per channel.
+    /// Rows may start with their channel name, in which case they're matched to the
+    /// header by name. Matrices exported as percentages are scaled back to fractions.
+    pub fn from_omiq_csv_text(text: &str) -> anyhow::Result<Self> {
+        let mut lines = text
+            .lines()
+            .map(|line| {
+                line.split(',')
+                    .map(|cell| cell.trim().trim_matches('"').trim())
+                    .collect::<Vec<_>>()
+            })
+            .filter(|cells| cells.iter().any(|c| !c.is_empty()));
+        let header = lines
+            .next()
+            .ok_or_else(|| anyhow!("The compensation file is empty"))?;
+        let rows: Vec<Vec<&str>> = lines.collect();
+
+        let labelled = rows
+            .first()
+            .and_then(|row| row.first())
+            .is_some_and(|cell| cell.parse::<f32>().is_err());
+        let channels: Vec<Arc<str>> = header
+            .iter()
+            .skip(usize::from(labelled))
+            .map(|c| Arc::from(*c))
+            .collect();
+
+        let mut spillover = vec![vec![]; channels.len()];
+        for (i, row) in rows.iter().enumerate() {
+            let (index, values) = if labelled {
+                let name = row[0];
+                let index = channels
+                    .iter()
+                    .position(|c| &**c == name)
+                    .ok_or_else(|| anyhow!("Row {name} is not a column of the matrix"))?;
+                (index, &row[1..])
+            } else {
+                (i, &row[..])
+            };
+            let values = values
+                .iter()
+                .map(|v| v.parse::<f32>())
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|e| anyhow!("Row {}: {e}", i + 1))?;
+            if let Some(slot) = spillover.get_mut(index) {
+                *slot = values;
+            }
+        }
+
+        let percent = spillover
+            .iter()
+            .enumerate()
+            .all(|(i, row)| row.get(i).is_some_and(|v| *v > 1.5));
+        if percent {
+            spillover.iter_mut().flatten().for_each(|v| *v /= 100.0);
+        }
+        Self::new(channels, spillover)
+    }
+
+    /// Gauss-Jordan elimination with partial pivoting
+    pub fn inverse(&self) -> anyhow::Result<Vec<Vec<f64>>> {
+        let n = self.channels.len();
+        let mut a: Vec<Vec<f64>> = self
+            .spillover
+            .iter()
+            .map(|row| row.iter().map(|v| *v as f64).collect())
+            .collect();
+        let mut inv: Vec<Vec<f64>> = (0..n)
+            .map(|i| (0..n).map(|j| if i == j { 1.0 } else { 0.0 }).collect())
+            .collect();
+
+        for col in 0..n {
+            let pivot = (col..n)
+                .max_by(|x, y| a[*x][col].abs().total_cmp(&a[*y][col].abs()))
+                .unwrap_or(col);
+            if a[pivot][col].abs() < SINGULAR_TOLERANCE {
+                return Err(anyhow!(
+                    "The compensation matrix can't be inverted - check {}",
+                    self.channels[col]
+                ));
+            }
+            a.swap(col, pivot);
+            inv.swap(col, pivot);
+
+            let scale = a[col][col];
+            a[col].iter_mut().for_each(|v| *v /= scale);
+            inv[col].iter_mut().for_each(|v| *v /= scale);
+            let (pivot_a, pivot_inv) = (a[col].clone(), inv[col].clone());
+            for (row, (a_row, inv_row)) in a.iter_mut().zip(inv.iter_mut()).enumerate() {
+                let factor = a_row[col];
+                if row == col || factor == 0.0 {
+                    continue;
+                }
+                a_row
+                    .iter_mut()
+                    .zip(&pivot_a)
+                    .for_each(|(v, p)| *v -= factor * p);
+                inv_row
+                    .iter_mut()
+                    .zip(&pivot_inv)
+                    .for_each(|(v, p)| *v -= factor * p);
+            }
+        }
+        Ok(inv)
+    }
+
+    /// Replaces the matrix's channels in `df` with their compensated values - every
+    /// observed event is unmixed by multiplying it with the inverse of the spillover
+    pub fn compensate(&self, df: &DataFrame) -> anyhow::Result<DataFrame> {
+        let inverse = self.inverse()?;
+        let observed = self
+            .channels
+            .iter()
+            .map(|channel| {
+                let column = df
+                    .column(channel)
+                    .map_err(|_| {
+                        anyhow!("{channel} is in the compensation matrix but not the file")
+                    })?
+                    .cast(&DataType::Float64)?;
+                Ok(column
+                    .f64()?
+                    .into_iter()
+                    .map(|v| v.unwrap_or(0.0))
+                    .collect())
+            })
+            .collect::<anyhow::Result<Vec<Vec<f64>>>>()?;
+
+        let compensated: Vec<Column> = (0..self.channels.len())
+            .into_par_iter()
+            .map(|j| {
+                let mut values = vec![0f64; df.height()];
+                for (i, channel) in observed.iter().enumerate() {
+                    let weight = inverse[i][j];
+                    if weight == 0.0 {
+                        continue;
+                    }
+                    values
+                        .iter_mut()
+                        .zip(channel)
+                        .for_each(|(out, v)| *out += v * weight);
+                }
+                Float32Chunked::from_iter_values(
+                    self.channels[j].as_ref().into(),
+                    values.into_iter().map(|v| v as f32),
+                )
+                .into_series()
+                .into()
+            })
+            .collect();
+
+        let mut df = df.clone();
+        for column in compensated {
+            df.with_column(column)?;
+        }
+        Ok(df)
+    }
+}
+
+/// One spillover coefficient that differs between two matrices
+#[derive(Clone, PartialEq, Debug)]
+pub struct SpilloverChange {
+    pub from: Arc<str>,
+    pub to: Arc<str>,
+    pub before: f32,
+    pub after: f32,
+}
+
+/// What a sample is compensated with
+#[derive(Clone, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+pub enum CompSource {
+    /// the matrix in the file's own $SPILLOVER keyword, uncompensated without one
+    #[default]
+    Acquisition,
+    /// an imported or edited matrix, by name
+    Matrix(Arc<str>),
+    Uncompensated,
+}
+
+impl CompSource {
+    pub fn label(&self) -> String {
+        match self {
+            CompSource::Acquisition => "Acquisition ($SPILLOVER)".to_string(),
+            CompSource::Matrix(name) => name.to_string(),
+            CompSource::Uncompensated => "Uncompensated".to_string(),
+        }
+    }
+}
+
+/// The level a compensation assignment is made at - a sample's own assignment wins over
+/// its group's, which wins over the global one
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub enum CompTarget {
+    Global,
+    Group(MetaDataKey),
+    Sample(FileId),
+}
+
+#[derive(Store, Clone, Default)]
+pub struct CompensationStore {
+    matrices: FxHashMap<Arc<str>, Arc<CompMatrix>>,
+    global: CompSource,
+    group_assignments: FxHashMap<MetaDataKey, CompSource>,
+    sample_assignments: FxHashMap<FileId, CompSource>,
+    // samples whose compensation changed after they were gated - their gates need checking
+    flagged_files: FxHashSet<FileId>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct GroupCompRecord {
+    pub parameter: MetaDataParameter,
+    pub group: GroupId,
+    pub source: CompSource,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct CompensationRecord {
+    pub matrices: FxHashMap<Arc<str>, CompMatrix>,
+    pub global: CompSource,
+    pub group_assignments: Vec<GroupCompRecord>,
+    pub sample_assignments: FxHashMap<FileId, CompSource>,
+}
+
+impl CompensationStore {
+    fn source_for(
+        &self,
+        file_id: &FileId,
+        groups: &FxHashMap<MetaDataParameter, GroupId>,
+    ) -> CompSource {
+        if let Some(source) = self.sample_assignments.get(file_id) {
+            return source.clone();
+        }
+        // sorted, so a sample in two assigned groups always gets the same one
+        let mut parameters: Vec<&MetaDataParameter> = groups.keys().collect();
+        parameters.sort();
+        parameters
+            .into_iter()
+            .find_map(|parameter| {
+                self.group_assignments.get(&MetaDataKey {
+                    parameter: parameter.clone(),
+                    group: groups[parameter].clone(),
+                })
+            })
+            .cloned()
+            .unwrap_or_else(|| self.global.clone())
+    }
+
+    /// The matrix a sample's events are compensated with - `metadata` is the sample's own
+    pub fn active_matrix(
+        &self,
+        file_id: &FileId,
+        groups: &FxHashMap<MetaDataParameter, GroupId>,
+        metadata: &Metadata,
+    ) -> anyhow::Result<Option<Arc<CompMatrix>>> {
+        match self.source_for(file_id, groups) {
+            CompSource::Acquisition => CompMatrix::from_metadata(metadata)
+                .transpose()
+                .map(|m| m.map(Arc::new)),
+            CompSource::Matrix(name) => self
+                .matrices
+                .get(&name)
+                .cloned()
+                .map(Some)
+                .ok_or_else(|| anyhow!("No compensation matrix named {name}")),
+            CompSource::Uncompensated => Ok(None),
+        }
+    }
+
+    // every sample whose source differs between the two stores
+    fn changed_files(&self, other: &Self, metadata: &MetaDataFileMap) -> Vec<FileId> {
+        let mut files: FxHashSet<FileId> = metadata.keys().cloned().collect();
+        files.extend(self.sample_assignments.keys().cloned());
+        files.extend(other.sample_assignments.keys().cloned());
+        files
+            .into_iter()
+            .filter(|file_id| {
+                let groups = metadata.get(file_id).cloned().unwrap_or_default();
+                self.source_for(file_id, &groups) != other.source_for(file_id, &groups)
+            })
+            .collect()
+    }
+}
+
+#[store(pub name = CompensationImplExt)]
+impl<Lens> Store<CompensationStore, Lens> {
+    fn source_for(
+        &self,
+        file_id: &FileId,
+        groups: &FxHashMap<MetaDataParameter, GroupId>,
+    ) -> CompSource {
+        self.read().source_for(file_id, groups)
+    }
+
+    /// The matrix a sample's events are compensated with - `metadata` is the sample's own
+    fn active_matrix(
+        &self,
+        file_id: &FileId,
+        groups: &FxHashMap<MetaDataParameter, GroupId>,
+        metadata: &Metadata,
+    ) -> anyhow::Result<Option<Arc<CompMatrix>>> {
+        self.read().active_matrix(file_id, groups, metadata)
+    }
+
+    fn matrix(&self, name: &str) -> Option<Arc<CompMatrix>> {
+        self.read().matrices.get(name).cloned()
+    }
+
+    fn matrix_names(&self) -> Vec<Arc<str>> {
+        let mut names: Vec<Arc<str>> = self.read().matrices.keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    fn is_flagged(&self, file_id: &FileId) -> bool {
+        self.read().flagged_files.contains(file_id)
+    }
+
+    fn clear_flag(&mut self, file_id: &FileId) {
+        self.write().flagged_files.remove(file_id);
+    }
+
+    /// Adds a matrix, or replaces the one with the same name - samples using a replaced
+    /// matrix are flagged
+    fn set_matrix(&mut self, name: Arc<str>, matrix: CompMatrix, metadata: &MetaDataFileMap) {
+        let mut store = self.write();
+        let replaced = store.matrices.get(&name).is_some_and(|old| **old != matrix);
+        store.matrices.insert(name.clone(), Arc::new(matrix));
+        if replaced {
+            let source = CompSource::Matrix(name);
+            let using: Vec<FileId> = metadata
+                .iter()
+                .filter(|(file_id, groups)| store.source_for(file_id, groups) == source)
+                .map(|(file_id, _)| file_id.clone())
+                .collect();
+            store.flagged_files.extend(using);
+        }
+    }
+
+    /// Imports a matrix named after its file, and applies it to every sample
+    fn import_omiq_matrix(
+        &mut self,
+        path: &Path,
+        metadata: &MetaDataFileMap,
+    ) -> anyhow::Result<()> {
+        let matrix = CompMatrix::from_omiq_csv(path)?;
+        let name: Arc<str> = path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or("OMIQ")
+            .into();
+        self.set_matrix(name.clone(), matrix, metadata);
+        self.assign(CompTarget::Global, CompSource::Matrix(name), metadata);
+        Ok(())
+    }
+
+    /// Sets the source for a level - a group or sample assignment equal to the level
+    /// above it is removed. Samples whose active matrix changes are flagged.
+    fn assign(&mut self, target: CompTarget, source: CompSource, metadata: &MetaDataFileMap) {
+        let before = self.read().clone();
+        {
+            let mut store = self.write();
+            match target {
+                CompTarget::Global => store.global = source,
+                CompTarget::Group(key) => {
+                    if source == store.global {
+                        store.group_assignments.remove(&key);
+                    } else {
+                        store.group_assignments.insert(key, source);
+                    }
+                }
+                CompTarget::Sample(file_id) => {
+                    let groups = metadata.get(&file_id).cloned().unwrap_or_default();
+                    store.sample_assignments.remove(&file_id);
+                    if store.source_for(&file_id, &groups) != source {
+                        store.sample_assignments.insert(file_id, source);
+                    }
+                }
+            }
+        }
+        let changed = before.changed_files(&self.read(), metadata);
+        self.write().flagged_files.extend(changed);
+    }
+
+    fn save_to_record(&self) -> CompensationRecord {
+        let store = self.peek();
+        CompensationRecord {
+            matrices: store
+                .matrices
+                .iter()
+                .map(|(k, v)| (k.clone(), (**v).clone()))
+                .collect(),
+            global: store.global.clone(),
+            group_assignments: store
+                .group_assignments
+                .iter()
+                .map(|(key, source)| GroupCompRecord {
+                    parameter: key.parameter.clone(),
+                    group: key.group.clone(),
+                    source: source.clone(),
+                })
+                .collect(),
+            sample_assignments: store.sample_assignments.clone(),
+        }
+    }
+
+    fn load_from_record(&mut self, record: CompensationRecord) {
+        self.with_mut(|s| {
+            s.matrices = record
+                .matrices
+                .into_iter()
+                .map(|(k, v)| (k, Arc::new(v)))
+                .collect();
+            s.global = record.global;
+            s.group_assignments = record
+                .group_assignments
+                .into_iter()
+                .map(|r| {
+                    (
+                        MetaDataKey {
+                            parameter: r.parameter,
+                            group: r.group,
+                        },
+                        r.source,
+                    )
+                })
+                .collect();
+            s.sample_assignments = record.sample_assignments;
+            s.flagged_files.clear();
+        });
+    }
+}
+
+/// Scatter and time aren't fluorescence, so compensation never moves gates drawn on them
+pub fn is_compensated_channel(channel: &str) -> bool {
+    !(channel.contains("FSC") || channel.contains("SSC") || channel.contains("Time"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn channels(names: &[&str]) -> Vec<Arc<str>> {
+        names.iter().map(|n| Arc::from(*n)).collect()
+    }
+
+    #[test]
+    fn test_spillover_keyword() {
+        let m = CompMatrix::from_spillover_keyword("2,FITC-A,PE-A,1,0.2,0.05,1").unwrap();
+        assert_eq!(m.channels, channels(&["FITC-A", "PE-A"]));
+        assert_eq!(m.spillover, vec![vec![1.0, 0.2], vec![0.05, 1.0]]);
+
+        assert!(CompMatrix::from_spillover_keyword("2,FITC-A,PE-A,1,0.2,0.05").is_err());
+        // FCS 2.0 $COMP values without channel names can't be matched to the data
+        assert!(CompMatrix::from_spillover_keyword("2,1,0.2,0.05,1").is_err());
+    }
+
+    #[test]
+    fn test_omiq_csv() {
+        // labelled rows in a different order to the header, as percentages
+        let text = "\"\",FITC-A,PE-A\nPE-A,5,100\nFITC-A,100,20\n";
+        let m = CompMatrix::from_omiq_csv_text(text).unwrap();
+        assert_eq!(m.channels, channels(&["FITC-A", "PE-A"]));
+        assert_eq!(m.spillover, vec![vec![1.0, 0.2], vec![0.05, 1.0]]);
+
+        let unlabelled = CompMatrix::from_omiq_csv_text("FITC-A,PE-A\n1,0.2\n0.05,1").unwrap();
+        assert_eq!(unlabelled, m);
+
+        assert!(CompMatrix::from_omiq_csv_text("FITC-A,PE-A\n1,0.2\n").is_err());
+    }
+
+    #[test]
+    fn test_compensate_unmixes_spillover() {
+        let m = CompMatrix::new(
+            channels(&["FITC-A", "PE-A"]),
+            vec![vec![1.0, 0.2], vec![0.05, 1.0]],
+        )
+        .unwrap();
+        // true signals (1000, 0) and (0, 500) observed with spillover, plus scatter
+        let df = df!(
+            "FSC-A" => [10f32, 20.0],
+            "FITC-A" => [1000f32, 25.0],
+            "PE-A" => [200f32, 500.0]
+        )
+        .unwrap();
+        let out = m.compensate(&df).unwrap();
+        let col = |name: &str| -> Vec<f32> {
+            out.column(name)
+                .unwrap()
+                .f32()
+                .unwrap()
+                .into_iter()
+                .flatten()
+                .collect()
+        };
+        for (got, want) in col("FITC-A").iter().zip([1000.0, 0.0]) {
+            assert!((got - want).abs() < 1e-2, "{got}");
+        }
+        for (got, want) in col("PE-A").iter().zip([0.0, 500.0]) {
+            assert!((got - want).abs() < 1e-2, "{got}");
+        }
+        assert_eq!(col("FSC-A"), vec![10.0, 20.0]);
+
+        let missing = CompMatrix::identity(channels(&["APC-A"]));
+        assert!(missing.compensate(&df).is_err());
+        let singular = CompMatrix::new(
+            channels(&["FITC-A", "PE-A"]),
+            vec![vec![1.0, 1.0], vec![1.0, 1.0]],
+        )
+        .unwrap();
+        assert!(singular.inverse().is_err());
+    }
+
+    #[test]
+    fn test_reindex_and_diff() {
+        let m = CompMatrix::new(
+            channels(&["FITC-A", "PE-A"]),
+            vec![vec![1.0, 0.2], vec![0.05, 1.0]],
+        )
+        .unwrap();
+        // new channels come in uncompensated, dropped ones go
+        let wider = m.reindexed(&channels(&["PE-A", "APC-A", "FITC-A"]));
+        assert_eq!(
+            wider.spillover,
+            vec![
+                vec![1.0, 0.0, 0.05],
+                vec![0.0, 1.0, 0.0],
+                vec![0.2, 0.0, 1.0]
+            ]
+        );
+        assert!(m.diff(&wider).is_empty());
+
+        let mut edited = wider.clone();
+        edited.spillover[1][0] = 0.1;
+        assert_eq!(
+            m.diff(&edited),
+            vec![SpilloverChange {
+                from: Arc::from("APC-A"),
+                to: Arc::from("PE-A"),
+                before: 0.0,
+                after: 0.1,
+            }]
+        );
+        assert_eq!(edited.reindexed(&m.channels), m);
+    }
+
+    #[test]
+    fn test_assignments_resolve_sample_then_group_then_global() {
+        let key = MetaDataKey {
+            parameter: Arc::from("Batch"),
+            group: Arc::from("1"),
+        };
+        let mut metadata = MetaDataFileMap::default();
+        for (file, batch) in [("a", "1"), ("b", "1"), ("c", "2")] {
+            let mut groups = FxHashMap::default();
+            groups.insert(Arc::from("Batch"), Arc::from(batch));
+            metadata.insert(Arc::from(file), groups);
+        }
+        let source = |store: &CompensationStore, file: &str| {
+            store.source_for(&Arc::from(file), metadata.get(file).unwrap())
+        };
+
+        let mut store = CompensationStore {
+            global: CompSource::Matrix(Arc::from("omiq")),
+            ..Default::default()
+        };
+        store
+            .group_assignments
+            .insert(key.clone(), CompSource::Uncompensated);
+        store
+            .sample_assignments
+            .insert(Arc::from("b"), CompSource::Acquisition);
+
+        assert_eq!(source(&store, "a"), CompSource::Uncompensated);
+        assert_eq!(source(&store, "b"), CompSource::Acquisition);
+        assert_eq!(source(&store, "c"), CompSource::Matrix(Arc::from("omiq")));
+
+        // only the sample that falls through to the global level changes
+        let before = store.clone();
+        store.global = CompSource::Acquisition;
+        assert_eq!(
+            before.changed_files(&store, &metadata),
+            vec![Arc::from("c")]
+        );
+    }
+}
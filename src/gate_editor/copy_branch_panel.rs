@@ -0,0 +1,119 @@
+use std::sync::Arc;
+
+use dioxus::prelude::*;
+use dioxus::stores::SyncStore;
+
+use crate::components::sheet::{Sheet, SheetContent, SheetHeader, SheetSide, SheetTitle};
+use crate::gate_editor::gates::{
+    GateId, GateState,
+    gate_store::{GateStateImplExt, GateStateStoreExt, GateSubStoreStoreExt, ROOTGATE},
+};
+
+// copies a gate and everything gated on it under another gate, or with `link` places the gate
+// alone there, sharing its edits. Open while `gate_id` is set.
+#[component]
+pub fn CopyBranchPanel(gate_id: Signal<Option<GateId>>, link: bool) -> Element {
+    let mut gate_store = use_context::<SyncStore<GateState>>();
+
+    let mut target: Signal<GateId> = use_signal(|| ROOTGATE.clone());
+    let mut message = use_signal(|| None::<String>);
+
+    use_effect(move || {
+        let _ = gate_id.read();
+        target.set(ROOTGATE.clone());
+        message.set(None);
+    });
+
+    let gate_name = gate_id
+        .read()
+        .as_ref()
+        .and_then(|id| gate_store.get_gate_name(id.clone()))
+        .unwrap_or_default();
+
+    // (id, label) of every gate, indented by depth - the branch can be copied into itself
+    let parents: Vec<(GateId, String)> = {
+        let hierarchy_binding = gate_store.hierarchy();
+        let hierarchy = hierarchy_binding.read();
+        let registry_binding = gate_store.gate_store().primary_and_subgate_registry();
+        let registry = registry_binding.read();
+        hierarchy
+            .iter_dfs(&ROOTGATE)
+            .filter(|id| *id != *ROOTGATE)
+            .map(|id| {
+                let indent = "- ".repeat(hierarchy.get_depth(&id).saturating_sub(1));
+                let name = registry.display_name(&id).unwrap_or_else(|| id.to_string());
+                (id, format!("{indent}{name}"))
+            })
+            .collect()
+    };
+
+    let root = ROOTGATE.clone();
+
+    let copy = move |_| {
+        let Some(source) = gate_id.peek().clone() else {
+            return;
+        };
+        let parent: Arc<str> = target.peek().clone();
+        let result = if link {
+            gate_store.link_gate_to(source, parent)
+        } else {
+            gate_store.copy_branch_to(source, parent)
+        };
+        match result {
+            Ok(_) => gate_id.set(None),
+            Err(e) if link => message.set(Some(format!("Failed to link gate: {e}"))),
+            Err(e) => message.set(Some(format!("Failed to copy branch: {e}"))),
+        }
+    };
+
+    let (title, target_label, action) = if link {
+        (format!("Link {gate_name}"), "Link under", "Link")
+    } else {
+        (
+            format!("Copy {gate_name} and its children"),
+            "Copy under",
+            "Copy",
+        )
+    };
+
+    rsx! {
+        Sheet {
+            open: gate_id.read().is_some(),
+            on_open_change: move |v: bool| {
+                if !v {
+                    gate_id.set(None);
+                }
+            },
+            SheetContent { side: SheetSide::Right, class: "stats-panel",
+                SheetHeader {
+                    SheetTitle { "{title}" }
+                }
+                div { class: "input-unit",
+                    label { "{target_label}" }
+                    select {
+                        onchange: move |e| target.set(Arc::from(e.value().as_str())),
+                        option {
+                            value: "{root}",
+                            selected: *target.read() == root,
+                            "Top level"
+                        }
+                        for (id , label) in parents {
+                            option {
+                                key: "{id}",
+                                value: "{id}",
+                                selected: *target.read() == id,
+                                "{label}"
+                            }
+                        }
+                    }
+                }
+                div { class: "stats-panel_actions",
+                    button { onclick: copy, "{action}" }
+                }
+                if let Some(m) = message() {
+                    div { class: "stats-panel_message", "{m}" }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,202 @@
+use std::sync::Arc;
+
+use dioxus::prelude::*;
+use dioxus::stores::SyncStore;
+
+use crate::components::sheet::{Sheet, SheetContent, SheetHeader, SheetSide, SheetTitle};
+use crate::gate_editor::gates::{
+    GateId, GateState,
+    gate_store::{FileId, GateSource, GateStateImplExt},
+};
+use crate::omiq::metadata::{MetaDataKey, MetaDataParameter, MetaDataStore, MetaDataStoreStoreExt};
+
+// copies a gate's position for the current sample to other samples, or to whole groups.
+// Open while `gate_id` is set.
+#[component]
+pub fn CopyPositionPanel(
+    gate_id: Signal<Option<GateId>>,
+    current_file: ReadSignal<Option<FileId>>,
+) -> Element {
+    let mut gate_store = use_context::<SyncStore<GateState>>();
+    let metadata_store = use_context::<SyncStore<MetaDataStore>>();
+
+    // None copies to samples
+    let mut group_parameter: Signal<Option<MetaDataParameter>> = use_signal(|| None);
+    // file ids, or groups of `group_parameter`
+    let mut selected: Signal<Vec<Arc<str>>> = use_signal(Vec::new);
+    let mut message = use_signal(|| None::<String>);
+
+    use_effect(move || {
+        let _ = gate_id.read();
+        let _ = group_parameter.read();
+        selected.set(vec![]);
+        message.set(None);
+    });
+
+    let gate_name = gate_id
+        .read()
+        .as_ref()
+        .and_then(|id| gate_store.get_gate_name(id.clone()))
+        .unwrap_or_default();
+
+    let parameters: Vec<MetaDataParameter> = {
+        let mut p: Vec<MetaDataParameter> = metadata_store
+            .metadata()
+            .read()
+            .values()
+            .flat_map(|groups| groups.keys().cloned())
+            .collect();
+        p.sort();
+        p.dedup();
+        p
+    };
+
+    // (value, label)
+    let targets: Vec<(Arc<str>, String)> = match &*group_parameter.read() {
+        None => {
+            let current = current_file.read().clone();
+            let mut samples: Vec<(Arc<str>, String)> = metadata_store
+                .file_name_to_gating_id()
+                .read()
+                .iter()
+                .filter(|(_, id)| Some(*id) != current.as_ref())
+                .map(|(name, id)| (id.clone(), name.to_string()))
+                .collect();
+            samples.sort_by(|a, b| a.1.cmp(&b.1));
+            samples
+        }
+        Some(parameter) => {
+            let mut groups: Vec<Arc<str>> = metadata_store
+                .metadata()
+                .read()
+                .values()
+                .filter_map(|groups| groups.get(parameter).cloned())
+                .collect();
+            groups.sort();
+            groups.dedup();
+            groups
+                .into_iter()
+                .map(|g| (g.clone(), g.to_string()))
+                .collect()
+        }
+    };
+
+    let copy = move |_| {
+        let Some(target) = gate_id.peek().clone() else {
+            return;
+        };
+        let Some(file_id) = current_file.peek().clone() else {
+            message.set(Some("No sample loaded".to_string()));
+            return;
+        };
+        let groups = metadata_store
+            .metadata()
+            .peek()
+            .get(&file_id)
+            .cloned()
+            .unwrap_or_default();
+        // the position as it is drawn for the current sample
+        let Some(gate) = gate_store
+            .get_current_sample(file_id, &groups)
+            .ok()
+            .and_then(|r| r.active_gates.get(&target).map(|g| g.0.clone()))
+        else {
+            message.set(Some("Gate not found for the current sample".to_string()));
+            return;
+        };
+
+        let drawable_id = gate.get_id();
+        let overrides = selected
+            .peek()
+            .iter()
+            .map(|value| {
+                let source = match &*group_parameter.peek() {
+                    None => GateSource::Sample((drawable_id.clone(), value.clone())),
+                    Some(parameter) => GateSource::Group((
+                        drawable_id.clone(),
+                        MetaDataKey {
+                            parameter: parameter.clone(),
+                            group: value.clone(),
+                        },
+                    )),
+                };
+                (source, gate.clone())
+            })
+            .collect();
+        match gate_store.insert_position_overrides(overrides) {
+            Ok(_) => gate_id.set(None),
+            Err(e) => message.set(Some(format!("Failed to copy position: {e}"))),
+        }
+    };
+
+    rsx! {
+        Sheet {
+            open: gate_id.read().is_some(),
+            on_open_change: move |v: bool| {
+                if !v {
+                    gate_id.set(None);
+                }
+            },
+            SheetContent { side: SheetSide::Right, class: "stats-panel",
+                SheetHeader {
+                    SheetTitle { "Copy position of {gate_name}" }
+                }
+                div { class: "input-unit",
+                    label { "Copy to" }
+                    select {
+                        onchange: move |e| {
+                            let value = e.value();
+                            group_parameter
+                                .set(if value.is_empty() { None } else { Some(value.as_str().into()) });
+                        },
+                        option { value: "", selected: group_parameter.read().is_none(),
+                            "Other samples"
+                        }
+                        for parameter in parameters {
+                            option {
+                                key: "{parameter}",
+                                value: "{parameter}",
+                                selected: group_parameter.read().as_ref() == Some(&parameter),
+                                "{parameter} groups"
+                            }
+                        }
+                    }
+                }
+                div { class: "stats-panel_channels",
+                    for (value , label) in targets {
+                        label { key: "{value}",
+                            input {
+                                r#type: "checkbox",
+                                checked: selected.read().contains(&value),
+                                onchange: {
+                                    let value = value.clone();
+                                    move |e: FormEvent| {
+                                        let mut selected = selected.write();
+                                        if e.checked() {
+                                            if !selected.contains(&value) {
+                                                selected.push(value.clone());
+                                            }
+                                        } else {
+                                            selected.retain(|v| *v != value);
+                                        }
+                                    }
+                                },
+                            }
+                            "{label}"
+                        }
+                    }
+                }
+                div { class: "stats-panel_actions",
+                    button {
+                        disabled: selected.read().is_empty(),
+                        onclick: copy,
+                        "Copy"
+                    }
+                }
+                if let Some(m) = message() {
+                    div { class: "stats-panel_message", "{m}" }
+                }
+            }
+        }
+    }
+}
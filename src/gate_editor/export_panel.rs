@@ -0,0 +1,366 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use dioxus::prelude::*;
+use dioxus::stores::SyncStore;
+
+use crate::components::sheet::{Sheet, SheetContent, SheetHeader, SheetSide, SheetTitle};
+use crate::file_load::FcsFiles;
+use crate::gate_editor::compensation::CompensationStore;
+use crate::gate_editor::gates::{
+    GateId, GateState,
+    batch_stats::collect_stats_gates,
+    event_export::{
+        EventScale, ExportSummary, TableExport, TableFormat, TableOptions, export_population_fcs,
+    },
+    gate_store::{GateStateImplExt, GateStateStoreExt, GateSubStoreStoreExt},
+};
+use crate::gate_editor::plots::axis_store::{AxisStore, AxisStoreStoreExt};
+use crate::gate_editor::stats_panel::collect_stats_files;
+use crate::omiq::metadata::{MetaDataParameter, MetaDataStore, MetaDataStoreStoreExt};
+
+#[derive(Clone, Copy, PartialEq)]
+enum ExportFormat {
+    Fcs,
+    Table(TableFormat),
+}
+
+impl ExportFormat {
+    const ALL: [ExportFormat; 3] = [
+        ExportFormat::Fcs,
+        ExportFormat::Table(TableFormat::Csv),
+        ExportFormat::Table(TableFormat::Parquet),
+    ];
+
+    fn label(&self) -> &'static str {
+        match self {
+            ExportFormat::Fcs => "FCS 3.1 (one file per sample)",
+            ExportFormat::Table(TableFormat::Csv) => "CSV",
+            ExportFormat::Table(TableFormat::Parquet) => "Parquet",
+        }
+    }
+
+    fn value(&self) -> &'static str {
+        match self {
+            ExportFormat::Fcs => "fcs",
+            ExportFormat::Table(TableFormat::Csv) => "csv",
+            ExportFormat::Table(TableFormat::Parquet) => "parquet",
+        }
+    }
+}
+
+const SCALES: [(EventScale, &str); 3] = [
+    (EventScale::Raw, "Raw"),
+    (EventScale::Compensated, "Compensated"),
+    (EventScale::Transformed, "Transformed"),
+];
+
+// writes the gate's events from every sample in the fcs directory - as new FCS files, or as
+// one table of the chosen channels. Open while `gate_id` is set.
+#[component]
+pub fn ExportPanel(
+    gate_id: Signal<Option<GateId>>,
+    files: ReadSignal<Option<FcsFiles>>,
+) -> Element {
+    let gate_store = use_context::<SyncStore<GateState>>();
+    let axis_store = use_context::<SyncStore<AxisStore>>();
+    let metadata_store = use_context::<SyncStore<MetaDataStore>>();
+    let comp_store = use_context::<SyncStore<CompensationStore>>();
+
+    let mut format = use_signal(|| ExportFormat::Fcs);
+    let mut out_dir: Signal<Option<PathBuf>> = use_signal(|| None);
+    let mut scale = use_signal(|| EventScale::Compensated);
+    let mut selected_channels: Signal<Vec<Arc<str>>> = use_signal(Vec::new);
+    let mut include_metadata = use_signal(|| true);
+    let mut include_memberships = use_signal(|| false);
+    let mut summary: Signal<Option<ExportSummary>> = use_signal(|| None);
+    let mut running = use_signal(|| false);
+    let mut message = use_signal(|| None::<String>);
+
+    // results are only shown for the gate they were exported from
+    use_effect(move || {
+        let _ = gate_id.read();
+        summary.set(None);
+        message.set(None);
+    });
+
+    let gate_name = gate_id
+        .read()
+        .as_ref()
+        .and_then(|id| gate_store.get_gate_name(id.clone()))
+        .unwrap_or_default();
+
+    let parameters: Vec<MetaDataParameter> = {
+        let mut p: Vec<MetaDataParameter> = metadata_store
+            .metadata()
+            .read()
+            .values()
+            .flat_map(|groups| groups.keys().cloned())
+            .collect();
+        p.sort();
+        p.dedup();
+        p
+    };
+    let parameter_count = parameters.len();
+
+    let choose_folder = move |_| {
+        spawn(async move {
+            if let Some(handle) = rfd::AsyncFileDialog::new()
+                .set_title("Export to folder")
+                .pick_folder()
+                .await
+            {
+                out_dir.set(Some(handle.path().to_path_buf()));
+            }
+        });
+    };
+
+    let export = move |_| {
+        let Some(target) = gate_id.peek().clone() else {
+            return;
+        };
+        let Some(fcs_files) = files.peek().clone() else {
+            message.set(Some("No FCS directory loaded".to_string()));
+            return;
+        };
+        let chosen = *format.peek();
+        let dir = out_dir.peek().clone();
+        if chosen == ExportFormat::Fcs && dir.is_none() {
+            message.set(Some("Choose a folder to export to".to_string()));
+            return;
+        }
+
+        let (export_files, mut warnings) =
+            collect_stats_files(&fcs_files, gate_store, metadata_store, comp_store);
+        let hierarchy = gate_store.hierarchy().peek().clone();
+        let gates = {
+            let registry_binding = gate_store.gate_store().primary_and_subgate_registry();
+            let registry = registry_binding.peek();
+            collect_stats_gates(&hierarchy, &registry)
+        };
+        let Some(gate) = gates.iter().find(|g| g.gate_id == target).cloned() else {
+            message.set(Some("Gate not found in the hierarchy".to_string()));
+            return;
+        };
+        let axis_settings = axis_store.settings().peek().clone();
+        let metadata = metadata_store.metadata().peek().clone();
+        let options = match chosen {
+            ExportFormat::Fcs => None,
+            ExportFormat::Table(table_format) => Some(TableOptions {
+                format: table_format,
+                scale: *scale.peek(),
+                channels: selected_channels.peek().clone(),
+                metadata_columns: if include_metadata() {
+                    parameters.clone()
+                } else {
+                    vec![]
+                },
+                membership_gates: if include_memberships() { gates } else { vec![] },
+            }),
+        };
+
+        running.set(true);
+        message.set(None);
+        spawn(async move {
+            let result = match (options, dir) {
+                (Some(options), _) => {
+                    let (name, extension) = match options.format {
+                        TableFormat::Csv => ("CSV", "csv"),
+                        TableFormat::Parquet => ("Parquet", "parquet"),
+                    };
+                    let Some(handle) = rfd::AsyncFileDialog::new()
+                        .set_title("Export events")
+                        .add_filter(name, &[extension])
+                        .save_file()
+                        .await
+                    else {
+                        running.set(false);
+                        return;
+                    };
+                    let path = handle.path().to_path_buf();
+                    tokio::task::spawn_blocking(move || {
+                        TableExport {
+                            files: &export_files,
+                            gate: &gate,
+                            hierarchy: &hierarchy,
+                            axis_settings: &axis_settings,
+                            metadata: &metadata,
+                            options: &options,
+                        }
+                        .write(&path)
+                    })
+                    .await
+                }
+                (None, Some(dir)) => {
+                    tokio::task::spawn_blocking(move || {
+                        Ok(export_population_fcs(
+                            &export_files,
+                            &gate,
+                            &hierarchy,
+                            &axis_settings,
+                            &dir,
+                        ))
+                    })
+                    .await
+                }
+                (None, None) => Ok(Err(anyhow::anyhow!("Choose a folder to export to"))),
+            };
+            match result {
+                Ok(Ok(mut s)) => {
+                    warnings.append(&mut s.errors);
+                    s.errors = warnings;
+                    summary.set(Some(s));
+                }
+                Ok(Err(e)) => message.set(Some(format!("Export failed: {e}"))),
+                Err(e) => message.set(Some(format!("Export failed: {e}"))),
+            }
+            running.set(false);
+        });
+    };
+
+    let ready = match format() {
+        ExportFormat::Fcs => out_dir.read().is_some(),
+        ExportFormat::Table(_) => !selected_channels.read().is_empty(),
+    };
+
+    rsx! {
+        Sheet {
+            open: gate_id.read().is_some(),
+            on_open_change: move |v: bool| {
+                if !v {
+                    gate_id.set(None);
+                }
+            },
+            SheetContent { side: SheetSide::Right, class: "stats-panel",
+                SheetHeader {
+                    SheetTitle { "Export {gate_name}" }
+                }
+                div { class: "input-unit",
+                    label { "Format" }
+                    select {
+                        onchange: move |e| {
+                            if let Some(f) = ExportFormat::ALL.into_iter().find(|f| f.value() == e.value()) {
+                                format.set(f);
+                            }
+                        },
+                        for f in ExportFormat::ALL {
+                            option {
+                                key: "{f.value()}",
+                                value: "{f.value()}",
+                                selected: format() == f,
+                                "{f.label()}"
+                            }
+                        }
+                    }
+                }
+                if format() == ExportFormat::Fcs {
+                    div { class: "input-unit",
+                        label { "Folder" }
+                        div { class: "stats-panel_actions",
+                            button { onclick: choose_folder, "Choose..." }
+                            if let Some(dir) = out_dir() {
+                                span { "{dir.display()}" }
+                            }
+                        }
+                    }
+                    div { class: "stats-panel_message",
+                        "One FCS 3.1 file per sample, holding the sample's untransformed events in this gate."
+                    }
+                } else {
+                    div { class: "input-unit",
+                        label { "Scale" }
+                        select {
+                            onchange: move |e| {
+                                if let Some((s, _)) = SCALES.into_iter().find(|(_, l)| *l == e.value()) {
+                                    scale.set(s);
+                                }
+                            },
+                            for (s , l) in SCALES {
+                                option { key: "{l}", value: "{l}", selected: scale() == s, "{l}" }
+                            }
+                        }
+                    }
+                    div { class: "stats-panel_channels",
+                        for param in axis_store.sorted_settings().read().iter().cloned() {
+                            label { key: "{param.fluoro}",
+                                input {
+                                    r#type: "checkbox",
+                                    checked: selected_channels.read().contains(&param.fluoro),
+                                    onchange: {
+                                        let fluoro = param.fluoro.clone();
+                                        move |e: FormEvent| {
+                                            let mut channels = selected_channels.write();
+                                            if e.checked() {
+                                                if !channels.contains(&fluoro) {
+                                                    channels.push(fluoro.clone());
+                                                }
+                                            } else {
+                                                channels.retain(|c| *c != fluoro);
+                                            }
+                                        }
+                                    },
+                                }
+                                "{param.marker} ({param.fluoro})"
+                            }
+                        }
+                    }
+                    div { class: "stats-panel_channels",
+                        label {
+                            input {
+                                r#type: "checkbox",
+                                checked: include_metadata(),
+                                onchange: move |e: FormEvent| include_metadata.set(e.checked()),
+                            }
+                            "Sample metadata ({parameter_count} columns)"
+                        }
+                        label {
+                            input {
+                                r#type: "checkbox",
+                                checked: include_memberships(),
+                                onchange: move |e: FormEvent| include_memberships.set(e.checked()),
+                            }
+                            "Gate membership columns"
+                        }
+                    }
+                }
+                div { class: "stats-panel_actions",
+                    button { disabled: running() || !ready, onclick: export,
+                        if running() {
+                            "Exporting..."
+                        } else {
+                            "Export"
+                        }
+                    }
+                }
+                if let Some(m) = message() {
+                    div { class: "stats-panel_message", "{m}" }
+                }
+                if let Some(s) = &*summary.read() {
+                    for e in s.errors.iter() {
+                        div { class: "error-message", "{e}" }
+                    }
+                    if !s.written.is_empty() {
+                        div { class: "stats-panel_table",
+                            table {
+                                thead {
+                                    tr {
+                                        th { "Sample" }
+                                        th { "Events" }
+                                    }
+                                }
+                                tbody {
+                                    for f in s.written.iter() {
+                                        tr { key: "{f.path.display()}/{f.file_name}",
+                                            td { title: "{f.path.display()}", "{f.file_name}" }
+                                            td { "{f.events}" }
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,181 @@
+use std::path::PathBuf;
+
+use dioxus::prelude::*;
+
+use crate::components::menubar::{
+    Menubar, MenubarContent, MenubarItem, MenubarMenu, MenubarTrigger,
+};
+
+// the independent inputs that make up a gating session
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SessionInput {
+    FcsDirectory,
+    Metadata,
+    Gates,
+    Scaling,
+    Compensation,
+}
+
+impl SessionInput {
+    pub const ALL: [SessionInput; 5] = [
+        SessionInput::FcsDirectory,
+        SessionInput::Metadata,
+        SessionInput::Gates,
+        SessionInput::Scaling,
+        SessionInput::Compensation,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            SessionInput::FcsDirectory => "FCS directory",
+            SessionInput::Metadata => "Metadata",
+            SessionInput::Gates => "Gates",
+            SessionInput::Scaling => "Scaling",
+            SessionInput::Compensation => "Compensation",
+        }
+    }
+
+    async fn pick(&self) -> Option<PathBuf> {
+        let dialog = rfd::AsyncFileDialog::new().set_title(format!("Open {}", self.label()));
+        let handle = match self {
+            SessionInput::FcsDirectory => dialog.pick_folder().await,
+            SessionInput::Metadata | SessionInput::Scaling | SessionInput::Compensation => {
+                dialog.add_filter("CSV", &["csv"]).pick_file().await
+            }
+            SessionInput::Gates => dialog.add_filter("JSON", &["json"]).pick_file().await,
+        };
+        handle.map(|h| h.path().to_path_buf())
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum FileMenuAction {
+    Open(SessionInput, PathBuf),
+    Reload(SessionInput),
+    OpenProject(PathBuf),
+    SaveProject(PathBuf),
+    ExportGates(PathBuf),
+    ShowStatistics,
+}
+
+#[component]
+pub fn FileMenu(on_action: EventHandler<FileMenuAction>) -> Element {
+    rsx! {
+        Menubar {
+            MenubarMenu { index: 0usize,
+                MenubarTrigger { "File" }
+                MenubarContent {
+                    for (i , input) in SessionInput::ALL.into_iter().enumerate() {
+                        MenubarItem {
+                            key: "{i}",
+                            value: format!("open-{i}"),
+                            index: i,
+                            on_select: move |_| {
+                                spawn(async move {
+                                    if let Some(path) = input.pick().await {
+                                        on_action.call(FileMenuAction::Open(input, path));
+                                    }
+                                });
+                            },
+                            "Open {input.label()}..."
+                        }
+                    }
+                    MenubarItem {
+                        value: "open-project".to_string(),
+                        index: SessionInput::ALL.len(),
+                        on_select: move |_| {
+                            spawn(async move {
+                                if let Some(handle) = rfd::AsyncFileDialog::new()
+                                    .set_title("Open project")
+                                    .add_filter("Project", &["json"])
+                                    .pick_file()
+                                    .await
+                                {
+                                    on_action.call(FileMenuAction::OpenProject(handle.path().to_path_buf()));
+                                }
+                            });
+                        },
+                        "Open project..."
+                    }
+                    MenubarItem {
+                        value: "save-project".to_string(),
+                        index: SessionInput::ALL.len() + 1,
+                        on_select: move |_| {
+                            spawn(async move {
+                                if let Some(handle) = rfd::AsyncFileDialog::new()
+                                    .set_title("Save project")
+                                    .add_filter("Project", &["json"])
+                                    .save_file()
+                                    .await
+                                {
+                                    on_action.call(FileMenuAction::SaveProject(handle.path().to_path_buf()));
+                                }
+                            });
+                        },
+                        "Save project..."
+                    }
+                    MenubarItem {
+                        value: "export-gates".to_string(),
+                        index: SessionInput::ALL.len() + 2,
+                        on_select: move |_| {
+                            spawn(async move {
+                                if let Some(handle) = rfd::AsyncFileDialog::new()
+                                    .set_title("Export gates (OMIQ)")
+                                    .add_filter("JSON", &["json"])
+                                    .save_file()
+                                    .await
+                                {
+                                    on_action.call(FileMenuAction::ExportGates(handle.path().to_path_buf()));
+                                }
+                            });
+                        },
+                        "Export gates (OMIQ)..."
+                    }
+                }
+            }
+            MenubarMenu { index: 1usize,
+                MenubarTrigger { "Reload" }
+                MenubarContent {
+                    for (i , input) in SessionInput::ALL.into_iter().enumerate() {
+                        MenubarItem {
+                            key: "{i}",
+                            value: format!("reload-{i}"),
+                            index: i,
+                            on_select: move |_| on_action.call(FileMenuAction::Reload(input)),
+                            "Reload {input.label()}"
+                        }
+                    }
+                }
+            }
+            MenubarMenu { index: 2usize,
+                MenubarTrigger { "Analysis" }
+                MenubarContent {
+                    MenubarItem {
+                        value: "statistics".to_string(),
+                        index: 0usize,
+                        on_select: move |_| on_action.call(FileMenuAction::ShowStatistics),
+                        "Population statistics..."
+                    }
+                }
+            }
+        }
+    }
+}
+
+// one line per input so a failure in one file doesn't hide the others
+#[component]
+pub fn InputStatus(input: SessionInput, path: Option<PathBuf>, error: Option<String>) -> Element {
+    let path_text = path
+        .as_ref()
+        .map(|p| p.display().to_string())
+        .unwrap_or_else(|| "not selected".to_string());
+    rsx! {
+        div { class: "input-status",
+            span { class: "input-status_label", "{input.label()}: " }
+            span { class: "input-status_path", "{path_text}" }
+            if let Some(e) = error {
+                span { class: "input-status_error error-message", "{e}" }
+            }
+        }
+    }
+}
@@ -1,7 +1,19 @@
 use crate::components::context_menu::*;
+use crate::gate_editor::boolean_builder_panel::BooleanTarget;
+use crate::gate_editor::compensation::{
+    CompensationImplExt, CompensationStore, is_compensated_channel,
+};
 use crate::gate_editor::gates::GateState;
-use crate::gate_editor::gates::gate_store::{GateStateImplExt, GateStateStoreExt, ROOTGATE};
+use crate::gate_editor::gates::boolean_expr::quote_name;
+use crate::gate_editor::gates::gate_single::boolean_gates::BooleanGate;
+use crate::gate_editor::gates::gate_stats::PopulationCounts;
+use crate::gate_editor::gates::gate_store::{
+    FileId, GateEditMode, GateOverrideResolver, GateSource, GateStateImplExt, GateStateStoreExt,
+    ROOTGATE,
+};
 use crate::gate_editor::plots::axis_store::{AxisStore, AxisStoreStoreExt, Param};
+use crate::gate_editor::plots::backgate::Backgates;
+use crate::omiq::metadata::{MetaDataParameter, MetaDataStore, MetaDataStoreStoreExt};
 use dioxus::prelude::*;
 use dioxus::stores::SyncStore;
 use std::sync::Arc;
@@ -12,18 +24,112 @@ pub fn GateSidebar(
     selected_id: Signal<Option<Arc<str>>>,
     x_axis_param: Signal<Param>,
     y_axis_param: Signal<Param>,
+    auto_adjust_gate: Signal<Option<Arc<str>>>,
+    export_gate: Signal<Option<Arc<str>>>,
+    copy_position_gate: Signal<Option<Arc<str>>>,
+    copy_branch_gate: Signal<Option<Arc<str>>>,
+    link_gate: Signal<Option<Arc<str>>>,
+    boolean_builder: Signal<Option<BooleanTarget>>,
+    // the sample whose gate positions are shown and edited
+    current_file: ReadSignal<Option<FileId>>,
 ) -> Element {
     // let gate_store: Store<GateState> = use_context::<Store<GateState>>();
-    let gate_store = use_context::<SyncStore<GateState>>();
+    let mut gate_store = use_context::<SyncStore<GateState>>();
+    let metadata_store = use_context::<SyncStore<MetaDataStore>>();
+    let mut comp_store = use_context::<SyncStore<CompensationStore>>();
     let hierarchy = gate_store.hierarchy();
     let roots = hierarchy.read().get_roots();
 
+    // the sample's compensation changed after its gates were drawn
+    let comp_flagged =
+        use_memo(move || current_file().is_some_and(|id| comp_store.is_flagged(&id)));
+
+    let resolver = use_memo(move || {
+        let file_id = current_file()?;
+        let groups = metadata_store.metadata().read().get(&file_id).cloned()?;
+        gate_store
+            .get_current_sample(file_id, &groups)
+            .ok()
+            .map(Arc::new)
+    });
+
+    let parameters: Vec<MetaDataParameter> = {
+        let mut p: Vec<MetaDataParameter> = metadata_store
+            .metadata()
+            .read()
+            .values()
+            .flat_map(|groups| groups.keys().cloned())
+            .collect();
+        p.sort();
+        p.dedup();
+        p
+    };
+    let edit_mode = gate_store.edit_mode().read().clone();
+    // the gate being dragged onto a new parent
+    let mut dragged_gate: Signal<Option<Arc<str>>> = use_signal(|| None);
+
     rsx! {
         document::Stylesheet { href: SIDEBAR_STYLE }
         div { class: "custom-sidebar",
             h3 { class: "sidebar-title", "Gate Hierarchy" }
 
-            div { class: "sidebar-tree",
+            div { class: "sidebar-edit-mode",
+                label { "Edit gates for" }
+                select {
+                    onchange: move |e| {
+                        let value = e.value();
+                        let mode = match value.as_str() {
+                            "sample" => GateEditMode::Sample,
+                            v => match v.strip_prefix("group:") {
+                                Some(parameter) => GateEditMode::Group(parameter.into()),
+                                None => GateEditMode::Global,
+                            },
+                        };
+                        gate_store.edit_mode().set(mode);
+                    },
+                    option { value: "global", selected: edit_mode == GateEditMode::Global,
+                        "All samples"
+                    }
+                    for parameter in parameters {
+                        option {
+                            key: "{parameter}",
+                            value: "group:{parameter}",
+                            selected: edit_mode == GateEditMode::Group(parameter.clone()),
+                            "This sample's {parameter} group"
+                        }
+                    }
+                    option { value: "sample", selected: edit_mode == GateEditMode::Sample,
+                        "This sample only"
+                    }
+                }
+            }
+
+            if comp_flagged() {
+                div { class: "sidebar-comp-warning",
+                    span { "Compensation changed - check gate positions" }
+                    button {
+                        onclick: move |_| {
+                            if let Some(id) = current_file() {
+                                comp_store.clear_flag(&id);
+                            }
+                        },
+                        "Dismiss"
+                    }
+                }
+            }
+
+            div {
+                class: "sidebar-tree",
+                title: "Drag a gate onto another to move it - Shift leaves its children behind, Ctrl copies the branch, Alt links the gate",
+                ondragover: move |e| e.prevent_default(),
+                // dropped below the gates - back to the top level
+                ondrop: move |e| {
+                    e.prevent_default();
+                    let Some(dragged) = dragged_gate.write().take() else {
+                        return;
+                    };
+                    drop_gate(gate_store, dragged, ROOTGATE.clone(), &e);
+                },
 
                 for root_id in roots {
                     for child_id in hierarchy.read().get_children(&root_id) {
@@ -34,6 +140,15 @@ pub fn GateSidebar(
                             level: 0,
                             x_axis_param,
                             y_axis_param,
+                            auto_adjust_gate,
+                            export_gate,
+                            copy_position_gate,
+                            copy_branch_gate,
+                            link_gate,
+                            boolean_builder,
+                            dragged_gate,
+                            resolver,
+                            comp_flagged,
                         }
                     }
                 }
@@ -43,6 +158,27 @@ pub fn GateSidebar(
     }
 }
 
+// moves `dragged` under `parent` - Shift leaves its children with its old parent,
+// Ctrl copies the whole branch instead and Alt links the gate there
+fn drop_gate(
+    mut gate_store: SyncStore<GateState>,
+    dragged: Arc<str>,
+    parent: Arc<str>,
+    e: &Event<DragData>,
+) {
+    let modifiers = e.modifiers();
+    let result = if modifiers.alt() {
+        gate_store.link_gate_to(dragged, parent)
+    } else if modifiers.ctrl() {
+        gate_store.copy_branch_to(dragged, parent)
+    } else {
+        gate_store.move_gate_to(dragged, parent, !modifiers.shift())
+    };
+    if let Err(e) = result {
+        println!("Failed to drop gate: {e}");
+    }
+}
+
 // Keep your exact ChevronIcon, we'll just rotate it with CSS
 #[component]
 fn ChevronIcon() -> Element {
@@ -67,10 +203,22 @@ fn GateNode(
     level: usize,
     x_axis_param: Signal<Param>,
     y_axis_param: Signal<Param>,
+    auto_adjust_gate: Signal<Option<Arc<str>>>,
+    export_gate: Signal<Option<Arc<str>>>,
+    copy_position_gate: Signal<Option<Arc<str>>>,
+    copy_branch_gate: Signal<Option<Arc<str>>>,
+    link_gate: Signal<Option<Arc<str>>>,
+    boolean_builder: Signal<Option<BooleanTarget>>,
+    dragged_gate: Signal<Option<Arc<str>>>,
+    resolver: ReadSignal<Option<Arc<GateOverrideResolver>>>,
+    comp_flagged: ReadSignal<bool>,
 ) -> Element {
     let mut gate_store = use_context::<SyncStore<GateState>>();
     let axis_store: SyncStore<AxisStore> = use_context::<SyncStore<AxisStore>>();
+    let population_counts = use_context::<Signal<PopulationCounts>>();
+    let mut backgates = use_context::<Signal<Backgates>>();
     let mut is_expanded = use_signal(|| true);
+    let mut is_drop_target = use_signal(|| false);
 
     // Fetch children
     let hierarchy = gate_store.hierarchy();
@@ -91,26 +239,50 @@ fn GateNode(
         }
     };
 
-    let gate_name = {
-        if let Some(gate) = gate_store
+    // read, so a rename shows straight away
+    let gate_name = gate_store
+        .gate_store()
+        .read()
+        .primary_and_subgate_registry
+        .display_name(&gate_id)
+        .unwrap_or_else(|| gate_id.to_string());
+    // how many places this gate is linked into, itself included
+    let link_count = gate_store.gate_store().read().link_count(&gate_id);
+    let other_links = link_count.saturating_sub(1);
+    let is_boolean = gate_store
+        .gate_store()
+        .read()
+        .primary_and_subgate_registry
+        .get(&gate_id)
+        .is_some_and(|g| g.as_any().is::<BooleanGate>());
+    // only gates on fluorescence channels are moved by a change of compensation
+    let needs_comp_check = comp_flagged()
+        && !is_boolean
+        && gate_store
             .gate_store()
-            .peek()
+            .read()
             .primary_and_subgate_registry
             .get(&gate_id)
-        {
-            if gate.is_composite() {
-                if let Some(subgate) = gate.get_gate_ref(Some(&gate_id)) {
-                    subgate.name.clone()
-                } else {
-                    gate.get_name().to_owned()
-                }
-            } else {
-                gate.get_name().to_owned()
-            }
-        } else {
-            gate_id.to_string()
-        }
-    };
+            .is_some_and(|g| {
+                let (x, y) = g.get_params();
+                is_compensated_channel(&x) || is_compensated_channel(&y)
+            });
+
+    let mut renaming = use_signal(|| false);
+    let mut draft_name = use_signal(String::new);
+    let mut rename_error = use_signal(|| None::<String>);
+
+    let population = population_counts.read().get(&gate_id).cloned();
+
+    // where this gate's position comes from for the current sample
+    let origin = resolver
+        .read()
+        .as_ref()
+        .and_then(|r| r.gate_origins.get(&gate_id).cloned());
+    let is_override = matches!(
+        origin,
+        Some(GateSource::Group(_)) | Some(GateSource::Sample(_))
+    );
 
     // Check if this node is the active one
     let is_selected = selected.read().as_ref() == Some(&gate_id);
@@ -120,14 +292,30 @@ fn GateNode(
 
     let gate_id_clone = gate_id.clone();
     let gate_id_delete_clone = gate_id.clone();
-    // let gate_id_rename_clone = gate_id.clone();
     let parent_for_delete = parent.clone();
     let gate_id_for_not_gate = gate_id.clone();
     let parent_for_not_gate = parent.clone();
     let gate_id_for_and_gate = gate_id.clone();
-    let parent_for_and_gate = parent.clone();
+    let and_text = format!("{} AND ", quote_name(&gate_name));
     let gate_id_for_or_gate = gate_id.clone();
-    let parent_for_or_gate = parent.clone();
+    let or_text = format!("{} OR ", quote_name(&gate_name));
+    let gate_id_for_edit_boolean = gate_id.clone();
+    let gate_id_for_auto_adjust = gate_id.clone();
+    let gate_id_for_export = gate_id.clone();
+    let gate_id_for_rename = gate_id.clone();
+    let name_for_rename = gate_name.clone();
+    let gate_id_for_copy = gate_id.clone();
+    let gate_id_for_copy_branch = gate_id.clone();
+    let gate_id_for_keep_children = gate_id.clone();
+    let gate_id_for_link = gate_id.clone();
+    let gate_id_for_unlink = gate_id.clone();
+    let parent_for_keep_children = parent.clone();
+    let gate_id_for_drag = gate_id.clone();
+    let gate_id_for_drop = gate_id.clone();
+    let origin_for_promote = origin.clone();
+    let origin_for_reset = origin.clone();
+    let gate_id_for_backgate = gate_id.clone();
+    let is_backgated = backgates.read().contains(&gate_id);
     rsx! {
 
         // 1. The Row (Clickable)
@@ -135,8 +323,37 @@ fn GateNode(
             ContextMenuTrigger {
                 div { class: "gate-node-container",
                     div {
-                        class: format!("gate-node-row{}", if is_selected { " selected" } else { "" }),
+                        class: format!(
+                            "gate-node-row{}{}",
+                            if is_selected { " selected" } else { "" },
+                            if is_drop_target() { " drop-target" } else { "" },
+                        ),
                         style: "padding-left: {padding};",
+                        draggable: !renaming(),
+                        ondragstart: move |e| {
+                            e.stop_propagation();
+                            dragged_gate.set(Some(gate_id_for_drag.clone()));
+                        },
+                        ondragend: move |_| dragged_gate.set(None),
+                        ondragover: move |e| {
+                            e.prevent_default();
+                            e.stop_propagation();
+                            if !is_drop_target() {
+                                is_drop_target.set(true);
+                            }
+                        },
+                        ondragleave: move |_| is_drop_target.set(false),
+                        ondrop: move |e| {
+                            e.prevent_default();
+                            e.stop_propagation();
+                            is_drop_target.set(false);
+                            let Some(dragged) = dragged_gate.write().take() else {
+                                return;
+                            };
+                            if dragged != gate_id_for_drop {
+                                drop_gate(gate_store, dragged, gate_id_for_drop.clone(), &e);
+                            }
+                        },
                         onclick: move |e: Event<MouseData>| {
 
                             e.stop_propagation();
@@ -182,7 +399,81 @@ fn GateNode(
                         }
 
                         // 3. The Label
-                        span { class: "gate-name", "{gate_name}" }
+                        if renaming() {
+                            input {
+                                class: "gate-name_input",
+                                value: "{draft_name}",
+                                title: rename_error().unwrap_or_default(),
+                                onmounted: move |e| async move {
+                                    let _ = e.set_focus(true).await;
+                                },
+                                onclick: move |e| e.stop_propagation(),
+                                oninput: move |e| {
+                                    draft_name.set(e.value());
+                                    rename_error.set(None);
+                                },
+                                onkeydown: move |e| match e.key() {
+                                    Key::Enter => {
+                                        match gate_store.rename_gate(gate_id_for_rename.clone(), &draft_name.peek()) {
+                                            Ok(_) => renaming.set(false),
+                                            Err(err) => rename_error.set(Some(err.to_string())),
+                                        }
+                                    }
+                                    Key::Escape => renaming.set(false),
+                                    _ => {}
+                                },
+                                onblur: move |_| renaming.set(false),
+                            }
+                            if let Some(err) = rename_error() {
+                                span { class: "gate-stats gate-stats_error", "{err}" }
+                            }
+                        } else {
+                            span { class: "gate-name", "{gate_name}" }
+                        }
+                        {
+                            match &origin {
+                                Some(GateSource::Group((_, key))) => rsx! {
+                                    span {
+                                        class: "gate-origin gate-origin_group",
+                                        title: "Positioned for {key.parameter} {key.group}",
+                                        "G"
+                                    }
+                                },
+                                Some(GateSource::Sample(_)) => rsx! {
+                                    span {
+                                        class: "gate-origin gate-origin_sample",
+                                        title: "Positioned for this sample only",
+                                        "S"
+                                    }
+                                },
+                                _ => rsx! {},
+                            }
+                        }
+                        if needs_comp_check {
+                            span {
+                                class: "gate-origin gate-origin_comp",
+                                title: "Compensation changed since this gate was drawn - check its position",
+                                "C"
+                            }
+                        }
+                        if link_count > 1 {
+                            span {
+                                class: "gate-origin gate-origin_linked",
+                                title: "Linked - edits are shared with {other_links} other placement(s)",
+                                "L"
+                            }
+                        }
+                        {
+                            match population {
+                                Some(Ok(p)) => rsx! {
+                                    span { class: "gate-stats", "{p.count} ({p.percent_parent:.1}%)" }
+                                },
+                                Some(Err(e)) => rsx! {
+                                    span { class: "gate-stats gate-stats_error", title: "{e}", "error" }
+                                },
+                                None => rsx! {},
+                            }
+                        }
                         button {
                             class: "activate-btn",
                             title: "Activate gate",
@@ -229,6 +520,15 @@ fn GateNode(
                                     level: level + 1,
                                     x_axis_param,
                                     y_axis_param,
+                                    auto_adjust_gate,
+                                    export_gate,
+                                    copy_position_gate,
+                                    copy_branch_gate,
+                                    link_gate,
+                                    boolean_builder,
+                                    dragged_gate,
+                                    resolver,
+                                    comp_flagged,
                                 }
                             }
                         }
@@ -259,7 +559,11 @@ fn GateNode(
                 ContextMenuItem {
                     value: "rename".to_string(),
                     index: 1usize,
-                    on_select: move |_| {},
+                    on_select: move |_| {
+                        draft_name.set(name_for_rename.clone());
+                        rename_error.set(None);
+                        renaming.set(true);
+                    },
                     "Rename"
                 }
                 ContextMenuItem {
@@ -289,47 +593,117 @@ fn GateNode(
                     value: "and".to_string(),
                     index: 3usize,
                     on_select: move |_| {
-                        // let id = format!("AND_{}", gate_id_for_and_gate.clone());
-                        let x_axis_param = x_axis_param.peek().fluoro.clone();
-                        let y_axis_param = y_axis_param.peek().fluoro.clone();
-                        match gate_store
-                            .add_boolean_gate(
-                                None,
-                                flow_gates::BooleanOperation::And,
-                                vec![gate_id_for_and_gate.clone()],
-                                Some(parent_for_and_gate.clone()),
-                                x_axis_param,
-                                y_axis_param,
-                            )
-                        {
-                            Ok(_) => {}
-                            Err(e) => println!("{e}"),
-                        }
+                        boolean_builder.set(Some(BooleanTarget::New {
+                            gate_id: gate_id_for_and_gate.clone(),
+                            text: and_text.clone(),
+                        }));
                     },
-                    "Add AND Gate"
+                    "Add AND Gate..."
                 }
                 ContextMenuItem {
                     value: "or".to_string(),
                     index: 4usize,
                     on_select: move |_| {
-
-                        let x_axis_param = x_axis_param.peek().fluoro.clone();
-                        let y_axis_param = y_axis_param.peek().fluoro.clone();
-                        match gate_store
-                            .add_boolean_gate(
-                                None,
-                                flow_gates::BooleanOperation::Or,
-                                vec![gate_id_for_or_gate.clone()],
-                                Some(parent_for_or_gate.clone()),
-                                x_axis_param,
-                                y_axis_param,
-                            )
+                        boolean_builder.set(Some(BooleanTarget::New {
+                            gate_id: gate_id_for_or_gate.clone(),
+                            text: or_text.clone(),
+                        }));
+                    },
+                    "Add OR Gate..."
+                }
+                ContextMenuItem {
+                    value: "auto-adjust".to_string(),
+                    index: 5usize,
+                    on_select: move |_| auto_adjust_gate.set(Some(gate_id_for_auto_adjust.clone())),
+                    "Auto-adjust..."
+                }
+                ContextMenuItem {
+                    value: "promote".to_string(),
+                    index: 6usize,
+                    disabled: !is_override,
+                    on_select: move |_| {
+                        if let Some(source) = origin_for_promote.clone()
+                            && let Err(e) = gate_store.promote_override(source)
                         {
-                            Ok(_) => {}
+                            println!("{e}");
+                        }
+                    },
+                    "Use this position for all samples"
+                }
+                ContextMenuItem {
+                    value: "reset".to_string(),
+                    index: 7usize,
+                    disabled: !is_override,
+                    on_select: move |_| {
+                        if let Some(source) = origin_for_reset.clone()
+                            && let Err(e) = gate_store.reset_override(source)
+                        {
+                            println!("{e}");
+                        }
+                    },
+                    "Reset to inherited position"
+                }
+                ContextMenuItem {
+                    value: "copy-position".to_string(),
+                    index: 8usize,
+                    on_select: move |_| copy_position_gate.set(Some(gate_id_for_copy.clone())),
+                    "Copy position to..."
+                }
+                ContextMenuItem {
+                    value: "copy-branch".to_string(),
+                    index: 9usize,
+                    on_select: move |_| copy_branch_gate.set(Some(gate_id_for_copy_branch.clone())),
+                    "Copy with children to..."
+                }
+                ContextMenuItem {
+                    value: "delete-keep-children".to_string(),
+                    index: 10usize,
+                    disabled: !has_children,
+                    on_select: move |_| {
+                        match gate_store.remove_gate_keep_children(gate_id_for_keep_children.clone()) {
+                            Ok(_) => selected.set(Some(parent_for_keep_children.clone())),
                             Err(e) => println!("{e}"),
                         }
                     },
-                    "Add OR Gate"
+                    "Delete, keeping children"
+                }
+                ContextMenuItem {
+                    value: "link".to_string(),
+                    index: 11usize,
+                    on_select: move |_| link_gate.set(Some(gate_id_for_link.clone())),
+                    "Link under..."
+                }
+                ContextMenuItem {
+                    value: "unlink".to_string(),
+                    index: 12usize,
+                    disabled: link_count < 2,
+                    on_select: move |_| {
+                        if let Err(e) = gate_store.unlink_gate(gate_id_for_unlink.clone()) {
+                            println!("{e}");
+                        }
+                    },
+                    "Unlink"
+                }
+                ContextMenuItem {
+                    value: "edit-boolean".to_string(),
+                    index: 13usize,
+                    disabled: !is_boolean,
+                    on_select: move |_| {
+                        boolean_builder.set(Some(BooleanTarget::Edit(gate_id_for_edit_boolean.clone())));
+                    },
+                    "Edit boolean..."
+                }
+                ContextMenuItem {
+                    value: "backgate".to_string(),
+                    index: 14usize,
+                    on_select: move |_| backgates.write().toggle(gate_id_for_backgate.clone()),
+                    if is_backgated { "Stop backgating" } else { "Backgate" }
+                }
+                ContextMenuItem {
+                    value: "export-events".to_string(),
+                    index: 15usize,
+                    on_select: move |_| export_gate.set(Some(gate_id_for_export.clone())),
+                    "Export events..."
                 }
             }
         
@@ -0,0 +1,433 @@
+use std::sync::Arc;
+
+use anyhow::anyhow;
+use flow_fcs::Fcs;
+use flow_gates::GateGeometry;
+use polars::prelude::*;
+use rayon::prelude::*;
+use rustc_hash::{FxBuildHasher, FxHashMap};
+
+use crate::gate_editor::AxisInfo;
+use crate::gate_editor::gates::GateId;
+use crate::gate_editor::gates::batch_stats::StatsFile;
+use crate::gate_editor::gates::gate_drag::GateDragData;
+use crate::gate_editor::gates::gate_filtering::PopulationMasks;
+use crate::gate_editor::gates::gate_hierarchy::GateHierarchy;
+use crate::gate_editor::gates::gate_single::line_gate::LineGate;
+use crate::gate_editor::gates::gate_store::FileId;
+use crate::gate_editor::gates::gate_traits::DrawableGate;
+use crate::gate_editor::plots::data_helpers::scale_fcs_data;
+use crate::gate_move::kde_shift::{DriftType, GateBoundary, analyse_population_shift};
+use crate::omiq::metadata::{MetaDataFileMap, MetaDataKey, MetaDataParameter};
+
+// Moves a gate drawn on a reference (QC) sample onto the other samples, by measuring how far
+// the negative population under the gate's parent has drifted in each.
+// The analysis runs on axes normalised to 0..1, so the thresholds below mean the same thing
+// on linear and transformed channels.
+
+const N_KDE_POINTS: usize = 512;
+const MIN_EVENTS: usize = 50;
+// fractions of the axis span
+const SIGNIFICANT_SHIFT: f64 = 0.01;
+const MIN_NEGATIVE_MARGIN: f64 = 0.005;
+// fraction of the gate's own extent
+const NEGATIVE_MARGIN_FRACTION: f64 = 0.05;
+const SIGNIFICANT_WIDTH_RATIO: f64 = 1.5;
+
+pub struct GateAdjustment {
+    pub file_id: FileId,
+    pub file_name: String,
+    // in scaled (plot) units
+    pub dx: f32,
+    pub dy: f32,
+    pub width_ratio_x: f64,
+    pub width_ratio_y: f64,
+    pub drift_type: DriftType,
+    // the reference gate moved by (dx, dy)
+    pub gate: Arc<dyn DrawableGate>,
+}
+
+impl GateAdjustment {
+    // anything other than a uniform instrument drift needs a person to look at it
+    pub fn recommended(&self) -> bool {
+        matches!(self.drift_type, DriftType::InstrumentDrift)
+    }
+}
+
+pub struct AutoAdjustResult {
+    pub gate_id: GateId,
+    pub reference_file: FileId,
+    pub reference_gate: Arc<dyn DrawableGate>,
+    pub adjustments: Vec<GateAdjustment>,
+    pub errors: Vec<String>,
+}
+
+// parent-gated events on the gate's two axes, normalised to the axis limits
+struct ParentEvents {
+    x: Column,
+    y: Column,
+}
+
+struct AxisRange {
+    lower: f64,
+    span: f64,
+}
+
+impl AxisRange {
+    fn from_settings(
+        axis_settings: &im::HashMap<Arc<str>, AxisInfo, FxBuildHasher>,
+        param: &str,
+    ) -> anyhow::Result<Self> {
+        let axis = axis_settings
+            .get(param)
+            .ok_or_else(|| anyhow!("No axis settings for {}", param))?;
+        let lower = axis.axis_lower as f64;
+        let span = axis.axis_upper as f64 - lower;
+        if !span.is_finite() || span <= 0.0 {
+            return Err(anyhow!("Axis limits for {} are not usable", param));
+        }
+        Ok(Self { lower, span })
+    }
+
+    fn normalise(&self, value: f32) -> f64 {
+        (value as f64 - self.lower) / self.span
+    }
+}
+
+pub fn compute_gate_adjustments(
+    gate_id: &GateId,
+    reference: &StatsFile,
+    files: &[StatsFile],
+    hierarchy: &GateHierarchy,
+    axis_settings: &im::HashMap<Arc<str>, AxisInfo, FxBuildHasher>,
+) -> anyhow::Result<AutoAdjustResult> {
+    let reference_gate = reference
+        .resolver
+        .active_gates
+        .get(gate_id)
+        .map(|g| g.0.clone())
+        .ok_or_else(|| anyhow!("Gate {} not found for the reference sample", gate_id))?;
+    let gate = reference_gate
+        .get_gate_ref(Some(gate_id))
+        .ok_or_else(|| anyhow!("Gate {} has no internal data", gate_id))?;
+    // catches booleans and quadrants before any files are read
+    if reference_gate
+        .replace_points(translation(reference_gate.as_ref(), 0.0, 0.0))?
+        .is_none()
+    {
+        return Err(anyhow!("{} gates can't be moved", gate.name));
+    }
+
+    let (x_param, y_param) = gate.parameters.clone();
+    let x_range = AxisRange::from_settings(axis_settings, &x_param)?;
+    let y_range = AxisRange::from_settings(axis_settings, &y_param)?;
+    let ((x_min, y_min), (x_max, y_max)) = gate_bounds(gate)?;
+
+    // a line only gates on x, so its negatives are everything to the left at any height
+    let is_line = reference_gate.as_any().is::<LineGate>();
+    let width = (x_range.normalise(x_max) - x_range.normalise(x_min)).min(1.0);
+    let height = (y_range.normalise(y_max) - y_range.normalise(y_min)).min(1.0);
+    let extent = if is_line { width } else { width.min(height) };
+    let negative_margin = (extent * NEGATIVE_MARGIN_FRACTION).max(MIN_NEGATIVE_MARGIN);
+    let boundary = GateBoundary {
+        x_lower: x_range.normalise(x_min),
+        y_lower: if is_line {
+            1.0 + negative_margin
+        } else {
+            y_range.normalise(y_min)
+        },
+    };
+
+    let qc = parent_events(
+        reference,
+        gate_id,
+        hierarchy,
+        (&*x_param, &x_range),
+        (&*y_param, &y_range),
+        axis_settings,
+    )
+    .map_err(|e| anyhow!("Reference {}: {}", reference.name, e))?;
+
+    let axes = ShiftAxes {
+        x: (&*x_param, &x_range),
+        y: (&*y_param, &y_range),
+        boundary: &boundary,
+        negative_margin,
+        is_line,
+    };
+    let per_file: Vec<Result<GateAdjustment, String>> = files
+        .par_iter()
+        .filter(|file| file.file_id != reference.file_id)
+        .map(|file| {
+            adjust_for_file(
+                file,
+                &qc,
+                &reference_gate,
+                gate_id,
+                hierarchy,
+                &axes,
+                axis_settings,
+            )
+            .map_err(|e| format!("{}: {}", file.name, e))
+        })
+        .collect();
+
+    let mut result = AutoAdjustResult {
+        gate_id: gate_id.clone(),
+        reference_file: reference.file_id.clone(),
+        reference_gate,
+        adjustments: vec![],
+        errors: vec![],
+    };
+    for r in per_file {
+        match r {
+            Ok(a) => result.adjustments.push(a),
+            Err(e) => result.errors.push(e),
+        }
+    }
+    Ok(result)
+}
+
+// everything about the gate's axes that is shared between files
+struct ShiftAxes<'a> {
+    x: (&'a str, &'a AxisRange),
+    y: (&'a str, &'a AxisRange),
+    boundary: &'a GateBoundary,
+    negative_margin: f64,
+    is_line: bool,
+}
+
+fn adjust_for_file(
+    file: &StatsFile,
+    qc: &ParentEvents,
+    reference_gate: &Arc<dyn DrawableGate>,
+    gate_id: &GateId,
+    hierarchy: &GateHierarchy,
+    axes: &ShiftAxes<'_>,
+    axis_settings: &im::HashMap<Arc<str>, AxisInfo, FxBuildHasher>,
+) -> anyhow::Result<GateAdjustment> {
+    let test = parent_events(file, gate_id, hierarchy, axes.x, axes.y, axis_settings)?;
+    let shift = analyse_population_shift(
+        (&qc.x, &qc.y),
+        (&test.x, &test.y),
+        (0.0, 1.0),
+        (0.0, 1.0),
+        axes.boundary,
+        axes.negative_margin,
+        N_KDE_POINTS,
+        MIN_EVENTS,
+        SIGNIFICANT_SHIFT,
+        SIGNIFICANT_WIDTH_RATIO,
+    )
+    .map_err(|e| anyhow!(e))?;
+
+    let dx = (shift.negative_dx * axes.x.1.span) as f32;
+    let dy = if axes.is_line {
+        0.0
+    } else {
+        (shift.negative_dy * axes.y.1.span) as f32
+    };
+    Ok(GateAdjustment {
+        file_id: file.file_id.clone(),
+        file_name: file.name.clone(),
+        dx,
+        dy,
+        width_ratio_x: shift.width_ratio_x,
+        width_ratio_y: shift.width_ratio_y,
+        drift_type: shift.drift_type,
+        gate: translate_gate(reference_gate, dx, dy)?,
+    })
+}
+
+// one group override per group of the accepted samples, moved by the group's mean shift
+pub fn group_adjustments(
+    result: &AutoAdjustResult,
+    accepted: &[FileId],
+    metadata: &MetaDataFileMap,
+    parameter: &MetaDataParameter,
+) -> anyhow::Result<Vec<(MetaDataKey, Arc<dyn DrawableGate>)>> {
+    let mut shifts: FxHashMap<MetaDataKey, (f32, f32, usize)> = FxHashMap::default();
+    for adjustment in result
+        .adjustments
+        .iter()
+        .filter(|a| accepted.contains(&a.file_id))
+    {
+        let Some(group) = metadata
+            .get(&adjustment.file_id)
+            .and_then(|groups| groups.get(parameter))
+        else {
+            continue;
+        };
+        let entry = shifts
+            .entry(MetaDataKey {
+                parameter: parameter.clone(),
+                group: group.clone(),
+            })
+            .or_insert((0.0, 0.0, 0));
+        entry.0 += adjustment.dx;
+        entry.1 += adjustment.dy;
+        entry.2 += 1;
+    }
+
+    shifts
+        .into_iter()
+        .map(|(key, (dx, dy, n))| {
+            let gate = translate_gate(&result.reference_gate, dx / n as f32, dy / n as f32)?;
+            Ok((key, gate))
+        })
+        .collect()
+}
+
+pub fn translate_gate(
+    gate: &Arc<dyn DrawableGate>,
+    dx: f32,
+    dy: f32,
+) -> anyhow::Result<Arc<dyn DrawableGate>> {
+    let moved = gate
+        .replace_points(translation(gate.as_ref(), dx, dy))?
+        .ok_or_else(|| anyhow!("{} gates can't be moved", gate.get_name()))?;
+    Ok(Arc::from(moved))
+}
+
+// a drag from start to current moves the gate by (current - start)
+fn translation(gate: &dyn DrawableGate, dx: f32, dy: f32) -> GateDragData {
+    let (start, current) = match gate.as_any().downcast_ref::<LineGate>() {
+        // a line's drag position is also its label height, so hold that where it was.
+        // dx is always along the gated channel, which a swapped line draws on y
+        Some(line) if line.axis_matched => ((0.0, line.height), (dx, line.height)),
+        Some(line) => ((line.height, 0.0), (line.height, dx)),
+        None => ((0.0, 0.0), (dx, dy)),
+    };
+    GateDragData::new(gate.get_id(), start, current)
+}
+
+// (min, max) corners of the gate's bounding box, in scaled units
+fn gate_bounds(gate: &flow_gates::Gate) -> anyhow::Result<((f32, f32), (f32, f32))> {
+    let (x_param, y_param) = &gate.parameters;
+    let missing = || anyhow!("Invalid coordinates for gate {}", gate.name);
+    match &gate.geometry {
+        GateGeometry::Rectangle { min, max } => Ok((
+            (
+                min.get_coordinate(x_param).ok_or_else(missing)?,
+                min.get_coordinate(y_param).ok_or_else(missing)?,
+            ),
+            (
+                max.get_coordinate(x_param).ok_or_else(missing)?,
+                max.get_coordinate(y_param).ok_or_else(missing)?,
+            ),
+        )),
+        GateGeometry::Polygon { nodes, .. } => {
+            let mut bounds = ((f32::MAX, f32::MAX), (f32::MIN, f32::MIN));
+            for node in nodes {
+                let x = node.get_coordinate(x_param).ok_or_else(missing)?;
+                let y = node.get_coordinate(y_param).ok_or_else(missing)?;
+                bounds.0 = (bounds.0.0.min(x), bounds.0.1.min(y));
+                bounds.1 = (bounds.1.0.max(x), bounds.1.1.max(y));
+            }
+            if nodes.is_empty() {
+                return Err(missing());
+            }
+            Ok(bounds)
+        }
+        GateGeometry::Ellipse {
+            center,
+            radius_x,
+            radius_y,
+            angle,
+        } => {
+            let h = center.get_coordinate(x_param).ok_or_else(missing)?;
+            let k = center.get_coordinate(y_param).ok_or_else(missing)?;
+            let (sin_a, cos_a) = angle.sin_cos();
+            let x_extent = ((radius_x * cos_a).powi(2) + (radius_y * sin_a).powi(2)).sqrt();
+            let y_extent = ((radius_x * sin_a).powi(2) + (radius_y * cos_a).powi(2)).sqrt();
+            Ok(((h - x_extent, k - y_extent), (h + x_extent, k + y_extent)))
+        }
+        GateGeometry::Boolean { .. } => Err(anyhow!(
+            "Boolean gate {} has no position to adjust",
+            gate.name
+        )),
+    }
+}
+
+fn parent_events(
+    file: &StatsFile,
+    gate_id: &GateId,
+    hierarchy: &GateHierarchy,
+    x: (&str, &AxisRange),
+    y: (&str, &AxisRange),
+    axis_settings: &im::HashMap<Arc<str>, AxisInfo, FxBuildHasher>,
+) -> anyhow::Result<ParentEvents> {
+    let fcs = Fcs::open(file.path.to_str().unwrap_or_default())?;
+    let scaled = scale_fcs_data(&fcs, file.compensation.as_deref(), axis_settings)?;
+    let mask =
+        PopulationMasks::new(&scaled, hierarchy, &file.resolver).parent_population(gate_id)?;
+
+    let normalised = |(param, range): (&str, &AxisRange)| -> anyhow::Result<Column> {
+        let values: Float64Chunked = scaled
+            .column(param)?
+            .cast(&DataType::Float64)?
+            .f64()?
+            .filter(&mask)?
+            .apply_values(|v| (v - range.lower) / range.span);
+        Ok(values.into_series().into())
+    };
+    Ok(ParentEvents {
+        x: normalised(x)?,
+        y: normalised(y)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gate_editor::gates::gate_single::rectangle_gate::RectangleGate;
+
+    fn inner_gate(min: (f32, f32), max: (f32, f32)) -> flow_gates::Gate {
+        flow_gates::Gate {
+            id: Arc::from("gate"),
+            name: "gate".to_string(),
+            geometry: flow_gates::geometry::create_rectangle_geometry(vec![min, max], "x", "y")
+                .unwrap(),
+            mode: flow_gates::GateMode::Global,
+            parameters: (Arc::from("x"), Arc::from("y")),
+            label_position: None,
+        }
+    }
+
+    #[test]
+    fn test_translate_gate_moves_every_point() {
+        let gate: Arc<dyn DrawableGate> =
+            Arc::new(RectangleGate::try_new(inner_gate((1.0, 2.0), (3.0, 5.0)), true).unwrap());
+        let moved = translate_gate(&gate, 0.5, -1.0).unwrap();
+
+        assert_eq!(moved.get_id(), gate.get_id());
+        let bounds = gate_bounds(moved.get_gate_ref(None).unwrap()).unwrap();
+        assert_eq!(bounds, ((1.5, 1.0), (3.5, 4.0)));
+    }
+
+    #[test]
+    fn test_translate_line_keeps_its_height() {
+        let gate: Arc<dyn DrawableGate> =
+            Arc::new(LineGate::try_new(inner_gate((1.0, -10.0), (3.0, 10.0)), 7.0, true).unwrap());
+        let moved = translate_gate(&gate, 0.5, 2.0).unwrap();
+
+        let line = moved.as_any().downcast_ref::<LineGate>().unwrap();
+        assert_eq!(line.height, 7.0);
+        let bounds = gate_bounds(moved.get_gate_ref(None).unwrap()).unwrap();
+        assert_eq!(bounds, ((1.5, -10.0), (3.5, 10.0)));
+    }
+
+    #[test]
+    fn test_translate_swapped_line_moves_along_its_channel() {
+        let mut line = LineGate::try_new(inner_gate((-10.0, 1.0), (10.0, 3.0)), 7.0, true).unwrap();
+        line.axis_matched = false;
+        let gate: Arc<dyn DrawableGate> = Arc::new(line);
+        let moved = translate_gate(&gate, 0.5, 2.0).unwrap();
+
+        let line = moved.as_any().downcast_ref::<LineGate>().unwrap();
+        assert_eq!(line.height, 7.0);
+        assert!(!line.axis_matched);
+        let bounds = gate_bounds(moved.get_gate_ref(None).unwrap()).unwrap();
+        assert_eq!(bounds, ((-10.0, 1.5), (10.0, 3.5)));
+    }
+}
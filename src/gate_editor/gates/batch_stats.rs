@@ -0,0 +1,450 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use flow_fcs::Fcs;
+use polars::prelude::*;
+use rayon::prelude::*;
+use rustc_hash::{FxBuildHasher, FxHashMap};
+
+use crate::gate_editor::AxisInfo;
+use crate::gate_editor::compensation::CompMatrix;
+use crate::gate_editor::gates::gate_filtering::PopulationMasks;
+use crate::gate_editor::gates::gate_hierarchy::GateHierarchy;
+use crate::gate_editor::gates::gate_store::{FileId, GateMap, GateOverrideResolver, ROOTGATE};
+use crate::gate_editor::gates::GateId;
+use crate::gate_editor::plots::data_helpers::{raw_fcs_data, scale_fcs_data};
+
+// Statistics for every gate in the hierarchy across every loaded file.
+// Gating is done on the scaled data (where the gates live), channel statistics on raw values.
+
+pub struct StatsFile {
+    pub file_id: FileId,
+    pub name: String,
+    pub path: PathBuf,
+    pub resolver: GateOverrideResolver,
+    pub compensation: Option<Arc<CompMatrix>>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct StatsGate {
+    pub gate_id: GateId,
+    pub parent_id: Option<GateId>,
+    // gate names from the root down, eg "Lymphs/Single Cells/CD3+"
+    pub path: String,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChannelSummary {
+    pub median: f64,
+    pub mean: f64,
+    pub geometric_mean: f64,
+    pub cv: f64,
+    pub rsd: f64,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PopulationStats {
+    pub file_name: String,
+    pub gate_id: GateId,
+    pub gate_path: String,
+    pub count: usize,
+    pub percent_parent: f64,
+    pub percent_total: f64,
+    // in the same order as StatsTable::channels
+    pub channels: Vec<ChannelSummary>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct StatsTable {
+    pub channels: Vec<Arc<str>>,
+    pub rows: Vec<PopulationStats>,
+    pub errors: Vec<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StatsLayout {
+    // one row per file, gate, statistic and channel
+    Long,
+    // one row per file, one column per gate/statistic/channel
+    Wide,
+}
+
+// the gates to report, parents always before their children
+pub fn collect_stats_gates(hierarchy: &GateHierarchy, registry: &GateMap) -> Vec<StatsGate> {
+    let mut paths: FxHashMap<GateId, String> = FxHashMap::default();
+    let mut gates = vec![];
+    for gate_id in hierarchy.iter_dfs(&ROOTGATE) {
+        if gate_id == *ROOTGATE {
+            continue;
+        }
+        let name = registry
+            .get(&gate_id)
+            .and_then(|g| g.get_gate_ref(Some(&gate_id)).map(|inner| inner.name.clone()))
+            .unwrap_or_else(|| gate_id.to_string());
+        let parent_id = hierarchy
+            .get_parent(&gate_id)
+            .filter(|p| **p != *ROOTGATE)
+            .cloned();
+        let path = match parent_id.as_ref().and_then(|p| paths.get(p)) {
+            Some(parent_path) => format!("{parent_path}/{name}"),
+            None => name,
+        };
+        paths.insert(gate_id.clone(), path.clone());
+        gates.push(StatsGate {
+            gate_id,
+            parent_id,
+            path,
+        });
+    }
+    gates
+}
+
+pub fn compute_batch_stats(
+    files: &[StatsFile],
+    gates: &[StatsGate],
+    hierarchy: &GateHierarchy,
+    channels: &[Arc<str>],
+    axis_settings: &im::HashMap<Arc<str>, AxisInfo, FxBuildHasher>,
+) -> StatsTable {
+    let per_file: Vec<(Vec<PopulationStats>, Vec<String>)> = files
+        .par_iter()
+        .map(|file| match stats_for_file(file, gates, hierarchy, channels, axis_settings) {
+            Ok(result) => result,
+            Err(e) => (vec![], vec![format!("{}: {}", file.name, e)]),
+        })
+        .collect();
+
+    let mut table = StatsTable {
+        channels: channels.to_vec(),
+        ..Default::default()
+    };
+    for (rows, errors) in per_file {
+        table.rows.extend(rows);
+        table.errors.extend(errors);
+    }
+    table
+}
+
+fn stats_for_file(
+    file: &StatsFile,
+    gates: &[StatsGate],
+    hierarchy: &GateHierarchy,
+    channels: &[Arc<str>],
+    axis_settings: &im::HashMap<Arc<str>, AxisInfo, FxBuildHasher>,
+) -> anyhow::Result<(Vec<PopulationStats>, Vec<String>)> {
+    let fcs = Fcs::open(file.path.to_str().unwrap_or_default())?;
+    let scaled = scale_fcs_data(&fcs, file.compensation.as_deref(), axis_settings)?;
+    let raw = raw_fcs_data(&fcs, file.compensation.as_deref())?;
+    let total = scaled.height();
+
+    let mut populations = PopulationMasks::new(&scaled, hierarchy, &file.resolver);
+    let mut rows = vec![];
+    let mut errors = vec![];
+
+    for gate in gates {
+        let masks = populations
+            .parent_population(&gate.gate_id)
+            .and_then(|parent| Ok((parent, populations.population(&gate.gate_id)?)));
+        let (parent_mask, mask) = match masks {
+            Ok(m) => m,
+            Err(e) => {
+                errors.push(format!("{} - {}: {}", file.name, gate.path, e));
+                continue;
+            }
+        };
+
+        let count = mask.num_trues();
+        let parent_count = parent_mask.num_trues();
+
+        let channel_stats = channels
+            .iter()
+            .map(|channel| {
+                let values: Vec<f64> = raw
+                    .column(channel)?
+                    .cast(&DataType::Float64)?
+                    .f64()?
+                    .filter(&mask)?
+                    .into_iter()
+                    .flatten()
+                    .collect();
+                Ok(summarise(values))
+            })
+            .collect::<anyhow::Result<Vec<_>>>();
+        let channel_stats = match channel_stats {
+            Ok(c) => c,
+            Err(e) => {
+                errors.push(format!("{} - {}: {}", file.name, gate.path, e));
+                continue;
+            }
+        };
+
+        rows.push(PopulationStats {
+            file_name: file.name.clone(),
+            gate_id: gate.gate_id.clone(),
+            gate_path: gate.path.clone(),
+            count,
+            percent_parent: percent(count, parent_count),
+            percent_total: percent(count, total),
+            channels: channel_stats,
+        });
+    }
+
+    Ok((rows, errors))
+}
+
+fn percent(count: usize, of: usize) -> f64 {
+    if of == 0 {
+        f64::NAN
+    } else {
+        count as f64 / of as f64 * 100.0
+    }
+}
+
+fn median_of_sorted(sorted: &[f64]) -> f64 {
+    let n = sorted.len();
+    if n == 0 {
+        return f64::NAN;
+    }
+    if n % 2 == 1 {
+        sorted[n / 2]
+    } else {
+        (sorted[n / 2 - 1] + sorted[n / 2]) / 2.0
+    }
+}
+
+// rSD is the robust SD: 1.4826 * median absolute deviation.
+// The geometric mean only considers positive events.
+pub fn summarise(mut values: Vec<f64>) -> ChannelSummary {
+    let n = values.len();
+    if n == 0 {
+        return ChannelSummary {
+            median: f64::NAN,
+            mean: f64::NAN,
+            geometric_mean: f64::NAN,
+            cv: f64::NAN,
+            rsd: f64::NAN,
+        };
+    }
+    values.sort_by(|a, b| a.total_cmp(b));
+
+    let median = median_of_sorted(&values);
+    let mean = values.iter().sum::<f64>() / n as f64;
+
+    let (log_sum, positives) = values
+        .iter()
+        .filter(|v| **v > 0.0)
+        .fold((0.0, 0usize), |(sum, c), v| (sum + v.ln(), c + 1));
+    let geometric_mean = if positives == 0 {
+        f64::NAN
+    } else {
+        (log_sum / positives as f64).exp()
+    };
+
+    let sd = if n > 1 {
+        (values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / (n - 1) as f64).sqrt()
+    } else {
+        0.0
+    };
+    let cv = if mean == 0.0 {
+        f64::NAN
+    } else {
+        sd / mean.abs() * 100.0
+    };
+
+    let mut deviations: Vec<f64> = values.iter().map(|v| (v - median).abs()).collect();
+    deviations.sort_by(|a, b| a.total_cmp(b));
+    let rsd = 1.4826 * median_of_sorted(&deviations);
+
+    ChannelSummary {
+        median,
+        mean,
+        geometric_mean,
+        cv,
+        rsd,
+    }
+}
+
+const CHANNEL_STATISTICS: [&str; 5] = ["Median", "Mean", "GeoMean", "CV", "rSD"];
+
+impl ChannelSummary {
+    fn values(&self) -> [f64; 5] {
+        [self.median, self.mean, self.geometric_mean, self.cv, self.rsd]
+    }
+}
+
+fn escape_field(field: &str, delimiter: char) -> String {
+    if field.contains(delimiter) || field.contains('"') || field.contains('\n') {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+fn format_value(value: f64) -> String {
+    if value.is_nan() {
+        String::new()
+    } else {
+        value.to_string()
+    }
+}
+
+impl StatsTable {
+    pub fn to_delimited(&self, layout: StatsLayout, delimiter: char) -> String {
+        let mut lines: Vec<Vec<String>> = vec![];
+        match layout {
+            StatsLayout::Long => {
+                lines.push(
+                    ["Sample", "Gate", "Statistic", "Channel", "Value"]
+                        .iter()
+                        .map(|s| s.to_string())
+                        .collect(),
+                );
+                for row in &self.rows {
+                    let mut push = |statistic: &str, channel: &str, value: String| {
+                        lines.push(vec![
+                            row.file_name.clone(),
+                            row.gate_path.clone(),
+                            statistic.to_string(),
+                            channel.to_string(),
+                            value,
+                        ]);
+                    };
+                    push("Count", "", row.count.to_string());
+                    push("%Parent", "", format_value(row.percent_parent));
+                    push("%Total", "", format_value(row.percent_total));
+                    for (channel, summary) in self.channels.iter().zip(&row.channels) {
+                        for (statistic, value) in CHANNEL_STATISTICS.iter().zip(summary.values()) {
+                            push(statistic, channel, format_value(value));
+                        }
+                    }
+                }
+            }
+            StatsLayout::Wide => {
+                // columns follow the gate order of the first file each gate appears in
+                let mut gate_paths: Vec<&str> = vec![];
+                let mut samples: Vec<&str> = vec![];
+                let mut by_sample: FxHashMap<(&str, &str), &PopulationStats> = FxHashMap::default();
+                for row in &self.rows {
+                    let (sample, path) = (row.file_name.as_str(), row.gate_path.as_str());
+                    if !gate_paths.contains(&path) {
+                        gate_paths.push(path);
+                    }
+                    if !samples.contains(&sample) {
+                        samples.push(sample);
+                    }
+                    by_sample.insert((sample, path), row);
+                }
+
+                let mut header = vec!["Sample".to_string()];
+                for path in &gate_paths {
+                    header.push(format!("{path} | Count"));
+                    header.push(format!("{path} | %Parent"));
+                    header.push(format!("{path} | %Total"));
+                    for channel in &self.channels {
+                        for statistic in CHANNEL_STATISTICS {
+                            header.push(format!("{path} | {statistic} {channel}"));
+                        }
+                    }
+                }
+                lines.push(header);
+
+                let empty_cells = 3 + self.channels.len() * CHANNEL_STATISTICS.len();
+                for sample in samples {
+                    let mut line = vec![sample.to_string()];
+                    for path in &gate_paths {
+                        match by_sample.get(&(sample, *path)) {
+                            Some(row) => {
+                                line.push(row.count.to_string());
+                                line.push(format_value(row.percent_parent));
+                                line.push(format_value(row.percent_total));
+                                for summary in &row.channels {
+                                    line.extend(summary.values().into_iter().map(format_value));
+                                }
+                            }
+                            None => line.extend(std::iter::repeat_n(String::new(), empty_cells)),
+                        }
+                    }
+                    lines.push(line);
+                }
+            }
+        }
+
+        let mut out = String::new();
+        for line in lines {
+            let escaped: Vec<String> = line.iter().map(|f| escape_field(f, delimiter)).collect();
+            out.push_str(&escaped.join(&delimiter.to_string()));
+            out.push('\n');
+        }
+        out
+    }
+
+    // tab separated for .tsv/.txt, comma separated otherwise
+    pub fn write(&self, path: &Path, layout: StatsLayout) -> anyhow::Result<()> {
+        let delimiter = match path.extension().and_then(|e| e.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("tsv") || ext.eq_ignore_ascii_case("txt") => '\t',
+            _ => ',',
+        };
+        std::fs::write(path, self.to_delimited(layout, delimiter))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summarise() {
+        let s = summarise(vec![4.0, 1.0, 2.0, 3.0]);
+        assert_eq!(s.median, 2.5);
+        assert_eq!(s.mean, 2.5);
+        assert!((s.geometric_mean - 24f64.powf(0.25)).abs() < 1e-9);
+        let sd = (5.0f64 / 3.0).sqrt();
+        assert!((s.cv - sd / 2.5 * 100.0).abs() < 1e-9);
+        // deviations from 2.5 are 0.5, 0.5, 1.5, 1.5
+        assert!((s.rsd - 1.4826).abs() < 1e-9);
+
+        let empty = summarise(vec![]);
+        assert!(empty.median.is_nan());
+        // no positive events, no geometric mean
+        assert!(summarise(vec![-1.0, 0.0]).geometric_mean.is_nan());
+    }
+
+    #[test]
+    fn test_long_and_wide_layouts() {
+        let summary = summarise(vec![1.0, 3.0]);
+        let row = |file: &str, gate: &str, count| PopulationStats {
+            file_name: file.to_string(),
+            gate_id: Arc::from(gate),
+            gate_path: gate.to_string(),
+            count,
+            percent_parent: 50.0,
+            percent_total: 25.0,
+            channels: vec![summary.clone()],
+        };
+        let table = StatsTable {
+            channels: vec![Arc::from("FITC-A")],
+            rows: vec![row("a.fcs", "Lymphs", 10), row("b.fcs", "Lymphs, big", 20)],
+            errors: vec![],
+        };
+
+        let long = table.to_delimited(StatsLayout::Long, ',');
+        let long_lines: Vec<&str> = long.lines().collect();
+        // header + 2 rows * (3 population stats + 5 channel stats)
+        assert_eq!(long_lines.len(), 1 + 2 * 8);
+        assert_eq!(long_lines[1], "a.fcs,Lymphs,Count,,10");
+        assert!(long_lines[9].starts_with("b.fcs,\"Lymphs, big\",Count"));
+
+        let wide = table.to_delimited(StatsLayout::Wide, '\t');
+        let wide_lines: Vec<&str> = wide.lines().collect();
+        assert_eq!(wide_lines.len(), 3);
+        let header: Vec<&str> = wide_lines[0].split('\t').collect();
+        assert_eq!(header.len(), 1 + 2 * 8);
+        assert_eq!(header[1], "Lymphs | Count");
+        assert_eq!(header[4], "Lymphs | Median FITC-A");
+        // a.fcs has no "Lymphs, big" population
+        let a: Vec<&str> = wide_lines[1].split('\t').collect();
+        assert_eq!(a[1], "10");
+        assert_eq!(a[9], "");
+    }
+}
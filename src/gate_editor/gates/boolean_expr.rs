@@ -0,0 +1,418 @@
+use anyhow::anyhow;
+use polars::prelude::*;
+
+use crate::gate_editor::gates::{
+    GateId, gate_filtering::PopulationMasks, gate_stats::PopulationCount,
+};
+
+// A boolean combination of gates, eg (A AND B) OR NOT C.
+// A boolean gate holds one operation over a list of operands, so each nested
+// sub-expression is stored as a boolean gate of its own - see GateState::add_boolean_expr.
+#[derive(Clone, Debug, PartialEq)]
+pub enum BooleanExpr {
+    Gate(GateId),
+    Not(Box<BooleanExpr>),
+    And(Vec<BooleanExpr>),
+    Or(Vec<BooleanExpr>),
+}
+
+const KEYWORDS: [&str; 3] = ["AND", "OR", "NOT"];
+
+impl BooleanExpr {
+    // AND of ANDs is one AND - the same for OR
+    pub fn and(terms: Vec<BooleanExpr>) -> Self {
+        let mut flat = vec![];
+        for term in terms {
+            match term {
+                BooleanExpr::And(inner) => flat.extend(inner),
+                term => flat.push(term),
+            }
+        }
+        BooleanExpr::And(flat)
+    }
+
+    pub fn or(terms: Vec<BooleanExpr>) -> Self {
+        let mut flat = vec![];
+        for term in terms {
+            match term {
+                BooleanExpr::Or(inner) => flat.extend(inner),
+                term => flat.push(term),
+            }
+        }
+        BooleanExpr::Or(flat)
+    }
+
+    // every gate the expression reads, in order
+    pub fn gate_ids(&self) -> Vec<GateId> {
+        match self {
+            BooleanExpr::Gate(id) => vec![id.clone()],
+            BooleanExpr::Not(inner) => inner.gate_ids(),
+            BooleanExpr::And(terms) | BooleanExpr::Or(terms) => {
+                terms.iter().flat_map(|t| t.gate_ids()).collect()
+            }
+        }
+    }
+
+    // parses text such as `(A AND B) OR NOT C` against the gates in `names`.
+    // NOT binds tighter than AND, which binds tighter than OR. Names may contain spaces,
+    // and can be quoted if they contain brackets or the words AND, OR or NOT - inside quotes
+    // \" is a quote and \\ a backslash.
+    pub fn parse(text: &str, names: &[(GateId, String)]) -> anyhow::Result<Self> {
+        let tokens = tokenise(text)?;
+        let mut parser = Parser {
+            tokens,
+            pos: 0,
+            names,
+        };
+        let expr = parser.or_expr()?;
+        match parser.tokens.get(parser.pos) {
+            None => Ok(expr),
+            Some(token) => Err(anyhow!("Unexpected {} in expression", token)),
+        }
+    }
+
+    // the text `parse` reads back, naming gates with `name_of`
+    pub fn to_text(&self, name_of: &impl Fn(&GateId) -> String) -> String {
+        match self {
+            BooleanExpr::Gate(id) => quote_name(&name_of(id)),
+            BooleanExpr::Not(inner) => match inner.as_ref() {
+                BooleanExpr::Gate(_) | BooleanExpr::Not(_) => {
+                    format!("NOT {}", inner.to_text(name_of))
+                }
+                _ => format!("NOT ({})", inner.to_text(name_of)),
+            },
+            BooleanExpr::And(terms) => terms
+                .iter()
+                .map(|t| match t {
+                    BooleanExpr::Or(_) => format!("({})", t.to_text(name_of)),
+                    _ => t.to_text(name_of),
+                })
+                .collect::<Vec<_>>()
+                .join(" AND "),
+            BooleanExpr::Or(terms) => terms
+                .iter()
+                .map(|t| match t {
+                    BooleanExpr::And(_) => format!("({})", t.to_text(name_of)),
+                    _ => t.to_text(name_of),
+                })
+                .collect::<Vec<_>>()
+                .join(" OR "),
+        }
+    }
+
+    // the events in the expression, from the full population of each gate
+    pub fn mask(&self, populations: &mut PopulationMasks<'_>) -> anyhow::Result<BooleanChunked> {
+        match self {
+            BooleanExpr::Gate(id) => populations.population(id),
+            BooleanExpr::Not(inner) => Ok(!inner.mask(populations)?),
+            BooleanExpr::And(terms) => {
+                let mut masks = terms.iter().map(|t| t.mask(populations));
+                let first = masks
+                    .next()
+                    .ok_or_else(|| anyhow!("AND needs something to combine"))??;
+                masks.try_fold(first, |acc, mask| Ok(&acc & &mask?))
+            }
+            BooleanExpr::Or(terms) => {
+                let mut masks = terms.iter().map(|t| t.mask(populations));
+                let first = masks
+                    .next()
+                    .ok_or_else(|| anyhow!("OR needs something to combine"))??;
+                masks.try_fold(first, |acc, mask| Ok(&acc | &mask?))
+            }
+        }
+    }
+
+    // the count a boolean gate with this expression would have under `parent`
+    pub fn preview_count(
+        &self,
+        populations: &mut PopulationMasks<'_>,
+        parent: &GateId,
+    ) -> anyhow::Result<PopulationCount> {
+        let parent_mask = populations.population(parent)?;
+        let mask = &parent_mask & &self.mask(populations)?;
+        let count = mask.num_trues();
+        let parent_count = parent_mask.num_trues();
+        let percent_parent = if parent_count == 0 {
+            0f32
+        } else {
+            (count as f32 / parent_count as f32) * 100f32
+        };
+        Ok(PopulationCount {
+            count,
+            percent_parent,
+        })
+    }
+}
+
+// a gate name as it has to be written in an expression
+pub fn quote_name(name: &str) -> String {
+    let plain = !name.is_empty()
+        && name.split_whitespace().collect::<Vec<_>>().join(" ") == name
+        && !name.contains(['(', ')', '"'])
+        && !name
+            .split_whitespace()
+            .any(|word| KEYWORDS.contains(&word.to_uppercase().as_str()));
+    if plain {
+        name.to_string()
+    } else {
+        format!("\"{}\"", name.replace('\\', "\\\\").replace('"', "\\\""))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Open,
+    Close,
+    And,
+    Or,
+    Not,
+    Word(String),
+    Quoted(String),
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Open => write!(f, "("),
+            Token::Close => write!(f, ")"),
+            Token::And => write!(f, "AND"),
+            Token::Or => write!(f, "OR"),
+            Token::Not => write!(f, "NOT"),
+            Token::Word(w) => write!(f, "{w}"),
+            Token::Quoted(q) => write!(f, "{}", quote_name(q)),
+        }
+    }
+}
+
+fn tokenise(text: &str) -> anyhow::Result<Vec<Token>> {
+    let mut tokens = vec![];
+    let mut chars = text.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '(' => {
+                chars.next();
+                tokens.push(Token::Open);
+            }
+            ')' => {
+                chars.next();
+                tokens.push(Token::Close);
+            }
+            '"' => {
+                chars.next();
+                let mut quoted = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        // a trailing backslash leaves the quote unclosed
+                        Some('\\') => quoted.extend(chars.next()),
+                        Some(c) => quoted.push(c),
+                        None => return Err(anyhow!("Missing closing quote after \"{}", quoted)),
+                    }
+                }
+                tokens.push(Token::Quoted(quoted));
+            }
+            _ => {
+                let mut word = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || matches!(c, '(' | ')' | '"') {
+                        break;
+                    }
+                    word.push(c);
+                    chars.next();
+                }
+                tokens.push(match word.to_uppercase().as_str() {
+                    "AND" => Token::And,
+                    "OR" => Token::Or,
+                    "NOT" => Token::Not,
+                    _ => Token::Word(word),
+                });
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    tokens: Vec<Token>,
+    pos: usize,
+    names: &'a [(GateId, String)],
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn or_expr(&mut self) -> anyhow::Result<BooleanExpr> {
+        let mut terms = vec![self.and_expr()?];
+        while self.peek() == Some(&Token::Or) {
+            self.pos += 1;
+            terms.push(self.and_expr()?);
+        }
+        Ok(if terms.len() == 1 {
+            terms.remove(0)
+        } else {
+            BooleanExpr::or(terms)
+        })
+    }
+
+    fn and_expr(&mut self) -> anyhow::Result<BooleanExpr> {
+        let mut terms = vec![self.not_expr()?];
+        while self.peek() == Some(&Token::And) {
+            self.pos += 1;
+            terms.push(self.not_expr()?);
+        }
+        Ok(if terms.len() == 1 {
+            terms.remove(0)
+        } else {
+            BooleanExpr::and(terms)
+        })
+    }
+
+    fn not_expr(&mut self) -> anyhow::Result<BooleanExpr> {
+        if self.peek() == Some(&Token::Not) {
+            self.pos += 1;
+            return Ok(BooleanExpr::Not(Box::new(self.not_expr()?)));
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> anyhow::Result<BooleanExpr> {
+        match self.peek().cloned() {
+            Some(Token::Open) => {
+                self.pos += 1;
+                let expr = self.or_expr()?;
+                if self.peek() != Some(&Token::Close) {
+                    return Err(anyhow!("Missing closing bracket"));
+                }
+                self.pos += 1;
+                Ok(expr)
+            }
+            Some(Token::Quoted(name)) => {
+                self.pos += 1;
+                self.gate(&name)
+            }
+            Some(Token::Word(first)) => {
+                // a name can run over several words
+                let mut words = vec![first];
+                self.pos += 1;
+                while let Some(Token::Word(word)) = self.peek().cloned() {
+                    words.push(word);
+                    self.pos += 1;
+                }
+                self.gate(&words.join(" "))
+            }
+            Some(token) => Err(anyhow!("Expected a gate name, found {}", token)),
+            None => Err(anyhow!("Expression is incomplete")),
+        }
+    }
+
+    fn gate(&self, name: &str) -> anyhow::Result<BooleanExpr> {
+        let mut matches = self.names.iter().filter(|(_, n)| n == name);
+        let Some((id, _)) = matches.next() else {
+            return Err(anyhow!("No gate called {} here", name));
+        };
+        if matches.next().is_some() {
+            return Err(anyhow!("More than one gate is called {}", name));
+        }
+        Ok(BooleanExpr::Gate(id.clone()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    fn names() -> Vec<(GateId, String)> {
+        [
+            ("a", "A"),
+            ("b", "CD4+ T cells"),
+            ("c", "C"),
+            ("d", "Not live"),
+        ]
+        .into_iter()
+        .map(|(id, name)| (Arc::from(id), name.to_string()))
+        .collect()
+    }
+
+    fn gate(id: &str) -> BooleanExpr {
+        BooleanExpr::Gate(Arc::from(id))
+    }
+
+    #[test]
+    fn test_parse_precedence_and_round_trip() {
+        let names = names();
+        let expr = BooleanExpr::parse("(A and CD4+ T cells) OR NOT C", &names).unwrap();
+        assert_eq!(
+            expr,
+            BooleanExpr::Or(vec![
+                BooleanExpr::And(vec![gate("a"), gate("b")]),
+                BooleanExpr::Not(Box::new(gate("c"))),
+            ])
+        );
+
+        // AND binds tighter than OR, and nested ANDs flatten
+        let expr = BooleanExpr::parse("A OR C AND (A AND \"Not live\")", &names).unwrap();
+        assert_eq!(
+            expr,
+            BooleanExpr::Or(vec![
+                gate("a"),
+                BooleanExpr::And(vec![gate("c"), gate("a"), gate("d")]),
+            ])
+        );
+
+        let name_of = |id: &GateId| {
+            names
+                .iter()
+                .find(|(gid, _)| gid == id)
+                .map(|(_, n)| n.clone())
+                .unwrap()
+        };
+        let text = expr.to_text(&name_of);
+        assert_eq!(text, "A OR (C AND A AND \"Not live\")");
+        assert_eq!(BooleanExpr::parse(&text, &names).unwrap(), expr);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let names = names();
+        assert!(BooleanExpr::parse("A AND", &names).is_err());
+        assert!(BooleanExpr::parse("(A OR C", &names).is_err());
+        assert!(BooleanExpr::parse("A OR B", &names).is_err());
+        assert!(BooleanExpr::parse("A C", &names).is_err());
+        assert!(BooleanExpr::parse("\"A", &names).is_err());
+        assert!(BooleanExpr::parse("\"A\\\"", &names).is_err());
+    }
+
+    #[test]
+    fn test_quoted_names_round_trip() {
+        let names: Vec<(GateId, String)> = [
+            ("q", "Say \"hi\""),
+            ("p", "Say hi"),
+            ("s", "back\\slash (x)"),
+        ]
+        .into_iter()
+        .map(|(id, name)| (Arc::from(id), name.to_string()))
+        .collect();
+        let name_of = |id: &GateId| {
+            names
+                .iter()
+                .find(|(gid, _)| gid == id)
+                .map(|(_, n)| n.clone())
+                .unwrap()
+        };
+
+        let expr = BooleanExpr::Or(vec![gate("q"), gate("p"), gate("s")]);
+        let text = expr.to_text(&name_of);
+        assert_eq!(
+            text,
+            "\"Say \\\"hi\\\"\" OR Say hi OR \"back\\\\slash (x)\""
+        );
+        // the quoted name isn't mistaken for its sibling without the quotes
+        assert_eq!(BooleanExpr::parse(&text, &names).unwrap(), expr);
+    }
+}
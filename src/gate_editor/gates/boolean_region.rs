@@ -0,0 +1,399 @@
+use std::f32::consts::TAU;
+use std::ops::RangeInclusive;
+
+use flow_gates::{BooleanOperation, Gate, GateGeometry};
+use geo::{BooleanOps, Coord, LineString, MultiPolygon, Polygon, Rect};
+
+use crate::gate_editor::gates::{
+    GateId,
+    gate_hierarchy::GateHierarchy,
+    gate_store::{GateMap, GateOverrideResolver},
+};
+use crate::gate_editor::plots::axis_store::PlotMapper;
+
+// Boolean gates have no geometry of their own - their region is found by clipping the operand
+// polygons against each other, with NOT taken from the visible plot area.
+// Only the geometry is combined, not the operands' ancestors, so this is a picture of the
+// gate rather than its population.
+
+// ellipses are clipped as polygons of this many sides
+const ELLIPSE_SEGMENTS: usize = 64;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct BooleanRegion {
+    pub gate_id: GateId,
+    pub name: String,
+    // closed loops in data coordinates - holes are loops inside loops, so fill even-odd
+    pub outlines: Vec<Vec<(f32, f32)>>,
+    // set instead of outlines when an operand isn't on the plot's axes
+    pub caption: Option<String>,
+}
+
+// the boolean gates directly under `parent`, drawn on the plot of `x` against `y`
+pub fn boolean_regions_for_plot(
+    hierarchy: &GateHierarchy,
+    registry: &GateMap,
+    resolver: &GateOverrideResolver,
+    x: &str,
+    y: &str,
+    parent: &str,
+    mapper: &PlotMapper,
+) -> Vec<BooleanRegion> {
+    let lookup = |id: &GateId| {
+        resolver
+            .active_gates
+            .get(id)
+            .and_then(|g| g.get_gate_ref(Some(id)).cloned())
+    };
+    let name_of = |id: &GateId| registry.display_name(id).unwrap_or_else(|| id.to_string());
+
+    hierarchy
+        .get_children(parent)
+        .into_iter()
+        .filter_map(|id| {
+            let gate = lookup(id)?;
+            let GateGeometry::Boolean { .. } = gate.geometry else {
+                return None;
+            };
+            let name = name_of(id);
+            let region = match build_region(id, &lookup, x, y) {
+                Ok(region) => BooleanRegion {
+                    gate_id: id.clone(),
+                    name,
+                    outlines: region_outlines(
+                        &region,
+                        mapper.x_axis_min_max(),
+                        mapper.y_axis_min_max(),
+                    ),
+                    caption: None,
+                },
+                Err(off_axis) => {
+                    let off_axis = off_axis
+                        .iter()
+                        .map(|id| match lookup(id) {
+                            Some(g) => {
+                                format!("{} ({} v {})", name_of(id), g.parameters.0, g.parameters.1)
+                            }
+                            None => name_of(id),
+                        })
+                        .collect::<Vec<_>>()
+                        .join(", ");
+                    BooleanRegion {
+                        gate_id: id.clone(),
+                        caption: Some(format!("{name}: uses {off_axis}")),
+                        name,
+                        outlines: vec![],
+                    }
+                }
+            };
+            Some(region)
+        })
+        .collect()
+}
+
+enum Region {
+    Shape(Shape),
+    Not(Box<Region>),
+    And(Vec<Region>),
+    Or(Vec<Region>),
+}
+
+// a geometry in the gate's own parameter order - `swapped` when the plot has them reversed
+struct Shape {
+    outline: Outline,
+    swapped: bool,
+}
+
+enum Outline {
+    Rectangle {
+        min: (f32, f32),
+        max: (f32, f32),
+    },
+    Ellipse {
+        center: (f32, f32),
+        radii: (f32, f32),
+        angle: f32,
+    },
+    Polygon(Vec<(f32, f32)>),
+}
+
+// the region of gate `id` on the plot of `x` against `y`, or every operand that isn't on it
+fn build_region(
+    id: &GateId,
+    lookup: &impl Fn(&GateId) -> Option<Gate>,
+    x: &str,
+    y: &str,
+) -> Result<Region, Vec<GateId>> {
+    let gate = lookup(id).ok_or_else(|| vec![id.clone()])?;
+    let (p0, p1) = (&gate.parameters.0, &gate.parameters.1);
+
+    let (operation, operands) = match &gate.geometry {
+        GateGeometry::Boolean {
+            operation,
+            operands,
+        } => (*operation, operands),
+        geometry => {
+            let swapped = match (
+                p0.as_ref() == x && p1.as_ref() == y,
+                p0.as_ref() == y && p1.as_ref() == x,
+            ) {
+                (true, _) => false,
+                (_, true) => true,
+                _ => return Err(vec![id.clone()]),
+            };
+            let outline = outline_of(geometry, p0, p1).ok_or_else(|| vec![id.clone()])?;
+            return Ok(Region::Shape(Shape { outline, swapped }));
+        }
+    };
+
+    let mut terms = vec![];
+    let mut off_axis = vec![];
+    for operand in operands {
+        match build_region(operand, lookup, x, y) {
+            Ok(term) => terms.push(term),
+            Err(ids) => off_axis.extend(ids),
+        }
+    }
+    if !off_axis.is_empty() {
+        return Err(off_axis);
+    }
+    Ok(match operation {
+        BooleanOperation::And => Region::And(terms),
+        BooleanOperation::Or => Region::Or(terms),
+        BooleanOperation::Not => {
+            Region::Not(Box::new(terms.pop().ok_or_else(|| vec![id.clone()])?))
+        }
+    })
+}
+
+fn outline_of(geometry: &GateGeometry, p0: &str, p1: &str) -> Option<Outline> {
+    match geometry {
+        GateGeometry::Rectangle { min, max } => Some(Outline::Rectangle {
+            min: (min.get_coordinate(p0)?, min.get_coordinate(p1)?),
+            max: (max.get_coordinate(p0)?, max.get_coordinate(p1)?),
+        }),
+        GateGeometry::Ellipse {
+            center,
+            radius_x,
+            radius_y,
+            angle,
+        } => Some(Outline::Ellipse {
+            center: (center.get_coordinate(p0)?, center.get_coordinate(p1)?),
+            radii: (*radius_x, *radius_y),
+            angle: *angle,
+        }),
+        GateGeometry::Polygon { nodes, .. } => {
+            let points: Vec<(f32, f32)> = nodes
+                .iter()
+                .filter_map(|n| Some((n.get_coordinate(p0)?, n.get_coordinate(p1)?)))
+                .collect();
+            (points.len() >= 3).then_some(Outline::Polygon(points))
+        }
+        GateGeometry::Boolean { .. } => None,
+    }
+}
+
+impl Region {
+    // the region as polygons, with `plot` standing in for everything
+    fn polygons(&self, plot: &MultiPolygon<f64>) -> MultiPolygon<f64> {
+        match self {
+            Region::Shape(shape) => {
+                let points = shape.outline.points().into_iter().map(|(a, b)| {
+                    let (x, y) = if shape.swapped { (b, a) } else { (a, b) };
+                    (x as f64, y as f64)
+                });
+                MultiPolygon::new(vec![Polygon::new(LineString::from_iter(points), vec![])])
+            }
+            Region::Not(inner) => plot.difference(&inner.polygons(plot)),
+            Region::And(terms) => terms
+                .iter()
+                .fold(plot.clone(), |acc, t| acc.intersection(&t.polygons(plot))),
+            Region::Or(terms) => terms.iter().fold(MultiPolygon::new(vec![]), |acc, t| {
+                acc.union(&t.polygons(plot))
+            }),
+        }
+    }
+}
+
+impl Outline {
+    // the outline's vertices, in the gate's parameter order
+    fn points(&self) -> Vec<(f32, f32)> {
+        match self {
+            Outline::Rectangle { min, max } => {
+                vec![
+                    (min.0, min.1),
+                    (max.0, min.1),
+                    (max.0, max.1),
+                    (min.0, max.1),
+                ]
+            }
+            Outline::Ellipse {
+                center,
+                radii: (rx, ry),
+                angle,
+            } => {
+                let (cos_a, sin_a) = (angle.cos(), angle.sin());
+                (0..ELLIPSE_SEGMENTS)
+                    .map(|i| {
+                        let t = TAU * i as f32 / ELLIPSE_SEGMENTS as f32;
+                        let (u, v) = (rx * t.cos(), ry * t.sin());
+                        (
+                            center.0 + u * cos_a - v * sin_a,
+                            center.1 + u * sin_a + v * cos_a,
+                        )
+                    })
+                    .collect()
+            }
+            Outline::Polygon(points) => points.clone(),
+        }
+    }
+}
+
+// Every ring of the region clipped to the plot - exteriors and holes alike, so the
+// result fills even-odd.
+fn region_outlines(
+    region: &Region,
+    x_range: RangeInclusive<f32>,
+    y_range: RangeInclusive<f32>,
+) -> Vec<Vec<(f32, f32)>> {
+    let plot: MultiPolygon<f64> = Rect::new(
+        Coord {
+            x: *x_range.start() as f64,
+            y: *y_range.start() as f64,
+        },
+        Coord {
+            x: *x_range.end() as f64,
+            y: *y_range.end() as f64,
+        },
+    )
+    .to_polygon()
+    .into();
+    let clipped = region.polygons(&plot).intersection(&plot);
+
+    let to_outline = |ring: &LineString<f64>| -> Vec<(f32, f32)> {
+        let mut points: Vec<(f32, f32)> = ring.coords().map(|c| (c.x as f32, c.y as f32)).collect();
+        // rings are closed by repeating the first point
+        if points.len() > 1 && points.first() == points.last() {
+            points.pop();
+        }
+        points
+    };
+    clipped
+        .iter()
+        .flat_map(|polygon| std::iter::once(polygon.exterior()).chain(polygon.interiors()))
+        .map(to_outline)
+        .filter(|outline| outline.len() >= 3)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flow_gates::{create_ellipse_geometry, create_polygon_geometry};
+    use std::sync::Arc;
+
+    fn square(id: &str, x: (f32, f32), y: (f32, f32), params: (&str, &str)) -> Gate {
+        let (px, py): (Arc<str>, Arc<str>) = (Arc::from(params.0), Arc::from(params.1));
+        let points = vec![(x.0, y.0), (x.1, y.0), (x.1, y.1), (x.0, y.1)];
+        let geometry = create_polygon_geometry(points, &px, &py).unwrap();
+        Gate::new(Arc::from(id), id.to_string(), geometry, px, py)
+    }
+
+    fn boolean(id: &str, operation: BooleanOperation, operands: &[&str]) -> Gate {
+        let geometry = GateGeometry::Boolean {
+            operation,
+            operands: operands.iter().map(|o| Arc::from(*o)).collect(),
+        };
+        Gate::new(
+            Arc::from(id),
+            id.to_string(),
+            geometry,
+            Arc::from("X"),
+            Arc::from("Y"),
+        )
+    }
+
+    fn outlines_of(id: &str, gates: &[Gate]) -> Result<Vec<Vec<(f32, f32)>>, Vec<GateId>> {
+        let lookup = |id: &GateId| gates.iter().find(|g| g.id == *id).cloned();
+        let region = build_region(&Arc::from(id), &lookup, "X", "Y")?;
+        Ok(region_outlines(&region, 0.0..=20.0, 0.0..=20.0))
+    }
+
+    fn bounds(outline: &[(f32, f32)]) -> (f32, f32, f32, f32) {
+        outline.iter().fold(
+            (f32::MAX, f32::MIN, f32::MAX, f32::MIN),
+            |(x0, x1, y0, y1), (x, y)| (x0.min(*x), x1.max(*x), y0.min(*y), y1.max(*y)),
+        )
+    }
+
+    #[test]
+    fn test_boolean_region_outlines() {
+        let gates = vec![
+            square("a", (2.0, 10.0), (2.0, 10.0), ("X", "Y")),
+            // on swapped axes - still drawable
+            square("b", (4.0, 12.0), (6.0, 14.0), ("Y", "X")),
+            square("c", (2.0, 10.0), (2.0, 10.0), ("X", "Z")),
+            boolean("and", BooleanOperation::And, &["a", "b"]),
+            boolean("or", BooleanOperation::Or, &["a", "b"]),
+            boolean("not", BooleanOperation::Not, &["a"]),
+            boolean("mixed", BooleanOperation::Or, &["a", "c"]),
+        ];
+        let near = |a: f32, b: f32| (a - b).abs() <= 1e-3;
+
+        // clipped exactly, rather than to the nearest grid cell
+        let and = outlines_of("and", &gates).unwrap();
+        assert_eq!(and.len(), 1);
+        let (x0, x1, y0, y1) = bounds(&and[0]);
+        assert!(near(x0, 6.0) && near(x1, 10.0) && near(y0, 4.0) && near(y1, 10.0));
+
+        let or = outlines_of("or", &gates).unwrap();
+        assert_eq!(or.len(), 1);
+        let (x0, x1, y0, y1) = bounds(&or[0]);
+        assert!(near(x0, 2.0) && near(x1, 14.0) && near(y0, 2.0) && near(y1, 12.0));
+
+        // the plot edge, with a hole for a
+        let not = outlines_of("not", &gates).unwrap();
+        assert_eq!(not.len(), 2);
+        let (x0, x1, y0, y1) = bounds(&not[0]);
+        assert!(near(x0, 0.0) && near(x1, 20.0) && near(y0, 0.0) && near(y1, 20.0));
+        let (x0, x1, y0, y1) = bounds(&not[1]);
+        assert!(near(x0, 2.0) && near(x1, 10.0) && near(y0, 2.0) && near(y1, 10.0));
+
+        assert_eq!(outlines_of("mixed", &gates), Err(vec![Arc::from("c")]));
+    }
+
+    #[test]
+    fn test_boolean_region_clips_ellipses_and_plot_edge() {
+        // centred on (10, 10), with radii 4 and 2
+        let (px, py): (Arc<str>, Arc<str>) = (Arc::from("X"), Arc::from("Y"));
+        let points = vec![
+            (10.0, 10.0),
+            (14.0, 10.0),
+            (10.0, 12.0),
+            (6.0, 10.0),
+            (10.0, 8.0),
+        ];
+        let geometry = create_ellipse_geometry(points, &px, &py).unwrap();
+        let ellipse = Gate::new(Arc::from("e"), "e".to_string(), geometry, px, py);
+        let gates = vec![
+            ellipse,
+            // runs off the right of the plot
+            square("r", (10.0, 30.0), (0.0, 20.0), ("X", "Y")),
+            boolean("and", BooleanOperation::And, &["e", "r"]),
+            boolean("or", BooleanOperation::Or, &["e", "r"]),
+        ];
+        let near = |a: f32, b: f32| (a - b).abs() <= 1e-3;
+
+        // the right half of the ellipse
+        let and = outlines_of("and", &gates).unwrap();
+        assert_eq!(and.len(), 1);
+        let (x0, x1, y0, y1) = bounds(&and[0]);
+        assert!(near(x0, 10.0) && near(x1, 14.0) && near(y0, 8.0) && near(y1, 12.0));
+
+        // the left half of the ellipse sticks out of a rectangle cut at the plot edge
+        let or = outlines_of("or", &gates).unwrap();
+        assert_eq!(or.len(), 1);
+        let (x0, x1, y0, y1) = bounds(&or[0]);
+        assert!(near(x0, 6.0) && near(x1, 20.0) && near(y0, 0.0) && near(y1, 20.0));
+    }
+}
@@ -1,4 +1,9 @@
-use crate::gate_editor::gates::gate_store::{GateOverrideResolver, GateStateStoreExt};
+use crate::gate_editor::gates::boolean_region::{BooleanRegion, boolean_regions_for_plot};
+use crate::gate_editor::gates::gate_stats::PopulationCounts;
+use crate::gate_editor::gates::gate_store::{
+    GateOverrideResolver, GateStateStoreExt, GateSubStoreStoreExt, ROOTGATE,
+};
+use crate::gate_editor::main_window::StatusMessage;
 use crate::gate_editor::plots::axis_store::AxisStore;
 use crate::gate_editor::plots::axis_store::AxisStoreStoreExt;
 use crate::gate_editor::plots::plot_store::{PlotStore, PlotStoreStoreExt};
@@ -6,11 +11,14 @@ use crate::gate_editor::{
     gates::{
         GateState,
         gate_draft::GateDraft,
-        gate_drag::{GateDragData, GateDragType, PointDragData, RotationData},
-        gate_single::rectangle_gate,
+        gate_drag::{GateDragData, GateDragType, LabelDragData, PointDragData, RotationData},
+        gate_label::{GateLabelContent, GateLabels, gate_labels},
+        gate_single::{boolean_gates::BooleanGate, line_gate::LineGate, rectangle_gate},
         gate_store::GateStateImplExt,
         gate_traits::DrawableGate,
-        gate_types::{Direction, GateRenderShape, GateStats, PrimaryGateType, ShapeType},
+        gate_types::{
+            BOOLEAN_REGION, Direction, GateRenderShape, GateStats, PrimaryGateType, ShapeType,
+        },
     },
     plots::axis_store::PlotMapper,
 };
@@ -48,6 +56,8 @@ pub fn GateLayer(
     x_channel: ReadSignal<Arc<str>>,
     y_channel: ReadSignal<Arc<str>>,
     parental_gate_id: ReadSignal<Option<Arc<str>>>,
+    // histograms only show, and only draw, range gates on the x axis
+    #[props(default)] range_only: bool,
 ) -> Element {
     let plot_map = use_context::<Signal<Option<Arc<PlotMapper>>>>();
 
@@ -59,6 +69,7 @@ pub fn GateLayer(
     let current_gate_type = use_context::<Signal<PrimaryGateType>>();
 
     let plot_store = use_context::<Store<PlotStore>>();
+    let label_content = use_context::<Signal<GateLabelContent>>();
     let axis_store = use_context::<Store<AxisStore, CopyValue<AxisStore, SyncStorage>>>();
 
     let gates = use_memo(move || {
@@ -66,12 +77,45 @@ pub fn GateLayer(
             return GateList(vec![]);
         };
         let (x, y, parent) = (x_channel(), y_channel(), parental_gate_id());
-        let g = gate_store
+        let mut g = gate_store
             .get_gates_for_plot(x, y, parent, &resolver)
             .unwrap_or_default();
+        if range_only {
+            g.retain(|gate| {
+                gate.as_any()
+                    .downcast_ref::<LineGate>()
+                    .is_some_and(|line| line.axis_matched)
+            });
+        }
         GateList(g)
     });
 
+    // boolean gates under the parent, outlined where their operands are on these axes
+    let boolean_regions = use_memo(move || {
+        let (Some(resolver), Some(mapper)) = (resolver(), plot_map()) else {
+            return vec![];
+        };
+        if range_only {
+            return vec![];
+        }
+        let (x, y) = (x_channel(), y_channel());
+        let parent = parental_gate_id().unwrap_or_else(|| ROOTGATE.clone());
+        let hierarchy_binding = gate_store.hierarchy();
+        let registry_binding = gate_store.gate_store().primary_and_subgate_registry();
+        boolean_regions_for_plot(
+            &hierarchy_binding.read(),
+            &registry_binding.read(),
+            &resolver,
+            &x,
+            &y,
+            &parent,
+            &mapper,
+        )
+    });
+    // counted in the sidebar, when there is one
+    let population_counts = try_use_context::<Signal<PopulationCounts>>();
+    let status = try_use_context::<StatusMessage>();
+
     use_effect(move || {
         println!("matching gates to plot");
         let (x, y, parent) = (x_channel(), y_channel(), parental_gate_id());
@@ -135,6 +179,10 @@ pub fn GateLayer(
                         let mut stat_map = FxHashMap::default();
                         let parental_events = event_index_map.event_index.len() as f32;
                         for gate in gates_on_plot {
+                            // boolean populations are counted in the sidebar
+                            if gate.as_any().is::<BooleanGate>() {
+                                continue;
+                            }
                             let id = gate.get_id();
                             let stats =
                                 crate::gate_editor::gates::gate_stats::get_percent_and_counts_gate(
@@ -208,6 +256,14 @@ pub fn GateLayer(
                 oncontextmenu: move |evt| evt.prevent_default(),
 
                 onclick: move |evt| {
+                    // dropping a label isn't a click on the plot
+                    let label_dropped = matches!(
+                        &*drag_data.peek(), Some(GateDragType::Label(label_drag)) if label_drag.new_offset().is_some()
+                    );
+                    if label_dropped {
+                        drag_data.set(None);
+                        return;
+                    }
                     if let Some(mapper) = plot_map() {
                         let local_coords = &evt.data.coordinates().element();
                         let norm_x = local_coords.x as f32;
@@ -231,7 +287,7 @@ pub fn GateLayer(
                             let gate_id = closest_gate.get_id().clone();
                             gate_store.selected_gate().set(Some(gate_id.clone()));
                         } else if selected_gate_op.is_none() {
-                            if &PrimaryGateType::Polygon == current_gate_type {
+                            if &PrimaryGateType::Polygon == current_gate_type && !range_only {
                                 draft_gate_coords.write().push((data_x, data_y));
                             }
                         } else if drag_data.is_none() {
@@ -268,7 +324,9 @@ pub fn GateLayer(
                         }
                     };
 
-                    let geo = if let PrimaryGateType::Line(_) = &*current_gate_type.peek() {
+                    let geo = if range_only
+                        || matches!(&*current_gate_type.peek(), PrimaryGateType::Line(_))
+                    {
                         PrimaryGateType::Line(Some(dy))
                     } else {
                         current_gate_type.peek().cloned()
@@ -358,12 +416,12 @@ pub fn GateLayer(
                                             &map,
                                             &current_resolver_move,
                                         )
-                                        .expect("Gate Move Failed");
+                                        .unwrap_or_else(|e| println!("Gate move failed: {e}"));
                                 }
                                 GateDragType::Gate(gate_drag_data) => {
                                     gate_store
                                         .move_gate(gate_drag_data.clone(), &current_resolver_move)
-                                        .expect("Gate Move Failed");
+                                        .unwrap_or_else(|e| println!("Gate move failed: {e}"));
                                 }
                                 GateDragType::Rotation(rotation_data) => {
                                     gate_store
@@ -372,8 +430,10 @@ pub fn GateLayer(
                                             rotation_data.current_loc(),
                                             &current_resolver_move,
                                         )
-                                        .expect("Gate Move Failed");
+                                        .unwrap_or_else(|e| println!("Gate move failed: {e}"));
                                 }
+                                // the label follows the mouse, and is stored when dropped
+                                GateDragType::Label(_) => {}
                             }
                         }
                         drag_data.set(Some(new_data));
@@ -382,6 +442,27 @@ pub fn GateLayer(
                     }
                 },
                 onmouseup: move |evt| {
+                    if let Some(GateDragType::Label(label_drag)) = drag_data() {
+                        if let (Some(offset), Some(mapper)) = (
+                            label_drag.new_offset(),
+                            &*plot_map.peek(),
+                        ) {
+                            gate_store
+                                .move_gate_label(
+                                    label_drag.gate_id(),
+                                    label_drag.label_id(),
+                                    offset,
+                                    mapper,
+                                    &current_resolver_up,
+                                )
+                                .unwrap_or_else(|e| println!("Label move failed: {e}"));
+                        } else {
+                            // a press without a drag leaves the label where it was
+                            drag_data.set(None);
+                        }
+                        gate_store.end_gesture();
+                        return;
+                    }
                     if let Some(data) = drag_data() {
                         let local_coords = &evt.data.coordinates().element();
                         let px = local_coords.x as f32;
@@ -409,14 +490,14 @@ pub fn GateLayer(
                                             mapper,
                                             &current_resolver_up,
                                         )
-                                        .expect("Gate Move Failed");
+                                        .unwrap_or_else(|e| println!("Gate move failed: {e}"));
                                 }
 
                             }
                             GateDragType::Gate(gate_drag_data) => {
                                 gate_store
                                     .move_gate(gate_drag_data, &current_resolver_up)
-                                    .expect("Gate Move Failed");
+                                    .unwrap_or_else(|e| println!("Gate move failed: {e}"));
                             }
                             GateDragType::Rotation(rotation_data) => {
                                 gate_store
@@ -425,11 +506,13 @@ pub fn GateLayer(
                                         rotation_data.current_loc(),
                                         &current_resolver_up,
                                     )
-                                    .expect("Gate Move Failed");
+                                    .unwrap_or_else(|e| println!("Gate move failed: {e}"));
                             }
+                            GateDragType::Label(_) => {}
                         }
                     }
-
+                    // the whole drag undoes as one step
+                    gate_store.end_gesture();
                 },
                 onmousedown: move |evt| {
                     evt.stop_propagation();
@@ -469,6 +552,11 @@ pub fn GateLayer(
                                                 data_coords,
                                                 data_coords,
                                             );
+                                            let Some(resolver) = resolver.peek().clone() else { return };
+                                            if let Err(e) = gate_store.begin_edit(&cg.get_id(), &resolver) {
+                                                show_edit_error(status, e);
+                                                return;
+                                            }
                                             drag_data.set(Some(GateDragType::Gate(data)));
                                         } else {
                                             println!("{} {}", selected_gate_id.unwrap(), cg.get_id());
@@ -497,13 +585,20 @@ pub fn GateLayer(
                             false
                         };
 
-                        let dd = if let Some(dd) = &*drag_data.read() {
-                            if is_selected { Some(dd.clone()) } else { None }
-                        } else {
-                            None
+                        // any gate's label can be dragged, selected or not
+                        let dd = match &*drag_data.read() {
+                            Some(GateDragType::Label(label_drag)) if label_drag.gate_id() == gate.get_id() => {
+                                Some(GateDragType::Label(label_drag.clone()))
+                            }
+                            Some(dd) if is_selected => Some(dd.clone()),
+                            _ => None,
                         };
 
-                        let gate_stats = plot_store.gate_stats().get(gate.get_id()).map(|stats| stats());
+                        let label_text = plot_store
+                            .gate_stats()
+                            .get(gate.get_id())
+                            .map(|stats| gate_labels(&**gate, &stats(), &label_content.read()))
+                            .unwrap_or_default();
 
                         rsx! {
                             RenderGate {
@@ -512,12 +607,25 @@ pub fn GateLayer(
                                 is_selected,
                                 drag_data: dd,
                                 mapper: mapper.clone(),
-                                gate_stats,
+                                label_text,
                             }
                         }
                     }
                 }
                 // }
+                for (caption_index , region) in boolean_regions.read().iter().enumerate() {
+                    RenderBooleanRegion {
+                        key: "boolean-{region.gate_id}",
+                        region: region.clone(),
+                        caption_index,
+                        mapper: mapper.clone(),
+                        percent: population_counts
+                            .and_then(|counts| match counts.read().get(&region.gate_id) {
+                                Some(Ok(p)) => Some(p.percent_parent),
+                                _ => None,
+                            }),
+                    }
+                }
                 match draft_gate() {
                     Some(draft) => {
                         let id = "draft".to_string();
@@ -540,7 +648,7 @@ pub struct RenderGateProps {
     is_selected: bool,
     drag_data: Option<GateDragType>,
     mapper: Arc<PlotMapper>,
-    gate_stats: Option<GateStats>,
+    label_text: GateLabels,
 }
 
 impl PartialEq for RenderGateProps {
@@ -548,7 +656,7 @@ impl PartialEq for RenderGateProps {
         self.is_selected == other.is_selected
             && self.gate_index == other.gate_index
             && Arc::ptr_eq(&self.gate, &other.gate)
-            && self.gate_stats == other.gate_stats
+            && self.label_text == other.label_text
             && self.drag_data == other.drag_data
             && Arc::ptr_eq(&self.mapper, &other.mapper)
     }
@@ -575,7 +683,7 @@ fn RenderGate(props: RenderGateProps) -> Element {
 
     rsx! {
 
-        for (shape_index , shape) in g.draw_self(is_selected, drag_data.clone(), &props.mapper, &props.gate_stats)
+        for (shape_index , shape) in g.draw_self(is_selected, drag_data.clone(), &props.mapper, &props.label_text)
             .into_iter()
             .enumerate()
         {
@@ -592,6 +700,95 @@ fn RenderGate(props: RenderGateProps) -> Element {
     }
 }
 
+// a boolean gate's region, or a caption in the plot's corner when it can't be drawn here
+#[component]
+fn RenderBooleanRegion(
+    region: BooleanRegion,
+    // position among the plot's boolean gates - stacks the captions
+    caption_index: usize,
+    mapper: Arc<PlotMapper>,
+    percent: Option<f32>,
+) -> Element {
+    let style = &BOOLEAN_REGION;
+    let label = match percent {
+        Some(p) => format!("{} ({p:.1}%)", region.name),
+        None => region.name.clone(),
+    };
+    let x_range = mapper.x_axis_min_max();
+    let y_range = mapper.y_axis_min_max();
+
+    if let Some(caption) = &region.caption {
+        let (x, y) = mapper.data_to_pixel(*x_range.start(), *y_range.end(), None, None);
+        let y = y + 14.0 * (caption_index + 1) as f32;
+        return rsx! {
+            text {
+                x: x + 4.0,
+                y,
+                font_size: 11,
+                fill: style.stroke,
+                pointer_events: "none",
+                "{caption}"
+            }
+        };
+    }
+
+    let path = region
+        .outlines
+        .iter()
+        .map(|outline| {
+            let points = outline
+                .iter()
+                .map(|(x, y)| {
+                    let p = mapper.data_to_pixel(*x, *y, None, None);
+                    format!("{},{}", p.0, p.1)
+                })
+                .collect::<Vec<_>>()
+                .join(" L ");
+            format!("M {points} Z")
+        })
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    // over the topmost point of the region
+    let top = region
+        .outlines
+        .iter()
+        .flatten()
+        .map(|(x, y)| mapper.data_to_pixel(*x, *y, None, None))
+        .min_by(|a, b| a.1.total_cmp(&b.1));
+
+    rsx! {
+        g { pointer_events: "none",
+            path {
+                d: "{path}",
+                fill_rule: "evenodd",
+                stroke: style.stroke,
+                stroke_width: style.stroke_width,
+                stroke_dasharray: if style.dashed { "4" } else { "none" },
+                fill: style.fill,
+            }
+            if let Some((x, y)) = top {
+                text {
+                    x,
+                    y: y - 4.0,
+                    text_anchor: "middle",
+                    font_size: 12,
+                    fill: style.stroke,
+                    "{label}"
+                }
+            }
+        }
+    }
+}
+
+// a drag the edit mode won't allow is refused - say why where the user can see it
+fn show_edit_error(status: Option<StatusMessage>, e: anyhow::Error) {
+    match status {
+        Some(status) => status.show(e),
+        None => println!("{e}"),
+    }
+}
+
 fn was_gate_clicked(
     click_coords: (f32, f32),
     mapper: &PlotMapper,
@@ -639,6 +836,9 @@ fn RenderShape(
 ) -> Element {
     let plot_map = use_context::<Signal<Option<Arc<PlotMapper>>>>();
     let mut drag_data_signal = use_context::<Signal<Option<GateDragType>>>();
+    let mut gate_store = use_context::<SyncStore<GateState>>();
+    let resolver = use_context::<Signal<Option<Arc<GateOverrideResolver>>>>();
+    let status = try_use_context::<StatusMessage>();
     if let Some(mapper) = &*plot_map.read() {
         let transform = "none".to_string();
         match shape {
@@ -697,6 +897,11 @@ fn RenderShape(
                                                     .unwrap()
                                                     .pixel_to_data(px, py, None, None);
                                                 let point_drag_data = PointDragData::new(index, data_coords);
+                                                let Some(resolver) = resolver.peek().clone() else { return };
+                                                if let Err(e) = gate_store.begin_edit(&gate_id, &resolver) {
+                                                    show_edit_error(status, e);
+                                                    return;
+                                                }
                                                 drag_data_signal.set(Some(GateDragType::Point(point_drag_data)));
                                             }
                                             Some(dioxus_elements::input_data::MouseButton::Secondary) => {
@@ -843,6 +1048,11 @@ fn RenderShape(
                                 let data_coords = plot_map()
                                     .unwrap()
                                     .pixel_to_data(px, py, None, None);
+                                let Some(resolver) = resolver.peek().clone() else { return };
+                                if let Err(e) = gate_store.begin_edit(&gate_id, &resolver) {
+                                    show_edit_error(status, e);
+                                    return;
+                                }
                                 drag_data_signal
                                     .set(
                                         Some(
@@ -922,30 +1132,41 @@ fn RenderShape(
                 text_anchor,
                 shape_type,
             } => {
-                let transform = match shape_type {
-                    ShapeType::Text => transform,
-                    ShapeType::UndraggableText(direction) => {
-                        if let Some(GateDragType::Gate(data)) = drag_data {
-                            if *gate_id == *data.gate_id() {
-                                let offset = data.offset();
-                                let p_start = mapper.data_to_pixel(0.0, 0.0, None, None);
+                let ShapeType::Label(label_id, pinned) = shape_type else {
+                    unreachable!()
+                };
+                let transform = match (&drag_data, pinned) {
+                    // follows the mouse until it's dropped
+                    (Some(GateDragType::Label(data)), _) if data.label_id() == label_id => {
+                        match data.start_and_current_loc() {
+                            Some((start, current)) => {
+                                let p_start = mapper.data_to_pixel(start.0, start.1, None, None);
                                 let p_current =
-                                    mapper.data_to_pixel(offset.0, offset.1, None, None);
-                                let dx = p_current.0 - p_start.0;
-                                let dy = p_current.1 - p_start.1;
-                                match direction {
-                                    Direction::X => format!("translate({} {})", 0, -dy),
-                                    Direction::Y => format!("translate({} {})", -dx, 0),
-                                    Direction::Both => "none".to_string(),
-                                }
-                            } else {
-                                "none".to_string()
+                                    mapper.data_to_pixel(current.0, current.1, None, None);
+                                format!(
+                                    "translate({} {})",
+                                    p_current.0 - p_start.0,
+                                    p_current.1 - p_start.1
+                                )
                             }
-                        } else {
-                            "none".to_string()
+                            None => "none".to_string(),
+                        }
+                    }
+                    (Some(GateDragType::Gate(data)), Some(direction))
+                        if *gate_id == *data.gate_id() =>
+                    {
+                        let offset = data.offset();
+                        let p_start = mapper.data_to_pixel(0.0, 0.0, None, None);
+                        let p_current = mapper.data_to_pixel(offset.0, offset.1, None, None);
+                        let dx = p_current.0 - p_start.0;
+                        let dy = p_current.1 - p_start.1;
+                        match direction {
+                            Direction::X => format!("translate({} {})", 0, -dy),
+                            Direction::Y => format!("translate({} {})", -dx, 0),
+                            Direction::Both => "none".to_string(),
                         }
                     }
-                    _ => unreachable!(),
+                    _ => "none".to_string(),
                 };
 
                 let loc =
@@ -957,7 +1178,28 @@ fn RenderShape(
                             y: loc.1,
                             text_anchor,
                             font_size: fontsize,
-                            // pointer_events: "none",
+                            style: "cursor: move;",
+                            onmousedown: move |evt| {
+                                if !matches!(
+                                    evt.trigger_button(),
+                                    Some(dioxus_elements::input_data::MouseButton::Primary)
+                                ) {
+                                    return;
+                                }
+                                let Some(resolver) = resolver.peek().clone() else { return };
+                                if let Err(e) = gate_store.begin_edit(&gate_id, &resolver) {
+                                    show_edit_error(status, e);
+                                    return;
+                                }
+                                drag_data_signal
+                                    .set(
+                                        Some(
+                                            GateDragType::Label(
+                                                LabelDragData::new(gate_id.clone(), label_id.clone(), offset),
+                                            ),
+                                        ),
+                                    );
+                            },
                             "{text}"
                         }
                     }
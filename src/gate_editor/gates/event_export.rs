@@ -0,0 +1,790 @@
+use std::fs::File;
+use std::io::{BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use flow_fcs::Fcs;
+use polars::prelude::*;
+use rayon::prelude::*;
+use rustc_hash::FxBuildHasher;
+
+use crate::gate_editor::AxisInfo;
+use crate::gate_editor::gates::GateId;
+use crate::gate_editor::gates::batch_stats::{StatsFile, StatsGate};
+use crate::gate_editor::gates::gate_filtering::{
+    PopulationMasks, filter_events_by_hierarchy_to_mask,
+};
+use crate::gate_editor::gates::gate_hierarchy::GateHierarchy;
+use crate::gate_editor::plots::data_helpers::{raw_fcs_data, scale_fcs_data};
+use crate::omiq::metadata::{MetaDataFileMap, MetaDataParameter};
+
+// Writes one gate's events out of every sample - as one new FCS file per sample, or as a
+// single table for all of them.
+// Events are gated on the scaled (and compensated) data, like everywhere else, but FCS files
+// get the sample's own untransformed values - picked out by their `original_index`.
+
+const HEADER_LEN: usize = 58;
+// the largest offset the fixed width HEADER fields can hold
+const MAX_HEADER_OFFSET: usize = 99_999_999;
+const DELIMITER: u8 = b'|';
+const SAMPLE_COLUMN: &str = "sample";
+const EVENT_COLUMN: &str = "original_index";
+// describe the source file's DATA segment, so they're written fresh for the exported one
+const REWRITTEN_KEYWORDS: [&str; 12] = [
+    "$BEGINANALYSIS",
+    "$ENDANALYSIS",
+    "$BEGINDATA",
+    "$ENDDATA",
+    "$BEGINSTEXT",
+    "$ENDSTEXT",
+    "$NEXTDATA",
+    "$BYTEORD",
+    "$DATATYPE",
+    "$MODE",
+    "$TOT",
+    "$FIL",
+];
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExportedFile {
+    pub file_name: String,
+    pub path: PathBuf,
+    pub events: usize,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ExportSummary {
+    pub written: Vec<ExportedFile>,
+    pub errors: Vec<String>,
+}
+
+// writes `gate`'s events from each file into `out_dir` as FCS 3.1
+pub fn export_population_fcs(
+    files: &[StatsFile],
+    gate: &StatsGate,
+    hierarchy: &GateHierarchy,
+    axis_settings: &im::HashMap<Arc<str>, AxisInfo, FxBuildHasher>,
+    out_dir: &Path,
+) -> ExportSummary {
+    let per_file: Vec<anyhow::Result<ExportedFile>> = files
+        .par_iter()
+        .map(|file| {
+            export_file_fcs(file, gate, hierarchy, axis_settings, out_dir)
+                .map_err(|e| anyhow::anyhow!("{}: {}", file.name, e))
+        })
+        .collect();
+
+    let mut summary = ExportSummary::default();
+    for result in per_file {
+        match result {
+            Ok(f) => summary.written.push(f),
+            Err(e) => summary.errors.push(e.to_string()),
+        }
+    }
+    summary
+}
+
+fn export_file_fcs(
+    file: &StatsFile,
+    gate: &StatsGate,
+    hierarchy: &GateHierarchy,
+    axis_settings: &im::HashMap<Arc<str>, AxisInfo, FxBuildHasher>,
+    out_dir: &Path,
+) -> anyhow::Result<ExportedFile> {
+    let fcs = open_fcs(&file.path)?;
+    let events = gated_raw_events(&fcs, file, &gate.gate_id, hierarchy, axis_settings)?;
+
+    let source = read_text_keywords(&file.path)?;
+    let channels = parameter_names(&source)?;
+    let file_name = export_file_name(&file.path, &gate.path, "fcs");
+    let source_name = file
+        .path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_else(|| file.name.clone());
+    let provenance = [
+        ("CLINGATE_SOURCE".to_string(), source_name),
+        (
+            "CLINGATE_SOURCE_TOT".to_string(),
+            keyword(&source, "$TOT").unwrap_or_default().to_string(),
+        ),
+        ("CLINGATE_GATE".to_string(), gate.path.clone()),
+        ("CLINGATE_GATE_ID".to_string(), gate.gate_id.to_string()),
+    ];
+    let keywords = export_keywords(&source, events.height(), &file_name, &provenance);
+
+    let path = out_dir.join(&file_name);
+    let mut out = BufWriter::new(File::create(&path)?);
+    write_fcs(&mut out, &keywords, &events.select(channels)?)?;
+    out.flush()?;
+
+    Ok(ExportedFile {
+        file_name,
+        path,
+        events: events.height(),
+    })
+}
+
+// flow_fcs only takes a str path - a lossy or empty stand-in would read some other file
+fn open_fcs(path: &Path) -> anyhow::Result<Fcs> {
+    let path = path
+        .to_str()
+        .ok_or_else(|| anyhow::anyhow!("{} is not a valid UTF-8 path", path.display()))?;
+    Ok(Fcs::open(path)?)
+}
+
+// the file's untransformed, uncompensated events that fall in `gate_id`
+pub fn gated_raw_events(
+    fcs: &Fcs,
+    file: &StatsFile,
+    gate_id: &GateId,
+    hierarchy: &GateHierarchy,
+    axis_settings: &im::HashMap<Arc<str>, AxisInfo, FxBuildHasher>,
+) -> anyhow::Result<DataFrame> {
+    let scaled = scale_fcs_data(fcs, file.compensation.as_deref(), axis_settings)?
+        .with_row_index(EVENT_COLUMN.into(), None)?;
+    let mask = filter_events_by_hierarchy_to_mask(&scaled, gate_id, hierarchy, &file.resolver)?;
+    let gated = scaled.filter(&mask)?;
+    let indices = gated.column(EVENT_COLUMN)?.u32()?;
+    Ok(raw_fcs_data(fcs, None)?.take(indices)?)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EventScale {
+    Raw,
+    // raw values through the sample's compensation matrix, if it has one
+    Compensated,
+    // compensated and on the axis transforms - what the gates see
+    Transformed,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TableFormat {
+    Csv,
+    Parquet,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TableOptions {
+    pub format: TableFormat,
+    pub scale: EventScale,
+    pub channels: Vec<Arc<str>>,
+    // one string column per parameter, null for samples without a group
+    pub metadata_columns: Vec<MetaDataParameter>,
+    // one boolean column per gate, named by its path
+    pub membership_gates: Vec<StatsGate>,
+}
+
+// every file's events in `gate` as rows of one table - sample, event index, metadata,
+// channels then gate memberships
+pub struct TableExport<'a> {
+    pub files: &'a [StatsFile],
+    pub gate: &'a StatsGate,
+    pub hierarchy: &'a GateHierarchy,
+    pub axis_settings: &'a im::HashMap<Arc<str>, AxisInfo, FxBuildHasher>,
+    pub metadata: &'a MetaDataFileMap,
+    pub options: &'a TableOptions,
+}
+
+impl TableExport<'_> {
+    pub fn write(&self, path: &Path) -> anyhow::Result<ExportSummary> {
+        match self.options.format {
+            TableFormat::Csv => {
+                let mut out = BufWriter::new(File::create(path)?);
+                let mut include_header = true;
+                let summary = self.for_each_file(path, |mut table| {
+                    CsvWriter::new(&mut out)
+                        .include_header(include_header)
+                        .finish(&mut table)?;
+                    include_header = false;
+                    Ok(())
+                })?;
+                out.flush()?;
+                Ok(summary)
+            }
+            TableFormat::Parquet => {
+                // every file's table has the same schema, so the first one sets it
+                let mut writer = None;
+                let summary = self.for_each_file(path, |table| {
+                    if writer.is_none() {
+                        writer = Some(
+                            ParquetWriter::new(File::create(path)?)
+                                .batched(table.schema().as_ref())?,
+                        );
+                    }
+                    if let Some(w) = writer.as_mut() {
+                        w.write_batch(&table)?;
+                    }
+                    Ok(())
+                })?;
+                if let Some(w) = writer {
+                    w.finish()?;
+                }
+                Ok(summary)
+            }
+        }
+    }
+
+    // Gates one file at a time and hands its table over before reading the next, so only one
+    // sample's events are held however many are exported. If no file can be read the output
+    // still gets the table's columns, and the summary says it has no events.
+    fn for_each_file(
+        &self,
+        path: &Path,
+        mut write: impl FnMut(DataFrame) -> anyhow::Result<()>,
+    ) -> anyhow::Result<ExportSummary> {
+        let mut summary = ExportSummary::default();
+        for file in self.files {
+            match self.file_table(file) {
+                Ok(table) => {
+                    summary.written.push(ExportedFile {
+                        file_name: file.name.clone(),
+                        path: path.to_path_buf(),
+                        events: table.height(),
+                    });
+                    write(table)?;
+                }
+                Err(e) => summary.errors.push(format!("{}: {}", file.name, e)),
+            }
+        }
+        if summary.written.is_empty() {
+            write(empty_table(
+                &self.options.metadata_columns,
+                &self.options.channels,
+                &self.options.membership_gates,
+            ))?;
+            summary.errors.push(format!(
+                "No sample could be exported - {} only has the column headers",
+                path.display()
+            ));
+        }
+        Ok(summary)
+    }
+
+    fn file_table(&self, file: &StatsFile) -> anyhow::Result<DataFrame> {
+        let fcs = open_fcs(&file.path)?;
+        let scaled = scale_fcs_data(&fcs, file.compensation.as_deref(), self.axis_settings)?;
+        let values = match self.options.scale {
+            EventScale::Raw => raw_fcs_data(&fcs, None)?,
+            EventScale::Compensated => raw_fcs_data(&fcs, file.compensation.as_deref())?,
+            EventScale::Transformed => scaled.clone(),
+        };
+
+        let mut populations = PopulationMasks::new(&scaled, self.hierarchy, &file.resolver);
+        let mut table = values.with_row_index(EVENT_COLUMN.into(), None)?;
+        for gate in &self.options.membership_gates {
+            let member = populations
+                .population(&gate.gate_id)?
+                .with_name(gate.path.as_str().into());
+            table.with_column(member.into_series())?;
+        }
+        let keep = populations.population(&self.gate.gate_id)?;
+
+        let groups = self.metadata.get(&file.file_id);
+        let metadata: Vec<(MetaDataParameter, Option<Arc<str>>)> = self
+            .options
+            .metadata_columns
+            .iter()
+            .map(|p| (p.clone(), groups.and_then(|g| g.get(p)).cloned()))
+            .collect();
+        shape_table(
+            table.filter(&keep)?,
+            &file.name,
+            &metadata,
+            &self.options.channels,
+            &self.options.membership_gates,
+        )
+    }
+}
+
+// the export's columns, in order, with the same types for every file
+fn shape_table(
+    events: DataFrame,
+    sample: &str,
+    metadata: &[(MetaDataParameter, Option<Arc<str>>)],
+    channels: &[Arc<str>],
+    membership_gates: &[StatsGate],
+) -> anyhow::Result<DataFrame> {
+    let mut literals = vec![lit(sample).alias(SAMPLE_COLUMN)];
+    for (parameter, group) in metadata {
+        let value = match group {
+            Some(g) => lit(g.as_ref()),
+            None => lit(NULL).cast(DataType::String),
+        };
+        literals.push(value.alias(parameter.as_ref()));
+    }
+
+    let mut columns = vec![col(SAMPLE_COLUMN), col(EVENT_COLUMN)];
+    columns.extend(metadata.iter().map(|(p, _)| col(p.as_ref())));
+    columns.extend(
+        channels
+            .iter()
+            .map(|c| col(c.as_ref()).cast(DataType::Float32)),
+    );
+    columns.extend(membership_gates.iter().map(|g| col(g.path.as_str())));
+
+    Ok(events
+        .lazy()
+        .with_columns(literals)
+        .select(columns)
+        .collect()?)
+}
+
+// shape_table's columns without any rows
+fn empty_table(
+    metadata_columns: &[MetaDataParameter],
+    channels: &[Arc<str>],
+    membership_gates: &[StatsGate],
+) -> DataFrame {
+    let mut fields = vec![
+        Field::new(SAMPLE_COLUMN.into(), DataType::String),
+        Field::new(EVENT_COLUMN.into(), IDX_DTYPE),
+    ];
+    fields.extend(
+        metadata_columns
+            .iter()
+            .map(|p| Field::new(p.as_ref().into(), DataType::String)),
+    );
+    fields.extend(
+        channels
+            .iter()
+            .map(|c| Field::new(c.as_ref().into(), DataType::Float32)),
+    );
+    fields.extend(
+        membership_gates
+            .iter()
+            .map(|g| Field::new(g.path.as_str().into(), DataType::Boolean)),
+    );
+    DataFrame::empty_with_schema(&Schema::from_iter(fields))
+}
+
+// eg "sample_01_Lymphs_CD3+.fcs" - the gate path flattened into something a file system takes
+pub fn export_file_name(source: &Path, gate_path: &str, extension: &str) -> String {
+    let stem = source
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let gate: String = gate_path
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || matches!(c, '-' | '+' | '.') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    format!("{stem}_{gate}.{extension}")
+}
+
+// the source keywords, minus those describing its DATA segment, plus the exported data's
+// layout and any provenance keywords
+pub fn export_keywords(
+    source: &[(String, String)],
+    events: usize,
+    file_name: &str,
+    provenance: &[(String, String)],
+) -> Vec<(String, String)> {
+    let mut keywords: Vec<(String, String)> = source
+        .iter()
+        .filter(|(k, _)| !REWRITTEN_KEYWORDS.contains(&k.as_str()) && !is_parameter_format(k))
+        .cloned()
+        .collect();
+    for (key, value) in [
+        ("$BYTEORD", "1,2,3,4".to_string()),
+        ("$DATATYPE", "F".to_string()),
+        ("$MODE", "L".to_string()),
+        ("$TOT", events.to_string()),
+        ("$FIL", file_name.to_string()),
+    ] {
+        keywords.push((key.to_string(), value));
+    }
+    let parameters = keyword(source, "$PAR")
+        .and_then(|p| p.trim().parse::<usize>().ok())
+        .unwrap_or_default();
+    for n in 1..=parameters {
+        keywords.push((format!("$P{n}B"), "32".to_string()));
+        keywords.push((format!("$P{n}E"), "0,0".to_string()));
+    }
+    keywords.extend(provenance.iter().cloned());
+    keywords
+}
+
+// $PnB, $PnE and $PnDATATYPE describe how the source stored a parameter, not what it measured
+fn is_parameter_format(key: &str) -> bool {
+    let Some(rest) = key.strip_prefix("$P") else {
+        return false;
+    };
+    let digits = rest.chars().take_while(|c| c.is_ascii_digit()).count();
+    digits > 0 && matches!(&rest[digits..], "B" | "E" | "DATATYPE")
+}
+
+pub fn keyword<'a>(keywords: &'a [(String, String)], key: &str) -> Option<&'a str> {
+    keywords
+        .iter()
+        .find(|(k, _)| k == key)
+        .map(|(_, v)| v.as_str())
+}
+
+// $P1N..$PnN, the DATA segment's column order
+pub fn parameter_names(keywords: &[(String, String)]) -> anyhow::Result<Vec<String>> {
+    let parameters: usize = keyword(keywords, "$PAR")
+        .ok_or_else(|| anyhow::anyhow!("No $PAR keyword"))?
+        .trim()
+        .parse()?;
+    (1..=parameters)
+        .map(|n| {
+            keyword(keywords, &format!("$P{n}N"))
+                .map(|name| name.to_string())
+                .ok_or_else(|| anyhow::anyhow!("No $P{n}N keyword"))
+        })
+        .collect()
+}
+
+// every keyword in the primary TEXT segment, upper-cased, in file order. Read straight from
+// the file so nothing flow_fcs doesn't know about is lost.
+pub fn read_text_keywords(path: &Path) -> anyhow::Result<Vec<(String, String)>> {
+    let mut file = File::open(path)?;
+    let mut header = [0u8; HEADER_LEN];
+    file.read_exact(&mut header)?;
+    let (start, end) = text_bounds(&header)?;
+    let mut text = vec![0u8; end + 1 - start];
+    file.seek(SeekFrom::Start(start as u64))?;
+    file.read_exact(&mut text)?;
+    parse_text_segment(&text)
+}
+
+fn text_bounds(header: &[u8]) -> anyhow::Result<(usize, usize)> {
+    if header.len() < HEADER_LEN || !header.starts_with(b"FCS") {
+        return Err(anyhow::anyhow!("Not an FCS file"));
+    }
+    let start = header_offset(header, 0)?;
+    let end = header_offset(header, 1)?;
+    if end < start {
+        return Err(anyhow::anyhow!("Invalid TEXT segment offsets"));
+    }
+    Ok((start, end))
+}
+
+// the HEADER's offsets are 8 byte, space padded ascii fields after the version
+fn header_offset(header: &[u8], field: usize) -> anyhow::Result<usize> {
+    let start = 10 + field * 8;
+    Ok(std::str::from_utf8(&header[start..start + 8])?
+        .trim()
+        .parse()?)
+}
+
+pub fn parse_text_segment(text: &[u8]) -> anyhow::Result<Vec<(String, String)>> {
+    let Some(&delimiter) = text.first() else {
+        return Err(anyhow::anyhow!("Empty TEXT segment"));
+    };
+    // a doubled delimiter is a literal one inside a keyword or value
+    let mut tokens = vec![];
+    let mut current = vec![];
+    let mut i = 1;
+    while i < text.len() {
+        if text[i] == delimiter {
+            if text.get(i + 1) == Some(&delimiter) {
+                current.push(delimiter);
+                i += 2;
+                continue;
+            }
+            tokens.push(String::from_utf8_lossy(&current).to_string());
+            current.clear();
+        } else {
+            current.push(text[i]);
+        }
+        i += 1;
+    }
+    if !current.is_empty() {
+        tokens.push(String::from_utf8_lossy(&current).to_string());
+    }
+    Ok(tokens
+        .chunks_exact(2)
+        .map(|pair| (pair[0].trim().to_uppercase(), pair[1].clone()))
+        .collect())
+}
+
+fn text_segment(keywords: &[(String, String)]) -> Vec<u8> {
+    let mut text = vec![DELIMITER];
+    // keywords can't have empty values, and an empty one would read as an escaped delimiter
+    for (key, value) in keywords.iter().filter(|(_, v)| !v.is_empty()) {
+        for part in [key, value] {
+            for &b in part.as_bytes() {
+                text.push(b);
+                if b == DELIMITER {
+                    text.push(DELIMITER);
+                }
+            }
+            text.push(DELIMITER);
+        }
+    }
+    text
+}
+
+// an FCS 3.1 file: HEADER, TEXT, then `data`'s columns as little endian f32 in list mode.
+// `keywords` must already describe the data, the segment offsets are added here.
+pub fn write_fcs<W: Write>(
+    out: &mut W,
+    keywords: &[(String, String)],
+    data: &DataFrame,
+) -> anyhow::Result<()> {
+    let columns = data
+        .get_columns()
+        .iter()
+        .map(|c| -> anyhow::Result<Vec<f32>> {
+            Ok(c.cast(&DataType::Float32)?
+                .f32()?
+                .into_iter()
+                .map(|v| v.unwrap_or(f32::NAN))
+                .collect())
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let data_len = data.height() * columns.len() * 4;
+
+    // the TEXT segment holds the DATA offsets, which depend on the TEXT segment's length -
+    // grow the offsets until they agree
+    let mut data_start = 0;
+    let text = loop {
+        let data_end = (data_start + data_len).saturating_sub(1);
+        let mut all: Vec<(String, String)> = [
+            ("$BEGINANALYSIS", 0),
+            ("$ENDANALYSIS", 0),
+            ("$BEGINSTEXT", 0),
+            ("$ENDSTEXT", 0),
+            ("$BEGINDATA", data_start),
+            ("$ENDDATA", data_end),
+            ("$NEXTDATA", 0),
+        ]
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+        all.extend(keywords.iter().cloned());
+        let text = text_segment(&all);
+        if HEADER_LEN + text.len() == data_start {
+            break text;
+        }
+        data_start = HEADER_LEN + text.len();
+    };
+    let text_end = HEADER_LEN + text.len() - 1;
+    let data_end = (data_start + data_len).saturating_sub(1);
+    // offsets too big for the HEADER are only in the TEXT segment
+    let (header_start, header_end) = if data_end > MAX_HEADER_OFFSET {
+        (0, 0)
+    } else {
+        (data_start, data_end)
+    };
+
+    write!(
+        out,
+        "FCS3.1    {:>8}{:>8}{:>8}{:>8}{:>8}{:>8}",
+        HEADER_LEN, text_end, header_start, header_end, 0, 0
+    )?;
+    out.write_all(&text)?;
+    for row in 0..data.height() {
+        for column in &columns {
+            out.write_all(&column[row].to_le_bytes())?;
+        }
+    }
+    // no CRC
+    out.write_all(b"00000000")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kw(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_text_segment_round_trip() {
+        let keywords = kw(&[("$FIL", "a|b.fcs"), ("$PAR", "2"), ("EMPTY", "")]);
+        let text = text_segment(&keywords);
+        assert_eq!(text, b"|$FIL|a||b.fcs|$PAR|2|".to_vec());
+        assert_eq!(parse_text_segment(&text).unwrap(), keywords[..2].to_vec());
+        // keywords are case insensitive, and other delimiters are fine
+        assert_eq!(
+            parse_text_segment(b"/$tot/10/").unwrap(),
+            kw(&[("$TOT", "10")])
+        );
+    }
+
+    #[test]
+    fn test_export_keywords() {
+        let source = kw(&[
+            ("$PAR", "2"),
+            ("$P1N", "FSC-A"),
+            ("$P1B", "16"),
+            ("$P1E", "0,0"),
+            ("$P1R", "1024"),
+            ("$P2N", "CD3"),
+            ("$P2B", "16"),
+            ("$DATATYPE", "I"),
+            ("$TOT", "1000"),
+            ("$BEGINDATA", "999"),
+            ("$CYT", "Aurora"),
+        ]);
+        let keywords = export_keywords(&source, 12, "out.fcs", &kw(&[("CLINGATE_GATE", "A/B")]));
+        let count = |key: &str| keywords.iter().filter(|(k, _)| k == key).count();
+        assert_eq!(keyword(&keywords, "$TOT"), Some("12"));
+        assert_eq!(keyword(&keywords, "$DATATYPE"), Some("F"));
+        assert_eq!(keyword(&keywords, "$FIL"), Some("out.fcs"));
+        assert_eq!(keyword(&keywords, "$P2B"), Some("32"));
+        assert_eq!(keyword(&keywords, "$P1R"), Some("1024"));
+        assert_eq!(keyword(&keywords, "$CYT"), Some("Aurora"));
+        assert_eq!(keyword(&keywords, "CLINGATE_GATE"), Some("A/B"));
+        assert_eq!(count("$P1B"), 1);
+        assert_eq!(count("$TOT"), 1);
+        // written with the file's offsets
+        assert_eq!(count("$BEGINDATA"), 0);
+        assert_eq!(
+            parameter_names(&keywords).unwrap(),
+            vec!["FSC-A".to_string(), "CD3".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_write_fcs_layout() {
+        let data = df![
+            "FSC-A" => [1.0f32, 2.0, 3.0],
+            "CD3" => [-0.5f32, 10.0, 100.0],
+        ]
+        .unwrap();
+        let source = kw(&[("$PAR", "2"), ("$P1N", "FSC-A"), ("$P2N", "CD3")]);
+        let keywords = export_keywords(&source, data.height(), "out.fcs", &[]);
+        let mut bytes = vec![];
+        write_fcs(&mut bytes, &keywords, &data).unwrap();
+
+        assert!(bytes.starts_with(b"FCS3.1"));
+        let (start, end) = text_bounds(&bytes).unwrap();
+        assert_eq!(start, HEADER_LEN);
+        let written = parse_text_segment(&bytes[start..=end]).unwrap();
+        assert_eq!(keyword(&written, "$TOT"), Some("3"));
+
+        // the header and TEXT agree on where the data is
+        let data_start = header_offset(&bytes, 2).unwrap();
+        let data_end = header_offset(&bytes, 3).unwrap();
+        assert_eq!(data_start, end + 1);
+        assert_eq!(
+            keyword(&written, "$BEGINDATA"),
+            Some(&*data_start.to_string())
+        );
+        assert_eq!(keyword(&written, "$ENDDATA"), Some(&*data_end.to_string()));
+        assert_eq!(data_end + 1 - data_start, 3 * 2 * 4);
+
+        // events are rows, parameters in $PnN order
+        let values: Vec<f32> = bytes[data_start..=data_end]
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes(b.try_into().unwrap()))
+            .collect();
+        assert_eq!(values, vec![1.0, -0.5, 2.0, 10.0, 3.0, 100.0]);
+        assert_eq!(&bytes[data_end + 1..], b"00000000");
+    }
+
+    #[test]
+    fn test_shape_table() {
+        let events = df![
+            "original_index" => [3u32, 7],
+            "X" => [1.0f64, 2.0],
+            "Y" => [5.0f32, 6.0],
+            "A/B" => [true, false],
+        ]
+        .unwrap();
+        let gate = StatsGate {
+            gate_id: Arc::from("b"),
+            parent_id: Some(Arc::from("a")),
+            path: "A/B".to_string(),
+        };
+        let metadata = vec![
+            (Arc::from("Condition"), Some(Arc::from("Stim"))),
+            (Arc::from("Donor"), None),
+        ];
+        let table = shape_table(
+            events,
+            "s1.fcs",
+            &metadata,
+            &[Arc::from("Y"), Arc::from("X")],
+            &[gate],
+        )
+        .unwrap();
+
+        let names: Vec<&str> = table
+            .get_column_names()
+            .iter()
+            .map(|n| n.as_str())
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                "sample",
+                "original_index",
+                "Condition",
+                "Donor",
+                "Y",
+                "X",
+                "A/B"
+            ]
+        );
+        assert_eq!(
+            table.column("sample").unwrap().str().unwrap().get(1),
+            Some("s1.fcs")
+        );
+        assert_eq!(
+            table.column("Condition").unwrap().str().unwrap().get(0),
+            Some("Stim")
+        );
+        // a sample without a group still has the column, so every file's schema matches
+        assert_eq!(table.column("Donor").unwrap().dtype(), &DataType::String);
+        assert_eq!(table.column("Donor").unwrap().null_count(), 2);
+        assert_eq!(table.column("X").unwrap().dtype(), &DataType::Float32);
+        assert_eq!(
+            table
+                .column("original_index")
+                .unwrap()
+                .u32()
+                .unwrap()
+                .get(1),
+            Some(7)
+        );
+
+        // what's written when no sample could be read has the same columns
+        let empty = empty_table(
+            &[Arc::from("Condition"), Arc::from("Donor")],
+            &[Arc::from("Y"), Arc::from("X")],
+            &[StatsGate {
+                gate_id: Arc::from("b"),
+                parent_id: Some(Arc::from("a")),
+                path: "A/B".to_string(),
+            }],
+        );
+        assert_eq!(empty.height(), 0);
+        assert_eq!(empty.schema(), table.schema());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_open_fcs_refuses_non_utf8_paths() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let path = Path::new(std::ffi::OsStr::from_bytes(b"/data/sample_\xff.fcs"));
+        let error = open_fcs(path).err().expect("opened a non UTF-8 path");
+        assert!(
+            error.to_string().contains("not a valid UTF-8 path"),
+            "{error}"
+        );
+    }
+
+    #[test]
+    fn test_export_file_name() {
+        assert_eq!(
+            export_file_name(Path::new("/data/sample 01.fcs"), "Lymphs/CD3+", "fcs"),
+            "sample 01_Lymphs_CD3+.fcs"
+        );
+    }
+}
@@ -1,6 +1,9 @@
 use dioxus::prelude::*;
 
-use crate::gate_editor::gates::gate_types::PrimaryGateType;
+use crate::gate_editor::gates::{
+    gate_label::GateLabelContent,
+    gate_types::{GateText, PrimaryGateType},
+};
 
 const GATE_CONFIG: &[(PrimaryGateType, &str)] = &[
     (PrimaryGateType::Polygon, "P"),
@@ -12,6 +15,12 @@ const GATE_CONFIG: &[(PrimaryGateType, &str)] = &[
     (PrimaryGateType::SkewedQuadrant, "S"),
 ];
 
+const LABEL_CONFIG: &[(GateText, &str)] = &[
+    (GateText::Name, "Name"),
+    (GateText::Percent, "%"),
+    (GateText::Count, "Count"),
+];
+
 #[component]
 pub fn NewGateButtons(callback: EventHandler<PrimaryGateType>) -> Element {
     let mut selected_index = use_signal(|| 0);
@@ -32,3 +41,21 @@ pub fn NewGateButtons(callback: EventHandler<PrimaryGateType>) -> Element {
 
     }
 }
+
+// switches each part of the gate labels on or off
+#[component]
+pub fn GateLabelButtons() -> Element {
+    let mut label_content = use_context::<Signal<GateLabelContent>>();
+    let selected_style = "background-color: orange";
+
+    rsx! {
+        for (part , d_text) in LABEL_CONFIG.iter().copied() {
+            button {
+                style: if label_content.read().shows(part) { Some(selected_style) } else { None },
+                title: "Show on gate labels",
+                onclick: move |_| label_content.write().toggle(part),
+                "{d_text}"
+            }
+        }
+    }
+}
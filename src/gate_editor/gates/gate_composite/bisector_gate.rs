@@ -1,6 +1,7 @@
 use flow_fcs::TransformType;
 
-use flow_gates::{Gate, GateGeometry};
+use flow_gates::{Gate, GateGeometry, types::LabelPosition};
+use rustc_hash::FxHashMap;
 use std::{ops::Index, sync::Arc};
 
 use crate::{
@@ -8,11 +9,11 @@ use crate::{
     gate_editor::{
         gates::{
             gate_drag::PointDragData,
+            gate_label::{GateLabels, label_offset, label_shape},
             gate_single::{line_gate::LineGate, rescale_helper_point},
-            gate_traits::DrawableGate,
+            gate_traits::{DrawableGate, new_gate_id},
             gate_types::{
-                self, DEFAULT_LINE, GREY_LINE_DASHED, GateRenderShape, GateStats, SELECTED_LINE,
-                ShapeType,
+                self, DEFAULT_LINE, GREY_LINE_DASHED, GateRenderShape, SELECTED_LINE, ShapeType,
             },
         },
         plots::axis_store::PlotMapper,
@@ -254,7 +255,7 @@ impl super::super::gate_traits::DrawableGate for BisectorGate {
         is_selected: bool,
         drag_point: Option<PointDragData>,
         plot_map: &PlotMapper,
-        gate_stats: &Option<GateStats>,
+        label_text: &GateLabels,
     ) -> Vec<GateRenderShape> {
         let (min, max) = {
             let (xmin, xmax) = {
@@ -366,74 +367,69 @@ impl super::super::gate_traits::DrawableGate for BisectorGate {
             None
         };
         let mut labels = vec![];
-        if let Some(gate_stats) = gate_stats {
-            let x_axis_min_max = plot_map.x_axis_min_max();
-            let y_axis_min_max = plot_map.y_axis_min_max();
-            let x_axis_offset = ((x_axis_min_max.end() - x_axis_min_max.start()) / 100f32) * 1f32;
-            let y_axis_offset = ((y_axis_min_max.end() - y_axis_min_max.start()) / 100f32) * 1f32;
-            for (i, (id, _)) in self.gates.iter().enumerate() {
-                if let Some(percent) = gate_stats.get_percent_for_id(id.clone()) {
-                    let text = format!("{:.2}%", percent);
-                    let (origin, offset, text_anchor) = if i == 0 {
-                        // LEFT LABEL
-                        if self.axis_matched {
-                            (
-                                (
-                                    *x_axis_min_max.start() + x_axis_offset,
-                                    self.points.1 + y_axis_offset,
-                                ),
-                                (0.0, 0.0),
-                                Some(String::from("start")),
-                            )
-                        } else {
-                            // BOTTOM LABEL
-                            (
-                                (
-                                    self.points.0 + x_axis_offset,
-                                    *y_axis_min_max.start() + y_axis_offset,
-                                ),
-                                (0.0, 0.0),
-                                None,
-                            )
-                        }
-                    } else {
-                        // RIGHT LABEL
-                        if self.axis_matched {
-                            (
-                                (
-                                    *x_axis_min_max.end() - y_axis_offset,
-                                    self.points.1 + y_axis_offset,
-                                ),
-                                (0.0, 0.0),
-                                Some(String::from("end")),
-                            )
-                        } else {
-                            // TOP LABEL
-                            (
-                                (
-                                    self.points.0 + x_axis_offset,
-                                    *y_axis_min_max.end() - 2f32 * y_axis_offset,
-                                ),
-                                (0.0, 0.0),
-                                None,
-                            )
-                        }
-                    };
-                    let shape = GateRenderShape::Text {
-                        origin,
-                        offset,
-                        fontsize: 10f32,
-                        text,
-                        text_anchor,
-                        shape_type: if self.axis_matched {
-                            ShapeType::UndraggableText(gate_types::Direction::X)
-                        } else {
-                            ShapeType::UndraggableText(gate_types::Direction::Y)
-                        },
-                    };
-                    labels.push(shape)
+        let x_axis_min_max = plot_map.x_axis_min_max();
+        let y_axis_min_max = plot_map.y_axis_min_max();
+        let x_axis_offset = ((x_axis_min_max.end() - x_axis_min_max.start()) / 100f32) * 1f32;
+        let y_axis_offset = ((y_axis_min_max.end() - y_axis_min_max.start()) / 100f32) * 1f32;
+        for (i, (id, subgate)) in self.gates.iter().enumerate() {
+            let Some(text) = label_text.get(id) else {
+                continue;
+            };
+            let (origin, text_anchor) = if i == 0 {
+                // LEFT LABEL
+                if self.axis_matched {
+                    (
+                        (
+                            *x_axis_min_max.start() + x_axis_offset,
+                            self.points.1 + y_axis_offset,
+                        ),
+                        Some(String::from("start")),
+                    )
+                } else {
+                    // BOTTOM LABEL
+                    (
+                        (
+                            self.points.0 + x_axis_offset,
+                            *y_axis_min_max.start() + y_axis_offset,
+                        ),
+                        None,
+                    )
                 }
-            }
+            } else {
+                // RIGHT LABEL
+                if self.axis_matched {
+                    (
+                        (
+                            *x_axis_min_max.end() - y_axis_offset,
+                            self.points.1 + y_axis_offset,
+                        ),
+                        Some(String::from("end")),
+                    )
+                } else {
+                    // TOP LABEL
+                    (
+                        (
+                            self.points.0 + x_axis_offset,
+                            *y_axis_min_max.end() - 2f32 * y_axis_offset,
+                        ),
+                        None,
+                    )
+                }
+            };
+            let pinned = if self.axis_matched {
+                gate_types::Direction::X
+            } else {
+                gate_types::Direction::Y
+            };
+            labels.push(label_shape(
+                id.clone(),
+                text.clone(),
+                origin,
+                label_offset(subgate.label_position(), (0.0, 0.0), plot_map),
+                text_anchor,
+                Some(pinned),
+                plot_map,
+            ));
         }
 
         let labels = Some(labels);
@@ -573,6 +569,51 @@ impl super::super::gate_traits::DrawableGate for BisectorGate {
         Ok(Some(Box::new(new_self)))
     }
 
+    // the composite itself, or one of its subgates
+    fn rename(&self, id: &str, name: &str) -> anyhow::Result<Box<dyn super::super::gate_traits::DrawableGate>> {
+        let mut new_self = self.clone();
+        if *self.id == *id {
+            new_self.name = name.to_string();
+        } else {
+            let subgate = new_self.gates.get_mut(id).ok_or_else(|| {
+                anyhow::anyhow!("Gate {} is not part of {}", id, self.name)
+            })?;
+            *subgate = subgate.with_name(name);
+        }
+        Ok(Box::new(new_self))
+    }
+
+    fn with_label_position(
+        &self,
+        id: &str,
+        position: LabelPosition,
+    ) -> anyhow::Result<Box<dyn super::super::gate_traits::DrawableGate>> {
+        let mut new_self = self.clone();
+        let subgate = new_self
+            .gates
+            .get_mut(id)
+            .ok_or_else(|| anyhow::anyhow!("Gate {} is not part of {}", id, self.name))?;
+        *subgate = subgate.with_label_position(position);
+        Ok(Box::new(new_self))
+    }
+
+    fn with_new_ids(
+        &self,
+        ids: &FxHashMap<Arc<str>, Arc<str>>,
+    ) -> anyhow::Result<Box<dyn super::super::gate_traits::DrawableGate>> {
+        let mut new_self = self.clone();
+        new_self.id = new_gate_id(ids, &self.id)?;
+        new_self.gates = self
+            .gates
+            .iter()
+            .map(|(id, gate)| {
+                let new_id = new_gate_id(ids, id)?;
+                Ok((new_id.clone(), gate.with_id(new_id)))
+            })
+            .collect::<anyhow::Result<_>>()?;
+        Ok(Box::new(new_self))
+    }
+
     fn clone_box(&self) -> Box<dyn super::super::gate_traits::DrawableGate> {
         Box::new(self.clone())
     }
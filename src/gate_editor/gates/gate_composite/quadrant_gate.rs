@@ -2,19 +2,22 @@ use flow_fcs::TransformType;
 
 use crate::gate_editor::{
     gates::{
-        gate_composite::skewed_quadrant_gate::{DataPoints, create_skewed_quadrant_geos, get_infinite_bounds},
+        gate_composite::skewed_quadrant_gate::{
+            DataPoints, create_skewed_quadrant_geos, get_infinite_bounds, quadrant_labels,
+        },
         gate_drag::{GateDragData, PointDragData},
+        gate_label::GateLabels,
         gate_single::{polygon_gate::PolygonGate, rescale_helper_point},
-        gate_traits::DrawableGate,
-        gate_types::{self, DEFAULT_LINE, GateRenderShape, GateStats, SELECTED_LINE, ShapeType},
+        gate_traits::{DrawableGate, new_gate_id},
+        gate_types::{DEFAULT_LINE, GateRenderShape, SELECTED_LINE, ShapeType},
     },
     plots::axis_store::PlotMapper,
 };
 use anyhow::Result;
-use flow_gates::Gate;
+use flow_gates::{Gate, types::LabelPosition};
 
 use indexmap::IndexMap;
-use rustc_hash::FxBuildHasher;
+use rustc_hash::{FxBuildHasher, FxHashMap};
 use std::{sync::Arc};
 type FxIndexMap<K, V> = IndexMap<K, V, FxBuildHasher>;
 
@@ -145,7 +148,7 @@ impl QuadrantGate {
             it.next().ok_or_else(|| anyhow::anyhow!("Missing gate 3"))?,
             it.next().ok_or_else(|| anyhow::anyhow!("Missing gate 4"))?,
         );
-        Self::try_new_from_data_points(
+        let mut moved = Self::try_new_from_data_points(
             self.id.clone(),
             self.name.clone(),
             data_points,
@@ -155,7 +158,14 @@ impl QuadrantGate {
             Some(gate_ids),
             Some(gate_names),
             if infs.is_some() {infs.unwrap()} else {self.infs}
-        )
+        )?;
+        // the rebuilt subgates keep their labels where they were dragged to
+        for (id, subgate) in moved.gates.iter_mut() {
+            if let Some(position) = self.gates.get(id).and_then(|g| g.label_position().clone()) {
+                *subgate = subgate.with_label_position(position);
+            }
+        }
+        Ok(moved)
     }
 
     fn clone_with_gates(
@@ -188,6 +198,10 @@ impl QuadrantGate {
             })
         }
     }
+
+    pub fn get_data_points(&self) -> &DataPoints {
+        &self.points
+    }
 }
 
 impl DrawableGate for QuadrantGate {
@@ -209,6 +223,51 @@ impl DrawableGate for QuadrantGate {
     fn get_params(&self) -> (Arc<str>, Arc<str>) {
         self.parameters.clone()
     }
+    // the composite itself, or one of its subgates
+    fn rename(&self, id: &str, name: &str) -> anyhow::Result<Box<dyn DrawableGate>> {
+        let mut new_self = self.clone();
+        if *self.id == *id {
+            new_self.name = name.to_string();
+        } else {
+            let subgate = new_self.gates.get_mut(id).ok_or_else(|| {
+                anyhow::anyhow!("Gate {} is not part of {}", id, self.name)
+            })?;
+            *subgate = subgate.with_name(name);
+        }
+        Ok(Box::new(new_self))
+    }
+
+    fn with_label_position(
+        &self,
+        id: &str,
+        position: LabelPosition,
+    ) -> anyhow::Result<Box<dyn DrawableGate>> {
+        let mut new_self = self.clone();
+        let subgate = new_self
+            .gates
+            .get_mut(id)
+            .ok_or_else(|| anyhow::anyhow!("Gate {} is not part of {}", id, self.name))?;
+        *subgate = subgate.with_label_position(position);
+        Ok(Box::new(new_self))
+    }
+
+    fn with_new_ids(
+        &self,
+        ids: &FxHashMap<Arc<str>, Arc<str>>,
+    ) -> anyhow::Result<Box<dyn DrawableGate>> {
+        let mut new_self = self.clone();
+        new_self.id = new_gate_id(ids, &self.id)?;
+        new_self.gates = self
+            .gates
+            .iter()
+            .map(|(id, gate)| {
+                let new_id = new_gate_id(ids, id)?;
+                Ok((new_id.clone(), gate.with_id(new_id)))
+            })
+            .collect::<anyhow::Result<_>>()?;
+        Ok(Box::new(new_self))
+    }
+
     fn clone_box(&self) -> Box<dyn DrawableGate> {
         Box::new(self.clone())
     }
@@ -218,7 +277,7 @@ impl DrawableGate for QuadrantGate {
         is_selected: bool,
         drag_point: Option<PointDragData>,
         plot_map: &PlotMapper,
-        gate_stats: &Option<GateStats>,
+        label_text: &GateLabels,
     ) -> Vec<GateRenderShape> {
         let (xmin, xmax) = {
             let a = plot_map.x_axis_min_max();
@@ -294,99 +353,7 @@ impl DrawableGate for QuadrantGate {
             });
         }
 
-        let mut labels = vec![];
-        if let Some(gate_stats) = gate_stats {
-            let x_axis_min_max = plot_map.x_axis_min_max();
-            let y_axis_min_max = plot_map.y_axis_min_max();
-            let x_axis_offset = ((x_axis_min_max.end() - x_axis_min_max.start()) / 100f32) * 1f32;
-            let y_axis_offset = ((y_axis_min_max.end() - y_axis_min_max.start()) / 100f32) * 1f32;
-            for (i, (id, _)) in self.gates.iter().enumerate() {
-                // order: bl, br, tr, tl
-                if let Some(percent) = gate_stats.get_percent_for_id(id.clone()) {
-                    let text = format!("{:.2}%", percent);
-                    let (origin, offset, text_anchor) = match i {
-                        0 => {
-                            // ALWAYS BOTTOM LEFT
-                            (
-                                (
-                                    *x_axis_min_max.start() + x_axis_offset,
-                                    *y_axis_min_max.start() + y_axis_offset,
-                                ),
-                                self.gates.get(id).expect("").get_label_offset(),
-                                Some(String::from("start")),
-                            )
-                        }
-                        1 => {
-                            // BOTTOM RIGHT LABEL
-                            if self.axis_matched {
-                                (
-                                    (
-                                        *x_axis_min_max.end() - x_axis_offset,
-                                        *y_axis_min_max.start() + y_axis_offset,
-                                    ),
-                                    self.gates.get(id).expect("").get_label_offset(),
-                                    Some(String::from("end")),
-                                )
-                            } else {
-                                // TOP LEFT LABEL
-                                (
-                                    (
-                                        *x_axis_min_max.start() + x_axis_offset,
-                                        *y_axis_min_max.end() - 2f32 * y_axis_offset,
-                                    ),
-                                    self.gates.get(id).expect("").get_label_offset(),
-                                    Some(String::from("start")),
-                                )
-                            }
-                        }
-                        2 => {
-                            // ALWAYS TOP RIGHT
-                            (
-                                (
-                                    *x_axis_min_max.end() - x_axis_offset,
-                                    *y_axis_min_max.end() - 2f32 * y_axis_offset,
-                                ),
-                                self.gates.get(id).expect("").get_label_offset(),
-                                Some(String::from("end")),
-                            )
-                        }
-                        3 => {
-                            // TOP LEFT LABEL
-                            if self.axis_matched {
-                                (
-                                    (
-                                        *x_axis_min_max.start() + x_axis_offset,
-                                        *y_axis_min_max.end() - 2f32 * y_axis_offset,
-                                    ),
-                                    self.gates.get(id).expect("").get_label_offset(),
-                                    Some(String::from("start")),
-                                )
-                            } else {
-                                // BOTTOM RIGHT LABEL
-                                (
-                                    (
-                                        *x_axis_min_max.end() - x_axis_offset,
-                                        *y_axis_min_max.start() + y_axis_offset,
-                                    ),
-                                    self.gates.get(id).expect("").get_label_offset(),
-                                    Some(String::from("end")),
-                                )
-                            }
-                        }
-                        _ => unreachable!(),
-                    };
-                    let shape = GateRenderShape::Text {
-                        origin,
-                        offset,
-                        fontsize: 10f32,
-                        text,
-                        text_anchor,
-                        shape_type: ShapeType::UndraggableText(gate_types::Direction::Both),
-                    };
-                    labels.push(shape)
-                }
-            }
-        }
+        let labels = quadrant_labels(&self.gates, self.axis_matched, plot_map, label_text);
 
         shapes.extend_from_slice(&labels);
 
@@ -1,18 +1,20 @@
 use flow_fcs::TransformType;
 
 use flow_gates::transforms::{raw_to_transformed, transformed_to_raw};
-use flow_gates::{Gate, GateGeometry};
+use flow_gates::{Gate, GateGeometry, types::LabelPosition};
 use indexmap::IndexMap;
-use rustc_hash::FxBuildHasher;
+use rustc_hash::{FxBuildHasher, FxHashMap};
 use std::ops::Index;
 use std::{ops::RangeInclusive, sync::Arc};
 
-use crate::gate_editor::gates::gate_types::{self, GateStats};
+use crate::gate_editor::gates::gate_types;
 use crate::gate_editor::{
+    axis_info::transform_f32,
     gates::{
         gate_drag::PointDragData,
+        gate_label::{GateLabels, label_offset, label_shape},
         gate_single::{polygon_gate::PolygonGate, rescale_helper_point},
-        gate_traits::DrawableGate,
+        gate_traits::{DrawableGate, new_gate_id},
         gate_types::{DEFAULT_LINE, GateRenderShape, SELECTED_LINE, ShapeType},
     },
     plots::axis_store::PlotMapper,
@@ -276,7 +278,7 @@ impl SkewedQuadrantGate {
             it.next().ok_or_else(|| anyhow::anyhow!("Missing gate 4"))?,
         );
         let gate_ids = vec![subgate_bl_id, subgate_br_id, subgate_tr_id, subgate_tl_id];
-        SkewedQuadrantGate::try_new_from_data_points(
+        let mut moved = SkewedQuadrantGate::try_new_from_data_points(
             self.id.clone(),
             self.name.clone(),
             data_points,
@@ -286,12 +288,67 @@ impl SkewedQuadrantGate {
             Some(gate_ids),
             Some(gate_names),
             if infs.is_some() {infs.unwrap()} else {self.infs}
-        )
+        )?;
+        // the rebuilt subgates keep their labels where they were dragged to
+        for (id, subgate) in moved.gates.iter_mut() {
+            if let Some(position) = self.gates.get(id).and_then(|g| g.label_position().clone()) {
+                *subgate = subgate.with_label_position(position);
+            }
+        }
+        Ok(moved)
     }
 
     pub fn get_subgate_map(&self) -> &FxIndexMap<Arc<str>, PolygonGate> {
         &self.gates
     }
+
+    pub fn get_data_points(&self) -> &DataPoints {
+        &self.points
+    }
+}
+
+// one label per quadrant, in the plot's corners - order: bl, br, tr, tl,
+// with the middle two swapping corners when the axes are swapped
+pub fn quadrant_labels(
+    gates: &FxIndexMap<Arc<str>, PolygonGate>,
+    axis_matched: bool,
+    plot_map: &PlotMapper,
+    label_text: &GateLabels,
+) -> Vec<GateRenderShape> {
+    let x_axis_min_max = plot_map.x_axis_min_max();
+    let y_axis_min_max = plot_map.y_axis_min_max();
+    let x_axis_offset = ((x_axis_min_max.end() - x_axis_min_max.start()) / 100f32) * 1f32;
+    let y_axis_offset = ((y_axis_min_max.end() - y_axis_min_max.start()) / 100f32) * 1f32;
+    let (left, right) = (
+        *x_axis_min_max.start() + x_axis_offset,
+        *x_axis_min_max.end() - x_axis_offset,
+    );
+    let (bottom, top) = (
+        *y_axis_min_max.start() + y_axis_offset,
+        *y_axis_min_max.end() - 2f32 * y_axis_offset,
+    );
+    let mut labels = vec![];
+    for (i, (id, subgate)) in gates.iter().enumerate() {
+        let Some(text) = label_text.get(id) else {
+            continue;
+        };
+        let (origin, text_anchor) = match (i, axis_matched) {
+            (0, _) => ((left, bottom), "start"),
+            (1, true) | (3, false) => ((right, bottom), "end"),
+            (2, _) => ((right, top), "end"),
+            _ => ((left, top), "start"),
+        };
+        labels.push(label_shape(
+            id.clone(),
+            text.clone(),
+            origin,
+            label_offset(subgate.label_position(), (0.0, 0.0), plot_map),
+            Some(String::from(text_anchor)),
+            Some(gate_types::Direction::Both),
+            plot_map,
+        ));
+    }
+    labels
 }
 
 impl super::super::gate_traits::DrawableGate for SkewedQuadrantGate {
@@ -309,7 +366,7 @@ impl super::super::gate_traits::DrawableGate for SkewedQuadrantGate {
         is_selected: bool,
         drag_point: Option<PointDragData>,
         plot_map: &PlotMapper,
-        gate_stats: &Option<GateStats>,
+        label_text: &GateLabels,
     ) -> Vec<GateRenderShape> {
         let (xmin, xmax) = {
             let axis = plot_map.x_axis_min_max();
@@ -439,99 +496,7 @@ impl super::super::gate_traits::DrawableGate for SkewedQuadrantGate {
             None
         };
 
-        let mut labels = vec![];
-        if let Some(gate_stats) = gate_stats {
-            let x_axis_min_max = plot_map.x_axis_min_max();
-            let y_axis_min_max = plot_map.y_axis_min_max();
-            let x_axis_offset = ((x_axis_min_max.end() - x_axis_min_max.start()) / 100f32) * 1f32;
-            let y_axis_offset = ((y_axis_min_max.end() - y_axis_min_max.start()) / 100f32) * 1f32;
-            for (i, (id, _)) in self.gates.iter().enumerate() {
-                // order: bl, br, tr, tl
-                if let Some(percent) = gate_stats.get_percent_for_id(id.clone()) {
-                    let text = format!("{:.2}%", percent);
-                    let (origin, offset, text_anchor) = match i {
-                        0 => {
-                            // ALWAYS BOTTOM LEFT
-                            (
-                                (
-                                    *x_axis_min_max.start() + x_axis_offset,
-                                    *y_axis_min_max.start() + y_axis_offset,
-                                ),
-                                self.gates.get(id).expect("").get_label_offset(),
-                                Some(String::from("start")),
-                            )
-                        }
-                        1 => {
-                            // BOTTOM RIGHT LABEL
-                            if self.axis_matched {
-                                (
-                                    (
-                                        *x_axis_min_max.end() - x_axis_offset,
-                                        *y_axis_min_max.start() + y_axis_offset,
-                                    ),
-                                    self.gates.get(id).expect("").get_label_offset(),
-                                    Some(String::from("end")),
-                                )
-                            } else {
-                                // TOP LEFT LABEL
-                                (
-                                    (
-                                        *x_axis_min_max.start() + x_axis_offset,
-                                        *y_axis_min_max.end() - 2f32 * y_axis_offset,
-                                    ),
-                                    self.gates.get(id).expect("").get_label_offset(),
-                                    Some(String::from("start")),
-                                )
-                            }
-                        }
-                        2 => {
-                            // ALWAYS TOP RIGHT
-                            (
-                                (
-                                    *x_axis_min_max.end() - x_axis_offset,
-                                    *y_axis_min_max.end() - 2f32 * y_axis_offset,
-                                ),
-                                self.gates.get(id).expect("").get_label_offset(),
-                                Some(String::from("end")),
-                            )
-                        }
-                        3 => {
-                            // TOP LEFT LABEL
-                            if self.axis_matched {
-                                (
-                                    (
-                                        *x_axis_min_max.start() + x_axis_offset,
-                                        *y_axis_min_max.end() - 2f32 * y_axis_offset,
-                                    ),
-                                    self.gates.get(id).expect("").get_label_offset(),
-                                    Some(String::from("start")),
-                                )
-                            } else {
-                                // BOTTOM RIGHT LABEL
-                                (
-                                    (
-                                        *x_axis_min_max.end() - x_axis_offset,
-                                        *y_axis_min_max.start() + y_axis_offset,
-                                    ),
-                                    self.gates.get(id).expect("").get_label_offset(),
-                                    Some(String::from("end")),
-                                )
-                            }
-                        }
-                        _ => unreachable!(),
-                    };
-                    let shape = GateRenderShape::Text {
-                        origin,
-                        offset,
-                        fontsize: 10f32,
-                        text,
-                        text_anchor,
-                        shape_type: ShapeType::UndraggableText(gate_types::Direction::Both),
-                    };
-                    labels.push(shape)
-                }
-            }
-        }
+        let labels = quadrant_labels(&self.gates, self.axis_matched, plot_map, label_text);
 
         let labels = if labels.is_empty() {
             None
@@ -854,6 +819,51 @@ impl super::super::gate_traits::DrawableGate for SkewedQuadrantGate {
         Ok(None)
     }
 
+    // the composite itself, or one of its subgates
+    fn rename(&self, id: &str, name: &str) -> anyhow::Result<Box<dyn super::super::gate_traits::DrawableGate>> {
+        let mut new_self = self.clone();
+        if *self.id == *id {
+            new_self.name = name.to_string();
+        } else {
+            let subgate = new_self.gates.get_mut(id).ok_or_else(|| {
+                anyhow::anyhow!("Gate {} is not part of {}", id, self.name)
+            })?;
+            *subgate = subgate.with_name(name);
+        }
+        Ok(Box::new(new_self))
+    }
+
+    fn with_label_position(
+        &self,
+        id: &str,
+        position: LabelPosition,
+    ) -> anyhow::Result<Box<dyn super::super::gate_traits::DrawableGate>> {
+        let mut new_self = self.clone();
+        let subgate = new_self
+            .gates
+            .get_mut(id)
+            .ok_or_else(|| anyhow::anyhow!("Gate {} is not part of {}", id, self.name))?;
+        *subgate = subgate.with_label_position(position);
+        Ok(Box::new(new_self))
+    }
+
+    fn with_new_ids(
+        &self,
+        ids: &FxHashMap<Arc<str>, Arc<str>>,
+    ) -> anyhow::Result<Box<dyn super::super::gate_traits::DrawableGate>> {
+        let mut new_self = self.clone();
+        new_self.id = new_gate_id(ids, &self.id)?;
+        new_self.gates = self
+            .gates
+            .iter()
+            .map(|(id, gate)| {
+                let new_id = new_gate_id(ids, id)?;
+                Ok((new_id.clone(), gate.with_id(new_id)))
+            })
+            .collect::<anyhow::Result<_>>()?;
+        Ok(Box::new(new_self))
+    }
+
     fn clone_box(&self) -> Box<dyn super::super::gate_traits::DrawableGate> {
         Box::new(self.clone())
     }
@@ -1208,7 +1218,10 @@ pub fn get_infinite_bounds(transform: &TransformType) -> f32 {
     match transform{
         TransformType::Linear => physical_max,
         TransformType::Arcsinh { cofactor } => (physical_max / cofactor).asinh() + 5.0,
-        TransformType::Biexponential { .. } => todo!(),
+        // as with arcsinh, pad past the transformed detector limit
+        TransformType::Biexponential { .. } => {
+            transform_f32(physical_max, transform).unwrap_or(f32::MAX) + 5.0
+        }
     }
 
 
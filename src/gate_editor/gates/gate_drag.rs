@@ -132,11 +132,64 @@ impl RotationData {
     }
 }
 
+// a (sub)gate label being dragged - `offset` is where it was drawn from its anchor.
+// the first mouse move sets the start, so the press itself needs no plot coordinates
+#[derive(Clone, PartialEq)]
+pub struct LabelDragData {
+    gate_id: Arc<str>,
+    label_id: Arc<str>,
+    offset: (f32, f32),
+    start_loc: Option<(f32, f32)>,
+    current_loc: Option<(f32, f32)>,
+}
+
+impl LabelDragData {
+    pub fn new(gate_id: Arc<str>, label_id: Arc<str>, offset: (f32, f32)) -> Self {
+        Self {
+            gate_id,
+            label_id,
+            offset,
+            start_loc: None,
+            current_loc: None,
+        }
+    }
+
+    // unlike a gate drag, the start is kept so the label moves by the whole drag
+    pub fn clone_from_data(new_loc: (f32, f32), old_data: Self) -> Self {
+        Self {
+            start_loc: old_data.start_loc.or(Some(new_loc)),
+            current_loc: Some(new_loc),
+            ..old_data
+        }
+    }
+
+    pub fn gate_id(&self) -> Arc<str> {
+        self.gate_id.clone()
+    }
+    pub fn label_id(&self) -> Arc<str> {
+        self.label_id.clone()
+    }
+    // how far the label has been dragged, once it has moved
+    fn movement(&self) -> Option<(f32, f32)> {
+        let (start, current) = (self.start_loc?, self.current_loc?);
+        Some((current.0 - start.0, current.1 - start.1))
+    }
+    pub fn start_and_current_loc(&self) -> Option<((f32, f32), (f32, f32))> {
+        Some((self.start_loc?, self.current_loc?))
+    }
+    // the label's offset from its anchor where it was dropped
+    pub fn new_offset(&self) -> Option<(f32, f32)> {
+        let (dx, dy) = self.movement()?;
+        Some((self.offset.0 + dx, self.offset.1 + dy))
+    }
+}
+
 #[derive(Clone, PartialEq)]
 pub enum GateDragType {
     Point(PointDragData),
     Gate(GateDragData),
     Rotation(RotationData),
+    Label(LabelDragData),
 }
 
 impl GateDragType {
@@ -151,6 +204,9 @@ impl GateDragType {
             GateDragType::Rotation(rotation_data) => {
                 GateDragType::Rotation(RotationData::clone_from_data(point, rotation_data))
             }
+            GateDragType::Label(label_drag_data) => {
+                GateDragType::Label(LabelDragData::clone_from_data(point, label_drag_data))
+            }
         }
     }
 }
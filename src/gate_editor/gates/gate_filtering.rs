@@ -1,8 +1,18 @@
 use dioxus::prelude::*;
 use flow_gates::{EventIndex, Gate, GateGeometry};
 use polars::prelude::*;
+use rustc_hash::{FxBuildHasher, FxHashMap, FxHashSet};
+use std::sync::Arc;
 
-use crate::gate_editor::gates::{GateId, gate_store::GateOverrideResolver};
+use crate::gate_editor::AxisInfo;
+use crate::gate_editor::compensation::CompMatrix;
+use crate::gate_editor::gates::{
+    GateId,
+    gate_hierarchy::GateHierarchy,
+    gate_single::boolean_gates::BooleanGate,
+    gate_store::{ComparableGate, FileId, GateMap, GateOverrideResolver, ROOTGATE},
+    mask_cache::{GateMaskCache, MaskDependency, MaskSignature},
+};
 
 pub fn filter_events_to_mask(
     df: &DataFrame,
@@ -176,76 +186,327 @@ pub fn filter_events_to_mask(
 
             Ok(mask.with_name("mask".into()))
         }
-        GateGeometry::Boolean {
+        // an operand's population depends on its own ancestors, which this gate knows nothing about
+        GateGeometry::Boolean { .. } => Err(anyhow::anyhow!(
+            "Boolean gate {} must be evaluated with PopulationMasks",
+            gate_id
+        )),
+    }
+}
+
+// Evaluates gate populations (the gate's own region AND every ancestor) for one sample.
+// Boolean gates combine the full populations of their operands, so NOT/OR of gates on
+// other branches behave like they do in OMIQ. Masks are cached so shared ancestors
+// are only computed once.
+pub struct PopulationMasks<'a> {
+    df: &'a DataFrame,
+    hierarchy: &'a GateHierarchy,
+    resolver: &'a GateOverrideResolver,
+    cache: FxHashMap<GateId, BooleanChunked>,
+    in_progress: FxHashSet<GateId>,
+    shared: Option<SharedMasks<'a>>,
+    signatures: FxHashMap<GateId, MaskSignature>,
+}
+
+// the cross-plot cache, and what's needed to key into it
+#[derive(Clone)]
+struct SharedMasks<'a> {
+    cache: &'a GateMaskCache,
+    file_id: FileId,
+    generation: u64,
+    axis_settings: &'a im::HashMap<Arc<str>, AxisInfo, FxBuildHasher>,
+    compensation: Option<&'a Arc<CompMatrix>>,
+}
+
+impl<'a> PopulationMasks<'a> {
+    pub fn new(
+        df: &'a DataFrame,
+        hierarchy: &'a GateHierarchy,
+        resolver: &'a GateOverrideResolver,
+    ) -> Self {
+        Self {
+            df,
+            hierarchy,
+            resolver,
+            cache: FxHashMap::default(),
+            in_progress: FxHashSet::default(),
+            shared: None,
+            signatures: FxHashMap::default(),
+        }
+    }
+
+    // reuse masks from earlier evaluations of this file - `generation` identifies `df`, and
+    // `axis_settings` and `compensation` must be what it was scaled with
+    pub fn with_cache(
+        mut self,
+        cache: &'a GateMaskCache,
+        file_id: FileId,
+        generation: u64,
+        axis_settings: &'a im::HashMap<Arc<str>, AxisInfo, FxBuildHasher>,
+        compensation: Option<&'a Arc<CompMatrix>>,
+    ) -> Self {
+        self.shared = Some(SharedMasks {
+            cache,
+            file_id,
+            generation,
+            axis_settings,
+            compensation,
+        });
+        self
+    }
+
+    pub fn population(&mut self, gate_id: &GateId) -> anyhow::Result<BooleanChunked> {
+        if *gate_id == *ROOTGATE {
+            return Ok(BooleanChunked::full("mask".into(), true, self.df.height()));
+        }
+        if let Some(mask) = self.cache.get(gate_id) {
+            return Ok(mask.clone());
+        }
+        if !self.in_progress.insert(gate_id.clone()) {
+            return Err(anyhow::anyhow!(
+                "Gate {} depends on its own population",
+                gate_id
+            ));
+        }
+
+        let result = self.evaluate(gate_id);
+        self.in_progress.remove(gate_id);
+        let mask = result?;
+        self.cache.insert(gate_id.clone(), mask.clone());
+        Ok(mask)
+    }
+
+    pub fn hierarchy(&self) -> &'a GateHierarchy {
+        self.hierarchy
+    }
+
+    // the population of the parent - everything for top level gates
+    pub fn parent_population(&mut self, gate_id: &GateId) -> anyhow::Result<BooleanChunked> {
+        let parent = self
+            .hierarchy
+            .get_parent(gate_id)
+            .cloned()
+            .unwrap_or_else(|| ROOTGATE.clone());
+        self.population(&parent)
+    }
+
+    fn evaluate(&mut self, gate_id: &GateId) -> anyhow::Result<BooleanChunked> {
+        let parent_mask = self.parent_population(gate_id)?;
+        let Some(shared) = self.shared.clone() else {
+            let own_mask = self.own_mask(gate_id)?;
+            return Ok(&parent_mask & &own_mask);
+        };
+
+        let resolver = self.resolver;
+        let gate = resolver
+            .active_gates
+            .get(gate_id)
+            .ok_or_else(|| anyhow::anyhow!("error fetching gate {} from resolver", gate_id))?;
+        // operands first, so their signatures exist (and cycles are caught) before we look up
+        let operands = match gate.get_gate_ref(Some(gate_id)).map(|g| &g.geometry) {
+            Some(GateGeometry::Boolean { operands, .. }) => operands.as_slice(),
+            _ => &[],
+        };
+        for operand in operands {
+            self.population(operand)?;
+        }
+
+        let signature = self.signature(gate_id, gate, operands, &shared);
+        if let Some(mask) =
+            shared
+                .cache
+                .get(&shared.file_id, shared.generation, gate_id, &signature)
+        {
+            return Ok(mask);
+        }
+
+        let own_mask = self.own_mask(gate_id)?;
+        let mask = &parent_mask & &own_mask;
+        shared.cache.insert(
+            shared.file_id,
+            shared.generation,
+            gate_id.clone(),
+            signature,
+            mask.clone(),
+        );
+        Ok(mask)
+    }
+
+    fn signature(
+        &mut self,
+        gate_id: &GateId,
+        gate: &ComparableGate,
+        operands: &[GateId],
+        shared: &SharedMasks<'_>,
+    ) -> MaskSignature {
+        let (x, y) = gate.get_params();
+        // only gates on compensated channels move when the matrix changes
+        let compensation = shared
+            .compensation
+            .filter(|m| m.channels.iter().any(|c| *c == x || *c == y))
+            .cloned();
+        let mut dependencies = vec![MaskDependency {
+            gate_id: gate_id.clone(),
+            gate: gate.clone(),
+            x_transform: shared.axis_settings.get(&x).map(|a| a.transform.clone()),
+            y_transform: shared.axis_settings.get(&y).map(|a| a.transform.clone()),
+            compensation,
+        }];
+        let parent = self.hierarchy.get_parent(gate_id);
+        for dependency in parent.into_iter().chain(operands) {
+            if let Some(signature) = self.signatures.get(dependency) {
+                dependencies.extend(signature.iter().cloned());
+            }
+        }
+        let signature = Arc::new(dependencies);
+        self.signatures.insert(gate_id.clone(), signature.clone());
+        signature
+    }
+
+    fn own_mask(&mut self, gate_id: &GateId) -> anyhow::Result<BooleanChunked> {
+        let resolver = self.resolver;
+        let gate = resolver
+            .active_gates
+            .get(gate_id)
+            .and_then(|g| g.get_gate_ref(Some(gate_id)))
+            .ok_or_else(|| anyhow::anyhow!("error fetching gate {} from resolver", gate_id))?;
+
+        let GateGeometry::Boolean {
             operation,
             operands,
-        } => match operation {
+        } = &gate.geometry
+        else {
+            return filter_events_to_mask(self.df, gate_id.clone(), resolver);
+        };
+
+        match operation {
             flow_gates::BooleanOperation::And => {
                 if operands.len() < 2 {
                     return Err(anyhow::anyhow!("AND gates must have > 1 operand"));
                 }
                 let mut final_mask: Option<BooleanChunked> = None;
-                for gate in operands {
-                    let current_mask = filter_events_to_mask(df, gate.clone(), resolver)?;
-                    match final_mask {
-                        None => final_mask = Some(current_mask),
-                        Some(ref mut acc) => {
-                            *acc = &*acc & &current_mask;
-
-                            if !acc.any() {
-                                break;
-                            }
-                        }
-                    }
-                }
-                match final_mask {
-                    Some(m) => Ok(m),
-                    None => Err(anyhow::anyhow!("AND gate operand could not be resolved")),
+                for operand in operands {
+                    let current_mask = self.population(operand)?;
+                    final_mask = Some(match final_mask {
+                        None => current_mask,
+                        Some(acc) => &acc & &current_mask,
+                    });
                 }
+                final_mask
+                    .ok_or_else(|| anyhow::anyhow!("AND gate operand could not be resolved"))
             }
             flow_gates::BooleanOperation::Or => {
                 if operands.len() < 2 {
                     return Err(anyhow::anyhow!("OR gates must have > 1 operand"));
                 }
                 let mut final_mask: Option<BooleanChunked> = None;
-                for gate in operands {
-                    let current_mask = filter_events_to_mask(df, gate.clone(), resolver)?;
-                    match final_mask {
-                        None => final_mask = Some(current_mask),
-                        Some(ref mut acc) => *acc = &*acc | &current_mask,
-                    }
-                }
-                match final_mask {
-                    Some(m) => Ok(m),
-                    None => Err(anyhow::anyhow!("OR gate operand could not be resolved")),
+                for operand in operands {
+                    let current_mask = self.population(operand)?;
+                    final_mask = Some(match final_mask {
+                        None => current_mask,
+                        Some(acc) => &acc | &current_mask,
+                    });
                 }
+                final_mask
+                    .ok_or_else(|| anyhow::anyhow!("OR gate operand could not be resolved"))
             }
             flow_gates::BooleanOperation::Not => {
                 if operands.len() != 1 {
                     return Err(anyhow::anyhow!("Not gates can only have 1 operand"));
                 }
-                let other_id = operands[0].clone();
-                let mask = !filter_events_to_mask(df, other_id, resolver)?;
-                Ok(mask)
+                Ok(!self.population(&operands[0])?)
+            }
+        }
+    }
+}
+
+// the gates a population is computed from - its parent, and a boolean gate's operands
+fn population_dependencies(
+    hierarchy: &GateHierarchy,
+    registry: &GateMap,
+    gate_id: &GateId,
+) -> Vec<GateId> {
+    let mut deps: Vec<GateId> = hierarchy.get_parent(gate_id).cloned().into_iter().collect();
+    if let Some(boolean) = registry
+        .get(gate_id)
+        .and_then(|g| g.as_any().downcast_ref::<BooleanGate>())
+    {
+        deps.extend_from_slice(boolean.get_operands());
+    }
+    deps
+}
+
+// `gates` and every gate their populations are computed from, so a plot can evaluate just
+// what it shows rather than the whole hierarchy
+pub fn population_dependency_closure(
+    hierarchy: &GateHierarchy,
+    registry: &GateMap,
+    gates: impl IntoIterator<Item = GateId>,
+) -> FxHashSet<GateId> {
+    let mut closure = FxHashSet::default();
+    let mut pending: Vec<GateId> = gates.into_iter().collect();
+    while let Some(gate_id) = pending.pop() {
+        if gate_id == *ROOTGATE || !closure.insert(gate_id.clone()) {
+            continue;
+        }
+        pending.extend(population_dependencies(hierarchy, registry, &gate_id));
+    }
+    closure
+}
+
+// Static check that no gate's population depends on itself - a gate depends on its parent,
+// and a boolean gate also depends on each of its operands.
+pub fn check_population_dependencies(
+    hierarchy: &GateHierarchy,
+    registry: &GateMap,
+) -> anyhow::Result<()> {
+
+    let mut done: FxHashSet<GateId> = FxHashSet::default();
+    for start in registry.keys() {
+        if done.contains(start) {
+            continue;
+        }
+        // iterative dfs - the path holds the gates whose dependencies are being visited
+        let mut path: Vec<(GateId, Vec<GateId>)> = vec![(
+            start.clone(),
+            population_dependencies(hierarchy, registry, start),
+        )];
+        let mut on_path: FxHashSet<GateId> = FxHashSet::from_iter([start.clone()]);
+        while let Some((gate_id, remaining)) = path.last_mut() {
+            match remaining.pop() {
+                Some(dep) => {
+                    if on_path.contains(&dep) {
+                        return Err(anyhow::anyhow!(
+                            "Gate {} depends on its own population via {}",
+                            dep,
+                            gate_id
+                        ));
+                    }
+                    if done.contains(&dep) {
+                        continue;
+                    }
+                    on_path.insert(dep.clone());
+                    let deps = population_dependencies(hierarchy, registry, &dep);
+                    path.push((dep, deps));
+                }
+                None => {
+                    on_path.remove(gate_id);
+                    done.insert(gate_id.clone());
+                    path.pop();
+                }
             }
-        },
+        }
     }
+    Ok(())
 }
 
 pub fn filter_events_by_hierarchy_to_mask(
     scaled_data: &DataFrame,
-    gate_chain: &[GateId],
+    gate_id: &GateId,
+    hierarchy: &GateHierarchy,
     resolver: &GateOverrideResolver,
 ) -> Result<BooleanChunked, anyhow::Error> {
-    let event_count = scaled_data.height();
-    let mut final_mask = BooleanChunked::full("mask".into(), true, event_count);
-    println!("called with gate chain length {}", gate_chain.len());
-    for gate_id in gate_chain {
-        let gate_mask = filter_events_to_mask(scaled_data, gate_id.clone(), resolver)?;
-        final_mask = final_mask & gate_mask;
-    }
-
-    Ok(final_mask)
+    PopulationMasks::new(scaled_data, hierarchy, resolver).population(gate_id)
 }
 
 // pub fn filter_events_by_gate(
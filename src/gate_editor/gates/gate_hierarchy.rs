@@ -140,6 +140,11 @@ impl GateHierarchy {
         self.parents.get(gate_id)
     }
 
+    /// Get the sibling order of a gate
+    pub fn get_order(&self, gate_id: &str) -> Option<u64> {
+        self.orders.get(gate_id).copied()
+    }
+
     /// Get the children of a gate
     pub fn get_children(&self, gate_id: &str) -> Vec<&Arc<str>> {
         self.children
@@ -501,9 +506,13 @@ impl GateHierarchy {
             if let Some(children) = self.children.get(old_id) {
                 let new_parent_id = id_map.get(old_id).unwrap();
                 for child in children {
-                    if let Some(new_child_id) = id_map.get(child)
-                        && let Some(ord) = self.orders.get(child)
-                    {
+                    if let Some(new_child_id) = id_map.get(child) {
+                        let Some(ord) = self.orders.get(child) else {
+                            return Err(anyhow!(
+                                "Failed to add child in cloned hierarchy - no order for child {}",
+                                child
+                            ));
+                        };
                         if !new_hierarchy.add_child(
                             new_parent_id.clone(),
                             new_child_id.clone(),
@@ -512,11 +521,6 @@ impl GateHierarchy {
                             return Err(anyhow!(
                                 "Failed to add child in cloned hierarchy - possible cycle",
                             ));
-                        } else {
-                            return Err(anyhow!(
-                                "Failed to add child in cloned hierarchy - no order for child {}",
-                                child
-                            ));
                         }
                     }
                 }
@@ -0,0 +1,106 @@
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+use rustc_hash::FxHashMap;
+
+use crate::gate_editor::gates::{
+    GateId, GatesOnPlotKey,
+    gate_hierarchy::GateHierarchy,
+    gate_store::{GateLinks, GateSubStore},
+    gate_traits::DrawableGate,
+};
+
+// oldest steps are dropped past this
+const MAX_HISTORY: usize = 100;
+
+// Everything an edit can change. The gate, override and link maps are im maps, so a snapshot
+// shares them with the live state and later edits only copy the entries they touch - the
+// gates themselves are Arcs, and stay shared too. The hierarchy and view lists are plain ids.
+#[derive(Clone, Default)]
+pub struct GateSnapshot {
+    pub gate_ids_by_view: FxHashMap<GatesOnPlotKey, Vec<GateId>>,
+    pub hierarchy: GateHierarchy,
+    pub boolean_gate_links: GateLinks,
+    pub gate_store: GateSubStore,
+}
+
+// Undo/redo stacks of gate state snapshots, each taken just before the edit it reverts.
+// A gesture (eg a drag) is only snapshotted before its first change, so however many moves
+// it makes it undoes in one step - and a click that moves nothing copies nothing.
+#[derive(Default)]
+pub struct GateHistory {
+    undo: VecDeque<GateSnapshot>,
+    redo: Vec<GateSnapshot>,
+    in_gesture: bool,
+    gesture_recorded: bool,
+}
+
+impl GateHistory {
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    pub fn in_gesture(&self) -> bool {
+        self.in_gesture
+    }
+
+    // a new edit branches the history, so anything undone can no longer be redone
+    pub fn record(&mut self, before: GateSnapshot) {
+        if self.undo.len() == MAX_HISTORY {
+            self.undo.pop_front();
+        }
+        self.undo.push_back(before);
+        self.redo.clear();
+        self.gesture_recorded = self.in_gesture;
+    }
+
+    pub fn begin_gesture(&mut self) {
+        self.in_gesture = true;
+        self.gesture_recorded = false;
+    }
+
+    // true until the current gesture has recorded the state before its first change
+    pub fn wants_gesture_start(&self) -> bool {
+        self.in_gesture && !self.gesture_recorded
+    }
+
+    pub fn end_gesture(&mut self) {
+        self.in_gesture = false;
+        self.gesture_recorded = false;
+    }
+
+    pub fn undo(&mut self, current: GateSnapshot) -> Option<GateSnapshot> {
+        self.end_gesture();
+        let previous = self.undo.pop_back()?;
+        self.redo.push(current);
+        Some(previous)
+    }
+
+    pub fn redo(&mut self, current: GateSnapshot) -> Option<GateSnapshot> {
+        self.end_gesture();
+        let next = self.redo.pop()?;
+        self.undo.push_back(current);
+        Some(next)
+    }
+
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+        self.end_gesture();
+    }
+
+    // Rescaling an axis changes the space gates are stored in rather than the gates themselves,
+    // so it is applied to every snapshot instead of being recorded.
+    pub fn map_gates(
+        &mut self,
+        f: &mut impl FnMut(&Arc<dyn DrawableGate>) -> Arc<dyn DrawableGate>,
+    ) {
+        for snapshot in self.undo.iter_mut().chain(self.redo.iter_mut()) {
+            snapshot.gate_store.map_gates(&mut *f);
+        }
+    }
+}
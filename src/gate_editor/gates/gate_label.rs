@@ -0,0 +1,223 @@
+use flow_gates::types::LabelPosition;
+use rustc_hash::FxHashMap;
+
+use crate::gate_editor::{
+    gates::{
+        gate_store::GateId,
+        gate_traits::DrawableGate,
+        gate_types::{Direction, GateRenderShape, GateStats, GateText, ShapeType},
+    },
+    plots::axis_store::PlotMapper,
+};
+
+pub const LABEL_FONT_SIZE: f32 = 10.0;
+// pixels kept clear between a label and the plot edge
+const EDGE_MARGIN: f32 = 2.0;
+
+/// Which parts a gate label shows - always drawn in `GateText` order
+#[derive(Clone, PartialEq, Debug)]
+pub struct GateLabelContent(Vec<GateText>);
+
+impl Default for GateLabelContent {
+    fn default() -> Self {
+        Self(vec![GateText::Name, GateText::Percent])
+    }
+}
+
+impl GateLabelContent {
+    pub fn shows(&self, part: GateText) -> bool {
+        self.0.contains(&part)
+    }
+
+    pub fn toggle(&mut self, part: GateText) {
+        if self.shows(part) {
+            self.0.retain(|p| *p != part);
+        } else {
+            self.0.push(part);
+            self.0.sort();
+        }
+    }
+
+    pub fn text(&self, name: &str, percent: Option<f32>, count: Option<f32>) -> Option<String> {
+        let parts = self
+            .0
+            .iter()
+            .filter_map(|part| match part {
+                GateText::Name => Some(name.to_string()),
+                GateText::Percent => percent.map(|p| format!("{p:.2}%")),
+                GateText::Count => count.map(|c| format!("n={c:.0}")),
+            })
+            .collect::<Vec<_>>();
+        if parts.is_empty() {
+            None
+        } else {
+            Some(parts.join(" "))
+        }
+    }
+}
+
+/// Label text for a gate, or for each subgate of a composite, keyed by (sub)gate id
+pub type GateLabels = FxHashMap<GateId, String>;
+
+pub fn gate_labels(
+    gate: &dyn DrawableGate,
+    stats: &GateStats,
+    content: &GateLabelContent,
+) -> GateLabels {
+    let ids = if gate.is_composite() {
+        gate.get_inner_gate_ids()
+    } else {
+        vec![gate.get_id()]
+    };
+    ids.into_iter()
+        .filter_map(|id| {
+            let name = gate
+                .get_gate_ref(Some(&id))
+                .map(|inner| inner.name.clone())
+                .unwrap_or_else(|| gate.get_name().to_string());
+            let text = content.text(
+                &name,
+                stats.get_percent_for_id(id.clone()),
+                stats.get_count_for_id(id.clone()),
+            )?;
+            Some((id, text))
+        })
+        .collect()
+}
+
+fn axis_spans(plot_map: &PlotMapper) -> (f32, f32) {
+    let x = plot_map.x_axis_min_max();
+    let y = plot_map.y_axis_min_max();
+    (x.end() - x.start(), y.end() - y.start())
+}
+
+/// A label's offset from its anchor in data units - `label_position` stores it as a
+/// fraction of each axis range, so it survives rescaling the axes
+pub fn label_offset(
+    label_position: &Option<LabelPosition>,
+    default: (f32, f32),
+    plot_map: &PlotMapper,
+) -> (f32, f32) {
+    let (fx, fy) = label_position
+        .as_ref()
+        .map(|l| (l.offset_x, l.offset_y))
+        .unwrap_or(default);
+    let (x_span, y_span) = axis_spans(plot_map);
+    (fx * x_span, fy * y_span)
+}
+
+/// The inverse of `label_offset`, for storing a dragged label
+pub fn label_position_for_offset(offset: (f32, f32), plot_map: &PlotMapper) -> LabelPosition {
+    let (x_span, y_span) = axis_spans(plot_map);
+    LabelPosition {
+        offset_x: if x_span == 0.0 {
+            0.0
+        } else {
+            offset.0 / x_span
+        },
+        offset_y: if y_span == 0.0 {
+            0.0
+        } else {
+            offset.1 / y_span
+        },
+    }
+}
+
+/// A draggable label for `label_id`, moved back inside the plot if it would cross an edge.
+/// `pinned` labels hold their position along that direction while the gate is dragged
+pub fn label_shape(
+    label_id: GateId,
+    text: String,
+    origin: (f32, f32),
+    offset: (f32, f32),
+    text_anchor: Option<String>,
+    pinned: Option<Direction>,
+    plot_map: &PlotMapper,
+) -> GateRenderShape {
+    let offset = offset_inside_plot(&text, origin, offset, text_anchor.as_deref(), plot_map);
+    GateRenderShape::Text {
+        origin,
+        offset,
+        fontsize: LABEL_FONT_SIZE,
+        text,
+        text_anchor,
+        shape_type: ShapeType::Label(label_id, pinned),
+    }
+}
+
+fn offset_inside_plot(
+    text: &str,
+    origin: (f32, f32),
+    offset: (f32, f32),
+    text_anchor: Option<&str>,
+    plot_map: &PlotMapper,
+) -> (f32, f32) {
+    let (px, py) = plot_map.data_to_pixel(origin.0 + offset.0, origin.1 + offset.1, None, None);
+    if !px.is_finite() || !py.is_finite() {
+        return offset;
+    }
+    // svg text isn't measured until it's rendered - estimate from the character count
+    let width = text.chars().count() as f32 * LABEL_FONT_SIZE * 0.6;
+    let (left, right) = match text_anchor {
+        Some("middle") => (px - width / 2.0, px + width / 2.0),
+        Some("end") => (px - width, px),
+        _ => (px, px + width),
+    };
+    let (max_x, max_y) = (
+        plot_map.width() - EDGE_MARGIN,
+        plot_map.height() - EDGE_MARGIN,
+    );
+    // a label wider than the plot keeps its start visible
+    let dx = if left < EDGE_MARGIN {
+        EDGE_MARGIN - left
+    } else if right > max_x {
+        (max_x - right).max(EDGE_MARGIN - left)
+    } else {
+        0.0
+    };
+    // y is the baseline - the text sits above it
+    let top = py - LABEL_FONT_SIZE;
+    let dy = if top < EDGE_MARGIN {
+        EDGE_MARGIN - top
+    } else if py > max_y {
+        max_y - py
+    } else {
+        0.0
+    };
+    if dx == 0.0 && dy == 0.0 {
+        return offset;
+    }
+    let (x, y) = plot_map.pixel_to_data(px + dx, py + dy, None, None);
+    (x - origin.0, y - origin.1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_label_content_text() {
+        let mut content = GateLabelContent::default();
+        assert_eq!(
+            content.text("CD4+", Some(12.5), Some(1234.0)),
+            Some("CD4+ 12.50%".to_string())
+        );
+
+        // parts keep their order whichever was switched on last
+        content.toggle(GateText::Count);
+        content.toggle(GateText::Name);
+        content.toggle(GateText::Name);
+        assert_eq!(
+            content.text("CD4+", Some(12.5), Some(1234.0)),
+            Some("CD4+ 12.50% n=1234".to_string())
+        );
+
+        // missing stats are left out rather than shown as blanks
+        assert_eq!(content.text("CD4+", None, None), Some("CD4+".to_string()));
+
+        content.toggle(GateText::Name);
+        content.toggle(GateText::Percent);
+        content.toggle(GateText::Count);
+        assert_eq!(content.text("CD4+", Some(1.0), Some(1.0)), None);
+    }
+}
@@ -0,0 +1,297 @@
+use anyhow::anyhow;
+use flow_gates::BooleanOperation;
+use rustc_hash::FxBuildHasher;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use crate::gate_editor::AxisInfo;
+use crate::gate_editor::gates::gate_single::boolean_gates::BooleanGate;
+use crate::gate_editor::gates::gate_store::{FileId, GateId, GroupGateMap, GroupId, SampleGateMap};
+use crate::gate_editor::gates::gate_traits::DrawableGate;
+use crate::omiq::deserialise::{
+    AtomicContainer, BooleanOpType, CompositeType, GateSerialized,
+    get_composite_gates_from_filter_container,
+};
+use crate::omiq::metadata::{MetaDataFileMap, MetaDataParameter};
+use crate::omiq::serialise::{
+    composite_gate_to_serialized, composite_group_id, composite_type_for_gate,
+    primary_gate_to_serialized,
+};
+
+// The native project representation of a GateState.
+// Gate geometry reuses the omiq filter format, but unlike an omiq container a gate
+// can carry group and sample overrides at the same time.
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct GateStateRecord {
+    pub selected_gate: Option<GateId>,
+    // parents always come before their children
+    pub hierarchy: Vec<HierarchyRecord>,
+    pub gates: Vec<GateRecord>,
+    // the drawable ids of each set of linked gates
+    #[serde(default)]
+    pub links: Vec<Vec<GateId>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct HierarchyRecord {
+    pub parent_id: GateId,
+    pub gate_id: GateId,
+    pub ord: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct GateRecord {
+    pub id: GateId,
+    pub name: Arc<str>,
+    pub kind: GateRecordKind,
+    // one filter for primary gates, one per subgate for composites
+    #[serde(default)]
+    pub default_filters: Vec<GateSerialized>,
+    #[serde(default)]
+    pub group_overrides: Vec<GroupOverrideRecord>,
+    #[serde(default)]
+    pub sample_overrides: Vec<SampleOverrideRecord>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "type")]
+pub enum GateRecordKind {
+    Primary,
+    Boolean {
+        operation: BooleanOpType,
+        operands: Vec<GateId>,
+        x_param: Arc<str>,
+        y_param: Arc<str>,
+    },
+    Bisector {
+        subgates: Vec<SubgateRecord>,
+    },
+    Quadrant {
+        subgates: Vec<SubgateRecord>,
+    },
+    SkewedQuadrant {
+        subgates: Vec<SubgateRecord>,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SubgateRecord {
+    pub id: GateId,
+    pub name: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct GroupOverrideRecord {
+    pub parameter: MetaDataParameter,
+    pub group: GroupId,
+    pub filters: Vec<GateSerialized>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SampleOverrideRecord {
+    pub file_id: FileId,
+    pub filters: Vec<GateSerialized>,
+}
+
+fn gate_to_filters(gate: &dyn DrawableGate) -> anyhow::Result<Vec<GateSerialized>> {
+    if gate.is_composite() {
+        composite_gate_to_serialized(gate)
+    } else {
+        Ok(vec![primary_gate_to_serialized(gate)?])
+    }
+}
+
+pub fn gate_to_record(
+    gate: &Arc<dyn DrawableGate>,
+    sample_overrides: &SampleGateMap,
+    group_overrides: &GroupGateMap,
+) -> anyhow::Result<GateRecord> {
+    let id = gate.get_id();
+    let name = Arc::from(gate.get_name());
+
+    if let Some(boolean_gate) = gate.as_any().downcast_ref::<BooleanGate>() {
+        let operation = match boolean_gate.get_operation() {
+            BooleanOperation::And => BooleanOpType::And,
+            BooleanOperation::Or => BooleanOpType::Or,
+            BooleanOperation::Not => BooleanOpType::Not,
+        };
+        let (x_param, y_param) = gate.get_params();
+        return Ok(GateRecord {
+            id,
+            name,
+            kind: GateRecordKind::Boolean {
+                operation,
+                operands: boolean_gate.get_operands().to_vec(),
+                x_param,
+                y_param,
+            },
+            default_filters: vec![],
+            group_overrides: vec![],
+            sample_overrides: vec![],
+        });
+    }
+
+    let kind = if gate.is_composite() {
+        let subgates = gate
+            .get_inner_gate_ids()
+            .into_iter()
+            .map(|sub_id| SubgateRecord {
+                name: gate
+                    .get_gate_ref(Some(&sub_id))
+                    .map(|g| g.name.clone())
+                    .unwrap_or_else(|| sub_id.to_string()),
+                id: sub_id,
+            })
+            .collect();
+        match composite_type_for_gate(gate.as_ref()) {
+            Some(CompositeType::Bisector(_)) => GateRecordKind::Bisector { subgates },
+            Some(CompositeType::Quadrant(_)) => GateRecordKind::Quadrant { subgates },
+            Some(CompositeType::SkewedQuadrant(_)) => GateRecordKind::SkewedQuadrant { subgates },
+            None => return Err(anyhow!("Unknown composite gate type for {}", id)),
+        }
+    } else {
+        GateRecordKind::Primary
+    };
+
+    // composite overrides are stored under the composite id and each subgate id,
+    // the composite id is enough to rebuild them all
+    let group_overrides = group_overrides
+        .iter()
+        .filter(|((gid, _), _)| gid == &id)
+        .map(|((_, key), ovr)| {
+            Ok(GroupOverrideRecord {
+                parameter: key.parameter.clone(),
+                group: key.group.clone(),
+                filters: gate_to_filters(ovr.as_ref())?,
+            })
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    let sample_overrides = sample_overrides
+        .iter()
+        .filter(|((gid, _), _)| gid == &id)
+        .map(|((_, file_id), ovr)| {
+            Ok(SampleOverrideRecord {
+                file_id: file_id.clone(),
+                filters: gate_to_filters(ovr.as_ref())?,
+            })
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    Ok(GateRecord {
+        id,
+        name,
+        kind,
+        default_filters: gate_to_filters(gate.as_ref())?,
+        group_overrides,
+        sample_overrides,
+    })
+}
+
+pub fn boolean_gate_from_record(record: &GateRecord) -> anyhow::Result<Arc<dyn DrawableGate>> {
+    let GateRecordKind::Boolean {
+        operation,
+        operands,
+        x_param,
+        y_param,
+    } = &record.kind
+    else {
+        return Err(anyhow!("Gate {} is not a boolean gate", record.id));
+    };
+    let op = match operation {
+        BooleanOpType::And => BooleanOperation::And,
+        BooleanOpType::Or => BooleanOperation::Or,
+        BooleanOpType::Not => BooleanOperation::Not,
+    };
+    Ok(Arc::new(BooleanGate::new(
+        record.id.clone(),
+        record.name.to_string(),
+        operands.clone(),
+        op,
+        x_param.clone(),
+        y_param.clone(),
+    )?))
+}
+
+// builds the drawable for either the default filters or one of the overrides
+pub fn drawable_from_record(
+    record: &GateRecord,
+    filters: &[GateSerialized],
+    axis_settings: &im::HashMap<Arc<str>, AxisInfo, FxBuildHasher>,
+) -> anyhow::Result<Arc<dyn DrawableGate>> {
+    let (composite_type, subgates) = match &record.kind {
+        GateRecordKind::Primary => {
+            let filter = filters
+                .first()
+                .ok_or_else(|| anyhow!("Gate {} has no filter", record.id))?;
+            return filter.to_drawable(
+                record.id.clone(),
+                record.name.clone(),
+                flow_gates::GateMode::Global,
+                false,
+            );
+        }
+        GateRecordKind::Boolean { .. } => {
+            return Err(anyhow!("Boolean gate {} has no geometry", record.id));
+        }
+        GateRecordKind::Bisector { subgates } => {
+            (CompositeType::Bisector(record.id.to_string()), subgates)
+        }
+        GateRecordKind::Quadrant { subgates } => {
+            (CompositeType::Quadrant(record.id.to_string()), subgates)
+        }
+        GateRecordKind::SkewedQuadrant { subgates } => {
+            (CompositeType::SkewedQuadrant(record.id.to_string()), subgates)
+        }
+    };
+
+    if subgates.len() != filters.len() {
+        return Err(anyhow!(
+            "Composite gate {} has {} subgates but {} filters",
+            record.id,
+            subgates.len(),
+            filters.len()
+        ));
+    }
+
+    // rebuild the omiq containers so the composite goes through the same path as an import
+    let n = subgates.len();
+    let mut containers: Vec<(u32, AtomicContainer)> = subgates
+        .iter()
+        .zip(filters)
+        .enumerate()
+        .map(|(i, (subgate, filter))| {
+            let (position, group_id) = composite_group_id(&composite_type, i, n);
+            (
+                position,
+                AtomicContainer {
+                    id: subgate.id.clone(),
+                    name: Arc::from(subgate.name.as_str()),
+                    default_filter: filter.clone(),
+                    group_id: Some(group_id),
+                    md: None,
+                    per_file_filters: Default::default(),
+                },
+            )
+        })
+        .collect();
+    containers.sort_by_key(|(pos, _)| *pos);
+
+    let no_metadata: MetaDataFileMap = im::HashMap::with_hasher(FxBuildHasher);
+    get_composite_gates_from_filter_container(
+        composite_type,
+        &containers,
+        axis_settings,
+        &no_metadata,
+    )?
+    .into_values()
+    .next()
+    .ok_or_else(|| anyhow!("Could not rebuild composite gate {}", record.id))
+}
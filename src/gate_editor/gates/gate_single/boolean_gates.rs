@@ -1,8 +1,9 @@
 use std::sync::Arc;
 
 use flow_gates::{BooleanOperation, GateGeometry};
+use rustc_hash::FxHashMap;
 
-use crate::gate_editor::gates::gate_traits::DrawableGate;
+use crate::gate_editor::gates::gate_traits::{DrawableGate, new_gate_id};
 
 #[derive(PartialEq, Clone)]
 pub struct BooleanGate {
@@ -10,6 +11,18 @@ pub struct BooleanGate {
 }
 
 impl BooleanGate {
+    pub fn with_name(&self, name: &str) -> Self {
+        let mut renamed = self.clone();
+        renamed.inner.name = name.to_string();
+        renamed
+    }
+
+    pub fn with_id(&self, id: Arc<str>) -> Self {
+        let mut copy = self.clone();
+        copy.inner.id = id;
+        copy
+    }
+
     pub fn new(
         id: Arc<str>,
         name: String,
@@ -60,12 +73,13 @@ impl DrawableGate for BooleanGate {
         true
     }
 
+    // the region depends on the operands, so the gate layer draws it - see boolean_region
     fn draw_self(
         &self,
         _is_selected: bool,
         _drag_point: Option<crate::gate_editor::gates::gate_drag::PointDragData>,
         _plot_map: &crate::gate_editor::plots::axis_store::PlotMapper,
-        _gate_stats: &Option<crate::gate_editor::gates::gate_types::GateStats>,
+        _label_text: &crate::gate_editor::gates::gate_label::GateLabels,
     ) -> Vec<crate::gate_editor::gates::gate_types::GateRenderShape> {
         vec![]
     }
@@ -141,6 +155,36 @@ impl DrawableGate for BooleanGate {
         Ok(None)
     }
 
+    fn rename(&self, _id: &str, name: &str) -> anyhow::Result<Box<dyn DrawableGate>> {
+        Ok(Box::new(self.with_name(name)))
+    }
+
+    fn with_label_position(
+        &self,
+        _id: &str,
+        position: flow_gates::types::LabelPosition,
+    ) -> anyhow::Result<Box<dyn DrawableGate>> {
+        let mut moved = self.clone();
+        moved.inner.label_position = Some(position);
+        Ok(Box::new(moved))
+    }
+
+    // operands copied along with the gate are swapped for their copies
+    fn with_new_ids(
+        &self,
+        ids: &FxHashMap<Arc<str>, Arc<str>>,
+    ) -> anyhow::Result<Box<dyn DrawableGate>> {
+        let mut copy = self.with_id(new_gate_id(ids, &self.inner.id)?);
+        if let GateGeometry::Boolean { operands, .. } = &mut copy.inner.geometry {
+            for operand in operands.iter_mut() {
+                if let Some(new_id) = ids.get(operand) {
+                    *operand = new_id.clone();
+                }
+            }
+        }
+        Ok(Box::new(copy))
+    }
+
     fn clone_box(&self) -> Box<dyn DrawableGate> {
         Box::new(self.clone())
     }
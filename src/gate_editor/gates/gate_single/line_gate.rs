@@ -2,14 +2,16 @@ use std::sync::Arc;
 
 use anyhow::anyhow;
 use flow_fcs::TransformType;
-use flow_gates::{GateGeometry, create_rectangle_geometry};
+use flow_gates::{GateGeometry, create_rectangle_geometry, types::LabelPosition};
+use rustc_hash::FxHashMap;
 
 use crate::gate_editor::{
     gates::{
         gate_drag::{GateDragData, PointDragData},
+        gate_label::{GateLabels, label_offset, label_shape},
         gate_single::rescale_helper,
-        gate_traits::DrawableGate,
-        gate_types::{DEFAULT_LINE, GateRenderShape, GateStats, SELECTED_LINE, ShapeType},
+        gate_traits::{DrawableGate, new_gate_id},
+        gate_types::{DEFAULT_LINE, GateRenderShape, SELECTED_LINE, ShapeType},
     },
     plots::axis_store::PlotMapper,
 };
@@ -24,6 +26,28 @@ pub struct LineGate {
 }
 
 impl LineGate {
+    pub fn with_name(&self, name: &str) -> Self {
+        let mut renamed = self.clone();
+        renamed.inner.name = name.to_string();
+        renamed
+    }
+
+    pub fn with_label_position(&self, position: LabelPosition) -> Self {
+        let mut moved = self.clone();
+        moved.inner.label_position = Some(position);
+        moved
+    }
+
+    pub fn label_position(&self) -> &Option<LabelPosition> {
+        &self.inner.label_position
+    }
+
+    pub fn with_id(&self, id: Arc<str>) -> Self {
+        let mut copy = self.clone();
+        copy.inner.id = id;
+        copy
+    }
+
     pub fn try_new(gate: flow_gates::Gate, height: f32, is_primary: bool) -> anyhow::Result<Self> {
         let p = {
             if let GateGeometry::Rectangle { min, max } = &gate.geometry {
@@ -166,6 +190,25 @@ impl DrawableGate for LineGate {
     fn get_inner_gate_ids(&self) -> Vec<Arc<str>> {
         vec![self.inner.id.clone()]
     }
+    fn rename(&self, _id: &str, name: &str) -> anyhow::Result<Box<dyn DrawableGate>> {
+        Ok(Box::new(self.with_name(name)))
+    }
+
+    fn with_label_position(
+        &self,
+        _id: &str,
+        position: LabelPosition,
+    ) -> anyhow::Result<Box<dyn DrawableGate>> {
+        Ok(Box::new(self.with_label_position(position)))
+    }
+
+    fn with_new_ids(
+        &self,
+        ids: &FxHashMap<Arc<str>, Arc<str>>,
+    ) -> anyhow::Result<Box<dyn DrawableGate>> {
+        Ok(Box::new(self.with_id(new_gate_id(ids, &self.inner.id)?)))
+    }
+
     fn clone_box(&self) -> Box<dyn DrawableGate> {
         Box::new(self.clone())
     }
@@ -281,7 +324,7 @@ impl DrawableGate for LineGate {
         is_selected: bool,
         drag_point: Option<PointDragData>,
         plot_map: &PlotMapper,
-        gate_stats: &Option<GateStats>,
+        label_text: &GateLabels,
     ) -> Vec<GateRenderShape> {
         let (min, max) = {
             let (xmin, xmax) = {
@@ -339,46 +382,27 @@ impl DrawableGate for LineGate {
         };
         let mut labels = vec![];
 
-        if let Some(gate_stats) = gate_stats {
-            let x_offset = {
-                let axis = plot_map.x_axis_min_max();
-                let xrange = *axis.end() - *axis.start();
-                if let Some(label_pos) = &self.inner.label_position {
-                    xrange * label_pos.offset_x
-                } else if self.axis_matched {
-                    0f32
-                } else {
-                    xrange * 0.02
-                }
+        if let Some(text) = label_text.get(&self.inner.id) {
+            let default = if self.axis_matched {
+                (0.0, 0.02)
+            } else {
+                (0.02, 0.0)
             };
-            let y_offset = {
-                let axis = plot_map.y_axis_min_max();
-                let yrange = *axis.end() - *axis.start();
-                if let Some(label_pos) = &self.inner.label_position {
-                    yrange * label_pos.offset_y
-                } else if self.axis_matched {
-                    yrange * 0.02
-                } else {
-                    0f32
-                }
+            let offset = label_offset(&self.inner.label_position, default, plot_map);
+            let origin = if self.axis_matched {
+                (self.points[0].0, self.height)
+            } else {
+                (self.height, self.points[0].1)
             };
-            let offset = (x_offset, y_offset);
-            if let Some(percent) = gate_stats.get_percent_for_id(self.inner.id.clone()) {
-                let origin = if self.axis_matched {
-                    (self.points[0].0, self.height)
-                } else {
-                    (self.height, self.points[0].1)
-                };
-                let shape = GateRenderShape::Text {
-                    origin,
-                    offset,
-                    fontsize: 10f32,
-                    text: format!("{:.2}%", percent),
-                    text_anchor: None,
-                    shape_type: ShapeType::Text,
-                };
-                labels.push(shape)
-            }
+            labels.push(label_shape(
+                self.inner.id.clone(),
+                text.clone(),
+                origin,
+                offset,
+                None,
+                None,
+                plot_map,
+            ));
         }
 
         let labels = Some(labels);
@@ -1,7 +1,7 @@
 use flow_fcs::TransformType;
 
 use crate::gate_editor::{
-    axis_info::{asinh_reverse_f32, asinh_transform_f32},
+    axis_info::{reverse_f32, transform_f32},
     gates::gate_types::{GateRenderShape, ShapeType},
 };
 
@@ -39,18 +39,8 @@ pub fn rescale_helper(
     println!("{:?}", new_pts);
     for p in new_pts.iter_mut() {
         let val = if is_x { &mut p.0 } else { &mut p.1 };
-        let raw = match old {
-            TransformType::Arcsinh { cofactor } => {
-                asinh_reverse_f32(*val, *cofactor).unwrap_or(*val)
-            }
-            _ => *val,
-        };
-        *val = match new {
-            TransformType::Arcsinh { cofactor } => {
-                asinh_transform_f32(raw, *cofactor).unwrap_or(raw)
-            }
-            _ => raw,
-        };
+        let raw = reverse_f32(*val, old).unwrap_or(*val);
+        *val = transform_f32(raw, new).unwrap_or(raw);
     }
     Ok(new_pts)
 }
@@ -65,14 +55,8 @@ pub fn rescale_helper_point(
     let is_x = x_param == param;
 
     let mut val = if is_x { pt.0 } else { pt.1 };
-    let raw = match old {
-        TransformType::Arcsinh { cofactor } => asinh_reverse_f32(val, *cofactor).unwrap_or(val),
-        _ => val,
-    };
-    val = match new {
-        TransformType::Arcsinh { cofactor } => asinh_transform_f32(raw, *cofactor).unwrap_or(raw),
-        _ => raw,
-    };
+    let raw = reverse_f32(val, old).unwrap_or(val);
+    val = transform_f32(raw, new).unwrap_or(raw);
 
     let new_point = if is_x { (val, pt.1) } else { (pt.0, val) };
     Ok(new_point)
@@ -84,14 +68,8 @@ pub fn rescale_helper_single(
     new: &TransformType,
 ) -> anyhow::Result<f32> {
     let mut val = pt;
-    let raw = match old {
-        TransformType::Arcsinh { cofactor } => asinh_reverse_f32(val, *cofactor).unwrap_or(val),
-        _ => val,
-    };
-    val = match new {
-        TransformType::Arcsinh { cofactor } => asinh_transform_f32(raw, *cofactor).unwrap_or(raw),
-        _ => raw,
-    };
+    let raw = reverse_f32(val, old).unwrap_or(val);
+    val = transform_f32(raw, new).unwrap_or(raw);
 
     Ok(val)
 }
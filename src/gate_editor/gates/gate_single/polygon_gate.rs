@@ -2,14 +2,16 @@ use std::sync::Arc;
 
 use anyhow::anyhow;
 use flow_fcs::TransformType;
-use flow_gates::{GateGeometry, create_polygon_geometry};
+use flow_gates::{GateGeometry, create_polygon_geometry, types::LabelPosition};
+use rustc_hash::FxHashMap;
 
 use crate::gate_editor::{
     gates::{
         gate_drag::{GateDragData, PointDragData},
+        gate_label::{GateLabels, label_offset, label_shape},
         gate_single::{draw_circles_for_selected_gate, rescale_helper},
-        gate_traits::DrawableGate,
-        gate_types::{DEFAULT_LINE, GateRenderShape, GateStats, SELECTED_LINE, ShapeType},
+        gate_traits::{DrawableGate, new_gate_id},
+        gate_types::{DEFAULT_LINE, GateRenderShape, SELECTED_LINE, ShapeType},
     },
     plots::axis_store::PlotMapper,
 };
@@ -22,6 +24,24 @@ pub struct PolygonGate {
 }
 
 impl PolygonGate {
+    pub fn with_name(&self, name: &str) -> Self {
+        let mut renamed = self.clone();
+        renamed.inner.name = name.to_string();
+        renamed
+    }
+
+    pub fn with_label_position(&self, position: LabelPosition) -> Self {
+        let mut moved = self.clone();
+        moved.inner.label_position = Some(position);
+        moved
+    }
+
+    pub fn with_id(&self, id: Arc<str>) -> Self {
+        let mut copy = self.clone();
+        copy.inner.id = id;
+        copy
+    }
+
     pub fn try_new(gate: flow_gates::Gate, is_primary: bool) -> anyhow::Result<Self> {
         let p;
         if let GateGeometry::Polygon { nodes, .. } = &gate.geometry {
@@ -134,11 +154,8 @@ impl PolygonGate {
         vec![]
     }
 
-    pub fn get_label_offset(&self) -> (f32, f32) {
-        match &self.inner.label_position {
-            Some(o) => (o.offset_x, o.offset_y),
-            None => (0f32, 0f32),
-        }
+    pub fn label_position(&self) -> &Option<LabelPosition> {
+        &self.inner.label_position
     }
 }
 
@@ -149,6 +166,25 @@ impl DrawableGate for PolygonGate {
     fn get_inner_gate_ids(&self) -> Vec<Arc<str>> {
         vec![self.inner.id.clone()]
     }
+    fn rename(&self, _id: &str, name: &str) -> anyhow::Result<Box<dyn DrawableGate>> {
+        Ok(Box::new(self.with_name(name)))
+    }
+
+    fn with_label_position(
+        &self,
+        _id: &str,
+        position: LabelPosition,
+    ) -> anyhow::Result<Box<dyn DrawableGate>> {
+        Ok(Box::new(self.with_label_position(position)))
+    }
+
+    fn with_new_ids(
+        &self,
+        ids: &FxHashMap<Arc<str>, Arc<str>>,
+    ) -> anyhow::Result<Box<dyn DrawableGate>> {
+        Ok(Box::new(self.with_id(new_gate_id(ids, &self.inner.id)?)))
+    }
+
     fn clone_box(&self) -> Box<dyn DrawableGate> {
         Box::new(self.clone())
     }
@@ -251,7 +287,7 @@ impl DrawableGate for PolygonGate {
         is_selected: bool,
         drag_point: Option<PointDragData>,
         plot_map: &PlotMapper,
-        gate_stats: &Option<GateStats>,
+        label_text: &GateLabels,
     ) -> Vec<GateRenderShape> {
         let style = if is_selected {
             &SELECTED_LINE
@@ -271,44 +307,23 @@ impl DrawableGate for PolygonGate {
 
         let mut labels = vec![];
 
-        if let Some(gate_stats) = gate_stats {
-            // let x_offset = {
-            //     let axis = plot_map.x_axis_min_max();
-            //     let xrange = *axis.end() - *axis.start();
-            //     if let Some(label_pos) = &self.inner.label_position {
-            //         xrange * label_pos.offset_x
-            //     } else {
-            //         0f32
-            //     }
-            // };
-            // let y_offset = {
-            //     let axis = plot_map.y_axis_min_max();
-            //     let yrange = *axis.end() - *axis.start();
-            //     if let Some(label_pos) = &self.inner.label_position {
-            //         yrange * label_pos.offset_y
-            //     } else {
-            //         0f32
-            //     }
-            // };
-            // let offset = (x_offset, y_offset);
-            let offset = (0f32, 0f32);
-            if let Some(percent) = gate_stats.get_percent_for_id(self.inner.id.clone()) {
-                let params = self.get_params();
-                let origin = self
-                    .inner
-                    .geometry
-                    .calculate_center(&params.0, &params.1)
-                    .expect("should not fail");
-                let shape = GateRenderShape::Text {
-                    origin,
-                    offset,
-                    fontsize: 10f32,
-                    text: format!("{:.2}%", percent),
-                    text_anchor: None,
-                    shape_type: ShapeType::Text,
-                };
-                labels.push(shape)
-            }
+        if let Some(text) = label_text.get(&self.inner.id) {
+            let params = self.get_params();
+            let origin = self
+                .inner
+                .geometry
+                .calculate_center(&params.0, &params.1)
+                .expect("should not fail");
+            let offset = label_offset(&self.inner.label_position, (0f32, 0f32), plot_map);
+            labels.push(label_shape(
+                self.inner.id.clone(),
+                text.clone(),
+                origin,
+                offset,
+                Some(String::from("middle")),
+                None,
+                plot_map,
+            ));
         }
 
         let labels = Some(labels);
@@ -1,20 +1,40 @@
 use std::sync::Arc;
 
-use rustc_hash::FxHashMap;
+use rustc_hash::{FxHashMap, FxHashSet};
 
 use crate::gate_editor::{
     gates::{
+        GateId,
+        gate_filtering::PopulationMasks,
+        gate_single::boolean_gates::BooleanGate,
+        gate_store::ROOTGATE,
         gate_traits::DrawableGate,
         gate_types::{GateStatValue, GateStats},
     },
     plots::plot_store::EventIndexMapped,
 };
 
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PopulationCount {
+    pub count: usize,
+    pub percent_parent: f32,
+}
+
+// per gate counts for the current sample - an error string if the population can't be evaluated
+pub type PopulationCounts = FxHashMap<GateId, Result<PopulationCount, String>>;
+
 pub fn get_percent_and_counts_gate(
     gate: Arc<dyn DrawableGate>,
     event_index_map: &EventIndexMapped,
     parental_events: f32,
 ) -> anyhow::Result<GateStats> {
+    // the event index only knows about the plotted region - booleans need their operands' populations
+    if gate.as_any().is::<BooleanGate>() {
+        return Err(anyhow::anyhow!(
+            "Boolean gate {} must be counted with get_population_counts",
+            gate.get_id()
+        ));
+    }
     if !gate.is_composite() {
         let inner = gate.get_gate_ref(None).unwrap();
         let events = event_index_map.event_index.filter_by_gate(inner)?;
@@ -49,3 +69,35 @@ pub fn get_percent_and_counts_gate(
         Ok(stats)
     }
 }
+
+// counts for every gate in the hierarchy, or only those in `only`
+pub fn get_population_counts(
+    mut populations: PopulationMasks<'_>,
+    only: Option<&FxHashSet<GateId>>,
+) -> PopulationCounts {
+    let hierarchy = populations.hierarchy();
+    let mut counts = PopulationCounts::default();
+    for gate_id in hierarchy.iter_dfs(&ROOTGATE) {
+        if gate_id == *ROOTGATE || only.is_some_and(|only| !only.contains(&gate_id)) {
+            continue;
+        }
+        let result = populations
+            .parent_population(&gate_id)
+            .and_then(|parent| {
+                let count = populations.population(&gate_id)?.num_trues();
+                let parent_count = parent.num_trues();
+                let percent_parent = if parent_count == 0 {
+                    0f32
+                } else {
+                    (count as f32 / parent_count as f32) * 100f32
+                };
+                Ok(PopulationCount {
+                    count,
+                    percent_parent,
+                })
+            })
+            .map_err(|e| e.to_string());
+        counts.insert(gate_id, result);
+    }
+    counts
+}
@@ -1,4 +1,6 @@
+use crate::gate_editor::gates::boolean_expr::BooleanExpr;
 use crate::gate_editor::gates::gate_hierarchy::GateHierarchy;
+use crate::gate_editor::gates::gate_history::{GateHistory, GateSnapshot};
 use crate::gate_editor::gates::gate_single::boolean_gates::BooleanGate;
 use anyhow::anyhow;
 use dioxus::prelude::*;
@@ -19,17 +21,23 @@ use crate::gate_editor::{
             skewed_quadrant_gate::SkewedQuadrantGate,
         },
         gate_drag::GateDragData,
+        gate_filtering::check_population_dependencies,
+        gate_label::label_position_for_offset,
         gate_single::{
             ellipse_gate::{EllipseGate, create_default_ellipse},
             line_gate::{LineGate, create_default_line},
             polygon_gate::PolygonGate,
             rectangle_gate::{RectangleGate, create_default_rectangle},
         },
-        gate_traits::DrawableGate,
+        gate_traits::{DrawableGate, new_gate_id},
         gate_types::PrimaryGateType,
     },
     plots::axis_store::PlotMapper,
 };
+use crate::gate_editor::gates::gate_record::{
+    GateRecordKind, GateStateRecord, HierarchyRecord, boolean_gate_from_record,
+    drawable_from_record, gate_to_record,
+};
 use crate::omiq::deserialise::{
     BooleanOpType, CompositeType, FilterContainer, find_atomic_params,
     get_composite_gates_from_filter_container,
@@ -67,11 +75,25 @@ impl GatesOnPlotKey {
     }
 }
 
-#[derive(Default)]
-pub struct GateMap(pub FxHashMap<GateId, Arc<dyn DrawableGate + 'static>>);
+// im maps share their structure between clones, so an undo snapshot only copies the
+// entries an edit touches
+#[derive(Clone, Default)]
+pub struct GateMap(pub im::HashMap<GateId, Arc<dyn DrawableGate + 'static>, FxBuildHasher>);
+
+impl GateMap {
+    // subgates of a composite have their own names
+    pub fn display_name(&self, id: &str) -> Option<String> {
+        let gate = self.get(id)?;
+        Some(
+            gate.get_gate_ref(Some(id))
+                .map(|inner| inner.name.clone())
+                .unwrap_or_else(|| gate.get_name().to_string()),
+        )
+    }
+}
 
 impl Deref for GateMap {
-    type Target = FxHashMap<GateId, Arc<dyn DrawableGate + 'static>>;
+    type Target = im::HashMap<GateId, Arc<dyn DrawableGate + 'static>, FxBuildHasher>;
 
     fn deref(&self) -> &Self::Target {
         &self.0
@@ -91,18 +113,262 @@ pub enum GateSource {
     Sample((GateId, FileId)),
 }
 
-pub type GroupGateMap = FxHashMap<(GateId, MetaDataKey), Arc<dyn DrawableGate>>;
-pub type SampleGateMap = FxHashMap<(GateId, FileId), Arc<dyn DrawableGate>>;
+impl std::fmt::Display for GateSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GateSource::Global => write!(f, "all samples"),
+            GateSource::Group((_, key)) => write!(f, "{} {}", key.parameter, key.group),
+            GateSource::Sample(_) => write!(f, "this sample only"),
+        }
+    }
+}
 
-#[derive(Default, Store)]
+// which level a gate edit is written to, relative to the sample being edited
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum GateEditMode {
+    #[default]
+    Global,
+    Group(MetaDataParameter),
+    Sample,
+}
+
+pub type GroupGateMap = im::HashMap<(GateId, MetaDataKey), Arc<dyn DrawableGate>, FxBuildHasher>;
+pub type SampleGateMap = im::HashMap<(GateId, FileId), Arc<dyn DrawableGate>, FxBuildHasher>;
+// gate id -> the gates linked to it
+pub type GateLinks = im::HashMap<GateId, Vec<GateId>, FxBuildHasher>;
+
+#[derive(Clone, Default, Store)]
 pub struct GateSubStore {
     pub primary_and_subgate_registry: GateMap,
     pub sample_position_overrides: SampleGateMap,
     pub group_position_overrides: GroupGateMap,
+    // linked gates share one definition - each drawable id maps to every gate in its link
+    pub gate_links: GateLinks,
+}
+
+// a gate is stored under its own id and, for a composite, each subgate id
+fn stored_ids(gate: &Arc<dyn DrawableGate>) -> Vec<GateId> {
+    let mut ids = vec![gate.get_id()];
+    if gate.is_composite() {
+        ids.extend(gate.get_inner_gate_ids());
+    }
+    ids
+}
+
+// the hierarchy nodes a gate hangs from - each subgate of a composite, otherwise the gate itself
+fn placed_ids(gate: &Arc<dyn DrawableGate>) -> Vec<GateId> {
+    if gate.is_composite() {
+        gate.get_inner_gate_ids()
+    } else {
+        vec![gate.get_id()]
+    }
+}
+
+impl GateSubStore {
+    // writes `gate` at `source` under every id it is stored under, and the same position for
+    // every gate linked to it - the gate id in a group or sample key doesn't matter
+    pub fn set_position(&mut self, source: &GateSource, gate: Arc<dyn DrawableGate>) {
+        for linked in self.linked_copies(&gate) {
+            self.write_position(source, linked);
+        }
+        self.write_position(source, gate);
+    }
+
+    fn write_position(&mut self, source: &GateSource, gate: Arc<dyn DrawableGate>) {
+        for id in stored_ids(&gate) {
+            match source {
+                GateSource::Global => {
+                    self.primary_and_subgate_registry.insert(id, gate.clone());
+                }
+                GateSource::Group((_, key)) => {
+                    self.group_position_overrides
+                        .insert((id, key.clone()), gate.clone());
+                }
+                GateSource::Sample((_, file_id)) => {
+                    self.sample_position_overrides
+                        .insert((id, file_id.clone()), gate.clone());
+                }
+            }
+        }
+    }
+
+    pub fn override_at(&self, source: &GateSource) -> Option<&Arc<dyn DrawableGate>> {
+        match source {
+            GateSource::Global => None,
+            GateSource::Group(key) => self.group_position_overrides.get(key),
+            GateSource::Sample(key) => self.sample_position_overrides.get(key),
+        }
+    }
+
+    pub fn remove_override(&mut self, source: &GateSource) -> Option<Arc<dyn DrawableGate>> {
+        let gate = self.override_at(source)?.clone();
+        let mut ids = stored_ids(&gate);
+        for linked in self.linked_copies(&gate) {
+            ids.extend(stored_ids(&linked));
+        }
+        for id in ids {
+            match source {
+                GateSource::Global => {}
+                GateSource::Group((_, key)) => {
+                    self.group_position_overrides.remove(&(id, key.clone()));
+                }
+                GateSource::Sample((_, file_id)) => {
+                    self.sample_position_overrides
+                        .remove(&(id, file_id.clone()));
+                }
+            }
+        }
+        Some(gate)
+    }
+
+    // adds `new` to the gates linked with `existing`
+    pub fn link(&mut self, existing: &GateId, new: GateId) {
+        let mut members = self
+            .gate_links
+            .get(existing)
+            .cloned()
+            .unwrap_or_else(|| vec![existing.clone()]);
+        if members.contains(&new) {
+            return;
+        }
+        members.push(new);
+        for member in &members {
+            self.gate_links.insert(member.clone(), members.clone());
+        }
+    }
+
+    // takes a gate out of its link - a link of one is no link at all
+    pub fn unlink(&mut self, id: &GateId) {
+        let Some(mut members) = self.gate_links.remove(id) else {
+            return;
+        };
+        members.retain(|member| member != id);
+        if members.len() < 2 {
+            for member in &members {
+                self.gate_links.remove(member);
+            }
+            return;
+        }
+        for member in &members {
+            self.gate_links.insert(member.clone(), members.clone());
+        }
+    }
+
+    // how many places the gate hanging from hierarchy node `id` is linked into
+    pub fn link_count(&self, id: &str) -> usize {
+        self.primary_and_subgate_registry
+            .get(id)
+            .and_then(|gate| self.gate_links.get(&gate.get_id()))
+            .map_or(0, |members| members.len())
+    }
+
+    // `id` and the matching gate, or subgate, of every gate linked to it
+    pub fn linked_ids(&self, id: &GateId) -> Vec<GateId> {
+        let Some(gate) = self.primary_and_subgate_registry.get(id) else {
+            return vec![id.clone()];
+        };
+        let Some(members) = self.gate_links.get(&gate.get_id()) else {
+            return vec![id.clone()];
+        };
+        members
+            .iter()
+            .filter_map(|member| self.ids_in(gate, member)?.get(id).cloned())
+            .collect()
+    }
+
+    // maps the ids of every linked gate after the first in its link onto the first's -
+    // on export a link is one filter container used by several nodes
+    pub fn canonical_ids(&self) -> FxHashMap<GateId, GateId> {
+        let mut canonical = FxHashMap::default();
+        for members in self.gate_links.values() {
+            let Some((first, rest)) = members.split_first() else {
+                continue;
+            };
+            for member in rest {
+                if let Some(ids) = self
+                    .primary_and_subgate_registry
+                    .get(member)
+                    .and_then(|gate| self.ids_in(gate, first))
+                {
+                    canonical.extend(ids);
+                }
+            }
+        }
+        canonical
+    }
+
+    // maps the ids of `gate`, subgates included, onto those of the gate `member`
+    fn ids_in(
+        &self,
+        gate: &Arc<dyn DrawableGate>,
+        member: &GateId,
+    ) -> Option<FxHashMap<Arc<str>, Arc<str>>> {
+        let other = self.primary_and_subgate_registry.get(member)?;
+        let mut ids: FxHashMap<Arc<str>, Arc<str>> = gate
+            .get_inner_gate_ids()
+            .into_iter()
+            .zip(other.get_inner_gate_ids())
+            .collect();
+        ids.insert(gate.get_id(), other.get_id());
+        Some(ids)
+    }
+
+    // `gate` under the ids of each of the other gates linked to it
+    fn linked_copies(&self, gate: &Arc<dyn DrawableGate>) -> Vec<Arc<dyn DrawableGate>> {
+        let drawable_id = gate.get_id();
+        let Some(members) = self.gate_links.get(&drawable_id) else {
+            return vec![];
+        };
+        members
+            .iter()
+            .filter(|member| **member != drawable_id)
+            .filter_map(|member| {
+                let ids = self.ids_in(gate, member)?;
+                gate.with_new_ids(&ids).ok().map(Arc::from)
+            })
+            .collect()
+    }
+
+    // Replaces every stored gate, keeping registry and override keys. A composite is stored
+    // under its own id and each subgate id - it's mapped once and the result stored under all
+    // of them, so its subgates keep sharing one definition.
+    pub fn map_gates(
+        &mut self,
+        f: &mut impl FnMut(&Arc<dyn DrawableGate>) -> Arc<dyn DrawableGate>,
+    ) {
+        let mut mapped: FxHashMap<*const (), Arc<dyn DrawableGate>> = FxHashMap::default();
+        let mut map = |gate: &Arc<dyn DrawableGate>| {
+            mapped
+                .entry(Arc::as_ptr(gate) as *const ())
+                .or_insert_with(|| f(gate))
+                .clone()
+        };
+
+        self.primary_and_subgate_registry = GateMap(
+            self.primary_and_subgate_registry
+                .iter()
+                .map(|(id, gate)| (id.clone(), map(gate)))
+                .collect(),
+        );
+
+        self.sample_position_overrides = self
+            .sample_position_overrides
+            .iter()
+            .map(|(key, gate)| (key.clone(), map(gate)))
+            .collect();
+
+        self.group_position_overrides = self
+            .group_position_overrides
+            .iter()
+            .map(|(key, gate)| (key.clone(), map(gate)))
+            .collect();
+    }
 }
 
 #[derive(Clone, Default, PartialEq)]
 pub struct GateOverrideResolver {
+    pub file_id: FileId,
+    pub group_ids: FxHashMap<MetaDataParameter, GroupId>,
     pub active_gates: im::HashMap<GateId, ComparableGate, FxBuildHasher>,
     pub gate_origins: im::HashMap<GateId, GateSource, FxBuildHasher>,
 }
@@ -143,6 +409,87 @@ impl GateOverrideResolver {
     //         .ok_or_else(|| anyhow::anyhow!("Gate {} has no internal data", id))
     // }
 
+    // sample overrides win over group overrides, which win over the global gate
+    pub fn build(
+        registry: &GateMap,
+        sample_overrides: &SampleGateMap,
+        group_overrides: &GroupGateMap,
+        file_id: &FileId,
+        group_ids: &FxHashMap<MetaDataParameter, GroupId>,
+    ) -> Self {
+        let mut active_gates: im::HashMap<Arc<str>, ComparableGate, FxBuildHasher> =
+            im::HashMap::with_hasher(FxBuildHasher);
+        let mut gate_origins = im::HashMap::with_hasher(FxBuildHasher);
+
+        for (default_id, base_arc) in &registry.0 {
+            if let Some((key, s_ovr)) =
+                sample_overrides.get_key_value(&(default_id.clone(), file_id.clone()))
+            {
+                active_gates.insert(default_id.clone(), s_ovr.clone().into());
+                gate_origins.insert(default_id.clone(), GateSource::Sample(key.clone()));
+            } else if let Some((key, g_ovr)) = group_ids.iter().find_map(|gid| {
+                let key = MetaDataKey {
+                    parameter: gid.0.clone(),
+                    group: gid.1.clone(),
+                };
+                group_overrides.get_key_value(&(default_id.clone(), key))
+            }) {
+                active_gates.insert(default_id.clone(), g_ovr.clone().into());
+                gate_origins.insert(default_id.clone(), GateSource::Group(key.clone()));
+            } else {
+                active_gates.insert(default_id.clone(), base_arc.clone().into());
+                gate_origins.insert(default_id.clone(), GateSource::Global);
+            }
+        }
+
+        Self {
+            file_id: file_id.clone(),
+            group_ids: group_ids.clone(),
+            active_gates,
+            gate_origins,
+        }
+    }
+
+    // Where an edit to `gate_id` made in `mode` is written for this sample. The edit has to
+    // stay visible, so it can't go beneath an override that wins for this sample.
+    pub fn edit_target(&self, gate_id: &GateId, mode: &GateEditMode) -> anyhow::Result<GateSource> {
+        let origin = self
+            .gate_origins
+            .get(gate_id)
+            .ok_or_else(|| anyhow!("error finding gate source for {}", gate_id))?;
+        let target = match mode {
+            GateEditMode::Global => GateSource::Global,
+            GateEditMode::Group(parameter) => {
+                let group = self
+                    .group_ids
+                    .get(parameter)
+                    .ok_or_else(|| anyhow!("This sample has no {} group", parameter))?;
+                GateSource::Group((
+                    gate_id.clone(),
+                    MetaDataKey {
+                        parameter: parameter.clone(),
+                        group: group.clone(),
+                    },
+                ))
+            }
+            GateEditMode::Sample => GateSource::Sample((gate_id.clone(), self.file_id.clone())),
+        };
+        let hidden = match (&target, origin) {
+            (_, GateSource::Global) | (GateSource::Sample(_), _) => false,
+            (GateSource::Group((_, target_key)), GateSource::Group((_, origin_key))) => {
+                target_key != origin_key
+            }
+            _ => true,
+        };
+        if hidden {
+            return Err(anyhow!(
+                "This gate is positioned for {} here - edit it at that level or reset it first",
+                origin
+            ));
+        }
+        Ok(target)
+    }
+
     fn resolve_drawable(&self, id: &str) -> anyhow::Result<Arc<dyn DrawableGate + 'static>> {
         let drawable = self
             .active_gates
@@ -168,8 +515,12 @@ pub struct GateState {
     // For the Filtering: "How are these gates nested?" - this is the master hierarchy
     hierarchy: GateHierarchy,
     // when deleting a gate, do you need to delete any boolean gates that depend on it?
-    boolean_gate_links: FxHashMap<GateId, Vec<GateId>>,
+    boolean_gate_links: GateLinks,
     gate_store: GateSubStore,
+    // undo/redo - not part of a saved project
+    history: GateHistory,
+    // where drags are written - see GateOverrideResolver::edit_target
+    edit_mode: GateEditMode,
 }
 
 #[store(pub name = GateStateImplExt)]
@@ -180,44 +531,20 @@ impl<Lens> Store<GateState, Lens> {
         group_ids: &FxHashMap<MetaDataParameter, GroupId>,
     ) -> Result<GateOverrideResolver> {
         // construct the GateResolver for this file
-        let mut active_gates: im::HashMap<Arc<str>, ComparableGate, FxBuildHasher> =
-            im::HashMap::with_hasher(FxBuildHasher);
-        let mut gate_origins = im::HashMap::with_hasher(FxBuildHasher);
-
-        {
-            let registry_binding = self.gate_store().primary_and_subgate_registry();
-            let registry = registry_binding.read();
-            let sample_ovr_binding = self.gate_store().sample_position_overrides();
-            let sample_overrides = sample_ovr_binding.read();
-            let group_ovr_binding = self.gate_store().group_position_overrides();
-            let group_overrides = group_ovr_binding.read();
-
-            for (default_id, base_arc) in &registry.0 {
-                if let Some((key, s_ovr)) =
-                    sample_overrides.get_key_value(&(default_id.clone(), file_id.clone()))
-                {
-                    active_gates.insert(default_id.clone(), s_ovr.clone().into());
-                    gate_origins.insert(default_id.clone(), GateSource::Sample(key.clone()));
-                } else if let Some((key, g_ovr)) = group_ids.iter().find_map(|gid| {
-                    let key = MetaDataKey {
-                        parameter: gid.0.clone(),
-                        group: gid.1.clone(),
-                    };
-                    group_overrides.get_key_value(&(default_id.clone(), key))
-                }) {
-                    active_gates.insert(default_id.clone(), g_ovr.clone().into());
-                    gate_origins.insert(default_id.clone(), GateSource::Group(key.clone()));
-                } else {
-                    active_gates.insert(default_id.clone(), base_arc.clone().into());
-                    gate_origins.insert(default_id.clone(), GateSource::Global);
-                }
-            }
-        }
-
-        Ok(GateOverrideResolver {
-            active_gates,
-            gate_origins,
-        })
+        let registry_binding = self.gate_store().primary_and_subgate_registry();
+        let registry = registry_binding.read();
+        let sample_ovr_binding = self.gate_store().sample_position_overrides();
+        let sample_overrides = sample_ovr_binding.read();
+        let group_ovr_binding = self.gate_store().group_position_overrides();
+        let group_overrides = group_ovr_binding.read();
+
+        Ok(GateOverrideResolver::build(
+            &registry,
+            &sample_overrides,
+            &group_overrides,
+            &file_id,
+            group_ids,
+        ))
     }
 
     fn get_gate_by_id(
@@ -341,6 +668,7 @@ impl<Lens> Store<GateState, Lens> {
             _ => panic!("add boolean gate with add_boolean_gate"),
         };
 
+        self.record_edit();
         let mut w = self.write();
 
         let gate_key = g.get_id();
@@ -399,6 +727,7 @@ impl<Lens> Store<GateState, Lens> {
         let id = Uuid::new_v4().to_string();
         let gate_id: Arc<str> = Arc::from(id.as_ref() as &str);
 
+        self.record_edit();
         self.boolean_gate_links().with_mut(|w| {
             for link in linked_gate_ids.iter() {
                 w.entry(link.clone())
@@ -430,6 +759,52 @@ impl<Lens> Store<GateState, Lens> {
         Ok(())
     }
 
+    // a boolean gate from an expression over gates under `parent` - returns its id
+    fn add_boolean_expression(
+        &mut self,
+        name: Option<String>,
+        expr: &BooleanExpr,
+        parent: GateId,
+    ) -> anyhow::Result<GateId> {
+        let before = self.peek().snapshot();
+        let mut state = self.write();
+        match state.add_boolean_expr(name, expr, &parent) {
+            Ok(id) => {
+                state.record_since(before);
+                Ok(id)
+            }
+            Err(e) => {
+                state.restore(before);
+                Err(e)
+            }
+        }
+    }
+
+    // replaces what an existing boolean gate combines, keeping its place and id
+    fn edit_boolean_gate(
+        &mut self,
+        gate_id: GateId,
+        name: Option<String>,
+        expr: &BooleanExpr,
+    ) -> anyhow::Result<()> {
+        let before = self.peek().snapshot();
+        let mut state = self.write();
+        match state.edit_boolean_expr(&gate_id, name, expr) {
+            Ok(_) => {
+                state.record_since(before);
+                Ok(())
+            }
+            Err(e) => {
+                state.restore(before);
+                Err(e)
+            }
+        }
+    }
+
+    fn get_boolean_expression(&self, gate_id: GateId) -> Option<BooleanExpr> {
+        self.peek().boolean_expr(&gate_id)
+    }
+
     fn remove_gate(&mut self, gate_id: GateId) -> anyhow::Result<()> {
         // build the collection of gates at the same level that need deleting
         // that's any composite 'brothers'
@@ -446,6 +821,7 @@ impl<Lens> Store<GateState, Lens> {
                 brothers.push(gate_id.clone());
             }
         }
+        self.record_edit();
         let mut state = self.write();
 
         let mut roots: HashSet<Arc<str>> = HashSet::default();
@@ -468,9 +844,10 @@ impl<Lens> Store<GateState, Lens> {
             if let Some((id, gate)) = state
                 .gate_store
                 .primary_and_subgate_registry
-                .remove_entry(&child_gate_id)
+                .remove_with_key(&child_gate_id)
             {
                 let drawable_gate_id = gate.get_id();
+                state.gate_store.unlink(&drawable_gate_id);
                 let params = gate.get_params();
                 let parent = state
                     .hierarchy
@@ -493,6 +870,7 @@ impl<Lens> Store<GateState, Lens> {
                     .retain(|(gid, _group_id), _| gid != &gate_id);
             }
         }
+        state.prune_unplaced_booleans();
         Ok(())
     }
 
@@ -508,41 +886,12 @@ impl<Lens> Store<GateState, Lens> {
             .resolve_drawable(&gate_id)?
             .replace_point(new_point, point_idx, plot_map)?;
         let new_gate_arc: Arc<dyn DrawableGate> = Arc::from(new_gate);
-        let gate_origin = resolver
-            .gate_origins
-            .get(&gate_id)
-            .ok_or_else(|| anyhow!("error finding gate source for {}", &gate_id))?
-            .clone();
-
-        let ids_to_update = if new_gate_arc.is_composite() {
-            let mut ids = new_gate_arc.get_inner_gate_ids();
-            ids.push(gate_id.clone());
-            ids
-        } else {
-            vec![gate_id.clone()]
-        };
+        let mode = self.edit_mode().peek().clone();
+        let target = resolver.edit_target(&gate_id, &mode)?;
 
-        self.gate_store().with_mut(|state| {
-            for id in ids_to_update {
-                match &gate_origin {
-                    GateSource::Global => {
-                        state
-                            .primary_and_subgate_registry
-                            .insert(id.clone(), new_gate_arc.clone());
-                    }
-                    GateSource::Group(k) => {
-                        state
-                            .group_position_overrides
-                            .insert(k.clone(), new_gate_arc.clone());
-                    }
-                    GateSource::Sample(k) => {
-                        state
-                            .sample_position_overrides
-                            .insert(k.clone(), new_gate_arc.clone());
-                    }
-                }
-            }
-        });
+        self.record_drag_edit();
+        self.gate_store()
+            .with_mut(|state| state.set_position(&target, new_gate_arc));
         Ok(())
     }
 
@@ -557,43 +906,14 @@ impl<Lens> Store<GateState, Lens> {
             .resolve_drawable(&gate_id)?
             .replace_points(gate_drag_data)?;
 
-        let gate_origin = resolver
-            .gate_origins
-            .get(&gate_id)
-            .ok_or_else(|| anyhow!("error finding gate source for {}", &gate_id))?
-            .clone();
+        let mode = self.edit_mode().peek().clone();
+        let target = resolver.edit_target(&gate_id, &mode)?;
 
         if let Some(new_gate) = new_gate {
             let new_gate_arc: Arc<dyn DrawableGate> = Arc::from(new_gate);
-            let ids_to_update = if new_gate_arc.is_composite() {
-                let mut ids = new_gate_arc.get_inner_gate_ids();
-                ids.push(gate_id.clone());
-                ids
-            } else {
-                vec![gate_id.clone()]
-            };
-
-            self.gate_store().with_mut(|state| {
-                for id in ids_to_update {
-                    match &gate_origin {
-                        GateSource::Global => {
-                            state
-                                .primary_and_subgate_registry
-                                .insert(id.clone(), new_gate_arc.clone());
-                        }
-                        GateSource::Group(k) => {
-                            state
-                                .group_position_overrides
-                                .insert(k.clone(), new_gate_arc.clone());
-                        }
-                        GateSource::Sample(k) => {
-                            state
-                                .sample_position_overrides
-                                .insert(k.clone(), new_gate_arc.clone());
-                        }
-                    }
-                }
-            });
+            self.record_drag_edit();
+            self.gate_store()
+                .with_mut(|state| state.set_position(&target, new_gate_arc));
         }
         Ok(())
     }
@@ -608,47 +928,43 @@ impl<Lens> Store<GateState, Lens> {
             .resolve_drawable(&gate_id)?
             .rotate_gate(current_position)?;
 
-        let gate_origin = resolver
-            .gate_origins
-            .get(&gate_id)
-            .ok_or_else(|| anyhow!("error finding gate source for {}", &gate_id))?
-            .clone();
+        let mode = self.edit_mode().peek().clone();
+        let target = resolver.edit_target(&gate_id, &mode)?;
 
         if let Some(new_gate) = new_gate {
             let new_gate_arc: Arc<dyn DrawableGate> = Arc::from(new_gate);
-            let ids_to_update = if new_gate_arc.is_composite() {
-                let mut ids = new_gate_arc.get_inner_gate_ids();
-                ids.push(gate_id.clone());
-                ids
-            } else {
-                vec![gate_id.clone()]
-            };
-
-            self.gate_store().with_mut(|state| {
-                for id in ids_to_update {
-                    match &gate_origin {
-                        GateSource::Global => {
-                            state
-                                .primary_and_subgate_registry
-                                .insert(id.clone(), new_gate_arc.clone());
-                        }
-                        GateSource::Group(k) => {
-                            state
-                                .group_position_overrides
-                                .insert(k.clone(), new_gate_arc.clone());
-                        }
-                        GateSource::Sample(k) => {
-                            state
-                                .sample_position_overrides
-                                .insert(k.clone(), new_gate_arc.clone());
-                        }
-                    }
-                }
-            });
+            self.record_drag_edit();
+            self.gate_store()
+                .with_mut(|state| state.set_position(&target, new_gate_arc));
         }
         Ok(())
     }
 
+    // stores where a dragged label now sits relative to its anchor - `label_id` is the gate
+    // itself or one of its subgates
+    fn move_gate_label(
+        &mut self,
+        gate_id: GateId,
+        label_id: GateId,
+        offset: (f32, f32),
+        mapper: &PlotMapper,
+        resolver: &GateOverrideResolver,
+    ) -> anyhow::Result<()> {
+        let position = label_position_for_offset(offset, mapper);
+        let new_gate = resolver
+            .resolve_drawable(&gate_id)?
+            .with_label_position(&label_id, position)?;
+
+        let mode = self.edit_mode().peek().clone();
+        let target = resolver.edit_target(&gate_id, &mode)?;
+
+        let new_gate_arc: Arc<dyn DrawableGate> = Arc::from(new_gate);
+        self.record_drag_edit();
+        self.gate_store()
+            .with_mut(|state| state.set_position(&target, new_gate_arc));
+        Ok(())
+    }
+
     fn get_gates_for_plot<T>(
         &mut self,
         x_axis_title: T,
@@ -719,32 +1035,13 @@ impl<Lens> Store<GateState, Lens> {
                     .ok_or_else(|| anyhow!("error finding gate source for {}", k))?
                     .clone();
 
-                updates.push((
-                    new_gate_arc.get_id(),
-                    new_gate_arc.clone(),
-                    gate_origin.clone(),
-                ));
-
-                if new_gate_arc.is_composite() {
-                    for sub_id in new_gate_arc.get_inner_gate_ids() {
-                        updates.push((sub_id, new_gate_arc.clone(), gate_origin.clone()));
-                    }
-                }
+                updates.push((new_gate_arc, gate_origin));
             }
         }
+        // lining gates up with the plot's axes isn't an edit, so it isn't recorded
         self.gate_store().with_mut(|s| {
-            for (k, v, o) in updates {
-                match &o {
-                    GateSource::Global => {
-                        s.primary_and_subgate_registry.insert(k.clone(), v.clone());
-                    }
-                    GateSource::Group(k) => {
-                        s.group_position_overrides.insert(k.clone(), v.clone());
-                    }
-                    GateSource::Sample(k) => {
-                        s.sample_position_overrides.insert(k.clone(), v.clone());
-                    }
-                }
+            for (gate, origin) in updates {
+                s.set_position(&origin, gate);
             }
         });
 
@@ -760,65 +1057,58 @@ impl<Lens> Store<GateState, Lens> {
         new_axis_options: &AxisInfo,
     ) -> Result<(), Vec<String>> {
         let mut errors = vec![];
-
-        self.gate_store().with_mut(|s| {
-            let mut memo: FxHashMap<usize, Arc<dyn DrawableGate>> = FxHashMap::default();
-            let mut scale_gate = |gate: &Arc<dyn DrawableGate>| -> Arc<dyn DrawableGate> {
-                // to avoid rescaling composite gates over and over (as they are also stored under subgate id's)
-                // we load any scaled gates into a hashmap
-                // we compare by heap memory address - anything pointing to the same address
-                // will use the cached rescaled value
-                let ptr = Arc::as_ptr(gate) as *const () as usize;
-                if let Some(scaled) = memo.get(&ptr) {
-                    return scaled.clone();
-                }
-                let (x_marker, y_marker) = gate.get_params();
-                // let is_x = marker == &x_marker;
-                // let data_range = if is_x {
-                //     (*(plot_map.x_data_min_max().start()), *(plot_map.x_data_min_max().end()))
-                // } else {
-                //     (*(plot_map.y_data_min_max().start()), *(plot_map.y_data_min_max().end()))
-                // };
-                
-                if marker == &x_marker || marker == &y_marker {
-                    let new_gate = match gate.recalculate_gate_for_rescaled_axis(
-                        marker.clone(),
-                        &old_axis_options.transform,
-                        &new_axis_options.transform,
-                        // data_range,
-                        (new_axis_options.axis_lower, new_axis_options.axis_upper),
-                    ) {
-                        Ok(new_gate) => Arc::from(new_gate),
-                        Err(e) => {
-                            errors.push(e.to_string());
-                            gate.clone()
-                        }
-                    };
-                    memo.insert(ptr, new_gate.clone());
-                    new_gate
-                } else {
-                    gate.clone()
+        // to avoid rescaling composite gates over and over (as they are also stored under subgate id's)
+        // we load any scaled gates into a hashmap
+        // we compare by heap memory address - anything pointing to the same address
+        // will use the cached rescaled value
+        let mut memo: FxHashMap<usize, Arc<dyn DrawableGate>> = FxHashMap::default();
+        let mut scale_gate = |gate: &Arc<dyn DrawableGate>| {
+            let ptr = Arc::as_ptr(gate) as *const () as usize;
+            if let Some(scaled) = memo.get(&ptr) {
+                return Ok(scaled.clone());
+            }
+            let (x_marker, y_marker) = gate.get_params();
+            // let is_x = marker == &x_marker;
+            // let data_range = if is_x {
+            //     (*(plot_map.x_data_min_max().start()), *(plot_map.x_data_min_max().end()))
+            // } else {
+            //     (*(plot_map.y_data_min_max().start()), *(plot_map.y_data_min_max().end()))
+            // };
+
+            let new_gate = if marker == &x_marker || marker == &y_marker {
+                match gate.recalculate_gate_for_rescaled_axis(
+                    marker.clone(),
+                    &old_axis_options.transform,
+                    &new_axis_options.transform,
+                    // data_range,
+                    (new_axis_options.axis_lower, new_axis_options.axis_upper),
+                ) {
+                    Ok(new_gate) => Arc::from(new_gate),
+                    Err(e) => {
+                        memo.insert(ptr, gate.clone());
+                        return Err(e);
+                    }
                 }
+            } else {
+                gate.clone()
             };
+            memo.insert(ptr, new_gate.clone());
+            Ok(new_gate)
+        };
 
-            s.primary_and_subgate_registry = GateMap(
-                s.primary_and_subgate_registry
-                    .iter()
-                    .map(|(id, gate)| (id.clone(), scale_gate(gate)))
-                    .collect(),
-            );
-
-            s.sample_position_overrides = s
-                .sample_position_overrides
-                .iter()
-                .map(|(key, gate)| (key.clone(), scale_gate(gate)))
-                .collect();
-
-            s.group_position_overrides = s
-                .group_position_overrides
-                .iter()
-                .map(|(key, gate)| (key.clone(), scale_gate(gate)))
-                .collect();
+        self.gate_store().with_mut(|s| {
+            s.map_gates(&mut |gate: &Arc<dyn DrawableGate>| {
+                scale_gate(gate).unwrap_or_else(|e| {
+                    errors.push(e.to_string());
+                    gate.clone()
+                })
+            })
+        });
+        // undo history has to follow the gates into the new axis space
+        self.history().with_mut(|h| {
+            h.map_gates(&mut |gate: &Arc<dyn DrawableGate>| {
+                scale_gate(gate).unwrap_or_else(|_| gate.clone())
+            })
         });
         if errors.is_empty() {
             Ok(())
@@ -835,58 +1125,51 @@ impl<Lens> Store<GateState, Lens> {
         transform: TransformType,
     ) -> Result<(), Vec<String>> {
         let mut errors = vec![];
-
-        self.gate_store().with_mut(|s| {
-            let mut memo: FxHashMap<usize, Arc<dyn DrawableGate>> = FxHashMap::default();
-            let mut scale_gate = |gate: &Arc<dyn DrawableGate>| -> Arc<dyn DrawableGate> {
-                // to avoid rescaling composite gates over and over (as they are also stored under subgate id's)
-                // we load any scaled gates into a hashmap
-                // we compare by heap memory address - anything pointing to the same address
-                // will use the cached rescaled value
-                let ptr = Arc::as_ptr(gate) as *const () as usize;
-                if let Some(scaled) = memo.get(&ptr) {
-                    return scaled.clone();
-                }
-                let (x_marker, y_marker) = gate.get_params();
-                if axis_name == x_marker || axis_name == y_marker {
-                    let new_gate = match gate.recalculate_gate_for_new_axis_limits(
-                        axis_name.clone(),
-                        lower,
-                        upper,
-                        &transform,
-                    ) {
-                        Ok(Some(new_gate)) => Arc::from(new_gate),
-                        Ok(None) => gate.clone(),
-                        Err(e) => {
-                            errors.push(e.to_string());
-                            gate.clone()
-                        }
-                    };
-                    memo.insert(ptr, new_gate.clone());
-                    new_gate
-                } else {
-                    gate.clone()
+        // to avoid rescaling composite gates over and over (as they are also stored under subgate id's)
+        // we load any scaled gates into a hashmap
+        // we compare by heap memory address - anything pointing to the same address
+        // will use the cached rescaled value
+        let mut memo: FxHashMap<usize, Arc<dyn DrawableGate>> = FxHashMap::default();
+        let mut scale_gate = |gate: &Arc<dyn DrawableGate>| {
+            let ptr = Arc::as_ptr(gate) as *const () as usize;
+            if let Some(scaled) = memo.get(&ptr) {
+                return Ok(scaled.clone());
+            }
+            let (x_marker, y_marker) = gate.get_params();
+            let new_gate = if axis_name == x_marker || axis_name == y_marker {
+                match gate.recalculate_gate_for_new_axis_limits(
+                    axis_name.clone(),
+                    lower,
+                    upper,
+                    &transform,
+                ) {
+                    Ok(Some(new_gate)) => Arc::from(new_gate),
+                    Ok(None) => gate.clone(),
+                    Err(e) => {
+                        memo.insert(ptr, gate.clone());
+                        return Err(e);
+                    }
                 }
+            } else {
+                gate.clone()
             };
+            memo.insert(ptr, new_gate.clone());
+            Ok(new_gate)
+        };
 
-            s.primary_and_subgate_registry = GateMap(
-                s.primary_and_subgate_registry
-                    .iter()
-                    .map(|(id, gate)| (id.clone(), scale_gate(gate)))
-                    .collect(),
-            );
-
-            s.sample_position_overrides = s
-                .sample_position_overrides
-                .iter()
-                .map(|(key, gate)| (key.clone(), scale_gate(gate)))
-                .collect();
-
-            s.group_position_overrides = s
-                .group_position_overrides
-                .iter()
-                .map(|(key, gate)| (key.clone(), scale_gate(gate)))
-                .collect();
+        self.gate_store().with_mut(|s| {
+            s.map_gates(&mut |gate: &Arc<dyn DrawableGate>| {
+                scale_gate(gate).unwrap_or_else(|e| {
+                    errors.push(e.to_string());
+                    gate.clone()
+                })
+            })
+        });
+        // undo history has to follow the gates into the new axis space
+        self.history().with_mut(|h| {
+            h.map_gates(&mut |gate: &Arc<dyn DrawableGate>| {
+                scale_gate(gate).unwrap_or_else(|_| gate.clone())
+            })
         });
 
         if errors.is_empty() {
@@ -896,6 +1179,25 @@ impl<Lens> Store<GateState, Lens> {
         }
     }
 
+    // sample or group positions for existing gates, eg from auto-adjust
+    fn insert_position_overrides(
+        &mut self,
+        overrides: Vec<(GateSource, Arc<dyn DrawableGate>)>,
+    ) -> anyhow::Result<()> {
+        if overrides
+            .iter()
+            .any(|(source, _)| matches!(source, GateSource::Global))
+        {
+            return Err(anyhow!("Position overrides must be for a sample or group"));
+        }
+        self.record_edit();
+        let mut state = self.write();
+        for (source, gate) in overrides {
+            state.gate_store.set_position(&source, gate);
+        }
+        Ok(())
+    }
+
     fn get_gate_name(&self, id: GateId) -> Option<String> {
         if let Some(g) = self
             .gate_store()
@@ -909,19 +1211,259 @@ impl<Lens> Store<GateState, Lens> {
         None
     }
 
-    fn upload_gates_from_file(
+    fn rename_gate(&mut self, gate_id: GateId, name: &str) -> anyhow::Result<()> {
+        // only recorded once the name has been accepted
+        let before = self.peek().snapshot();
+        let mut state = self.write();
+        state.rename(&gate_id, name)?;
+        state.record_since(before);
+        Ok(())
+    }
+
+    // moves a gate (all of a composite) under `new_parent`, with its branch or leaving its
+    // children with its old parent
+    fn move_gate_to(
         &mut self,
-        path: PathBuf,
-        metadata: &crate::omiq::metadata::MetaDataFileMap,
-        axis_settings: im::HashMap<Arc<str>, AxisInfo, FxBuildHasher>,
+        gate_id: GateId,
+        new_parent: GateId,
+        with_descendants: bool,
     ) -> anyhow::Result<()> {
-        // 1. Open the file
-        let file = std::fs::File::open(&path)?;
-        let reader = std::io::BufReader::new(file);
-
-        // 2. Deserialize into your ExperimentJson struct
-        let experiment: crate::omiq::deserialise::ExperimentJson = serde_json::from_reader(reader)?;
+        let before = self.peek().snapshot();
+        let mut state = self.write();
+        state.move_to(&gate_id, &new_parent, with_descendants)?;
+        state.record_since(before);
+        Ok(())
+    }
 
+    // copies a gate and everything gated on it under `new_parent`, overrides included
+    fn copy_branch_to(&mut self, gate_id: GateId, new_parent: GateId) -> anyhow::Result<()> {
+        let before = self.peek().snapshot();
+        let mut state = self.write();
+        state.copy_branch(&gate_id, &new_parent, true)?;
+        state.record_since(before);
+        Ok(())
+    }
+
+    // places `gate_id` under `new_parent` too - edits to either are shared
+    fn link_gate_to(&mut self, gate_id: GateId, new_parent: GateId) -> anyhow::Result<()> {
+        let before = self.peek().snapshot();
+        let mut state = self.write();
+        state.link_to(&gate_id, &new_parent)?;
+        state.record_since(before);
+        Ok(())
+    }
+
+    // stops sharing edits with the gates `gate_id` is linked to
+    fn unlink_gate(&mut self, gate_id: GateId) -> anyhow::Result<()> {
+        let before = self.peek().snapshot();
+        let mut state = self.write();
+        state.unlink(&gate_id)?;
+        state.record_since(before);
+        Ok(())
+    }
+
+    // deletes a gate, handing its children to its parent
+    fn remove_gate_keep_children(&mut self, gate_id: GateId) -> anyhow::Result<()> {
+        let before = self.peek().snapshot();
+        let mut state = self.write();
+        state.remove_keep_children(&gate_id)?;
+        state.record_since(before);
+        Ok(())
+    }
+
+    // call before a discrete edit - undo returns to the state captured here
+    fn record_edit(&mut self) {
+        let before = self.peek().snapshot();
+        self.history().with_mut(|h| {
+            // an edit can't belong to a drag that was never finished
+            h.end_gesture();
+            h.record(before);
+        });
+    }
+
+    // call before each step of a drag - only the first step of a gesture is recorded
+    fn record_drag_edit(&mut self) {
+        if !self.history().peek().in_gesture() {
+            self.record_edit();
+            return;
+        }
+        if self.history().peek().wants_gesture_start() {
+            let before = self.peek().snapshot();
+            self.history().write().record(before);
+        }
+    }
+
+    // starts a drag of `gate_id`, unless the edit mode would write it somewhere hidden
+    fn begin_edit(
+        &mut self,
+        gate_id: &GateId,
+        resolver: &GateOverrideResolver,
+    ) -> anyhow::Result<()> {
+        let mode = self.edit_mode().peek().clone();
+        resolver.edit_target(gate_id, &mode)?;
+        self.begin_gesture();
+        Ok(())
+    }
+
+    // makes a sample or group position the one every sample inherits
+    fn promote_override(&mut self, source: GateSource) -> anyhow::Result<()> {
+        let Some(gate) = self.gate_store().peek().override_at(&source).cloned() else {
+            return Err(anyhow!("No position for {} to promote", source));
+        };
+        self.record_edit();
+        self.gate_store().with_mut(|s| {
+            s.remove_override(&source);
+            s.set_position(&GateSource::Global, gate);
+        });
+        Ok(())
+    }
+
+    // drops a sample or group position, so the gate falls back to the inherited one
+    fn reset_override(&mut self, source: GateSource) -> anyhow::Result<()> {
+        if self.gate_store().peek().override_at(&source).is_none() {
+            return Err(anyhow!("No position for {} to reset", source));
+        }
+        self.record_edit();
+        self.gate_store().with_mut(|s| {
+            s.remove_override(&source);
+        });
+        Ok(())
+    }
+
+    fn begin_gesture(&mut self) {
+        self.history().write().begin_gesture();
+    }
+
+    fn end_gesture(&mut self) {
+        self.history().write().end_gesture();
+    }
+
+    fn undo(&mut self) -> bool {
+        if !self.history().peek().can_undo() {
+            return false;
+        }
+        self.write().undo()
+    }
+
+    fn redo(&mut self) -> bool {
+        if !self.history().peek().can_redo() {
+            return false;
+        }
+        self.write().redo()
+    }
+
+    fn upload_gates_from_file(
+        &mut self,
+        path: PathBuf,
+        metadata: &crate::omiq::metadata::MetaDataFileMap,
+        axis_settings: im::HashMap<Arc<str>, AxisInfo, FxBuildHasher>,
+    ) -> anyhow::Result<()> {
+        // 1. Open the file
+        let file = std::fs::File::open(&path)?;
+        let reader = std::io::BufReader::new(file);
+
+        // 2. Deserialize into your ExperimentJson struct
+        let experiment: crate::omiq::deserialise::ExperimentJson = serde_json::from_reader(reader)?;
+
+        // build into a fresh state so reloading replaces the current gates rather than merging
+        let mut state = GateState::default();
+        state.load_experiment(experiment, metadata, axis_settings)?;
+        *self.write() = state;
+        Ok(())
+    }
+
+    fn save_to_record(&self) -> anyhow::Result<GateStateRecord> {
+        self.peek().to_record()
+    }
+
+    fn load_from_record(
+        &mut self,
+        record: GateStateRecord,
+        axis_settings: &im::HashMap<Arc<str>, AxisInfo, FxBuildHasher>,
+    ) -> anyhow::Result<()> {
+        // build the whole state first so a bad project leaves the current one untouched
+        let state = GateState::from_record(record, axis_settings)?;
+        *self.write() = state;
+        Ok(())
+    }
+
+    fn export_gates_to_file(
+        &self,
+        path: PathBuf,
+        metadata: &crate::omiq::metadata::MetaDataFileMap,
+    ) -> anyhow::Result<()> {
+        let experiment = self.peek().to_experiment_json(metadata)?;
+
+        let file = std::fs::File::create(&path)?;
+        let writer = std::io::BufWriter::new(file);
+        serde_json::to_writer_pretty(writer, &experiment)?;
+        Ok(())
+    }
+}
+
+impl GateState {
+    fn snapshot(&self) -> GateSnapshot {
+        GateSnapshot {
+            gate_ids_by_view: self.gate_ids_by_view.clone(),
+            hierarchy: self.hierarchy.clone(),
+            boolean_gate_links: self.boolean_gate_links.clone(),
+            gate_store: self.gate_store.clone(),
+        }
+    }
+
+    fn restore(&mut self, snapshot: GateSnapshot) {
+        let GateSnapshot {
+            gate_ids_by_view,
+            hierarchy,
+            boolean_gate_links,
+            gate_store,
+        } = snapshot;
+        self.gate_ids_by_view = gate_ids_by_view;
+        self.hierarchy = hierarchy;
+        self.boolean_gate_links = boolean_gate_links;
+        self.gate_store = gate_store;
+
+        // the selection may not exist at this point in the history
+        if let Some(id) = &self.selected_gate
+            && !self
+                .gate_store
+                .primary_and_subgate_registry
+                .contains_key(id)
+        {
+            self.selected_gate = None;
+        }
+    }
+
+    // records an edit that has already been made, from the state before it
+    fn record_since(&mut self, before: GateSnapshot) {
+        self.history.end_gesture();
+        self.history.record(before);
+    }
+
+    fn undo(&mut self) -> bool {
+        let current = self.snapshot();
+        let Some(previous) = self.history.undo(current) else {
+            return false;
+        };
+        self.restore(previous);
+        true
+    }
+
+    fn redo(&mut self) -> bool {
+        let current = self.snapshot();
+        let Some(next) = self.history.redo(current) else {
+            return false;
+        };
+        self.restore(next);
+        true
+    }
+
+    pub fn load_experiment(
+        &mut self,
+        experiment: crate::omiq::deserialise::ExperimentJson,
+        metadata: &crate::omiq::metadata::MetaDataFileMap,
+        axis_settings: im::HashMap<Arc<str>, AxisInfo, FxBuildHasher>,
+    ) -> anyhow::Result<()> {
         let mut reachable: FxHashSet<Arc<str>> = FxHashSet::default();
         for node in experiment.tree.nodes.values() {
             collect_reachable(&node.filter_container_id, &experiment.tree.filter_containers, &mut reachable);
@@ -988,12 +1530,11 @@ impl<Lens> Store<GateState, Lens> {
             node_to_gate_id.insert(node_id.clone(), node.filter_container_id.clone());
         }
 
-        let mut w = self.write();
-
         let mut sorted_nodes: Vec<_> = experiment.tree.nodes.values().collect();
 
         // 2. Sort nodes by their depth in the tree
-        // This ensures parents always exist before children
+        // This ensures parents always exist before children - and by id within a depth,
+        // so the same node always places a linked gate
         sorted_nodes.sort_by_cached_key(|node| {
             let mut depth = 0;
             let mut current_parent: &str = &node.parent_id;
@@ -1008,9 +1549,14 @@ impl<Lens> Store<GateState, Lens> {
                     break;
                 }
             }
-            depth
+            (depth, node.id.clone())
         });
 
+        // a filter container used by more than one node is a linked gate - the first node
+        // places the gate, the rest are instances hung from their own node id
+        let mut placed: FxHashSet<GateId> = FxHashSet::default();
+        let mut instances: Vec<(GateId, GateId, GateId)> = vec![];
+
         // build the hierarchy first.
         for node in sorted_nodes.into_iter() {
             // deal with composites - you need to add the sub-gates not the gates
@@ -1024,7 +1570,17 @@ impl<Lens> Store<GateState, Lens> {
             } else {
                 ROOTGATE.clone()
             };
-            w.hierarchy
+            if !placed.insert(node.filter_container_id.clone()) {
+                self.hierarchy.add_gate_child(
+                    parent_id.clone(),
+                    node.id.clone(),
+                    Some(node.ord),
+                )?;
+                node_to_gate_id.insert(node.id.clone(), node.id.clone());
+                instances.push((node.id.clone(), node.filter_container_id.clone(), parent_id));
+                continue;
+            }
+            self.hierarchy
                 .add_gate_child(parent_id, node.filter_container_id.clone(), Some(node.ord))?;
             node_to_gate_id.insert(node.id.clone(), node.filter_container_id.clone());
         }
@@ -1036,30 +1592,7 @@ impl<Lens> Store<GateState, Lens> {
 
             for (source, gate) in drawables {
                 // 4. Insert into your Store based on Source
-                match source {
-                    GateSource::Global => {
-                        let gate_id = gate.get_id();
-                        let parent =
-                            w.hierarchy.get_parent(&gate_id).cloned().ok_or_else(|| {
-                                anyhow::anyhow!("Could not locate parent of {} in hierarchy", gate_id)
-                            })?;
-                        let params = gate.get_params();
-                        let key = GatesOnPlotKey::new(params.0, params.1, Some(parent));
-                        w.gate_ids_by_view
-                            .entry(key)
-                            .or_default()
-                            .push(gate.get_id());
-                        w.gate_store
-                            .primary_and_subgate_registry
-                            .insert(gate.get_id(), gate);
-                    }
-                    GateSource::Group(key) => {
-                        w.gate_store.group_position_overrides.insert(key, gate);
-                    }
-                    GateSource::Sample(key) => {
-                        w.gate_store.sample_position_overrides.insert(key, gate);
-                    }
-                }
+                self.insert_loaded_gate(source, gate)?;
             }
         }
 
@@ -1071,54 +1604,8 @@ impl<Lens> Store<GateState, Lens> {
                 &axis_settings,
                 metadata,
             )?;
-            for ((id, source), gate) in to_add {
-                let subgate_ids = gate.get_inner_gate_ids();
-                match source {
-                    GateSource::Global => {
-                        let any_subgate = subgate_ids
-                            .first()
-                            .ok_or_else(|| anyhow::anyhow!("Composite gate has no subgates"))?;
-                        let parent =
-                            w.hierarchy
-                                .get_parent(any_subgate)
-                                .cloned()
-                                .ok_or_else(|| {
-                                    anyhow::anyhow!("Could not locate parent of subgate {} in hierarchy", any_subgate)
-                                })?;
-                        let params = gate.get_params();
-                        let key = GatesOnPlotKey::new(params.0, params.1, Some(parent));
-
-                        w.gate_ids_by_view.entry(key).or_default().push(id.clone());
-                        w.gate_store
-                            .primary_and_subgate_registry
-                            .insert(id.clone(), gate.clone());
-                        for sub_id in subgate_ids {
-                            w.gate_store
-                                .primary_and_subgate_registry
-                                .insert(sub_id, gate.clone());
-                        }
-                    }
-                    GateSource::Group(key) => {
-                        w.gate_store
-                            .group_position_overrides
-                            .insert(key.clone(), gate.clone());
-                        for sub_id in subgate_ids {
-                            w.gate_store
-                                .group_position_overrides
-                                .insert((sub_id, key.1.clone()), gate.clone());
-                        }
-                    }
-                    GateSource::Sample(key) => {
-                        w.gate_store
-                            .sample_position_overrides
-                            .insert(key.clone(), gate.clone());
-                        for sub_id in subgate_ids {
-                            w.gate_store
-                                .sample_position_overrides
-                                .insert((sub_id, key.1.clone()), gate.clone());
-                        }
-                    }
-                }
+            for ((_id, source), gate) in to_add {
+                self.insert_loaded_gate(source, gate)?;
             }
         }
 
@@ -1145,37 +1632,1808 @@ impl<Lens> Store<GateState, Lens> {
                 y_param,
             )?;
 
-            let arc_gate: Arc<dyn DrawableGate> = Arc::new(bool_gate);
-            for link_id in &boolean_gate.filter_container_ids {
-                w.boolean_gate_links
-                    .entry(link_id.clone())
-                    .or_default()
-                    .push(boolean_gate.id.clone());
+            self.insert_loaded_boolean_gate(Arc::new(bool_gate), &boolean_gate.filter_container_ids);
+        }
+
+        self.load_linked_instances(instances)?;
+
+        check_population_dependencies(&self.hierarchy, &self.gate_store.primary_and_subgate_registry)?;
+
+        Ok(())
+    }
+
+    // renames a gate, or one subgate of a composite, in every stored copy of it -
+    // and in every gate linked to it, which share its name
+    fn rename(&mut self, gate_id: &GateId, name: &str) -> anyhow::Result<()> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(anyhow!("Gate name can't be empty"));
+        }
+        let targets = self.gate_store.linked_ids(gate_id);
+        for id in &targets {
+            self.check_name_free(id, name)?;
+        }
+        for id in &targets {
+            self.rename_stored(id, name)?;
+        }
+        Ok(())
+    }
+
+    fn check_name_free(&self, gate_id: &GateId, name: &str) -> anyhow::Result<()> {
+        let parent = self
+            .hierarchy
+            .get_parent(gate_id)
+            .cloned()
+            .unwrap_or_else(|| ROOTGATE.clone());
+        let registry = &self.gate_store.primary_and_subgate_registry;
+        let name_taken = self
+            .hierarchy
+            .get_children(&parent)
+            .into_iter()
+            .filter(|id| *id != gate_id)
+            .filter_map(|id| registry.display_name(id))
+            .any(|sibling| sibling == name);
+        if name_taken {
+            return Err(anyhow!("There is already a gate called {} here", name));
+        }
+        Ok(())
+    }
+
+    fn rename_stored(&mut self, gate_id: &GateId, name: &str) -> anyhow::Result<()> {
+        let registry = &self.gate_store.primary_and_subgate_registry;
+        let drawable_id = registry
+            .get(gate_id)
+            .ok_or_else(|| anyhow!("Gate {} not found", gate_id))?
+            .get_id();
+
+        // a composite is shared between the keys of all its subgates, so rename each copy once
+        // and hold the old one so its address can't be reused
+        let mut renamed: FxHashMap<*const (), (Arc<dyn DrawableGate>, Arc<dyn DrawableGate>)> =
+            FxHashMap::default();
+        let mut rename = |gate: &mut Arc<dyn DrawableGate>| -> anyhow::Result<()> {
+            if gate.get_id() != drawable_id {
+                return Ok(());
             }
-            w.gate_store
+            let key = Arc::as_ptr(gate) as *const ();
+            let new_gate = match renamed.get(&key) {
+                Some((_, new_gate)) => new_gate.clone(),
+                None => {
+                    let new_gate: Arc<dyn DrawableGate> = Arc::from(gate.rename(gate_id, name)?);
+                    renamed.insert(key, (gate.clone(), new_gate.clone()));
+                    new_gate
+                }
+            };
+            *gate = new_gate;
+            Ok(())
+        };
+
+        let store = &mut self.gate_store;
+        for (_, gate) in store.primary_and_subgate_registry.iter_mut() {
+            rename(gate)?;
+        }
+        for (_, gate) in store.sample_position_overrides.iter_mut() {
+            rename(gate)?;
+        }
+        for (_, gate) in store.group_position_overrides.iter_mut() {
+            rename(gate)?;
+        }
+        Ok(())
+    }
+
+    // a gate and the parent it hangs from, if it can be moved
+    fn placed_gate(&self, gate_id: &GateId) -> anyhow::Result<(Arc<dyn DrawableGate>, GateId)> {
+        let gate = self
+            .gate_store
+            .primary_and_subgate_registry
+            .get(gate_id)
+            .cloned()
+            .ok_or_else(|| anyhow!("Gate {} not found", gate_id))?;
+        let placed_id = placed_ids(&gate)
+            .into_iter()
+            .next()
+            .ok_or_else(|| anyhow!("Gate {} has no subgates", gate_id))?;
+        let parent = self
+            .hierarchy
+            .get_parent(&placed_id)
+            .cloned()
+            .ok_or_else(|| anyhow!("Could not locate parent of {} in hierarchy", placed_id))?;
+        Ok((gate, parent))
+    }
+
+    fn check_parent(&self, parent: &GateId) -> anyhow::Result<()> {
+        if *parent != *ROOTGATE
+            && !self
+                .gate_store
                 .primary_and_subgate_registry
-                .insert(arc_gate.get_id(), arc_gate);
+                .contains_key(parent)
+        {
+            return Err(anyhow!("Gate {} not found", parent));
+        }
+        Ok(())
+    }
+
+    // plots gated on `from` become plots gated on `to`
+    fn move_views_gated_on(&mut self, from: &GateId, to: &GateId) {
+        let keys: Vec<GatesOnPlotKey> = self
+            .gate_ids_by_view
+            .keys()
+            .filter(|key| key.parental_gate_id.as_ref() == Some(from))
+            .cloned()
+            .collect();
+        for key in keys {
+            if let Some(ids) = self.gate_ids_by_view.remove(&key) {
+                let key = GatesOnPlotKey {
+                    parental_gate_id: Some(to.clone()),
+                    ..key
+                };
+                self.gate_ids_by_view.entry(key).or_default().extend(ids);
+            }
+        }
+    }
+
+    // boolean gates aren't drawn, so have no view to move
+    fn move_view(&mut self, gate: &Arc<dyn DrawableGate>, from: &GateId, to: &GateId) {
+        let (x, y) = gate.get_params();
+        let drawable_id = gate.get_id();
+        let from_key = GatesOnPlotKey::new(x.clone(), y.clone(), Some(from.clone()));
+        let Some(ids) = self.gate_ids_by_view.get_mut(&from_key) else {
+            return;
+        };
+        if !ids.contains(&drawable_id) {
+            return;
+        }
+        ids.retain(|id| *id != drawable_id);
+        self.gate_ids_by_view
+            .entry(GatesOnPlotKey::new(x, y, Some(to.clone())))
+            .or_default()
+            .push(drawable_id);
+    }
+
+    fn move_to(
+        &mut self,
+        gate_id: &GateId,
+        new_parent: &GateId,
+        with_descendants: bool,
+    ) -> anyhow::Result<()> {
+        let (gate, old_parent) = self.placed_gate(gate_id)?;
+        self.check_parent(new_parent)?;
+        if with_descendants && old_parent == *new_parent {
+            return Ok(());
+        }
+        let placed = placed_ids(&gate);
+
+        // work on a copy, so a move that breaks the hierarchy changes nothing
+        let mut hierarchy = self.hierarchy.clone();
+        if !with_descendants {
+            for id in &placed {
+                let children: Vec<GateId> =
+                    hierarchy.get_children(id).into_iter().cloned().collect();
+                for child in children {
+                    hierarchy.reparent(child, old_parent.clone())?;
+                }
+            }
         }
+        for id in &placed {
+            hierarchy.move_subtree(id.clone(), new_parent.clone())?;
+        }
+        hierarchy.validate()?;
+        check_population_dependencies(&hierarchy, &self.gate_store.primary_and_subgate_registry)?;
 
+        self.hierarchy = hierarchy;
+        if !with_descendants {
+            for id in &placed {
+                self.move_views_gated_on(id, &old_parent);
+            }
+        }
+        self.move_view(&gate, &old_parent, new_parent);
         Ok(())
     }
-}
 
-// Collect all reachable filterContainer IDs from the tree nodes,
-// recursively following CompoundFilterContainer sub-ids.
-fn collect_reachable(
-    fc_id: &str,
-    filter_containers: &std::collections::HashMap<Arc<str>, FilterContainer>,
-    reachable: &mut rustc_hash::FxHashSet<Arc<str>>,
-) {
-    if !reachable.insert(fc_id.into()) {
-        return; // already visited
+    // copies a gate under `new_parent`, optionally with its branch, returning the copy's id
+    fn copy_branch(
+        &mut self,
+        gate_id: &GateId,
+        new_parent: &GateId,
+        with_descendants: bool,
+    ) -> anyhow::Result<GateId> {
+        let (gate, _) = self.placed_gate(gate_id)?;
+        self.check_parent(new_parent)?;
+        let placed = placed_ids(&gate);
+        let registry = &self.gate_store.primary_and_subgate_registry;
+
+        // every node in the branch, then the drawable gates hanging from them
+        let mut nodes = placed.clone();
+        if with_descendants {
+            for id in &placed {
+                nodes.extend(self.hierarchy.get_descendants(id));
+            }
+        }
+        let mut gates: Vec<(GateId, Arc<dyn DrawableGate>)> = vec![];
+        for node in &nodes {
+            let gate = registry
+                .get(node)
+                .cloned()
+                .ok_or_else(|| anyhow!("Gate {} not found", node))?;
+            if !gates.iter().any(|(_, g)| g.get_id() == gate.get_id()) {
+                gates.push((node.clone(), gate));
+            }
+        }
+
+        // subgate ids are built from their composite's id, so the copies follow suit
+        let mut ids: FxHashMap<Arc<str>, Arc<str>> = FxHashMap::default();
+        for (_, gate) in &gates {
+            let drawable_id = gate.get_id();
+            let new_drawable_id: Arc<str> = Arc::from(Uuid::new_v4().to_string().as_str());
+            if gate.is_composite() {
+                for sub_id in gate.get_inner_gate_ids() {
+                    let new_sub_id: Arc<str> = match sub_id.strip_prefix(drawable_id.as_ref()) {
+                        Some(suffix) => Arc::from(format!("{new_drawable_id}{suffix}").as_str()),
+                        None => Arc::from(Uuid::new_v4().to_string().as_str()),
+                    };
+                    ids.insert(sub_id, new_sub_id);
+                }
+            }
+            ids.insert(drawable_id, new_drawable_id);
+        }
+
+        let mut hierarchy = self.hierarchy.clone();
+        for id in &placed {
+            let new_root = ids
+                .get(id)
+                .cloned()
+                .ok_or_else(|| anyhow!("No new id given for gate {}", id))?;
+            hierarchy.add_gate_child(
+                new_parent.clone(),
+                new_root.clone(),
+                self.hierarchy.get_order(id),
+            )?;
+            if !with_descendants {
+                continue;
+            }
+            let branch = self.hierarchy.clone_subtree(id, |old| {
+                ids.get(old)
+                    .map(|new_id| new_id.to_string())
+                    .unwrap_or_else(|| old.to_string())
+            })?;
+            for node in branch.iter_dfs(&new_root) {
+                for child in branch.get_children(&node) {
+                    hierarchy.add_gate_child(
+                        node.clone(),
+                        child.clone(),
+                        branch.get_order(child),
+                    )?;
+                }
+            }
+        }
+        hierarchy.validate()?;
+
+        // a composite is shared between the keys of all its subgates, so copy each gate once
+        // and hold the original so its address can't be reused
+        let mut copies: FxHashMap<*const (), (Arc<dyn DrawableGate>, Arc<dyn DrawableGate>)> =
+            FxHashMap::default();
+        let mut copy = |gate: &Arc<dyn DrawableGate>| -> anyhow::Result<Arc<dyn DrawableGate>> {
+            let key = Arc::as_ptr(gate) as *const ();
+            if let Some((_, copied)) = copies.get(&key) {
+                return Ok(copied.clone());
+            }
+            let copied: Arc<dyn DrawableGate> = Arc::from(gate.with_new_ids(&ids)?);
+            copies.insert(key, (gate.clone(), copied.clone()));
+            Ok(copied)
+        };
+
+        let mut registry = self.gate_store.primary_and_subgate_registry.clone();
+        let mut views = self.gate_ids_by_view.clone();
+        let mut boolean_gate_links = self.boolean_gate_links.clone();
+        for (node, gate) in &gates {
+            let copied = copy(gate)?;
+            for id in stored_ids(&copied) {
+                registry.insert(id, copied.clone());
+            }
+
+            let (x, y) = gate.get_params();
+            let old_parent = self.hierarchy.get_parent(node).cloned();
+            let old_key = GatesOnPlotKey::new(x.clone(), y.clone(), old_parent.clone());
+            if views
+                .get(&old_key)
+                .is_some_and(|view| view.contains(&gate.get_id()))
+            {
+                let parent = old_parent
+                    .and_then(|p| ids.get(&p).cloned())
+                    .unwrap_or_else(|| new_parent.clone());
+                views
+                    .entry(GatesOnPlotKey::new(x, y, Some(parent)))
+                    .or_default()
+                    .push(copied.get_id());
+            }
+
+            if let Some(boolean) = copied.as_any().downcast_ref::<BooleanGate>() {
+                for operand in boolean.get_operands() {
+                    boolean_gate_links
+                        .entry(operand.clone())
+                        .or_default()
+                        .push(copied.get_id());
+                }
+            }
+        }
+
+        let mut sample_overrides = vec![];
+        for ((id, file_id), gate) in &self.gate_store.sample_position_overrides {
+            if let Some(new_id) = ids.get(id) {
+                sample_overrides.push(((new_id.clone(), file_id.clone()), copy(gate)?));
+            }
+        }
+        let mut group_overrides = vec![];
+        for ((id, key), gate) in &self.gate_store.group_position_overrides {
+            if let Some(new_id) = ids.get(id) {
+                group_overrides.push(((new_id.clone(), key.clone()), copy(gate)?));
+            }
+        }
+
+        check_population_dependencies(&hierarchy, &registry)?;
+        self.hierarchy = hierarchy;
+        self.gate_store.primary_and_subgate_registry = registry;
+        self.gate_ids_by_view = views;
+        self.boolean_gate_links = boolean_gate_links;
+        self.gate_store
+            .sample_position_overrides
+            .extend(sample_overrides);
+        self.gate_store
+            .group_position_overrides
+            .extend(group_overrides);
+        new_gate_id(&ids, &gate.get_id())
     }
 
-    if let Some(FilterContainer::Compound(c)) = filter_containers.get(fc_id) {
-        for sub_id in &c.filter_container_ids {
-            collect_reachable(sub_id, filter_containers, reachable);
+    // places a copy of a gate under `new_parent` whose edits are shared with the original
+    fn link_to(&mut self, gate_id: &GateId, new_parent: &GateId) -> anyhow::Result<()> {
+        let (gate, _) = self.placed_gate(gate_id)?;
+        let drawable_id = gate.get_id();
+        for member in self.gate_store.linked_ids(&drawable_id) {
+            let (_, parent) = self.placed_gate(&member)?;
+            if parent == *new_parent {
+                return Err(anyhow!(
+                    "{} is already linked under that gate",
+                    gate.get_name()
+                ));
+            }
         }
+        let linked_id = self.copy_branch(gate_id, new_parent, false)?;
+        self.gate_store.link(&drawable_id, linked_id);
+        Ok(())
     }
-    
-}
\ No newline at end of file
+
+    // the gate keeps its position, but edits to it are no longer shared
+    fn unlink(&mut self, gate_id: &GateId) -> anyhow::Result<()> {
+        let drawable_id = self
+            .gate_store
+            .primary_and_subgate_registry
+            .get(gate_id)
+            .ok_or_else(|| anyhow!("Gate {} not found", gate_id))?
+            .get_id();
+        if !self.gate_store.gate_links.contains_key(&drawable_id) {
+            return Err(anyhow!("Gate {} isn't linked", gate_id));
+        }
+        self.gate_store.unlink(&drawable_id);
+        Ok(())
+    }
+
+    fn remove_keep_children(&mut self, gate_id: &GateId) -> anyhow::Result<()> {
+        let (gate, parent) = self.placed_gate(gate_id)?;
+        let placed = placed_ids(&gate);
+        let ids = stored_ids(&gate);
+        if ids.iter().any(|id| {
+            self.boolean_gate_links
+                .get(id)
+                .is_some_and(|deps| !deps.is_empty())
+        }) {
+            return Err(anyhow!(
+                "{} is used by a boolean gate, so can't be removed on its own",
+                gate.get_name()
+            ));
+        }
+
+        let mut hierarchy = self.hierarchy.clone();
+        for id in &placed {
+            hierarchy.delete_node_keep_children(id, Some(parent.clone()))?;
+        }
+        hierarchy.validate()?;
+        self.hierarchy = hierarchy;
+
+        for id in &placed {
+            self.move_views_gated_on(id, &parent);
+        }
+        let (x, y) = gate.get_params();
+        if let Some(view) = self
+            .gate_ids_by_view
+            .get_mut(&GatesOnPlotKey::new(x, y, Some(parent)))
+        {
+            view.retain(|id| *id != gate.get_id());
+        }
+        for (_, deps) in self.boolean_gate_links.iter_mut() {
+            deps.retain(|id| !ids.contains(id));
+        }
+
+        let store = &mut self.gate_store;
+        store.unlink(&gate.get_id());
+        for id in &ids {
+            store.primary_and_subgate_registry.remove(id);
+        }
+        store
+            .sample_position_overrides
+            .retain(|(id, _), _| !ids.contains(id));
+        store
+            .group_position_overrides
+            .retain(|(id, _), _| !ids.contains(id));
+        if self
+            .selected_gate
+            .as_ref()
+            .is_some_and(|id| ids.contains(id))
+        {
+            self.selected_gate = None;
+        }
+        Ok(())
+    }
+
+    // links the extra nodes of a filter container to the gate it was loaded as.
+    // `instances` is (node id, filter container id, parent) - the subgates of a composite
+    // placed under one parent make up one instance
+    fn load_linked_instances(
+        &mut self,
+        instances: Vec<(GateId, GateId, GateId)>,
+    ) -> anyhow::Result<()> {
+        let mut by_gate: FxHashMap<(GateId, GateId), Vec<(GateId, GateId)>> = FxHashMap::default();
+        let mut order: Vec<(GateId, GateId)> = vec![];
+        for (node_id, fc_id, parent) in instances {
+            let gate = self
+                .gate_store
+                .primary_and_subgate_registry
+                .get(&fc_id)
+                .ok_or_else(|| anyhow!("Could not find gate {} for node {}", fc_id, node_id))?;
+            let key = (gate.get_id(), parent);
+            if !by_gate.contains_key(&key) {
+                order.push(key.clone());
+            }
+            by_gate.entry(key).or_default().push((fc_id, node_id));
+        }
+
+        for key in order {
+            let (drawable_id, parent) = &key;
+            let gate = self.gate_store.primary_and_subgate_registry[drawable_id].clone();
+            let mut ids: FxHashMap<Arc<str>, Arc<str>> = by_gate
+                .remove(&key)
+                .unwrap_or_default()
+                .into_iter()
+                .collect();
+            if gate.is_composite() {
+                // subgates without a node of their own still need ids
+                for sub_id in gate.get_inner_gate_ids() {
+                    ids.entry(sub_id)
+                        .or_insert_with(|| Arc::from(Uuid::new_v4().to_string().as_str()));
+                }
+                ids.insert(
+                    drawable_id.clone(),
+                    Arc::from(Uuid::new_v4().to_string().as_str()),
+                );
+            }
+            let instance = self.instantiate(&gate, &ids, parent)?;
+            self.gate_store.link(drawable_id, instance.get_id());
+        }
+        Ok(())
+    }
+
+    // registers a copy of `gate` under the ids in `ids`, with its overrides. The copy's
+    // hierarchy nodes must already hang from `parent`
+    fn instantiate(
+        &mut self,
+        gate: &Arc<dyn DrawableGate>,
+        ids: &FxHashMap<Arc<str>, Arc<str>>,
+        parent: &GateId,
+    ) -> anyhow::Result<Arc<dyn DrawableGate>> {
+        let copy: Arc<dyn DrawableGate> = Arc::from(gate.with_new_ids(ids)?);
+        let stored = stored_ids(gate);
+
+        // overrides are stored under each id of a composite - copy one per sample or group
+        let mut sample_overrides: FxHashMap<FileId, Arc<dyn DrawableGate>> = FxHashMap::default();
+        for ((id, file_id), ovr) in &self.gate_store.sample_position_overrides {
+            if stored.contains(id) {
+                sample_overrides.insert(file_id.clone(), ovr.clone());
+            }
+        }
+        let mut group_overrides: FxHashMap<MetaDataKey, Arc<dyn DrawableGate>> =
+            FxHashMap::default();
+        for ((id, key), ovr) in &self.gate_store.group_position_overrides {
+            if stored.contains(id) {
+                group_overrides.insert(key.clone(), ovr.clone());
+            }
+        }
+
+        let store = &mut self.gate_store;
+        store.write_position(&GateSource::Global, copy.clone());
+        for (file_id, ovr) in sample_overrides {
+            let source = GateSource::Sample((copy.get_id(), file_id));
+            store.write_position(&source, Arc::from(ovr.with_new_ids(ids)?));
+        }
+        for (key, ovr) in group_overrides {
+            let source = GateSource::Group((copy.get_id(), key));
+            store.write_position(&source, Arc::from(ovr.with_new_ids(ids)?));
+        }
+
+        if let Some(boolean) = copy.as_any().downcast_ref::<BooleanGate>() {
+            for operand in boolean.get_operands() {
+                self.boolean_gate_links
+                    .entry(operand.clone())
+                    .or_default()
+                    .push(copy.get_id());
+            }
+        } else {
+            let (x, y) = copy.get_params();
+            self.gate_ids_by_view
+                .entry(GatesOnPlotKey::new(x, y, Some(parent.clone())))
+                .or_default()
+                .push(copy.get_id());
+        }
+        Ok(copy)
+    }
+
+    fn add_boolean_expr(
+        &mut self,
+        name: Option<String>,
+        expr: &BooleanExpr,
+        parent: &GateId,
+    ) -> anyhow::Result<GateId> {
+        self.check_parent(parent)?;
+        let id: GateId = Arc::from(Uuid::new_v4().to_string().as_str());
+        let gates = match &name {
+            Some(name) => self.boolean_gates_for(&id, name.clone(), expr)?,
+            None => self.boolean_gates_for(&id, self.expr_text(expr), expr)?,
+        };
+        self.hierarchy
+            .add_gate_child(parent.clone(), id.clone(), None)?;
+        // named after its expression unless given a name
+        if let Some(name) = &name {
+            self.check_name_free(&id, name)?;
+        }
+        self.insert_boolean_gates(gates);
+        check_population_dependencies(
+            &self.hierarchy,
+            &self.gate_store.primary_and_subgate_registry,
+        )?;
+        Ok(id)
+    }
+
+    fn edit_boolean_expr(
+        &mut self,
+        gate_id: &GateId,
+        name: Option<String>,
+        expr: &BooleanExpr,
+    ) -> anyhow::Result<()> {
+        let old_name = self
+            .gate_store
+            .primary_and_subgate_registry
+            .get(gate_id)
+            .filter(|g| g.as_any().is::<BooleanGate>())
+            .ok_or_else(|| anyhow!("{} is not a boolean gate", gate_id))?
+            .get_name()
+            .to_string();
+        let name = match name {
+            Some(name) if name != old_name => {
+                for id in self.gate_store.linked_ids(gate_id) {
+                    self.check_name_free(&id, &name)?;
+                }
+                name
+            }
+            _ => old_name,
+        };
+        let gates = self.boolean_gates_for(gate_id, name, expr)?;
+        self.insert_boolean_gates(gates);
+        self.prune_unplaced_booleans();
+        check_population_dependencies(
+            &self.hierarchy,
+            &self.gate_store.primary_and_subgate_registry,
+        )
+    }
+
+    // the expression a boolean gate evaluates - operands that are boolean gates outside the
+    // hierarchy are its sub-expressions
+    fn boolean_expr(&self, gate_id: &GateId) -> Option<BooleanExpr> {
+        let gate = self.gate_store.primary_and_subgate_registry.get(gate_id)?;
+        let boolean = gate.as_any().downcast_ref::<BooleanGate>()?;
+        let mut terms = boolean
+            .get_operands()
+            .iter()
+            .map(|operand| {
+                if self.is_unplaced_boolean(operand) {
+                    self.boolean_expr(operand)
+                } else {
+                    Some(BooleanExpr::Gate(operand.clone()))
+                }
+            })
+            .collect::<Option<Vec<_>>>()?;
+        Some(match boolean.get_operation() {
+            BooleanOperation::And => BooleanExpr::and(terms),
+            BooleanOperation::Or => BooleanExpr::or(terms),
+            BooleanOperation::Not => BooleanExpr::Not(Box::new(terms.pop()?)),
+        })
+    }
+
+    fn is_unplaced_boolean(&self, id: &GateId) -> bool {
+        self.hierarchy.get_parent(id).is_none()
+            && self
+                .gate_store
+                .primary_and_subgate_registry
+                .get(id)
+                .is_some_and(|g| g.as_any().is::<BooleanGate>())
+    }
+
+    fn expr_text(&self, expr: &BooleanExpr) -> String {
+        let registry = &self.gate_store.primary_and_subgate_registry;
+        expr.to_text(&|id: &GateId| registry.display_name(id).unwrap_or_else(|| id.to_string()))
+    }
+
+    // the boolean gate `id` for `expr`, then one for each nested sub-expression.
+    // Sub-expressions aren't placed in the hierarchy, like OMIQ's nested compound containers
+    fn boolean_gates_for(
+        &self,
+        id: &GateId,
+        name: String,
+        expr: &BooleanExpr,
+    ) -> anyhow::Result<Vec<BooleanGate>> {
+        let (operation, terms) = match expr {
+            BooleanExpr::Gate(_) => {
+                return Err(anyhow!("Combine gates with AND, OR or NOT"));
+            }
+            BooleanExpr::Not(inner) => {
+                (BooleanOperation::Not, std::slice::from_ref(inner.as_ref()))
+            }
+            BooleanExpr::And(terms) => (BooleanOperation::And, terms.as_slice()),
+            BooleanExpr::Or(terms) => (BooleanOperation::Or, terms.as_slice()),
+        };
+        if !matches!(operation, BooleanOperation::Not) && terms.len() < 2 {
+            return Err(anyhow!("AND and OR need at least two gates"));
+        }
+
+        let registry = &self.gate_store.primary_and_subgate_registry;
+        let mut gates = vec![];
+        let mut operands = vec![];
+        for term in terms {
+            match term {
+                BooleanExpr::Gate(operand) => {
+                    if !registry.contains_key(operand) {
+                        return Err(anyhow!("Gate {} not found", operand));
+                    }
+                    if operand == id {
+                        return Err(anyhow!("A boolean gate can't use itself"));
+                    }
+                    operands.push(operand.clone());
+                }
+                term => {
+                    let sub_id: GateId = Arc::from(Uuid::new_v4().to_string().as_str());
+                    gates.extend(self.boolean_gates_for(&sub_id, self.expr_text(term), term)?);
+                    operands.push(sub_id);
+                }
+            }
+        }
+
+        // booleans aren't drawn - the axes are those of the first gate they read
+        let (x_param, y_param) = expr
+            .gate_ids()
+            .first()
+            .and_then(|first| registry.get(first))
+            .map(|g| g.get_params())
+            .ok_or_else(|| anyhow!("Boolean expression has no gates"))?;
+        gates.insert(
+            0,
+            BooleanGate::new(id.clone(), name, operands, operation, x_param, y_param)?,
+        );
+        Ok(gates)
+    }
+
+    // writes boolean gates to every gate linked to them, and points their operands at them
+    fn insert_boolean_gates(&mut self, gates: Vec<BooleanGate>) {
+        for gate in gates {
+            let id = gate.get_id();
+            self.gate_store
+                .set_position(&GateSource::Global, Arc::new(gate));
+            for linked in self.gate_store.linked_ids(&id) {
+                self.relink_boolean(&linked);
+            }
+        }
+    }
+
+    fn relink_boolean(&mut self, id: &GateId) {
+        for (_, deps) in self.boolean_gate_links.iter_mut() {
+            deps.retain(|dep| dep != id);
+        }
+        let operands = self
+            .gate_store
+            .primary_and_subgate_registry
+            .get(id)
+            .and_then(|g| {
+                g.as_any()
+                    .downcast_ref::<BooleanGate>()
+                    .map(|b| b.get_operands().to_vec())
+            })
+            .unwrap_or_default();
+        for operand in operands {
+            self.boolean_gate_links
+                .entry(operand)
+                .or_default()
+                .push(id.clone());
+        }
+    }
+
+    // drops sub-expression gates that no boolean gate reads any more
+    fn prune_unplaced_booleans(&mut self) {
+        loop {
+            let registry = &self.gate_store.primary_and_subgate_registry;
+            let used: FxHashSet<GateId> = registry
+                .values()
+                .filter_map(|g| g.as_any().downcast_ref::<BooleanGate>())
+                .flat_map(|b| b.get_operands().iter().cloned())
+                .collect();
+            let unused: Vec<GateId> = registry
+                .keys()
+                .filter(|id| self.is_unplaced_boolean(id) && !used.contains(*id))
+                .cloned()
+                .collect();
+            if unused.is_empty() {
+                return;
+            }
+            for id in unused {
+                self.gate_store.primary_and_subgate_registry.remove(&id);
+                self.gate_store.unlink(&id);
+                self.boolean_gate_links.remove(&id);
+                for (_, deps) in self.boolean_gate_links.iter_mut() {
+                    deps.retain(|dep| *dep != id);
+                }
+            }
+        }
+    }
+
+    // Registers a gate read from file. Global gates must already be in the hierarchy -
+    // composites are placed by their subgates, which is what the hierarchy holds.
+    fn insert_loaded_gate(
+        &mut self,
+        source: GateSource,
+        gate: Arc<dyn DrawableGate>,
+    ) -> anyhow::Result<()> {
+        let subgate_ids = if gate.is_composite() {
+            gate.get_inner_gate_ids()
+        } else {
+            vec![]
+        };
+        match source {
+            GateSource::Global => {
+                let gate_id = gate.get_id();
+                let placed_id = subgate_ids.first().unwrap_or(&gate_id);
+                let parent = self
+                    .hierarchy
+                    .get_parent(placed_id)
+                    .cloned()
+                    .ok_or_else(|| {
+                        anyhow::anyhow!("Could not locate parent of {} in hierarchy", placed_id)
+                    })?;
+                let params = gate.get_params();
+                let key = GatesOnPlotKey::new(params.0, params.1, Some(parent));
+
+                self.gate_ids_by_view
+                    .entry(key)
+                    .or_default()
+                    .push(gate_id.clone());
+                self.gate_store
+                    .primary_and_subgate_registry
+                    .insert(gate_id, gate.clone());
+                for sub_id in subgate_ids {
+                    self.gate_store
+                        .primary_and_subgate_registry
+                        .insert(sub_id, gate.clone());
+                }
+            }
+            GateSource::Group(key) => {
+                for sub_id in subgate_ids {
+                    self.gate_store
+                        .group_position_overrides
+                        .insert((sub_id, key.1.clone()), gate.clone());
+                }
+                self.gate_store.group_position_overrides.insert(key, gate);
+            }
+            GateSource::Sample(key) => {
+                for sub_id in subgate_ids {
+                    self.gate_store
+                        .sample_position_overrides
+                        .insert((sub_id, key.1.clone()), gate.clone());
+                }
+                self.gate_store.sample_position_overrides.insert(key, gate);
+            }
+        }
+        Ok(())
+    }
+
+    fn insert_loaded_boolean_gate(&mut self, gate: Arc<dyn DrawableGate>, operands: &[GateId]) {
+        for link_id in operands {
+            self.boolean_gate_links
+                .entry(link_id.clone())
+                .or_default()
+                .push(gate.get_id());
+        }
+        self.gate_store
+            .primary_and_subgate_registry
+            .insert(gate.get_id(), gate);
+    }
+
+    pub fn to_record(&self) -> anyhow::Result<GateStateRecord> {
+        let registry = &self.gate_store.primary_and_subgate_registry;
+        let mut hierarchy = vec![];
+        let mut gates = vec![];
+        let mut seen: FxHashSet<GateId> = FxHashSet::default();
+
+        for gate_id in self.hierarchy.iter_dfs(&ROOTGATE) {
+            if gate_id == *ROOTGATE {
+                continue;
+            }
+            let parent_id = self
+                .hierarchy
+                .get_parent(&gate_id)
+                .cloned()
+                .unwrap_or_else(|| ROOTGATE.clone());
+            hierarchy.push(HierarchyRecord {
+                parent_id,
+                gate_id: gate_id.clone(),
+                ord: self.hierarchy.get_order(&gate_id).unwrap_or(0),
+            });
+
+            let gate = registry.get(&gate_id).ok_or_else(|| {
+                anyhow::anyhow!("Gate {} is in the hierarchy but not the registry", gate_id)
+            })?;
+            // composite subgates share a gate - only record it once
+            if seen.insert(gate.get_id()) {
+                gates.push(gate_to_record(
+                    gate,
+                    &self.gate_store.sample_position_overrides,
+                    &self.gate_store.group_position_overrides,
+                )?);
+            }
+        }
+
+        // sub-expressions of boolean gates aren't in the hierarchy
+        let mut pending: Vec<GateId> = gates
+            .iter()
+            .filter_map(|g| match &g.kind {
+                GateRecordKind::Boolean { operands, .. } => Some(operands.clone()),
+                _ => None,
+            })
+            .flatten()
+            .collect();
+        while let Some(id) = pending.pop() {
+            if !self.is_unplaced_boolean(&id) || !seen.insert(id.clone()) {
+                continue;
+            }
+            let gate = &registry[&id];
+            if let Some(boolean) = gate.as_any().downcast_ref::<BooleanGate>() {
+                pending.extend(boolean.get_operands().iter().cloned());
+            }
+            gates.push(gate_to_record(
+                gate,
+                &self.gate_store.sample_position_overrides,
+                &self.gate_store.group_position_overrides,
+            )?);
+        }
+
+        // every member of a link maps to the same list - record it once
+        let mut links: Vec<Vec<GateId>> = vec![];
+        for members in self.gate_store.gate_links.values() {
+            if !links.contains(members) {
+                links.push(members.clone());
+            }
+        }
+
+        Ok(GateStateRecord {
+            selected_gate: self.selected_gate.clone(),
+            hierarchy,
+            gates,
+            links,
+        })
+    }
+
+    pub fn from_record(
+        record: GateStateRecord,
+        axis_settings: &im::HashMap<Arc<str>, AxisInfo, FxBuildHasher>,
+    ) -> anyhow::Result<Self> {
+        let mut state = Self::default();
+
+        for node in &record.hierarchy {
+            state
+                .hierarchy
+                .add_gate_child(node.parent_id.clone(), node.gate_id.clone(), Some(node.ord))?;
+        }
+
+        for gate_record in &record.gates {
+            if let GateRecordKind::Boolean { operands, .. } = &gate_record.kind {
+                let gate = boolean_gate_from_record(gate_record)?;
+                state.insert_loaded_boolean_gate(gate, operands);
+                continue;
+            }
+
+            let gate = drawable_from_record(gate_record, &gate_record.default_filters, axis_settings)?;
+            state.insert_loaded_gate(GateSource::Global, gate)?;
+
+            for ovr in &gate_record.group_overrides {
+                let gate = drawable_from_record(gate_record, &ovr.filters, axis_settings)?;
+                let key = MetaDataKey {
+                    parameter: ovr.parameter.clone(),
+                    group: ovr.group.clone(),
+                };
+                state.insert_loaded_gate(GateSource::Group((gate.get_id(), key)), gate)?;
+            }
+            for ovr in &gate_record.sample_overrides {
+                let gate = drawable_from_record(gate_record, &ovr.filters, axis_settings)?;
+                state.insert_loaded_gate(
+                    GateSource::Sample((gate.get_id(), ovr.file_id.clone())),
+                    gate,
+                )?;
+            }
+        }
+
+        for members in &record.links {
+            if let Some(missing) = members.iter().find(|id| {
+                !state
+                    .gate_store
+                    .primary_and_subgate_registry
+                    .contains_key(*id)
+            }) {
+                return Err(anyhow!("Linked gate {} is not in the project", missing));
+            }
+            if let Some((first, rest)) = members.split_first() {
+                for member in rest {
+                    state.gate_store.link(first, member.clone());
+                }
+            }
+        }
+
+        state.hierarchy.validate()?;
+        check_population_dependencies(
+            &state.hierarchy,
+            &state.gate_store.primary_and_subgate_registry,
+        )?;
+        state.selected_gate = record.selected_gate;
+        Ok(state)
+    }
+
+    pub fn to_experiment_json(
+        &self,
+        metadata: &crate::omiq::metadata::MetaDataFileMap,
+    ) -> anyhow::Result<crate::omiq::deserialise::ExperimentJson> {
+        crate::omiq::serialise::build_experiment_json(
+            &self.hierarchy,
+            &self.gate_store.primary_and_subgate_registry,
+            &self.gate_store.sample_position_overrides,
+            &self.gate_store.group_position_overrides,
+            &self.gate_store.canonical_ids(),
+            metadata,
+        )
+    }
+}
+
+// Collect all reachable filterContainer IDs from the tree nodes,
+// recursively following CompoundFilterContainer sub-ids.
+fn collect_reachable(
+    fc_id: &str,
+    filter_containers: &std::collections::HashMap<Arc<str>, FilterContainer>,
+    reachable: &mut rustc_hash::FxHashSet<Arc<str>>,
+) {
+    if !reachable.insert(fc_id.into()) {
+        return; // already visited
+    }
+
+    if let Some(FilterContainer::Compound(c)) = filter_containers.get(fc_id) {
+        for sub_id in &c.filter_container_ids {
+            collect_reachable(sub_id, filter_containers, reachable);
+        }
+    }
+    
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gate_editor::gates::auto_adjust::translate_gate;
+    use crate::gate_editor::gates::gate_filtering::{
+        PopulationMasks, population_dependency_closure,
+    };
+    use crate::gate_editor::gates::gate_stats::{PopulationCounts, get_population_counts};
+    use crate::gate_editor::gates::mask_cache::GateMaskCache;
+    use crate::omiq::deserialise::{ExperimentJson, GatingNode};
+    use crate::omiq::metadata::MetaDataFileMap;
+    use polars::prelude::*;
+
+    // A and B are separate branches, A1 sits under A. The compound containers mix branches
+    // so the operands' ancestors matter - e2/e4 fall inside A1's rectangle but not under A.
+    const EXPERIMENT: &str = r#"{
+        "tree": {
+            "nodes": {
+                "n1": {"id": "n1", "parentId": "", "filterContainerId": "A", "ord": 0, "collapsed": false},
+                "n2": {"id": "n2", "parentId": "", "filterContainerId": "B", "ord": 1, "collapsed": false},
+                "n3": {"id": "n3", "parentId": "n1", "filterContainerId": "A1", "ord": 0, "collapsed": false},
+                "n4": {"id": "n4", "parentId": "n1", "filterContainerId": "notA1", "ord": 1, "collapsed": false},
+                "n5": {"id": "n5", "parentId": "", "filterContainerId": "orA1B", "ord": 2, "collapsed": false},
+                "n6": {"id": "n6", "parentId": "", "filterContainerId": "andOrB", "ord": 3, "collapsed": false},
+                "n7": {"id": "n7", "parentId": "n5", "filterContainerId": "low", "ord": 0, "collapsed": false}
+            },
+            "filterContainers": {
+                "A": {"containerType": "AtomicFilterContainer", "id": "A", "name": "A",
+                    "defaultFilter": {"type": "RectangleGate", "f1": "X", "f2": "Y",
+                        "min": {"f1Val": 0.0, "f2Val": -1.0}, "max": {"f1Val": 10.0, "f2Val": 100.0}},
+                    "perFileFilters": {
+                        "file1": {"type": "RectangleGate", "f1": "X", "f2": "Y",
+                            "min": {"f1Val": 0.0, "f2Val": -1.0}, "max": {"f1Val": 4.0, "f2Val": 100.0}}
+                    }},
+                "B": {"containerType": "AtomicFilterContainer", "id": "B", "name": "B",
+                    "defaultFilter": {"type": "RectangleGate", "f1": "X", "f2": "Y",
+                        "min": {"f1Val": 20.0, "f2Val": -1.0}, "max": {"f1Val": 30.0, "f2Val": 100.0}}},
+                "A1": {"containerType": "AtomicFilterContainer", "id": "A1", "name": "A1",
+                    "defaultFilter": {"type": "RectangleGate", "f1": "X", "f2": "Y",
+                        "min": {"f1Val": -1.0, "f2Val": -1.0}, "max": {"f1Val": 100.0, "f2Val": 50.0}}},
+                "low": {"containerType": "AtomicFilterContainer", "id": "low", "name": "Low",
+                    "defaultFilter": {"type": "RectangleGate", "f1": "X", "f2": "Y",
+                        "min": {"f1Val": -1.0, "f2Val": -1.0}, "max": {"f1Val": 100.0, "f2Val": 50.0}}},
+                "notA1": {"containerType": "CompoundFilterContainer", "id": "notA1", "name": "not A1",
+                    "type": "NOT", "filterContainerIds": ["A1"]},
+                "orA1B": {"containerType": "CompoundFilterContainer", "id": "orA1B", "name": "A1 or B",
+                    "type": "OR", "filterContainerIds": ["A1", "B"]},
+                "andOrB": {"containerType": "CompoundFilterContainer", "id": "andOrB", "name": "(A1 or B) and B",
+                    "type": "AND", "filterContainerIds": ["orA1B", "B"]}
+            }
+        }
+    }"#;
+
+    fn load(json: &str) -> anyhow::Result<GateState> {
+        let experiment: ExperimentJson = serde_json::from_str(json).unwrap();
+        let metadata: MetaDataFileMap = im::HashMap::with_hasher(FxBuildHasher);
+        let mut state = GateState::default();
+        state.load_experiment(
+            experiment,
+            &metadata,
+            im::HashMap::with_hasher(FxBuildHasher),
+        )?;
+        Ok(state)
+    }
+
+    // e0..e5
+    fn events() -> DataFrame {
+        df!(
+            "X" => [5f32, 5.0, 25.0, 25.0, 50.0, 50.0],
+            "Y" => [10f32, 80.0, 10.0, 80.0, 10.0, 80.0]
+        )
+        .unwrap()
+    }
+
+    fn resolver_for(state: &GateState, file_id: &str) -> GateOverrideResolver {
+        GateOverrideResolver::build(
+            &state.gate_store.primary_and_subgate_registry,
+            &state.gate_store.sample_position_overrides,
+            &state.gate_store.group_position_overrides,
+            &Arc::from(file_id),
+            &FxHashMap::default(),
+        )
+    }
+
+    fn counts_for(state: &GateState, file_id: &str) -> PopulationCounts {
+        let df = events();
+        let resolver = resolver_for(state, file_id);
+        get_population_counts(PopulationMasks::new(&df, &state.hierarchy, &resolver), None)
+    }
+
+    fn count(counts: &PopulationCounts, id: &str) -> usize {
+        counts[id].as_ref().expect("population failed").count
+    }
+
+    #[test]
+    fn test_boolean_operands_use_their_hierarchy() {
+        let state = load(EXPERIMENT).unwrap();
+        let counts = counts_for(&state, "file2");
+
+        assert_eq!(count(&counts, "A"), 2);
+        assert_eq!(count(&counts, "A1"), 1);
+        // under A, NOT A1 is the rest of A
+        assert_eq!(count(&counts, "notA1"), 1);
+        let not_a1 = counts["notA1"].as_ref().unwrap();
+        assert!((not_a1.percent_parent - 50.0).abs() < 1e-3);
+        // e4 is inside A1's rectangle but not under A
+        assert_eq!(count(&counts, "orA1B"), 3);
+        assert_eq!(count(&counts, "andOrB"), 2);
+        // gated from a boolean parent
+        assert_eq!(count(&counts, "low"), 2);
+        let low = counts["low"].as_ref().unwrap();
+        assert!((low.percent_parent - 200.0 / 3.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_boolean_operands_use_sample_overrides() {
+        let state = load(EXPERIMENT).unwrap();
+        // file1's A excludes x = 5, so A1 and everything built on it is empty
+        let counts = counts_for(&state, "file1");
+
+        assert_eq!(count(&counts, "A"), 0);
+        assert_eq!(count(&counts, "notA1"), 0);
+        assert_eq!(count(&counts, "orA1B"), 2);
+        assert_eq!(count(&counts, "low"), 1);
+    }
+
+    #[test]
+    fn test_plot_counts_only_its_dependencies() {
+        let state = load(EXPERIMENT).unwrap();
+        let registry = &state.gate_store.primary_and_subgate_registry;
+        // a plot of the A1 or B population shows Low
+        let plotted = population_dependency_closure(
+            &state.hierarchy,
+            registry,
+            [Arc::from("orA1B"), Arc::from("low")],
+        );
+        let mut ids: Vec<&str> = plotted.iter().map(|id| id.as_ref()).collect();
+        ids.sort();
+        assert_eq!(ids, ["A", "A1", "B", "low", "orA1B"]);
+
+        let df = events();
+        let resolver = resolver_for(&state, "file2");
+        let counts = get_population_counts(
+            PopulationMasks::new(&df, &state.hierarchy, &resolver),
+            Some(&plotted),
+        );
+        assert_eq!(counts.len(), 5);
+        assert!(!counts.contains_key("notA1") && !counts.contains_key("andOrB"));
+        assert_eq!(count(&counts, "orA1B"), 3);
+        assert_eq!(count(&counts, "low"), 2);
+    }
+
+    #[test]
+    fn test_mask_cache_invalidates_edited_branch() {
+        let mut state = load(EXPERIMENT).unwrap();
+        let cache = GateMaskCache::default();
+        let axis_settings = im::HashMap::with_hasher(FxBuildHasher);
+        let file_id: FileId = Arc::from("file2");
+        let cached_counts = |state: &GateState, df: &DataFrame, generation: u64| {
+            let resolver = resolver_for(state, &file_id);
+            get_population_counts(
+                PopulationMasks::new(df, &state.hierarchy, &resolver).with_cache(
+                    &cache,
+                    file_id.clone(),
+                    generation,
+                    &axis_settings,
+                    None,
+                ),
+                None,
+            )
+        };
+
+        let first = cached_counts(&state, &events(), 0);
+        assert_eq!(count(&first, "A"), 2);
+        assert_eq!(cache.hits(), 0);
+
+        // same gates, same data - every mask comes from the cache
+        assert_eq!(cached_counts(&state, &events(), 0), first);
+        assert_eq!(cache.hits(), 7);
+
+        // the file's events are rescaled - a new generation, so nothing is reused
+        let shifted = df!(
+            "X" => [5f32, 5.0, 5.0, 5.0, 5.0, 5.0],
+            "Y" => [10f32, 80.0, 10.0, 80.0, 10.0, 80.0]
+        )
+        .unwrap();
+        let second = cached_counts(&state, &shifted, 1);
+        assert_eq!(cache.hits(), 7);
+        assert_eq!(count(&second, "A"), 6);
+        assert_eq!(count(&second, "A1"), 3);
+        assert_eq!(count(&second, "orA1B"), 3);
+        assert_eq!(count(&second, "B"), 0);
+
+        // moving A swaps its Arc - A and everything built on it is recomputed, B is not
+        let a_id: GateId = Arc::from("A");
+        let moved: Arc<dyn DrawableGate> =
+            Arc::from(state.gate_store.primary_and_subgate_registry[&a_id].clone_box());
+        state
+            .gate_store
+            .primary_and_subgate_registry
+            .insert(a_id, moved);
+        let third = cached_counts(&state, &shifted, 1);
+        assert_eq!(cache.hits(), 8);
+        assert_eq!(third, second);
+    }
+
+    #[test]
+    fn test_mask_cache_keys_on_compensation() {
+        use crate::gate_editor::compensation::CompMatrix;
+
+        let state = load(EXPERIMENT).unwrap();
+        let cache = GateMaskCache::default();
+        let axis_settings = im::HashMap::with_hasher(FxBuildHasher);
+        let file_id: FileId = Arc::from("file2");
+        let resolver = resolver_for(&state, &file_id);
+        let df = events();
+        let cached_counts = |compensation: Option<&Arc<CompMatrix>>| {
+            get_population_counts(
+                PopulationMasks::new(&df, &state.hierarchy, &resolver).with_cache(
+                    &cache,
+                    file_id.clone(),
+                    0,
+                    &axis_settings,
+                    compensation,
+                ),
+                None,
+            )
+        };
+
+        let first = cached_counts(None);
+        // a matrix that doesn't touch the gates' channels leaves their masks valid
+        let other_channels = Arc::new(CompMatrix::identity(vec![Arc::from("Z")]));
+        assert_eq!(cached_counts(Some(&other_channels)), first);
+        assert_eq!(cache.hits(), 7);
+
+        // compensating X is new data for every gate drawn on it
+        let on_x = Arc::new(CompMatrix::identity(vec![Arc::from("X")]));
+        assert_eq!(cached_counts(Some(&on_x)), first);
+        assert_eq!(cache.hits(), 7);
+    }
+
+    #[test]
+    fn test_boolean_cycle_is_rejected() {
+        // the NOT gate's operand is its own child
+        let json = r#"{
+            "tree": {
+                "nodes": {
+                    "n1": {"id": "n1", "parentId": "", "filterContainerId": "loop", "ord": 0, "collapsed": false},
+                    "n2": {"id": "n2", "parentId": "n1", "filterContainerId": "inner", "ord": 0, "collapsed": false}
+                },
+                "filterContainers": {
+                    "inner": {"containerType": "AtomicFilterContainer", "id": "inner", "name": "inner",
+                        "defaultFilter": {"type": "RectangleGate", "f1": "X", "f2": "Y",
+                            "min": {"f1Val": 0.0, "f2Val": 0.0}, "max": {"f1Val": 10.0, "f2Val": 10.0}}},
+                    "loop": {"containerType": "CompoundFilterContainer", "id": "loop", "name": "loop",
+                        "type": "NOT", "filterContainerIds": ["inner"]}
+                }
+            }
+        }"#;
+        let err = load(json).err().expect("cycle should be rejected");
+        assert!(err.to_string().contains("depends on its own population"));
+    }
+
+    #[test]
+    fn test_rename_updates_overrides_and_checks_siblings() {
+        let mut state = load(EXPERIMENT).unwrap();
+        let a: GateId = Arc::from("A");
+
+        assert!(state.rename(&a, "B").is_err());
+        assert!(state.rename(&a, "   ").is_err());
+        // names only need to be unique among siblings
+        state.rename(&Arc::from("A1"), "B").unwrap();
+
+        state.rename(&a, " Alpha ").unwrap();
+        let registry = &state.gate_store.primary_and_subgate_registry;
+        assert_eq!(registry.display_name("A").as_deref(), Some("Alpha"));
+        let file1 = resolver_for(&state, "file1");
+        assert!(matches!(
+            file1.gate_origins.get("A"),
+            Some(GateSource::Sample(_))
+        ));
+        let a_for_file1 = file1.active_gates.get("A").unwrap();
+        assert_eq!(a_for_file1.get_gate_ref(Some("A")).unwrap().name, "Alpha");
+
+        // keeping its own name isn't a clash
+        state.rename(&a, "Alpha").unwrap();
+    }
+
+    #[test]
+    fn test_undo_redo_collapses_gestures() {
+        let mut state = load(EXPERIMENT).unwrap();
+        let a: GateId = Arc::from("A");
+        let b: GateId = Arc::from("B");
+        let original_b = state.gate_store.primary_and_subgate_registry[&b].clone();
+
+        let before = state.snapshot();
+        state.rename(&a, "Alpha").unwrap();
+        state.history.record(before);
+
+        // a click that moves nothing records nothing
+        state.history.begin_gesture();
+        state.history.end_gesture();
+
+        // a drag records where it started once, however many moves it makes
+        state.history.begin_gesture();
+        for _ in 0..3 {
+            if state.history.wants_gesture_start() {
+                let start = state.snapshot();
+                state.history.record(start);
+            }
+            let registry = &mut state.gate_store.primary_and_subgate_registry;
+            let moved = translate_gate(&registry[&b], 1.0, 0.0).unwrap();
+            registry.insert(b.clone(), moved);
+        }
+        state.history.end_gesture();
+        let moved_b = state.gate_store.primary_and_subgate_registry[&b].clone();
+
+        assert!(state.undo());
+        let registry = &state.gate_store.primary_and_subgate_registry;
+        assert!(Arc::ptr_eq(&registry[&b], &original_b));
+        assert_eq!(registry.display_name("A").as_deref(), Some("Alpha"));
+
+        assert!(state.undo());
+        let registry = &state.gate_store.primary_and_subgate_registry;
+        assert_eq!(registry.display_name("A").as_deref(), Some("A"));
+        assert!(!state.undo());
+
+        assert!(state.redo());
+        assert!(state.redo());
+        let registry = &state.gate_store.primary_and_subgate_registry;
+        assert!(Arc::ptr_eq(&registry[&b], &moved_b));
+        assert_eq!(registry.display_name("A").as_deref(), Some("Alpha"));
+        assert!(!state.redo());
+
+        // a new edit drops whatever had been undone
+        assert!(state.undo());
+        let before = state.snapshot();
+        state.rename(&a, "Beta").unwrap();
+        state.history.record(before);
+        assert!(!state.redo());
+    }
+
+    #[test]
+    fn test_edit_targets_and_override_promotion() {
+        let mut state = load(EXPERIMENT).unwrap();
+        let a: GateId = Arc::from("A");
+        let file1_source = GateSource::Sample((a.clone(), Arc::from("file1")));
+
+        // file1 has its own A, so a global edit wouldn't show there
+        let file1 = resolver_for(&state, "file1");
+        assert!(file1.edit_target(&a, &GateEditMode::Global).is_err());
+        assert_eq!(
+            file1.edit_target(&a, &GateEditMode::Sample).unwrap(),
+            file1_source
+        );
+        let file2 = resolver_for(&state, "file2");
+        assert_eq!(
+            file2.edit_target(&a, &GateEditMode::Global).unwrap(),
+            GateSource::Global
+        );
+        assert!(
+            file2
+                .edit_target(&a, &GateEditMode::Group(Arc::from("Condition")))
+                .is_err()
+        );
+
+        // promoting file1's position makes it everyone's
+        let promoted = state.gate_store.remove_override(&file1_source).unwrap();
+        state
+            .gate_store
+            .set_position(&GateSource::Global, promoted.clone());
+        for file_id in ["file1", "file2"] {
+            let resolver = resolver_for(&state, file_id);
+            assert!(Arc::ptr_eq(&resolver.active_gates[&a].0, &promoted));
+            assert_eq!(resolver.gate_origins[&a], GateSource::Global);
+        }
+
+        // a group position applies to its samples until it is reset
+        let group_source = GateSource::Group((
+            a.clone(),
+            MetaDataKey {
+                parameter: Arc::from("Condition"),
+                group: Arc::from("treated"),
+            },
+        ));
+        let group_ids: FxHashMap<MetaDataParameter, GroupId> =
+            [(Arc::from("Condition"), Arc::from("treated"))]
+                .into_iter()
+                .collect();
+        let moved = translate_gate(&promoted, 1.0, 0.0).unwrap();
+        state.gate_store.set_position(&group_source, moved);
+        let in_group = |state: &GateState| {
+            GateOverrideResolver::build(
+                &state.gate_store.primary_and_subgate_registry,
+                &state.gate_store.sample_position_overrides,
+                &state.gate_store.group_position_overrides,
+                &Arc::from("file2"),
+                &group_ids,
+            )
+        };
+        let resolver = in_group(&state);
+        assert_eq!(resolver.gate_origins[&a], group_source);
+        assert_eq!(
+            resolver
+                .edit_target(&a, &GateEditMode::Group(Arc::from("Condition")))
+                .unwrap(),
+            group_source
+        );
+        assert!(state.gate_store.remove_override(&group_source).is_some());
+        assert_eq!(in_group(&state).gate_origins[&a], GateSource::Global);
+    }
+
+    #[test]
+    fn test_move_copy_and_remove_keep_children() {
+        let mut state = load(EXPERIMENT).unwrap();
+        let [a, b, a1, not_a1]: [GateId; 4] = ["A", "B", "A1", "notA1"].map(Arc::from);
+        let view = |state: &GateState, parent: &GateId| {
+            let key = GatesOnPlotKey::new(Arc::from("X"), Arc::from("Y"), Some(parent.clone()));
+            state
+                .gate_ids_by_view
+                .get(&key)
+                .cloned()
+                .unwrap_or_default()
+        };
+
+        // A can't go under its own child, and A1 can't be gated on a boolean built from it
+        assert!(state.move_to(&a, &a1, true).is_err());
+        assert!(state.move_to(&a1, &not_a1, true).is_err());
+        assert_eq!(state.hierarchy.get_parent(&a), Some(&ROOTGATE.clone()));
+        assert_eq!(state.hierarchy.get_parent(&a1), Some(&a));
+
+        // left behind, A's children move up to the root - plots gated on A go with them
+        state.move_to(&a, &b, false).unwrap();
+        assert_eq!(state.hierarchy.get_parent(&a), Some(&b));
+        assert_eq!(state.hierarchy.get_parent(&a1), Some(&ROOTGATE.clone()));
+        assert!(view(&state, &b).contains(&a));
+        assert!(view(&state, &ROOTGATE).contains(&a1));
+        assert!(!view(&state, &ROOTGATE).contains(&a));
+
+        state.move_to(&a1, &a, true).unwrap();
+        assert_eq!(state.hierarchy.get_parent(&a1), Some(&a));
+        assert!(view(&state, &a).contains(&a1));
+
+        // the copy of A brings A1 and its file1 position, each under new ids
+        state.copy_branch(&a, &ROOTGATE, true).unwrap();
+        let registry = &state.gate_store.primary_and_subgate_registry;
+        let copies: Vec<GateId> = state
+            .hierarchy
+            .get_children(&ROOTGATE)
+            .into_iter()
+            .filter(|id| registry.display_name(id).as_deref() == Some("A"))
+            .cloned()
+            .collect();
+        assert_eq!(copies.len(), 1);
+        let a_copy = copies[0].clone();
+        assert!(view(&state, &ROOTGATE).contains(&a_copy));
+        let children = state.hierarchy.get_children(&a_copy);
+        assert_eq!(children.len(), 1);
+        let a1_copy = children[0].clone();
+        assert_ne!(a1_copy, a1);
+        assert!(view(&state, &a_copy).contains(&a1_copy));
+        assert!(matches!(
+            resolver_for(&state, "file1").gate_origins.get(&a_copy),
+            Some(GateSource::Sample(_))
+        ));
+
+        // A1 is used by boolean gates, so can't be taken out on its own
+        assert!(state.remove_keep_children(&a1).is_err());
+        state.remove_keep_children(&a).unwrap();
+        assert_eq!(state.hierarchy.get_parent(&a1), Some(&b));
+        assert!(view(&state, &b).contains(&a1));
+        assert!(!view(&state, &b).contains(&a));
+        assert!(
+            !state
+                .gate_store
+                .primary_and_subgate_registry
+                .contains_key(&a)
+        );
+        assert!(
+            !state
+                .gate_store
+                .sample_position_overrides
+                .contains_key(&(a.clone(), Arc::from("file1")))
+        );
+    }
+
+    #[test]
+    fn test_linked_gates_share_edits() {
+        let mut state = load(EXPERIMENT).unwrap();
+        let [a1, b]: [GateId; 2] = ["A1", "B"].map(Arc::from);
+        let name = |state: &GateState, id: &GateId| {
+            state
+                .gate_store
+                .primary_and_subgate_registry
+                .display_name(id)
+        };
+
+        state.link_to(&a1, &b).unwrap();
+        let linked: Vec<GateId> = state
+            .hierarchy
+            .get_children(&b)
+            .into_iter()
+            .cloned()
+            .collect();
+        assert_eq!(linked.len(), 1);
+        let a1_link = linked[0].clone();
+        assert_ne!(a1_link, a1);
+        assert_eq!(state.gate_store.link_count(&a1), 2);
+        assert_eq!(state.gate_store.link_count(&a1_link), 2);
+        // once under a parent is enough
+        assert!(state.link_to(&a1_link, &b).is_err());
+
+        // each placement has its own population - under B only e2 is left
+        assert_eq!(count(&counts_for(&state, "file2"), &a1_link), 1);
+
+        // edits reach every placement, each under its own ids
+        state.rename(&a1, "CD3").unwrap();
+        assert_eq!(name(&state, &a1_link).as_deref(), Some("CD3"));
+        let gate = state.gate_store.primary_and_subgate_registry[&a1].clone();
+        let source = GateSource::Sample((a1.clone(), Arc::from("file2")));
+        state.gate_store.set_position(&source, gate);
+        assert!(matches!(
+            resolver_for(&state, "file2").gate_origins.get(&a1_link),
+            Some(GateSource::Sample(_))
+        ));
+        let link_source = GateSource::Sample((a1_link.clone(), Arc::from("file2")));
+        assert!(state.gate_store.remove_override(&link_source).is_some());
+        assert_eq!(
+            resolver_for(&state, "file2").gate_origins[&a1],
+            GateSource::Global
+        );
+
+        // the link survives a save
+        let record = state.to_record().unwrap();
+        let restored =
+            GateState::from_record(record, &im::HashMap::with_hasher(FxBuildHasher)).unwrap();
+        assert_eq!(restored.gate_store.link_count(&a1_link), 2);
+
+        // unlinked, the copy keeps its place but no longer follows the original
+        state.unlink(&a1_link).unwrap();
+        assert_eq!(state.gate_store.link_count(&a1), 0);
+        state.rename(&a1, "A1").unwrap();
+        assert_eq!(name(&state, &a1_link).as_deref(), Some("CD3"));
+        assert_eq!(state.hierarchy.get_parent(&a1_link), Some(&b));
+    }
+
+    #[test]
+    fn test_shared_filter_container_loads_as_link() {
+        let mut experiment: ExperimentJson = serde_json::from_str(EXPERIMENT).unwrap();
+        let node_id: GateId = Arc::from("n8");
+        experiment.tree.nodes.insert(
+            node_id.clone(),
+            GatingNode {
+                id: node_id.clone(),
+                parent_id: Arc::from("n2"),
+                filter_container_id: Arc::from("A1"),
+                ord: 0,
+                collapsed: false,
+            },
+        );
+        let metadata: MetaDataFileMap = im::HashMap::with_hasher(FxBuildHasher);
+        let mut state = GateState::default();
+        state
+            .load_experiment(
+                experiment,
+                &metadata,
+                im::HashMap::with_hasher(FxBuildHasher),
+            )
+            .unwrap();
+
+        // the second node is a placement of its own, linked to the first
+        assert_eq!(state.hierarchy.get_parent(&node_id), Some(&Arc::from("B")));
+        assert_eq!(state.gate_store.link_count(&node_id), 2);
+        assert_eq!(count(&counts_for(&state, "file2"), &node_id), 1);
+
+        // and both are written back as one container
+        let exported = state.to_experiment_json(&metadata).unwrap();
+        assert_eq!(
+            exported.tree.nodes[&node_id].filter_container_id.as_ref(),
+            "A1"
+        );
+        assert!(!exported.tree.filter_containers.contains_key(&node_id));
+    }
+
+    #[test]
+    fn test_boolean_expression_gates() {
+        let mut state = load(EXPERIMENT).unwrap();
+        let names: Vec<(GateId, String)> = ["A", "B", "orA1B"]
+            .into_iter()
+            .map(|id| {
+                let name = state
+                    .gate_store
+                    .primary_and_subgate_registry
+                    .display_name(id);
+                (Arc::from(id), name.unwrap())
+            })
+            .collect();
+        let unplaced = |state: &GateState| {
+            state
+                .gate_store
+                .primary_and_subgate_registry
+                .keys()
+                .filter(|id| state.is_unplaced_boolean(id))
+                .count()
+        };
+
+        // each bracketed sub-expression is a boolean gate outside the hierarchy
+        let expr = BooleanExpr::parse("(A OR B) AND NOT \"A1 or B\"", &names).unwrap();
+        let id = state.add_boolean_expr(None, &expr, &ROOTGATE).unwrap();
+        assert_eq!(unplaced(&state), 2);
+        assert_eq!(state.boolean_expr(&id), Some(expr));
+        assert_eq!(count(&counts_for(&state, "file2"), &id), 1);
+        let record = state.to_record().unwrap();
+        assert_eq!(
+            record
+                .gates
+                .iter()
+                .filter(|g| matches!(g.kind, GateRecordKind::Boolean { .. }))
+                .count(),
+            6
+        );
+
+        // editing keeps the gate and drops the sub-expressions it no longer reads
+        let expr = BooleanExpr::parse("A OR B", &names).unwrap();
+        state.edit_boolean_expr(&id, None, &expr).unwrap();
+        assert_eq!(unplaced(&state), 0);
+        assert_eq!(count(&counts_for(&state, "file2"), &id), 4);
+        assert!(state.boolean_gate_links["A"].contains(&id));
+        assert!(
+            !state
+                .boolean_gate_links
+                .values()
+                .flatten()
+                .any(|dep| state.is_unplaced_boolean(dep))
+        );
+
+        // a gate can't be combined with itself
+        let expr = BooleanExpr::Not(Box::new(BooleanExpr::Gate(id.clone())));
+        assert!(state.edit_boolean_expr(&id, None, &expr).is_err());
+    }
+
+    #[test]
+    fn test_composite_subgates_share_one_gate() {
+        use crate::omiq::serialise::tests::{
+            EXPERIMENT as OMIQ_EXPERIMENT, test_axis_settings, test_metadata,
+        };
+
+        let metadata = test_metadata();
+        let mut first = GateState::default();
+        first
+            .load_experiment(
+                serde_json::from_str(OMIQ_EXPERIMENT).unwrap(),
+                &metadata,
+                test_axis_settings(),
+            )
+            .unwrap();
+        let exported = first.to_experiment_json(&metadata).unwrap();
+        let text = serde_json::to_string(&exported).unwrap();
+        let mut state = GateState::default();
+        state
+            .load_experiment(
+                serde_json::from_str(&text).unwrap(),
+                &metadata,
+                test_axis_settings(),
+            )
+            .unwrap();
+
+        let composites = [
+            vec!["q0", "q1", "q2", "q3"],
+            vec!["s0", "s1"],
+            vec!["k0", "k1", "k2", "k3"],
+        ];
+        let assert_shared = |store: &GateSubStore| {
+            let registry = &store.primary_and_subgate_registry;
+            for subgates in &composites {
+                let gate = &registry[subgates[0]];
+                assert!(Arc::ptr_eq(gate, &registry[&gate.get_id()]), "{subgates:?}");
+                for id in subgates {
+                    assert!(Arc::ptr_eq(gate, &registry[*id]), "{id}");
+                }
+            }
+        };
+        assert_shared(&state.gate_store);
+
+        // every entry gets a new gate, but each composite is still one gate
+        let before = state.gate_store.primary_and_subgate_registry["q0"].clone();
+        state
+            .gate_store
+            .map_gates(&mut |gate: &Arc<dyn DrawableGate>| Arc::from(gate.clone_box()));
+        assert!(!Arc::ptr_eq(
+            &before,
+            &state.gate_store.primary_and_subgate_registry["q0"]
+        ));
+        assert_shared(&state.gate_store);
+    }
+
+    #[test]
+    fn test_record_round_trip_restores_state() {
+        use crate::omiq::serialise::tests::{
+            EXPERIMENT as OMIQ_EXPERIMENT, assert_experiments_match, test_axis_settings,
+            test_metadata,
+        };
+
+        let metadata = test_metadata();
+        let axis_settings = test_axis_settings();
+        let mut original = GateState::default();
+        original
+            .load_experiment(
+                serde_json::from_str(OMIQ_EXPERIMENT).unwrap(),
+                &metadata,
+                axis_settings.clone(),
+            )
+            .unwrap();
+
+        // through text, as a project file would be
+        let text = serde_json::to_string(&original.to_record().unwrap()).unwrap();
+        let restored =
+            GateState::from_record(serde_json::from_str(&text).unwrap(), &axis_settings).unwrap();
+
+        let nodes = |state: &GateState| -> Vec<(GateId, Option<GateId>, Option<u64>)> {
+            state
+                .hierarchy
+                .iter_dfs(&ROOTGATE)
+                .map(|id| {
+                    let parent = state.hierarchy.get_parent(&id).cloned();
+                    let ord = state.hierarchy.get_order(&id);
+                    (id, parent, ord)
+                })
+                .collect()
+        };
+        assert_eq!(nodes(&original), nodes(&restored));
+
+        // composites come back whole, with their subgates in order
+        let registry = |state: &GateState| -> FxHashMap<GateId, (GateId, Vec<GateId>)> {
+            state
+                .gate_store
+                .primary_and_subgate_registry
+                .iter()
+                .map(|(id, gate)| (id.clone(), (gate.get_id(), gate.get_inner_gate_ids())))
+                .collect()
+        };
+        assert_eq!(registry(&original), registry(&restored));
+        assert!(restored.gate_store.primary_and_subgate_registry["q0"].is_composite());
+
+        let sample_keys = |state: &GateState| -> FxHashSet<(GateId, FileId)> {
+            state
+                .gate_store
+                .sample_position_overrides
+                .keys()
+                .cloned()
+                .collect()
+        };
+        assert_eq!(sample_keys(&original), sample_keys(&restored));
+        assert!(sample_keys(&restored).contains(&(Arc::from("rect"), Arc::from("file1"))));
+        let group_keys = |state: &GateState| -> FxHashSet<(GateId, MetaDataKey)> {
+            state
+                .gate_store
+                .group_position_overrides
+                .keys()
+                .cloned()
+                .collect()
+        };
+        assert_eq!(group_keys(&original), group_keys(&restored));
+        assert!(group_keys(&restored).contains(&(
+            Arc::from("poly"),
+            MetaDataKey {
+                parameter: Arc::from("Donor"),
+                group: Arc::from("A"),
+            }
+        )));
+
+        let sorted = |links: &GateLinks| -> FxHashMap<GateId, Vec<GateId>> {
+            links
+                .iter()
+                .map(|(id, deps)| {
+                    let mut deps = deps.clone();
+                    deps.sort();
+                    (id.clone(), deps)
+                })
+                .collect()
+        };
+        assert_eq!(
+            sorted(&original.boolean_gate_links),
+            sorted(&restored.boolean_gate_links)
+        );
+        assert!(restored.boolean_gate_links["poly"].contains(&Arc::from("both")));
+        let views = |state: &GateState| -> FxHashMap<GatesOnPlotKey, Vec<GateId>> {
+            state
+                .gate_ids_by_view
+                .iter()
+                .map(|(key, ids)| {
+                    let mut ids = ids.clone();
+                    ids.sort();
+                    (key.clone(), ids)
+                })
+                .collect()
+        };
+        assert_eq!(views(&original), views(&restored));
+
+        // and every position, default or overridden, is where it was
+        assert_experiments_match(
+            &original.to_experiment_json(&metadata).unwrap(),
+            &restored.to_experiment_json(&metadata).unwrap(),
+        );
+    }
+}
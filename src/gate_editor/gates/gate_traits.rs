@@ -1,11 +1,14 @@
 use flow_fcs::TransformType;
+use flow_gates::types::LabelPosition;
+use rustc_hash::FxHashMap;
 use std::any::Any;
 use std::sync::Arc;
 
 use crate::gate_editor::{
     gates::{
         gate_drag::{GateDragData, PointDragData},
-        gate_types::{GateRenderShape, GateStats},
+        gate_label::GateLabels,
+        gate_types::GateRenderShape,
     },
     plots::axis_store::PlotMapper,
 };
@@ -23,7 +26,7 @@ pub trait DrawableGate: Send + Sync {
         is_selected: bool,
         drag_point: Option<PointDragData>,
         plot_map: &PlotMapper,
-        gate_stats: &Option<GateStats>,
+        labels: &GateLabels,
     ) -> Vec<GateRenderShape>;
 
     fn is_near_segment(
@@ -116,9 +119,32 @@ pub trait DrawableGate: Send + Sync {
         gate_drag_data: GateDragData,
     ) -> anyhow::Result<Option<Box<dyn DrawableGate>>>;
 
+    // a copy with `id` renamed - composites can rename themselves or any of their subgates
+    fn rename(&self, id: &str, name: &str) -> anyhow::Result<Box<dyn DrawableGate>>;
+
+    // a copy with the label of `id` moved - composites can move any of their subgates' labels
+    fn with_label_position(
+        &self,
+        id: &str,
+        position: LabelPosition,
+    ) -> anyhow::Result<Box<dyn DrawableGate>>;
+
+    // a copy under new ids, for copying a branch of the hierarchy -
+    // composites take the new id for themselves and each of their subgates from `ids`
+    fn with_new_ids(
+        &self,
+        ids: &FxHashMap<Arc<str>, Arc<str>>,
+    ) -> anyhow::Result<Box<dyn DrawableGate>>;
+
     fn clone_box(&self) -> Box<dyn DrawableGate>;
 }
 
+pub fn new_gate_id(ids: &FxHashMap<Arc<str>, Arc<str>>, id: &str) -> anyhow::Result<Arc<str>> {
+    ids.get(id)
+        .cloned()
+        .ok_or_else(|| anyhow::anyhow!("No new id given for gate {}", id))
+}
+
 impl Clone for Box<dyn DrawableGate> {
     fn clone(&self) -> Self {
         self.clone_box()
@@ -46,11 +46,12 @@ use crate::gate_editor::gates::gate_store::GateId;
 //     }
 // }
 
-#[derive(Clone, PartialEq)]
+// the parts a gate label can show, in the order they're drawn
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
 pub enum GateText {
-    Name(String),
-    Percent(String),
-    Count(String),
+    Name,
+    Percent,
+    Count,
 }
 
 #[derive(Clone, PartialEq, Copy)]
@@ -99,8 +100,8 @@ pub enum ShapeType {
     Rotation(f32),
     UndraggableLine,
     UndraggablePoint(usize),
-    Text,
-    UndraggableText(Direction),
+    // a (sub)gate's label - a pinned label stays put along that direction while its gate is dragged
+    Label(GateId, Option<Direction>),
 }
 
 #[derive(PartialEq, Clone)]
@@ -472,6 +473,13 @@ pub static GREY_LINE_DASHED: DrawingStyle = DrawingStyle {
     dashed: true,
 };
 
+pub static BOOLEAN_REGION: DrawingStyle = DrawingStyle {
+    stroke: "magenta",
+    fill: "rgba(255, 0, 255, 0.15)",
+    stroke_width: 1.5,
+    dashed: true,
+};
+
 #[derive(Clone, Debug, PartialEq)]
 pub enum GateStatValue {
     Single(f32),
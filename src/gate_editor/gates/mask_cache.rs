@@ -0,0 +1,234 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use flow_fcs::TransformType;
+use polars::prelude::BooleanChunked;
+use rustc_hash::FxHashMap;
+
+use crate::gate_editor::compensation::CompMatrix;
+use crate::gate_editor::gates::{
+    GateId,
+    gate_store::{ComparableGate, FileId},
+};
+
+// masks are bit-packed, so a 5M event mask is ~610KB and the cache never holds more than
+// 256MB of them (~420 such masks) - the least recently used are evicted past that
+const MAX_CACHED_BYTES: usize = 256 * 1024 * 1024;
+
+static NEXT_GENERATION: AtomicU64 = AtomicU64::new(0);
+
+// A new id for every scaling of a file's events. Masks are only reused on the generation they
+// were computed from, so reloaded or replaced data under the same FileId starts afresh.
+pub fn next_data_generation() -> u64 {
+    NEXT_GENERATION.fetch_add(1, Ordering::Relaxed)
+}
+
+// One gate a population mask was computed from, and the transforms and compensation its axes
+// were scaled with.
+// Moving, rescaling or overriding a gate always swaps its Arc, and holding the Arc here means
+// the old allocation can't be reused - so pointer equality is an exact version check.
+#[derive(Clone, PartialEq)]
+pub struct MaskDependency {
+    pub gate_id: GateId,
+    pub gate: ComparableGate,
+    pub x_transform: Option<TransformType>,
+    pub y_transform: Option<TransformType>,
+    pub compensation: Option<Arc<CompMatrix>>,
+}
+
+// the gate itself, then every ancestor and boolean operand it depends on
+pub type MaskSignature = Arc<Vec<MaskDependency>>;
+
+struct CachedMask {
+    generation: u64,
+    signature: MaskSignature,
+    mask: BooleanChunked,
+    last_used: u64,
+}
+
+fn mask_bytes(mask: &BooleanChunked) -> usize {
+    mask.len().div_ceil(8)
+}
+
+struct CacheState {
+    entries: FxHashMap<(FileId, GateId), CachedMask>,
+    bytes: usize,
+    max_bytes: usize,
+    // bumped on every get/insert, so the smallest last_used is the least recently used
+    clock: u64,
+    hits: usize,
+}
+
+impl Default for CacheState {
+    fn default() -> Self {
+        Self {
+            entries: FxHashMap::default(),
+            bytes: 0,
+            max_bytes: MAX_CACHED_BYTES,
+            clock: 0,
+            hits: 0,
+        }
+    }
+}
+
+impl CacheState {
+    fn tick(&mut self) -> u64 {
+        self.clock += 1;
+        self.clock
+    }
+
+    fn remove(&mut self, key: &(FileId, GateId)) {
+        if let Some(old) = self.entries.remove(key) {
+            self.bytes -= mask_bytes(&old.mask);
+        }
+    }
+
+    // evicts least recently used masks until `incoming` more bytes fit
+    fn make_room(&mut self, incoming: usize) {
+        if self.bytes + incoming <= self.max_bytes {
+            return;
+        }
+        let mut by_age: Vec<(u64, (FileId, GateId))> = self
+            .entries
+            .iter()
+            .map(|(key, entry)| (entry.last_used, key.clone()))
+            .collect();
+        by_age.sort_unstable_by_key(|(last_used, _)| *last_used);
+        for (_, key) in by_age {
+            if self.bytes + incoming <= self.max_bytes {
+                break;
+            }
+            self.remove(&key);
+        }
+    }
+}
+
+// Population masks shared across plot changes, keyed by file and gate.
+// An entry is only used for the data generation it was computed on and while its signature
+// still matches, so an edit to a gate or any of its ancestors invalidates exactly the masks
+// built on it.
+#[derive(Clone, Default)]
+pub struct GateMaskCache(Arc<Mutex<CacheState>>);
+
+impl GateMaskCache {
+    pub fn get(
+        &self,
+        file_id: &FileId,
+        generation: u64,
+        gate_id: &GateId,
+        signature: &[MaskDependency],
+    ) -> Option<BooleanChunked> {
+        let mut state = self.0.lock().ok()?;
+        let now = state.tick();
+        let entry = state.entries.get_mut(&(file_id.clone(), gate_id.clone()))?;
+        if entry.generation != generation || entry.signature.as_slice() != signature {
+            return None;
+        }
+        entry.last_used = now;
+        let mask = entry.mask.clone();
+        state.hits += 1;
+        Some(mask)
+    }
+
+    pub fn insert(
+        &self,
+        file_id: FileId,
+        generation: u64,
+        gate_id: GateId,
+        signature: MaskSignature,
+        mask: BooleanChunked,
+    ) {
+        let Ok(mut state) = self.0.lock() else {
+            return;
+        };
+        let key = (file_id, gate_id);
+        state.remove(&key);
+        let bytes = mask_bytes(&mask);
+        if bytes > state.max_bytes {
+            return;
+        }
+        state.make_room(bytes);
+        let last_used = state.tick();
+        state.bytes += bytes;
+        state.entries.insert(
+            key,
+            CachedMask {
+                generation,
+                signature,
+                mask,
+                last_used,
+            },
+        );
+    }
+
+    // how many masks have been served from the cache
+    pub fn hits(&self) -> usize {
+        self.0.lock().map(|s| s.hits).unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mask(events: usize) -> BooleanChunked {
+        BooleanChunked::full("mask".into(), true, events)
+    }
+
+    #[test]
+    fn test_cache_evicts_least_recently_used_by_size() {
+        // room for three 8000 event masks
+        let cache = GateMaskCache(Arc::new(Mutex::new(CacheState {
+            max_bytes: 3000,
+            ..Default::default()
+        })));
+        let file: FileId = Arc::from("file");
+        let signature: MaskSignature = Arc::new(vec![]);
+        for gate in ["a", "b", "c"] {
+            cache.insert(
+                file.clone(),
+                0,
+                Arc::from(gate),
+                signature.clone(),
+                mask(8000),
+            );
+        }
+        // a is now more recent than b
+        assert!(cache.get(&file, 0, &Arc::from("a"), &signature).is_some());
+
+        cache.insert(
+            file.clone(),
+            0,
+            Arc::from("d"),
+            signature.clone(),
+            mask(8000),
+        );
+        assert!(cache.get(&file, 0, &Arc::from("b"), &signature).is_none());
+        for gate in ["a", "c", "d"] {
+            assert!(
+                cache.get(&file, 0, &Arc::from(gate), &signature).is_some(),
+                "{gate}"
+            );
+        }
+        assert_eq!(cache.0.lock().unwrap().bytes, 3000);
+
+        // replacing an entry doesn't count it twice, and masks over the budget aren't kept
+        cache.insert(
+            file.clone(),
+            0,
+            Arc::from("d"),
+            signature.clone(),
+            mask(8000),
+        );
+        assert_eq!(cache.0.lock().unwrap().bytes, 3000);
+        cache.insert(
+            file.clone(),
+            0,
+            Arc::from("e"),
+            signature.clone(),
+            mask(80_000),
+        );
+        assert!(cache.get(&file, 0, &Arc::from("e"), &signature).is_none());
+        assert_eq!(cache.0.lock().unwrap().entries.len(), 3);
+    }
+}
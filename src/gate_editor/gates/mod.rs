@@ -3,11 +3,20 @@ pub mod gate_drag;
 pub mod gate_single;
 pub mod gate_store;
 pub mod gate_types;
+pub mod mask_cache;
 pub use gate_store::{GateId, GateState, GatesOnPlotKey};
+pub mod auto_adjust;
+pub mod batch_stats;
+pub mod boolean_expr;
+pub mod boolean_region;
 pub mod draw_gates;
+pub mod event_export;
 pub mod gate_buttons;
 pub mod gate_composite;
 pub mod gate_filtering;
 pub mod gate_hierarchy;
+pub mod gate_label;
+pub mod gate_history;
+pub mod gate_record;
 pub mod gate_stats;
 pub mod gate_traits;
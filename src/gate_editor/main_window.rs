@@ -1,22 +1,36 @@
-use crate::gate_editor::gates::gate_buttons::NewGateButtons;
+use crate::gate_editor::axis_controls::{
+    BiexControls, CompensationControls, OverlaySelect, PlotModeControls, PlotStyleControls,
+    TransformSelect,
+};
+use crate::gate_editor::compensation::CompensationStore;
+use crate::gate_editor::file_menu::{FileMenu, FileMenuAction, InputStatus, SessionInput};
+use crate::gate_editor::gates::gate_buttons::{GateLabelButtons, NewGateButtons};
 use crate::gate_editor::plots::axis_store::AxisStore;
 use crate::gate_editor::plots::axis_store::AxisStoreImplExt;
 use crate::gate_editor::plots::axis_store::AxisStoreStoreExt;
-use crate::gate_editor::plots::axis_store::ScalingInfoSource;
+use crate::gate_editor::plots::histogram::{HistogramOptions, HistogramOverlay, PlotMode};
 use crate::gate_editor::plots::plot_window::PlotWindow;
-use crate::omiq::metadata::MetaDataImplExt;
-use crate::omiq::metadata::MetaDataOrigin;
+use crate::gate_editor::auto_adjust_panel::AutoAdjustPanel;
+use crate::gate_editor::boolean_builder_panel::{BooleanBuilderPanel, BooleanTarget};
+use crate::gate_editor::copy_branch_panel::CopyBranchPanel;
+use crate::gate_editor::copy_position_panel::CopyPositionPanel;
+use crate::gate_editor::export_panel::ExportPanel;
+use crate::gate_editor::sample_browser::SampleBrowser;
+use crate::gate_editor::session::Session;
+use crate::gate_editor::stats_panel::StatsPanel;
 use crate::omiq::metadata::MetaDataStore;
 
 use crate::omiq::metadata::MetaDataStoreStoreExt;
+use crate::project::{self, ProjectSelection};
 use crate::searchable_select::SearchableSelectSet;
 use crate::{
-    file_load::FcsFiles,
     gate_editor::{
         AxisInfo,
         gate_sidebar::GateSidebar,
         gates::{
             GateState,
+            gate_label::GateLabelContent,
+            gate_stats::PopulationCounts,
             gate_store::{GateStateImplExt, ROOTGATE},
             gate_types::PrimaryGateType,
         },
@@ -25,101 +39,84 @@ use crate::{
     searchable_select::SearchableSelectList,
 };
 use dioxus::prelude::*;
-use dioxus::stores::use_store_sync;
+use dioxus::stores::SyncStore;
 
-use std::path::PathBuf;
 use std::sync::Arc;
 
 static CSS_STYLE: Asset = asset!("assets/main_window.css");
 
+// the status line under the toolbar - components deeper in the editor report errors to it
+#[derive(Clone, Copy)]
+pub struct StatusMessage(pub Signal<Option<String>>);
+
+impl StatusMessage {
+    pub fn show(mut self, message: impl std::fmt::Display) {
+        self.0.set(Some(message.to_string()));
+    }
+}
+
 #[component]
 pub fn MainWindow() -> Element {
-    let mut filehandler: Signal<Option<FcsFiles>> = use_signal(|| None);
+    let session = use_context::<Session>();
+    let filehandler = session.files;
+    let mut sample_index = session.sample_index;
+    let mut fcs_dir_path = session.fcs_dir_path;
     let mut message = use_signal(|| None::<String>);
-
-    let mut metadata_store = use_store_sync(MetaDataStore::default);
-    use_context_provider(|| metadata_store);
-
-    let meta_result = use_resource(move || async move {
-        let result = tokio::task::spawn_blocking(move || -> Result<(), anyhow::Error> {
-            let content = std::fs::read_to_string("file_paths.txt")?;
-            let second_line = content
-                .lines()
-                .filter(|l| !l.trim().is_empty())
-                .nth(1)
-                .ok_or_else(|| anyhow::anyhow!("File does not have a second non-empty line"))?;
-            let path = PathBuf::from(second_line);
-            metadata_store.set_metadata_from_file(path, "OmiqID", "Filename", MetaDataOrigin::Omiq)
-        })
-        .await;
-
-        match result {
-            Ok(r) => r,
-            Err(e) => Err(anyhow::anyhow!("Failed to load metadata from file {}", e)),
+    use_context_provider(|| StatusMessage(message));
+
+    let metadata_store = use_context::<SyncStore<MetaDataStore>>();
+    let mut gate_store = use_context::<SyncStore<GateState>>();
+    let mut axis_store = use_context::<SyncStore<AxisStore>>();
+    let comp_store = use_context::<SyncStore<CompensationStore>>();
+
+    // Ctrl+Z / Ctrl+Shift+Z (Cmd on mac) - text fields keep their own undo.
+    // The handler is kept on window so it can be removed when the editor unmounts.
+    use_future(move || async move {
+        let mut shortcuts = document::eval(
+            r#"
+            if (window.gateUndoShortcuts) {
+                document.removeEventListener("keydown", window.gateUndoShortcuts);
+            }
+            window.gateUndoShortcuts = (e) => {
+                if (!(e.ctrlKey || e.metaKey) || e.key.toLowerCase() !== "z") return;
+                const t = e.target;
+                if (t && (t.tagName === "INPUT" || t.tagName === "TEXTAREA" || t.isContentEditable)) return;
+                e.preventDefault();
+                dioxus.send(e.shiftKey ? "redo" : "undo");
+            };
+            document.addEventListener("keydown", window.gateUndoShortcuts);
+            "#,
+        );
+        while let Ok(action) = shortcuts.recv::<String>().await {
+            let changed = if action == "redo" {
+                gate_store.redo()
+            } else {
+                gate_store.undo()
+            };
+            if !changed {
+                println!("Nothing to {action}");
+            }
         }
     });
-
-    
-
-    let mut gate_store: Store<GateState, CopyValue<GateState, SyncStorage>> =
-        use_store_sync(GateState::default);
-    use_context_provider(|| gate_store);
+    use_drop(|| {
+        let _ = document::eval(
+            r#"
+            if (window.gateUndoShortcuts) {
+                document.removeEventListener("keydown", window.gateUndoShortcuts);
+                window.gateUndoShortcuts = null;
+            }
+            "#,
+        );
+    });
 
     let mut current_gate_type = use_signal(|| PrimaryGateType::Polygon);
     use_context_provider(|| current_gate_type);
 
-    let mut axis_store: Store<AxisStore, CopyValue<AxisStore, SyncStorage>> = use_store_sync(AxisStore::default);
-    use_context_provider(|| axis_store);
-
-    let axis_result = use_resource(move || async move {
-        let result = tokio::task::spawn_blocking(move || -> Result<(), anyhow::Error> {
-            let content = std::fs::read_to_string("file_paths.txt")?;
-            let forth_line = content
-                .lines()
-                .filter(|l| !l.trim().is_empty())
-                .nth(3)
-                .ok_or_else(|| anyhow::anyhow!("File does not have a forth non-empty line"))?;
-            let path = PathBuf::from(forth_line);
-            axis_store.set_axes_from_file(path, ScalingInfoSource::Omiq)
-        })
-        .await;
-
-        match result {
-            Ok(r) => r,
-            Err(e) => Err(anyhow::anyhow!("Failed to load axis settings from file {}", e)),
-        }
-    });
-
-    let file_result = use_resource(move || async move {
-        let result = tokio::task::spawn_blocking(move || -> anyhow::Result<FcsFiles> {
-            let content = std::fs::read_to_string("file_paths.txt")?;
-            let path = content
-                .lines()
-                .find(|l| !l.trim().is_empty())
-                .ok_or_else(|| anyhow::anyhow!("No path found"))?;
-
-            FcsFiles::create(path.trim())
-        })
-        .await;
-
-        match result {
-            Ok(Ok(files)) => {
-                message.set(None);
-                filehandler.set(Some(files));
-                Ok(())
-            }
-            Ok(Err(e)) => {
-                message.set(Some(e.to_string()));
-                Err(e)
-            }
-            Err(e) => {
-                message.set(Some(e.to_string()));
-                Err(anyhow::anyhow!("Failed to load files from path {}", e))
-            }
-        }
-    });
+    let label_content = use_signal(GateLabelContent::default);
+    use_context_provider(|| label_content);
 
-    let mut sample_index = use_signal(|| 0);
+    let population_counts: Signal<PopulationCounts> = use_signal(PopulationCounts::default);
+    use_context_provider(|| population_counts);
 
     let mut x_axis_marker: Signal<Param> = use_signal(|| {
         let p: Arc<str> = Arc::from("FSC-A");
@@ -170,92 +167,132 @@ pub fn MainWindow() -> Element {
             .unwrap_or(0)
     });
 
-    let mut upload_succeded = use_signal(|| false);
-    let gate_resource = use_resource(move || {
-        // cheap im clones
-        let metadata = metadata_store.metadata().read().clone();
-        let axis_settings = axis_store.settings().read().clone();
-        async move {
-            if *upload_succeded.peek() {
-                return Ok(());
-            }
+    let mut parental_gate: Signal<Option<Arc<str>>> = use_signal(|| Some(ROOTGATE.clone()));
 
-            if metadata.is_empty() || axis_settings.is_empty() {
-                return Err(anyhow::anyhow!("Metadata or Axis settings are empty"));
-            }
+    let plot_mode = use_signal(PlotMode::default);
+    let histogram_options = use_signal(HistogramOptions::default);
+    let histogram_overlays: Signal<Vec<HistogramOverlay>> = use_signal(Vec::new);
+
+    let mut stats_open = use_signal(|| false);
+    let mut browser_open = use_signal(|| false);
+    let auto_adjust_gate: Signal<Option<Arc<str>>> = use_signal(|| None);
+    let export_gate: Signal<Option<Arc<str>>> = use_signal(|| None);
+    let copy_position_gate: Signal<Option<Arc<str>>> = use_signal(|| None);
+    let copy_branch_gate: Signal<Option<Arc<str>>> = use_signal(|| None);
+    let link_gate: Signal<Option<Arc<str>>> = use_signal(|| None);
+    let boolean_builder: Signal<Option<BooleanTarget>> = use_signal(|| None);
+
+    // the sample in the first plot - the sidebar's positions and edit modes refer to it
+    let current_file = session.current_file;
 
-            let result = tokio::task::spawn_blocking(move || {
-                let content = std::fs::read_to_string("file_paths.txt")
-                    .map_err(|e| anyhow::anyhow!("Failed to read file: {}", e))?;
-                
-                let path_str = content.lines()
-                    .filter(|l| !l.trim().is_empty())
-                    .nth(2)
-                    .ok_or_else(|| anyhow::anyhow!("File does not have a third line"))?;
-                
-                let path = PathBuf::from(path_str);
-
-                gate_store.upload_gates_from_file(path, &metadata, axis_settings)
-                    .map_err(|e| anyhow::anyhow!("Upload failed: {}", e))
-
-            }).await;
-
-            // 5. Handle the thread result and update UI signals
-            match result {
-                Ok(Ok(_)) => {
-                    upload_succeded.set(true);
-                    Ok(())
+    let on_file_action = move |action: FileMenuAction| match action {
+        FileMenuAction::Open(input, path) => {
+            message.set(None);
+            session.path(input).set(Some(path));
+        }
+        FileMenuAction::Reload(input) => session.result(input).restart(),
+        FileMenuAction::OpenProject(path) => {
+            spawn(async move {
+                let result = tokio::task::spawn_blocking(move || {
+                    project::load_project(&path, gate_store, axis_store, metadata_store, comp_store)
+                })
+                .await;
+                match result {
+                    Ok(Ok((fcs_directory, selection))) => {
+                        message.set(None);
+                        if let Some(x) = selection.x_axis {
+                            x_axis_marker.set(x);
+                        }
+                        if let Some(y) = selection.y_axis {
+                            y_axis_marker.set(y);
+                        }
+                        parental_gate.set(selection.parental_gate.or(Some(ROOTGATE.clone())));
+                        fcs_dir_path.set(fcs_directory);
+                    }
+                    Ok(Err(e)) => message.set(Some(format!("Failed to open project: {e}"))),
+                    Err(e) => message.set(Some(format!("Failed to open project: {e}"))),
                 }
-                Ok(Err(e)) => {
-                    println!("{e}");
-                    Err(e)
-                },
-                Err(e) => Err(anyhow::anyhow!("Thread joined with error: {}", e)),
+            });
+        }
+        FileMenuAction::SaveProject(path) => {
+            let selection = ProjectSelection {
+                x_axis: Some(x_axis_marker.peek().clone()),
+                y_axis: Some(y_axis_marker.peek().clone()),
+                parental_gate: parental_gate.peek().clone(),
+            };
+            let fcs_directory = fcs_dir_path.peek().clone();
+            match project::save_project(
+                &path,
+                fcs_directory,
+                selection,
+                gate_store,
+                axis_store,
+                metadata_store,
+                comp_store,
+            ) {
+                Ok(_) => message.set(None),
+                Err(e) => message.set(Some(format!("Failed to save project: {e}"))),
             }
         }
-    });
+        FileMenuAction::ExportGates(path) => {
+            let metadata = metadata_store.metadata().peek().clone();
+            match gate_store.export_gates_to_file(path, &metadata) {
+                Ok(_) => message.set(None),
+                Err(e) => message.set(Some(format!("Failed to export gates: {e}"))),
+            }
+        }
+        FileMenuAction::ShowStatistics => stats_open.set(true),
+    };
 
-    let parental_gate: Signal<Option<Arc<str>>> = use_signal(|| Some(ROOTGATE.clone()));
+    let error_of = |r: Option<&anyhow::Result<()>>| match r {
+        Some(Err(e)) => Some(e.to_string()),
+        _ => None,
+    };
 
     rsx! {
         document::Stylesheet { href: CSS_STYLE }
-        div { class: "sidebar-local",
-
-            match &*meta_result.read() {
-                Some(Ok(())) => {}
-                Some(Err(e)) => return rsx! {
-                    div { class: "spinner-container", "{e}" }
-                },
-                None => return rsx! {
-                    div { class: "spinner-container",
-                        div { class: "spinner" }
-                    }
-                },
+        FileMenu { on_action: on_file_action }
+        StatsPanel { open: stats_open, files: filehandler }
+        SampleBrowser { open: browser_open }
+        AutoAdjustPanel { gate_id: auto_adjust_gate, files: filehandler }
+        ExportPanel { gate_id: export_gate, files: filehandler }
+        CopyPositionPanel { gate_id: copy_position_gate, current_file }
+        CopyBranchPanel { gate_id: copy_branch_gate, link: false }
+        CopyBranchPanel { gate_id: link_gate, link: true }
+        BooleanBuilderPanel { target: boolean_builder, files: filehandler, current_file }
+        div { class: "input-status-panel",
+            for input in SessionInput::ALL {
+                InputStatus {
+                    key: "{input.label()}",
+                    input,
+                    path: session.path(input)(),
+                    error: error_of(session.result(input).read().as_ref()),
+                }
             }
-
-            match &*file_result.read() {
-                Some(Ok(())) => {}
-                Some(Err(e)) => return rsx! {
-                    div { class: "spinner-container", "{e}" }
-                },
-                None => return rsx! {
-                    div { class: "spinner-container",
-                        div { class: "spinner" }
-                    }
-                },
+            if let Some(m) = message() {
+                div { class: "status-message error-message", "{m}" }
             }
+        }
+        div { class: "sidebar-local",
 
             GateSidebar {
                 selected_id: parental_gate,
                 x_axis_param: x_axis_marker,
                 y_axis_param: y_axis_marker,
+                auto_adjust_gate,
+                export_gate,
+                copy_position_gate,
+                copy_branch_gate,
+                link_gate,
+                boolean_builder,
+                current_file,
             }
 
             main { class: "main-content",
 
                 div { class: "gate-window",
 
-                    div { class: "axis-controls-grid", style: "width: 600px;",
+                    div { class: "axis-controls-grid", style: "width: 720px;",
                         div { class: "grid-label", "X-Axis" }
                         SearchableSelectSet {
                             items: axis_store.sorted_settings()(),
@@ -266,38 +303,67 @@ pub fn MainWindow() -> Element {
                             selected_index: Some(x_axis_selected_index.into()),
                         }
 
-                        div { class: "input-unit",
-                            label { "Cofactor" }
-                            input {
-                                r#type: "number",
-                                value: "{x_axis_limits.read().get_cofactor().unwrap_or_default().round()}",
-                                disabled: x_axis_limits.read().is_linear(),
-                                oninput: move |evt| {
-                                    if let Ok(val) = evt.value().parse::<i32>() {
-                                        if val >= 1 {
-                                            let param = x_axis_marker.peek();
-                                            let res = axis_store.update_cofactor(&param.fluoro, val as f32);
-                                            match res {
-                                                Ok((old, new)) => {
-                                                    match gate_store.rescale_gates(&param.fluoro, &old, &new) {
-                                                        Ok(_) => message.set(None),
-                                                        Err(e) => {
-                                                            message.set(Some(e.join("\n")));
-                                                        }
-                                                    };
-                                                }
-                                                Err(e) => println!("{e}"),
+                        TransformSelect {
+                            axis: x_axis_limits,
+                            on_change: move |transform| {
+                                let param = x_axis_marker.peek();
+                                match axis_store.switch_transform(&param.fluoro, transform, gate_store) {
+                                    Ok(_) => message.set(None),
+                                    Err(e) => message.set(Some(e.join("\n"))),
+                                }
+                            },
+                        }
 
+                        if x_axis_limits.read().is_biexponential() {
+                            BiexControls {
+                                axis: x_axis_limits,
+                                on_change: move |params| {
+                                    let param = x_axis_marker.peek();
+                                    match axis_store.update_biex_params(&param.fluoro, params) {
+                                        Ok((old, new)) => {
+                                            match gate_store.rescale_gates(&param.fluoro, &old, &new) {
+                                                Ok(_) => message.set(None),
+                                                Err(e) => message.set(Some(e.join("\n"))),
                                             }
-                                        } else {
-                                            message
-                                                .set(
-                                                    Some("Arcsinh cofactor should be a positive integer".to_string()),
-                                                );
                                         }
+                                        Err(e) => message.set(Some(e.to_string())),
                                     }
                                 },
-                                step: "any",
+                            }
+                        } else {
+                            div { class: "input-unit",
+                                label { "Cofactor" }
+                                input {
+                                    r#type: "number",
+                                    value: "{x_axis_limits.read().get_cofactor().unwrap_or_default().round()}",
+                                    disabled: x_axis_limits.read().is_linear(),
+                                    oninput: move |evt| {
+                                        if let Ok(val) = evt.value().parse::<i32>() {
+                                            if val >= 1 {
+                                                let param = x_axis_marker.peek();
+                                                let res = axis_store.update_cofactor(&param.fluoro, val as f32);
+                                                match res {
+                                                    Ok((old, new)) => {
+                                                        match gate_store.rescale_gates(&param.fluoro, &old, &new) {
+                                                            Ok(_) => message.set(None),
+                                                            Err(e) => {
+                                                                message.set(Some(e.join("\n")));
+                                                            }
+                                                        };
+                                                    }
+                                                    Err(e) => println!("{e}"),
+
+                                                }
+                                            } else {
+                                                message
+                                                    .set(
+                                                        Some("Arcsinh cofactor should be a positive integer".to_string()),
+                                                    );
+                                            }
+                                        }
+                                    },
+                                    step: "any",
+                                }
                             }
                         }
                         div { class: "input-unit",
@@ -370,38 +436,67 @@ pub fn MainWindow() -> Element {
                             selected_index: Some(y_axis_selected_index.into()),
                         }
 
-                        div { class: "input-unit",
-                            label { "Cofactor" }
-                            input {
-                                r#type: "number",
-                                value: "{y_axis_limits.read().get_cofactor().unwrap_or_default().round()}",
-                                disabled: y_axis_limits.read().is_linear(),
-                                oninput: move |evt| {
-                                    if let Ok(val) = evt.value().parse::<i32>() {
-                                        if val >= 1 {
-                                            message.set(None);
-                                            let param = y_axis_marker.peek();
-                                            let res = axis_store.update_cofactor(&param.fluoro, val as f32);
-                                            match res {
-                                                Ok((old, new)) => {
-                                                    match gate_store.rescale_gates(&param.fluoro, &old, &new) {
-                                                        Ok(_) => message.set(None),
-                                                        Err(e) => {
-                                                            message.set(Some(e.join("\n")));
-                                                        }
-                                                    };
-                                                }
-                                                Err(e) => println!("{e}"),
+                        TransformSelect {
+                            axis: y_axis_limits,
+                            on_change: move |transform| {
+                                let param = y_axis_marker.peek();
+                                match axis_store.switch_transform(&param.fluoro, transform, gate_store) {
+                                    Ok(_) => message.set(None),
+                                    Err(e) => message.set(Some(e.join("\n"))),
+                                }
+                            },
+                        }
+
+                        if y_axis_limits.read().is_biexponential() {
+                            BiexControls {
+                                axis: y_axis_limits,
+                                on_change: move |params| {
+                                    let param = y_axis_marker.peek();
+                                    match axis_store.update_biex_params(&param.fluoro, params) {
+                                        Ok((old, new)) => {
+                                            match gate_store.rescale_gates(&param.fluoro, &old, &new) {
+                                                Ok(_) => message.set(None),
+                                                Err(e) => message.set(Some(e.join("\n"))),
                                             }
-                                        } else {
-                                            message
-                                                .set(
-                                                    Some("Arcsinh cofactor should be a positive integer".to_string()),
-                                                );
                                         }
+                                        Err(e) => message.set(Some(e.to_string())),
                                     }
                                 },
-                                step: "any",
+                            }
+                        } else {
+                            div { class: "input-unit",
+                                label { "Cofactor" }
+                                input {
+                                    r#type: "number",
+                                    value: "{y_axis_limits.read().get_cofactor().unwrap_or_default().round()}",
+                                    disabled: y_axis_limits.read().is_linear(),
+                                    oninput: move |evt| {
+                                        if let Ok(val) = evt.value().parse::<i32>() {
+                                            if val >= 1 {
+                                                message.set(None);
+                                                let param = y_axis_marker.peek();
+                                                let res = axis_store.update_cofactor(&param.fluoro, val as f32);
+                                                match res {
+                                                    Ok((old, new)) => {
+                                                        match gate_store.rescale_gates(&param.fluoro, &old, &new) {
+                                                            Ok(_) => message.set(None),
+                                                            Err(e) => {
+                                                                message.set(Some(e.join("\n")));
+                                                            }
+                                                        };
+                                                    }
+                                                    Err(e) => println!("{e}"),
+                                                }
+                                            } else {
+                                                message
+                                                    .set(
+                                                        Some("Arcsinh cofactor should be a positive integer".to_string()),
+                                                    );
+                                            }
+                                        }
+                                    },
+                                    step: "any",
+                                }
                             }
                         }
                         div { class: "input-unit",
@@ -461,6 +556,17 @@ pub fn MainWindow() -> Element {
                                 },
                             }
                         }
+
+                        div { class: "grid-label", "Plot" }
+                        PlotModeControls { mode: plot_mode, options: histogram_options }
+                        if plot_mode() == PlotMode::Histogram {
+                            OverlaySelect { files: filehandler, overlays: histogram_overlays }
+                        } else {
+                            PlotStyleControls { x_axis: x_axis_marker, y_axis: y_axis_marker }
+                        }
+
+                        div { class: "grid-label", "Comp" }
+                        CompensationControls { current_file }
                     }
                     div { class: "file-info",
                         div { class: "file-info_button-panel",
@@ -485,6 +591,11 @@ pub fn MainWindow() -> Element {
                                 },
                                 "Next"
                             }
+                            button {
+                                disabled: filehandler.read().is_none(),
+                                onclick: move |_| browser_open.set(true),
+                                "Browse"
+                            }
                         }
                         match &*filehandler.read() {
                             Some(fh) => {
@@ -506,6 +617,7 @@ pub fn MainWindow() -> Element {
 
                 div {
                     NewGateButtons { callback: move |gate_type| current_gate_type.set(gate_type) }
+                    GateLabelButtons {}
                     {
                         let maybe_stubs = filehandler
                             .read()
@@ -525,6 +637,9 @@ pub fn MainWindow() -> Element {
                                             x_axis_marker,
                                             y_axis_marker,
                                             parental_gate,
+                                            plot_mode,
+                                            histogram_options,
+                                            histogram_overlays,
                                         }
                                     }
                                     div { class: "gate-window",
@@ -533,6 +648,9 @@ pub fn MainWindow() -> Element {
                                             x_axis_marker,
                                             y_axis_marker,
                                             parental_gate,
+                                            plot_mode,
+                                            histogram_options,
+                                            histogram_overlays,
                                         }
                                     }
                                 }
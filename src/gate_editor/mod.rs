@@ -1,8 +1,20 @@
+pub mod auto_adjust_panel;
+pub mod axis_controls;
 pub mod axis_info;
+pub mod boolean_builder_panel;
+pub mod comp_window;
+pub mod compensation;
+pub mod copy_branch_panel;
+pub mod copy_position_panel;
+pub mod export_panel;
 pub mod gates;
 pub mod macros;
 pub mod route;
+pub mod sample_browser;
 pub use axis_info::AxisInfo;
+pub mod file_menu;
 pub mod gate_sidebar;
 pub mod main_window;
 pub mod plots;
+pub mod session;
+pub mod stats_panel;
@@ -2,16 +2,23 @@ use anyhow::anyhow;
 use polars::{frame::DataFrame, prelude::{CsvReadOptions, DataType, Field, Schema}};
 use core::f32;
 use dioxus::prelude::*;
+use dioxus::stores::SyncStore;
 use flow_fcs::{TransformType, Transformable};
 use flow_gates::transforms::{
     Axis, get_plotting_area, pixel_to_raw, pixel_to_raw_y, raw_to_pixel, raw_to_pixel_y
 };
-use rustc_hash::FxBuildHasher;
+use rustc_hash::{FxBuildHasher, FxHashMap};
+use serde::{Deserialize, Serialize};
 use std::{ops::RangeInclusive, path::PathBuf, sync::Arc};
 
 use polars::prelude::*;
 use itertools::izip;
-use crate::gate_editor::{AxisInfo, gates::GateId};
+use crate::gate_editor::{
+    AxisInfo,
+    axis_info::{AxisRecord, BiexParams},
+    gates::{GateId, GateState, gate_store::GateStateImplExt},
+    plots::plot_style::{PlotStyleKey, PlotStyleRecord, PlotStyleSettings},
+};
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct PlotMapper {
@@ -139,7 +146,7 @@ impl PlotMapper {
     }
 }
 
-#[derive(Clone, PartialEq, Eq, Debug, Hash)]
+#[derive(Clone, PartialEq, Eq, Debug, Hash, Serialize, Deserialize)]
 pub struct Param {
     pub marker: Arc<str>,
     pub fluoro: Arc<str>,
@@ -166,10 +173,66 @@ pub struct AxisStore {
     pub settings: im::HashMap<Arc<str>, AxisInfo, FxBuildHasher>,
     //current file's param names listed by file's internal order
     pub sorted_settings: indexmap::IndexSet<Param, FxBuildHasher>,
+    // how each x/y pair is drawn - pairs without an entry use the default style
+    pub plot_styles: FxHashMap<PlotStyleKey, PlotStyleSettings>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct AxisStoreRecord {
+    pub settings: Vec<AxisRecord>,
+    pub sorted_settings: Vec<Param>,
+    #[serde(default)]
+    pub plot_styles: Vec<PlotStyleRecord>,
 }
 
 #[store(pub name = AxisStoreImplExt)]
 impl<Lens> Store<AxisStore, Lens> {
+    fn save_to_record(&self) -> AxisStoreRecord {
+        let store = self.peek();
+        AxisStoreRecord {
+            settings: store.settings.values().map(AxisRecord::from).collect(),
+            sorted_settings: store.sorted_settings.iter().cloned().collect(),
+            plot_styles: store
+                .plot_styles
+                .iter()
+                .map(|((x, y), settings)| PlotStyleRecord {
+                    x: x.clone(),
+                    y: y.clone(),
+                    settings: settings.clone(),
+                })
+                .collect(),
+        }
+    }
+
+    fn load_from_record(&mut self, record: AxisStoreRecord) {
+        self.with_mut(|s| {
+            s.settings = record
+                .settings
+                .into_iter()
+                .map(|axis| (axis.param.fluoro.clone(), AxisInfo::from(axis)))
+                .collect();
+            s.sorted_settings = record.sorted_settings.into_iter().collect();
+            s.plot_styles = record
+                .plot_styles
+                .into_iter()
+                .map(|style| ((style.x, style.y), style.settings))
+                .collect();
+        });
+    }
+
+    fn plot_style(&self, x: &Arc<str>, y: &Arc<str>) -> PlotStyleSettings {
+        self.plot_styles()
+            .read()
+            .get(&(x.clone(), y.clone()))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    fn set_plot_style(&mut self, x: Arc<str>, y: Arc<str>, settings: PlotStyleSettings) {
+        self.plot_styles().write().insert((x, y), settings);
+    }
+
     fn add_new_default_axis_settings(&mut self, p: &Param, fcs_file: &flow_fcs::Fcs) {
         if self.settings().peek().contains_key(&p.fluoro) {
             return
@@ -237,6 +300,68 @@ impl<Lens> Store<AxisStore, Lens> {
         Err(anyhow!("Could not find axis"))
     }
 
+    fn update_transform(
+        &mut self,
+        id: &Arc<str>,
+        transform: TransformType,
+    ) -> anyhow::Result<(AxisInfo, AxisInfo)> {
+        let mut result = None;
+
+        self.settings()
+            .write()
+            .entry(id.clone())
+            .and_modify(|axis| {
+                let new_axis = axis.into_transform(&transform);
+                result = Some(new_axis.map(|new_axis| {
+                    let old_axis = std::mem::replace(axis, new_axis.clone());
+                    (old_axis, new_axis)
+                }));
+            });
+
+        result.unwrap_or_else(|| Err(anyhow!("Could not find axis {}", id)))
+    }
+
+    // switch a channel's transform and re-project every gate on it,
+    // so the gates keep their raw-data positions
+    fn switch_transform(
+        &mut self,
+        id: &Arc<str>,
+        transform: TransformType,
+        mut gate_store: SyncStore<GateState>,
+    ) -> Result<(), Vec<String>> {
+        let (old, new) = self
+            .update_transform(id, transform)
+            .map_err(|e| vec![e.to_string()])?;
+        if old.transform == new.transform {
+            return Ok(());
+        }
+        gate_store.rescale_gates(id, &old, &new)
+    }
+
+    fn update_biex_params(
+        &mut self,
+        id: &Arc<str>,
+        params: BiexParams,
+    ) -> anyhow::Result<(AxisInfo, AxisInfo)> {
+        params.validate()?;
+        let mut result = None;
+
+        self.settings()
+            .write()
+            .entry(id.clone())
+            .and_modify(|axis| {
+                if axis.is_biexponential() {
+                    let new_axis = axis.into_biexponential(params);
+                    result = Some(new_axis.map(|new_axis| {
+                        let old_axis = std::mem::replace(axis, new_axis.clone());
+                        (old_axis, new_axis)
+                    }));
+                }
+            });
+
+        result.unwrap_or_else(|| Err(anyhow!("Could not find biexponential axis {}", id)))
+    }
+
     fn update_lower(
         &mut self,
         id: &GateId,
@@ -379,3 +504,110 @@ fn fetch_axes_from_omiq_csv(path: PathBuf,) -> anyhow::Result<DataFrame> {
 
     Ok(csv)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gate_editor::axis_info::reverse_f32;
+    use crate::gate_editor::gates::gate_store::{GateStateStoreExt, GateSubStoreStoreExt};
+    use crate::omiq::serialise::tests::{EXPERIMENT, test_axis_settings, test_metadata};
+    use dioxus::stores::use_store_sync;
+    use flow_gates::GateGeometry;
+    use std::cell::RefCell;
+
+    #[derive(Default)]
+    struct Seen {
+        // the rect gate's raw lower x, after each switch
+        raw_min_x: Vec<f32>,
+        transforms: Vec<TransformType>,
+        switch_errors: Vec<String>,
+        unknown_axis_rejected: bool,
+    }
+
+    thread_local! {
+        // stores need a runtime, so the switches run in a component and are checked after it
+        static SEEN: RefCell<Seen> = RefCell::new(Seen::default());
+    }
+
+    fn switch_fsc() -> Element {
+        let mut axis_store = use_store_sync(|| AxisStore {
+            settings: test_axis_settings(),
+            ..Default::default()
+        });
+        let gate_store = use_store_sync(|| {
+            let mut state = GateState::default();
+            state
+                .load_experiment(
+                    serde_json::from_str(EXPERIMENT).unwrap(),
+                    &test_metadata(),
+                    test_axis_settings(),
+                )
+                .unwrap();
+            state
+        });
+        let fsc: Arc<str> = Arc::from("FSC-A");
+        let raw_min_x = {
+            let fsc = fsc.clone();
+            move || {
+                let transform = axis_store.settings().peek()[&fsc].transform.clone();
+                let registry = gate_store.gate_store().primary_and_subgate_registry();
+                let registry = registry.peek();
+                match registry["rect"].get_gate_ref(None).map(|g| &g.geometry) {
+                    Some(GateGeometry::Rectangle { min, .. }) => min
+                        .get_coordinate(&fsc)
+                        .and_then(|x| reverse_f32(x, &transform).ok())
+                        .unwrap_or(f32::NAN),
+                    _ => f32::NAN,
+                }
+            }
+        };
+
+        let mut seen = Seen {
+            raw_min_x: vec![raw_min_x()],
+            ..Default::default()
+        };
+        for transform in [
+            TransformType::Arcsinh { cofactor: 150.0 },
+            BiexParams::default().into_transform(),
+            TransformType::Linear,
+        ] {
+            if let Err(e) = axis_store.switch_transform(&fsc, transform, gate_store) {
+                seen.switch_errors.extend(e);
+            }
+            let landed = axis_store.settings().peek()[&fsc].transform.clone();
+            seen.transforms.push(landed);
+            seen.raw_min_x.push(raw_min_x());
+        }
+        seen.unknown_axis_rejected = axis_store
+            .update_transform(&Arc::from("nope"), TransformType::Linear)
+            .is_err();
+
+        SEEN.with(|s| *s.borrow_mut() = seen);
+        rsx! {}
+    }
+
+    #[test]
+    fn test_switch_transform_keeps_gate_raw_positions() {
+        let mut dom = VirtualDom::new(switch_fsc);
+        dom.rebuild_in_place();
+
+        SEEN.with(|s| {
+            let seen = s.borrow();
+            assert!(seen.switch_errors.is_empty(), "{:?}", seen.switch_errors);
+            // each switch lands on the transform asked for
+            assert_eq!(
+                seen.transforms,
+                vec![
+                    TransformType::Arcsinh { cofactor: 150.0 },
+                    BiexParams::default().into_transform(),
+                    TransformType::Linear,
+                ]
+            );
+            assert_eq!(seen.raw_min_x.len(), 4);
+            for raw in &seen.raw_min_x {
+                assert!((raw - 10.0).abs() < 1e-2, "{:?}", seen.raw_min_x);
+            }
+            assert!(seen.unknown_axis_rejected);
+        });
+    }
+}
@@ -0,0 +1,147 @@
+use std::sync::Arc;
+
+use crate::gate_editor::gates::{
+    gate_hierarchy::GateHierarchy,
+    gate_store::{GateId, ROOTGATE},
+};
+
+// populations are coloured in the order they're added - bright enough to show over any map
+pub const BACKGATE_COLOURS: [&str; 6] = [
+    "#ff00ff", "#00c8ff", "#ff8c00", "#00e040", "#ff1744", "#8000ff",
+];
+// dense populations are thinned before drawing - the shape reads the same
+pub const MAX_BACKGATE_EVENTS: usize = 20_000;
+// the side of the square drawn for each event, in pixels
+pub const BACKGATE_POINT_SIZE: f32 = 1.5;
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Backgate {
+    pub gate_id: GateId,
+    pub colour: String,
+}
+
+/// The populations picked for backgating, in the order they were added
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct Backgates(Vec<Backgate>);
+
+impl Backgates {
+    pub fn contains(&self, gate_id: &str) -> bool {
+        self.0.iter().any(|b| &*b.gate_id == gate_id)
+    }
+
+    /// Adds the gate in the first colour not already in use, or removes it if it's there
+    pub fn toggle(&mut self, gate_id: GateId) {
+        if self.contains(&gate_id) {
+            self.0.retain(|b| b.gate_id != gate_id);
+            return;
+        }
+        let colour = BACKGATE_COLOURS
+            .iter()
+            .find(|c| !self.0.iter().any(|b| b.colour == **c))
+            .unwrap_or(&BACKGATE_COLOURS[self.0.len() % BACKGATE_COLOURS.len()]);
+        self.0.push(Backgate {
+            gate_id,
+            colour: colour.to_string(),
+        });
+    }
+
+    pub fn set_colour(&mut self, gate_id: &str, colour: String) {
+        if let Some(b) = self.0.iter_mut().find(|b| &*b.gate_id == gate_id) {
+            b.colour = colour;
+        }
+    }
+
+    /// The backgates that lie downstream of `parent` - only those can be drawn on its plot
+    pub fn below(&self, hierarchy: &GateHierarchy, parent: &GateId) -> Vec<Backgate> {
+        self.0
+            .iter()
+            .filter(|b| {
+                *parent == *ROOTGATE || hierarchy.get_ancestors(&b.gate_id).contains(parent)
+            })
+            .cloned()
+            .collect()
+    }
+}
+
+/// One backgated population's events on the plot's x/y parameters
+#[derive(Clone, PartialEq, Debug)]
+pub struct BackgateSeries {
+    pub gate_id: GateId,
+    pub name: String,
+    pub colour: String,
+    pub points: Arc<Vec<(f32, f32)>>,
+}
+
+/// Every `n`th event, so at most `MAX_BACKGATE_EVENTS` are drawn
+pub fn thin_events(points: Vec<(f32, f32)>) -> Vec<(f32, f32)> {
+    let step = points.len().div_ceil(MAX_BACKGATE_EVENTS).max(1);
+    if step == 1 {
+        return points;
+    }
+    points.into_iter().step_by(step).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_toggle_reuses_free_colours() {
+        let mut backgates = Backgates::default();
+        backgates.toggle("a".into());
+        backgates.toggle("b".into());
+        backgates.toggle("c".into());
+        assert!(backgates.contains("b"));
+
+        // the colour given up is the next one handed out
+        backgates.toggle("b".into());
+        assert!(!backgates.contains("b"));
+        backgates.toggle("d".into());
+        let colours: Vec<&str> = backgates.0.iter().map(|b| b.colour.as_str()).collect();
+        assert_eq!(
+            colours,
+            vec![
+                BACKGATE_COLOURS[0],
+                BACKGATE_COLOURS[2],
+                BACKGATE_COLOURS[1]
+            ]
+        );
+
+        backgates.set_colour("d", "#123456".to_string());
+        assert_eq!(backgates.0[2].colour, "#123456");
+    }
+
+    #[test]
+    fn test_only_descendants_are_below() {
+        let mut hierarchy = GateHierarchy::new();
+        hierarchy.add_child("lymph", "cd3", 0);
+        hierarchy.add_child("cd3", "cd4", 0);
+        hierarchy.add_child("lymph", "cd19", 1);
+
+        let mut backgates = Backgates::default();
+        backgates.toggle("cd4".into());
+        backgates.toggle("cd19".into());
+        backgates.toggle("cd3".into());
+
+        let ids = |parent: &str| -> Vec<GateId> {
+            backgates
+                .below(&hierarchy, &Arc::from(parent))
+                .into_iter()
+                .map(|b| b.gate_id)
+                .collect()
+        };
+        assert_eq!(ids("cd3"), vec![Arc::from("cd4")]);
+        assert_eq!(ids("lymph").len(), 3);
+        assert_eq!(ids(&ROOTGATE).len(), 3);
+        assert!(ids("cd4").is_empty());
+    }
+
+    #[test]
+    fn test_thin_events() {
+        let points: Vec<(f32, f32)> = (0..MAX_BACKGATE_EVENTS * 3 + 1)
+            .map(|i| (i as f32, 0.0))
+            .collect();
+        assert!(thin_events(points).len() <= MAX_BACKGATE_EVENTS);
+        assert_eq!(thin_events(vec![(1.0, 2.0)]), vec![(1.0, 2.0)]);
+    }
+}
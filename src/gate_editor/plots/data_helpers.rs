@@ -1,14 +1,23 @@
 use std::sync::Arc;
 
+use crate::gate_editor::AxisInfo;
+use crate::gate_editor::compensation::CompMatrix;
+use crate::gate_editor::gates::GateId;
 use crate::gate_editor::gates::GateState;
-use crate::gate_editor::gates::gate_store::{GateOverrideResolver, GateStateStoreExt, ROOTGATE};
+use crate::gate_editor::gates::gate_filtering::PopulationMasks;
+use crate::gate_editor::gates::gate_hierarchy::GateHierarchy;
+use crate::gate_editor::gates::gate_store::{
+    FileId, GateOverrideResolver, GateStateStoreExt, ROOTGATE,
+};
+use crate::gate_editor::gates::mask_cache::GateMaskCache;
 
 use dioxus::prelude::*;
 use dioxus::stores::SyncStore;
-use flow_fcs::Fcs;
+use flow_fcs::{Fcs, Transformable};
 use flow_gates::EventIndex;
 
 use polars::prelude::*;
+use rustc_hash::FxBuildHasher;
 use tokio::task;
 
 pub async fn get_flow_data(path: std::path::PathBuf) -> Result<Fcs, Arc<anyhow::Error>> {
@@ -20,45 +29,184 @@ pub async fn get_flow_data(path: std::path::PathBuf) -> Result<Fcs, Arc<anyhow::
     .map_err(|e| Arc::new(e.into()))?
 }
 
+// scale every channel of a file onto its axis transform, ready for gating
+pub fn scale_fcs_data(
+    fcs: &Fcs,
+    compensation: Option<&CompMatrix>,
+    axis_settings: &im::HashMap<Arc<str>, AxisInfo, FxBuildHasher>,
+) -> anyhow::Result<DataFrame> {
+    if let Some(matrix) = compensation {
+        // compensation mixes the raw channels, so every transform has to come after it
+        let compensated = raw_fcs_data(fcs, Some(matrix))?;
+        let params: Vec<(Arc<str>, flow_fcs::TransformType)> = axis_settings
+            .iter()
+            .filter(|(_, v)| !v.is_linear())
+            .map(|(k, v)| (k.clone(), v.transform.clone()))
+            .collect();
+        return apply_transforms(&compensated, &params);
+    }
+    let mut arcsinh_params: Vec<(&str, f32)> = Vec::new();
+    let mut biex_params = Vec::new();
+    for (k, v) in axis_settings.iter() {
+        if let Some(cofactor) = v.get_cofactor() {
+            arcsinh_params.push((k.as_ref(), cofactor))
+        } else if v.is_biexponential() {
+            biex_params.push((k.clone(), v.transform.clone()))
+        }
+    }
+    let scaled_df = fcs.apply_arcsinh_transforms(arcsinh_params.as_slice())?;
+    apply_transforms(&scaled_df, &biex_params)
+}
+
+// the untransformed events, compensated if there's a matrix
+pub fn raw_fcs_data(fcs: &Fcs, compensation: Option<&CompMatrix>) -> anyhow::Result<DataFrame> {
+    // no transforms requested, so this is the raw data
+    let raw = fcs.apply_arcsinh_transforms(&[])?;
+    match compensation {
+        Some(matrix) => matrix.compensate(&raw),
+        None => Ok(raw),
+    }
+}
+
+// flow_fcs only scales arcsinh channels for us - biex channels, and every channel of
+// compensated data, are transformed here through the same TransformType the axes and
+// gates use
+pub fn apply_transforms(
+    df: &DataFrame,
+    params: &[(Arc<str>, flow_fcs::TransformType)],
+) -> anyhow::Result<DataFrame> {
+    let mut df = df.clone();
+    for (fluoro, transform) in params {
+        let column = df.column(fluoro.as_ref())?.cast(&DataType::Float32)?;
+        let transformed: Float32Chunked = column
+            .f32()?
+            .apply_values(|v| transform.transform(&v));
+        df.with_column(transformed.into_series().with_name(fluoro.as_ref().into()))?;
+    }
+    Ok(df)
+}
+
+// raw events compensated with `matrix`, as x/y points on their axes - for previewing a
+// matrix without rescaling every channel
+pub fn compensated_points(
+    raw: &DataFrame,
+    matrix: &CompMatrix,
+    (x_channel, y_channel): (&str, &str),
+    axis_settings: &im::HashMap<Arc<str>, AxisInfo, FxBuildHasher>,
+) -> anyhow::Result<Vec<(f32, f32)>> {
+    let compensated = matrix.compensate(raw)?;
+    let params: Vec<(Arc<str>, flow_fcs::TransformType)> = axis_settings
+        .iter()
+        .filter(|(k, v)| (&***k == x_channel || &***k == y_channel) && !v.is_linear())
+        .map(|(k, v)| (k.clone(), v.transform.clone()))
+        .collect();
+    let scaled = apply_transforms(&compensated, &params)?;
+    let x = scaled.column(x_channel)?.f32()?;
+    let y = scaled.column(y_channel)?.f32()?;
+    Ok(x.into_iter()
+        .zip(y.into_iter())
+        .filter_map(|(x, y)| Some((x?, y?)))
+        .collect())
+}
+
+// the scaled events, and the axis settings and compensation they were scaled with.
+// `generation` is unique to this scaling, and keys the masks cached from it.
+#[derive(Clone)]
+pub struct ScaledData {
+    pub df: Arc<DataFrame>,
+    pub generation: u64,
+    pub axis_settings: im::HashMap<Arc<str>, AxisInfo, FxBuildHasher>,
+    pub compensation: Option<Arc<CompMatrix>>,
+}
+
 pub async fn get_filtered_dataframe(
-    df: Arc<DataFrame>,
+    scaled: ScaledData,
     parental_gate_id: Option<Arc<str>>,
     resolver: GateOverrideResolver,
+    file_id: FileId,
 ) -> Result<Arc<DataFrame>, anyhow::Error> {
-    let df_clone = df.clone();
     let gate_store = use_context::<SyncStore<GateState>>();
+    let mask_cache = use_context::<GateMaskCache>();
 
     task::spawn_blocking(move || -> Result<Arc<DataFrame>, anyhow::Error> {
-        let gate_chain: Option<Vec<Arc<str>>> = if let Some(parent) = parental_gate_id {
-            let arcs: Vec<Arc<str>> = gate_store
-                .hierarchy()
-                .peek()
-                .get_chain_to_root(&parent)
-                .into_iter()
-                .filter(|v| *v != *ROOTGATE)
-                .collect();
-
-            if arcs.is_empty() { None } else { Some(arcs) }
-        } else {
-            None
-        };
-
-        if let Some(chain) = gate_chain {
-            println!("{:#?}", chain);
-
-            // 1. Get the final narrowed mask for the whole hierarchy
-            let mask = super::super::gates::gate_filtering::filter_events_by_hierarchy_to_mask(
-                &df, &chain, &resolver,
-            )?;
-            // 2. Filter the dataframe
-            Ok(df.filter(&mask)?.into())
-        } else {
-            Ok(df_clone)
+        match parental_gate_id {
+            Some(parent) if parent != *ROOTGATE => {
+                // 1. Get the final narrowed mask for the whole hierarchy
+                let hierarchy = gate_store.hierarchy().peek().clone();
+                let mask = PopulationMasks::new(&scaled.df, &hierarchy, &resolver)
+                    .with_cache(
+                        &mask_cache,
+                        file_id,
+                        scaled.generation,
+                        &scaled.axis_settings,
+                        scaled.compensation.as_ref(),
+                    )
+                    .population(&parent)?;
+                // 2. Filter the dataframe
+                Ok(scaled.df.filter(&mask)?.into())
+            }
+            _ => Ok(scaled.df),
         }
     })
     .await?
 }
 
+// one population's events on a single channel, for samples that aren't on a plot
+pub fn population_values(
+    scaled: &ScaledData,
+    file_id: FileId,
+    hierarchy: &GateHierarchy,
+    resolver: &GateOverrideResolver,
+    population: &GateId,
+    channel: &str,
+    mask_cache: &GateMaskCache,
+) -> anyhow::Result<Vec<f32>> {
+    let mask = PopulationMasks::new(&scaled.df, hierarchy, resolver)
+        .with_cache(
+            mask_cache,
+            file_id,
+            scaled.generation,
+            &scaled.axis_settings,
+            scaled.compensation.as_ref(),
+        )
+        .population(population)?;
+    Ok(scaled
+        .df
+        .column(channel)?
+        .f32()?
+        .filter(&mask)?
+        .into_iter()
+        .flatten()
+        .collect())
+}
+
+// one population's events on an x/y pair, for drawing over an ancestor's plot
+pub fn population_points(
+    scaled: &ScaledData,
+    file_id: FileId,
+    hierarchy: &GateHierarchy,
+    resolver: &GateOverrideResolver,
+    population: &GateId,
+    (x_channel, y_channel): (&str, &str),
+    mask_cache: &GateMaskCache,
+) -> anyhow::Result<Vec<(f32, f32)>> {
+    let mask = PopulationMasks::new(&scaled.df, hierarchy, resolver)
+        .with_cache(
+            mask_cache,
+            file_id,
+            scaled.generation,
+            &scaled.axis_settings,
+            scaled.compensation.as_ref(),
+        )
+        .population(population)?;
+    let x = scaled.df.column(x_channel)?.f32()?.filter(&mask)?;
+    let y = scaled.df.column(y_channel)?.f32()?.filter(&mask)?;
+    Ok(x.into_iter()
+        .zip(y.into_iter())
+        .filter_map(|(x, y)| Some((x?, y?)))
+        .collect())
+}
+
 pub async fn zip_cols_from_filtered_df(
     df: Arc<DataFrame>,
     col1_name: Arc<str>,
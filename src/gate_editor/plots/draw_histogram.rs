@@ -0,0 +1,301 @@
+#![allow(non_snake_case)]
+use std::{ops::RangeInclusive, sync::Arc};
+
+use dioxus::prelude::*;
+use flow_fcs::TransformType;
+
+use crate::gate_editor::{
+    AxisInfo,
+    axis_info::reverse_f32,
+    gates::draw_gates::GateLayer,
+    plots::{
+        axis_store::PlotMapper,
+        draw_plot::get_bounds,
+        histogram::{
+            HistogramOptions, HistogramSeries, SERIES_COLOURS, histogram_curve, y_axis_top,
+        },
+    },
+};
+
+const TICKS: usize = 5;
+const TICK_LENGTH: f32 = 5.0;
+const FONT_SIZE: f32 = 11.0;
+
+#[derive(Clone, PartialEq)]
+struct HistogramCurves {
+    curves: Vec<(String, Vec<(f32, f32)>)>,
+    mapper: Arc<PlotMapper>,
+    y_top: f32,
+}
+
+// One parameter's distribution, with the same population from other samples overlaid.
+// Gates are keyed to the x/y pair as on the 2D plot, so the range gates drawn here are the
+// line gates on that plot's x axis.
+#[component]
+pub fn HistogramPlot(
+    data: ReadSignal<Vec<(f32, f32)>>,
+    sample_name: ReadSignal<String>,
+    overlays: ReadSignal<Vec<HistogramSeries>>,
+    options: ReadSignal<HistogramOptions>,
+    size: ReadSignal<(u32, u32)>,
+    x_axis_info: ReadSignal<AxisInfo>,
+    y_axis_info: ReadSignal<AxisInfo>,
+    parental_gate_id: ReadSignal<Option<Arc<str>>>,
+) -> Element {
+    // the gates are drawn on the 2D axes - line gates keep their height from that plot
+    let mut plot_map = use_signal(|| None::<Arc<PlotMapper>>);
+    use_context_provider::<Signal<Option<Arc<PlotMapper>>>>(|| plot_map);
+
+    use_effect(move || {
+        let (width, height) = size();
+        let (x_axis_info, y_axis_info) = (x_axis_info(), y_axis_info());
+        let points = data.read();
+        let Some(bounds) = get_bounds(&points) else {
+            plot_map.set(None);
+            return;
+        };
+        plot_map.set(Some(Arc::new(PlotMapper::new(
+            width as f32,
+            height as f32,
+            x_axis_info.axis_lower..=x_axis_info.axis_upper,
+            y_axis_info.axis_lower..=y_axis_info.axis_upper,
+            RangeInclusive::new(bounds.0.0, bounds.0.1),
+            RangeInclusive::new(bounds.1.0, bounds.1.1),
+            x_axis_info.transform.clone(),
+            y_axis_info.transform.clone(),
+        ))));
+    });
+
+    let render_result = use_resource(move || {
+        let values: Vec<f32> = data.read().iter().map(|(x, _)| *x).collect();
+        let mut series = vec![(sample_name(), Arc::new(values))];
+        series.extend(overlays().into_iter().map(|s| (s.name, s.values)));
+        async move {
+            let options = options();
+            let x_axis_info = x_axis_info();
+            let (width, height) = size();
+
+            let result = tokio::task::spawn_blocking(move || {
+                let range = (x_axis_info.axis_lower, x_axis_info.axis_upper);
+                let curves: Vec<(String, Vec<(f32, f32)>)> = series
+                    .into_iter()
+                    .map(|(name, values)| (name, histogram_curve(&values, range, &options)))
+                    .collect();
+                let y_top = y_axis_top(curves.iter().map(|(_, c)| c.as_slice()), options.scale);
+                let x_range = range.0..=range.1;
+                let mapper = PlotMapper::new(
+                    width as f32,
+                    height as f32,
+                    x_range.clone(),
+                    0.0..=y_top,
+                    x_range,
+                    0.0..=y_top,
+                    x_axis_info.transform.clone(),
+                    TransformType::Linear,
+                );
+                HistogramCurves {
+                    curves,
+                    mapper: Arc::new(mapper),
+                    y_top,
+                }
+            })
+            .await;
+
+            result.map_err(|e| anyhow::anyhow!("Failed to generate histogram {}", e))
+        }
+    });
+
+    let size_now = size();
+    let render_guard = render_result.read();
+    let rendered = match &*render_guard {
+        Some(Ok(r)) => r,
+        Some(Err(e)) => {
+            return rsx! {
+                {e.to_string()}
+            };
+        }
+        None => {
+            let style = format!("width: {}px; height: {}px;", size_now.0, size_now.1);
+            return rsx! {
+                div { style, class: "spinner-container",
+                    div { class: "spinner" }
+                    span { style: "margin-top: 10px; font-size: 12px; color: #666;", "Rendering Plot..." }
+                }
+            };
+        }
+    };
+
+    let map = &rendered.mapper;
+    let x_axis = x_axis_info();
+    let (x_lo, x_hi) = (x_axis.axis_lower, x_axis.axis_upper);
+    let (left, bottom) = map.data_to_pixel(x_lo, 0.0, None, None);
+    let (right, top) = map.data_to_pixel(x_hi, rendered.y_top, None, None);
+
+    let x_ticks: Vec<(f32, String)> = (0..TICKS)
+        .map(|i| {
+            let value = x_lo + (x_hi - x_lo) * i as f32 / (TICKS - 1) as f32;
+            let raw = reverse_f32(value, &x_axis.transform).unwrap_or(value);
+            (map.data_to_pixel(value, 0.0, None, None).0, tick_label(raw))
+        })
+        .collect();
+    let y_max = options().scale.max_tick(rendered.y_top);
+    let y_ticks: Vec<(f32, String)> = (0..TICKS)
+        .map(|i| {
+            let value = y_max * i as f32 / (TICKS - 1) as f32;
+            (
+                map.data_to_pixel(x_lo, value, None, None).1,
+                tick_label(value),
+            )
+        })
+        .collect();
+
+    // the plot's own sample is filled, overlays are outlines on top of it
+    let paths: Vec<(String, &'static str, &'static str)> = rendered
+        .curves
+        .iter()
+        .enumerate()
+        .map(|(i, (_, curve))| {
+            let colour = SERIES_COLOURS[i % SERIES_COLOURS.len()];
+            let fill = if i == 0 {
+                "rgba(120, 120, 120, 0.25)"
+            } else {
+                "none"
+            };
+            (curve_path(curve, map, bottom), colour, fill)
+        })
+        .collect();
+    let legend: Vec<(String, &'static str)> = if rendered.curves.len() > 1 {
+        rendered
+            .curves
+            .iter()
+            .enumerate()
+            .map(|(i, (name, _))| (name.clone(), SERIES_COLOURS[i % SERIES_COLOURS.len()]))
+            .collect()
+    } else {
+        vec![]
+    };
+    let y_label = options().scale.label();
+
+    rsx! {
+        div { style: "position: relative; width: {size_now.0}px; height: {size_now.1}px;",
+            svg {
+                width: "{size_now.0}",
+                height: "{size_now.1}",
+                view_box: "0 0 {map.width()} {map.height()}",
+                style: "position: absolute; top: 0; left: 0; user-select: none; -webkit-user-select: none;",
+                rect {
+                    x: "{left}",
+                    y: "{top}",
+                    width: "{right - left}",
+                    height: "{bottom - top}",
+                    fill: "white",
+                    stroke: "#999",
+                }
+                for (d , colour , fill) in paths {
+                    path {
+                        d,
+                        fill,
+                        stroke: colour,
+                        stroke_width: "1.5",
+                    }
+                }
+                for (x , text) in x_ticks {
+                    line {
+                        x1: "{x}",
+                        y1: "{bottom}",
+                        x2: "{x}",
+                        y2: "{bottom + TICK_LENGTH}",
+                        stroke: "#666",
+                    }
+                    text {
+                        x: "{x}",
+                        y: "{bottom + TICK_LENGTH + FONT_SIZE + 2.0}",
+                        font_size: "{FONT_SIZE}",
+                        text_anchor: "middle",
+                        "{text}"
+                    }
+                }
+                for (y , text) in y_ticks {
+                    line {
+                        x1: "{left - TICK_LENGTH}",
+                        y1: "{y}",
+                        x2: "{left}",
+                        y2: "{y}",
+                        stroke: "#666",
+                    }
+                    text {
+                        x: "{left - TICK_LENGTH - 2.0}",
+                        y: "{y + FONT_SIZE / 3.0}",
+                        font_size: "{FONT_SIZE}",
+                        text_anchor: "end",
+                        "{text}"
+                    }
+                }
+                text {
+                    x: "{(left + right) / 2.0}",
+                    y: "{map.height() - 4.0}",
+                    font_size: "{FONT_SIZE + 1.0}",
+                    text_anchor: "middle",
+                    "{x_axis.param}"
+                }
+                text {
+                    x: "12",
+                    y: "{(top + bottom) / 2.0}",
+                    font_size: "{FONT_SIZE + 1.0}",
+                    text_anchor: "middle",
+                    transform: "rotate(-90 12 {(top + bottom) / 2.0})",
+                    "{y_label}"
+                }
+                for (i , (name , colour)) in legend.into_iter().enumerate() {
+                    text {
+                        x: "{right - 4.0}",
+                        y: "{top + (i as f32 + 1.0) * (FONT_SIZE + 3.0)}",
+                        font_size: "{FONT_SIZE}",
+                        text_anchor: "end",
+                        fill: colour,
+                        "{name}"
+                    }
+                }
+            }
+            if plot_map.read().is_some() {
+                GateLayer {
+                    x_channel: x_axis_info().param.fluoro.clone(),
+                    y_channel: y_axis_info().param.fluoro.clone(),
+                    parental_gate_id,
+                    range_only: true,
+                }
+            }
+        }
+    }
+}
+
+// a closed outline from the x axis up over the curve and back down
+fn curve_path(curve: &[(f32, f32)], map: &PlotMapper, baseline: f32) -> String {
+    let mut points = curve
+        .iter()
+        .map(|(x, y)| map.data_to_pixel(*x, *y, None, None));
+    let Some(first) = points.next() else {
+        return String::new();
+    };
+    let mut d = format!("M {} {} L {} {}", first.0, baseline, first.0, first.1);
+    let mut last = first;
+    for (px, py) in points {
+        d.push_str(&format!(" L {px} {py}"));
+        last = (px, py);
+    }
+    d.push_str(&format!(" L {} {} Z", last.0, baseline));
+    d
+}
+
+fn tick_label(value: f32) -> String {
+    let abs = value.abs();
+    if abs >= 1_000_000.0 {
+        format!("{:.1}M", value / 1_000_000.0)
+    } else if abs >= 1_000.0 {
+        format!("{:.0}k", value / 1_000.0)
+    } else if abs >= 10.0 || abs == 0.0 {
+        format!("{value:.0}")
+    } else {
+        format!("{value:.1}")
+    }
+}
@@ -4,12 +4,24 @@ use std::{ops::RangeInclusive, sync::Arc};
 use base64::Engine as _;
 use base64::engine::general_purpose::STANDARD as BASE64_STANDARD;
 use dioxus::prelude::*;
+use dioxus::stores::SyncStore;
 
 use flow_plots::{
-    BasePlotOptions, ColorMaps, DensityPlot, DensityPlotOptions, Plot, ScatterPlotData, render::RenderConfig
+    BasePlotOptions, DensityPlot, DensityPlotOptions, Plot, ScatterPlotData, render::RenderConfig
 };
 
-use crate::gate_editor::{AxisInfo, gates::draw_gates::GateLayer, plots::axis_store::PlotMapper};
+use crate::gate_editor::{
+    AxisInfo,
+    gates::draw_gates::GateLayer,
+    plots::{
+        axis_store::{AxisStore, AxisStoreImplExt, PlotMapper},
+        backgate::{BACKGATE_POINT_SIZE, BackgateSeries, Backgates},
+        plot_style::contours,
+    },
+};
+
+// the rendered plot, its mapper, and any contour lines as svg paths in pixels
+type RenderedPlot = (String, Arc<PlotMapper>, Vec<String>);
 
 #[component]
 pub fn PseudoColourPlot(
@@ -18,27 +30,58 @@ pub fn PseudoColourPlot(
     x_axis_info: ReadSignal<AxisInfo>,
     y_axis_info: ReadSignal<AxisInfo>,
     parental_gate_id: ReadSignal<Option<Arc<str>>>,
+    backgates: ReadSignal<Vec<BackgateSeries>>,
+    // previews that only show events leave the gates off
+    #[props(default = true)] show_gates: bool,
 ) -> Element {
     // let mut plot_image_src = use_signal(|| String::new());
     let mut plot_map = use_signal(|| None::<Arc<PlotMapper>>);
     use_context_provider::<Signal<Option<Arc<PlotMapper>>>>(|| plot_map);
 
+    let axis_store = use_context::<SyncStore<AxisStore>>();
+    let style = use_memo(move || {
+        axis_store.plot_style(
+            &x_axis_info.read().param.fluoro,
+            &y_axis_info.read().param.fluoro,
+        )
+    });
+
+    // each backgated population is one path of small squares, drawn over the plot
+    let mut backgate_selection = use_context::<Signal<Backgates>>();
+    let backgate_paths = use_memo(move || {
+        let Some(map) = plot_map() else {
+            return vec![];
+        };
+        backgates
+            .read()
+            .iter()
+            .map(|series| {
+                (
+                    points_to_svg_path(&series.points, &map),
+                    series.colour.clone(),
+                )
+            })
+            .collect::<Vec<_>>()
+    });
+
     let render_result = use_resource(move || {
         
-        let data_final: flow_plots::ScatterPlotData = ScatterPlotData{ points: data(), gate_ids: None, z_values: None };
+        let points = data();
         async move {
             let x_axis_info = x_axis_info();
             let y_axis_info = y_axis_info();
             let (width, height) = size();
+            let style = style();
 
             let result = tokio::task::spawn_blocking(
-                move || -> Result<(String, Arc<PlotMapper>), anyhow::Error> {
-                    let bounds = get_bounds(&data_final.points).ok_or_else(|| anyhow::anyhow!("Could not get bounds"))?;
+                move || -> Result<RenderedPlot, anyhow::Error> {
+                    let bounds = get_bounds(&points).ok_or_else(|| anyhow::anyhow!("Could not get bounds"))?;
                     let plot = DensityPlot::new();
+                    let title = format!("{} v {}", x_axis_info.param, y_axis_info.param);
                     let base_options = BasePlotOptions::new()
                         .width(width)
                         .height(height)
-                        .title("My Density Plot")
+                        .title(&title)
                         .show_colorbar(false)
                         .build()?;
 
@@ -75,21 +118,45 @@ pub fn PseudoColourPlot(
                     );
                     let options = DensityPlotOptions::new()
                         .base(base_options)
-                        .plot_type(flow_plots::PlotType::Density)
-                        .colormap(ColorMaps::Jet)
+                        .plot_type(style.plot_type())
+                        .colormap(style.colour_map.colormap())
                         .x_axis(x_axis_options)
                         .y_axis(y_axis_options)
-                        .point_size(0.5)
+                        .point_size(style.point_size)
                         .build()?;
 
+                    // contour styles draw their lines over whatever events fall outside them
+                    let (points, contour_paths) = if style.style.has_contours() {
+                        let contours = contours(
+                            &points,
+                            (x_axis_info.axis_lower, x_axis_info.axis_upper),
+                            (y_axis_info.axis_lower, y_axis_info.axis_upper),
+                            &style,
+                        );
+                        let paths = contours
+                            .levels
+                            .iter()
+                            .map(|segments| segments_to_svg_path(segments, &mapper))
+                            .collect();
+                        (contours.outliers, paths)
+                    } else {
+                        (points, vec![])
+                    };
+
+                    let data_final = ScatterPlotData {
+                        points,
+                        gate_ids: None,
+                        z_values: None,
+                    };
                     let mut render_config = RenderConfig::default();
-                    
+
                     let plot_data = plot.render(data_final, &options, &mut render_config)?;
 
                     let base64_str = BASE64_STANDARD.encode(&plot_data);
                     Ok((
                         format!("data:image/jpeg;base64,{}", base64_str),
                         Arc::new(mapper),
+                        contour_paths,
                     ))
                 },
             )
@@ -104,7 +171,7 @@ pub fn PseudoColourPlot(
 
     rsx! {
         match &*render_result.read() {
-            Some(Ok((data, map))) => {
+            Some(Ok((data, map, contour_paths))) => {
                 plot_map.set(Some(map.clone()));
                 let size = size();
                 rsx! {
@@ -115,11 +182,67 @@ pub fn PseudoColourPlot(
                             width: "{size.0}",
                             height: "{size.1}",
                         }
-                        GateLayer {
-                            x_channel: x_axis_info().param.fluoro.clone(),
-                            y_channel: y_axis_info().param.fluoro.clone(),
-                            parental_gate_id,
+                        if !contour_paths.is_empty() {
+                            svg {
+                                width: "100%",
+                                height: "100%",
+                                view_box: "0 0 {map.width()} {map.height()}",
+                                style: "position: absolute; top: 0; left: 0; pointer-events: none;",
+                                for d in contour_paths.iter() {
+                                    path {
+                                        d: "{d}",
+                                        fill: "none",
+                                        stroke: "#222",
+                                        stroke_width: "1",
+                                    }
+                                }
+                            }
+                        }
+                        if !backgate_paths.read().is_empty() {
+                            svg {
+                                width: "100%",
+                                height: "100%",
+                                view_box: "0 0 {map.width()} {map.height()}",
+                                style: "position: absolute; top: 0; left: 0; pointer-events: none;",
+                                for (d , colour) in backgate_paths() {
+                                    path { d, fill: colour }
+                                }
+                            }
+                        }
+                        if show_gates {
+                            GateLayer {
+                                x_channel: x_axis_info().param.fluoro.clone(),
+                                y_channel: y_axis_info().param.fluoro.clone(),
+                                parental_gate_id,
 
+                            }
+                        }
+                        if !backgates.read().is_empty() {
+                            div { class: "backgate-legend",
+                                for series in backgates() {
+                                    div { key: "{series.gate_id}", class: "backgate-legend-item",
+                                        input {
+                                            r#type: "color",
+                                            title: "Backgate colour",
+                                            value: "{series.colour}",
+                                            oninput: {
+                                                let gate_id = series.gate_id.clone();
+                                                move |e: FormEvent| backgate_selection.write().set_colour(&gate_id, e.value())
+                                            },
+                                        }
+                                        span { "{series.name}" }
+                                        span {
+                                            class: "backgate-remove",
+                                            title: "Stop backgating",
+                                            onclick: {
+                                                let gate_id = series.gate_id.clone();
+                                                move |_| backgate_selection.write().toggle(gate_id.clone())
+                                            },
+                                            "×"
+                                        }
+                                    }
+                                }
+                            }
                         }
                     }
                 }
@@ -147,7 +270,32 @@ pub fn PseudoColourPlot(
 }
 
 
-fn get_bounds(data: &[(f32, f32)]) -> Option<((f32, f32), (f32, f32))> {
+fn segments_to_svg_path(segments: &[((f32, f32), (f32, f32))], mapper: &PlotMapper) -> String {
+    segments
+        .iter()
+        .map(|(a, b)| {
+            let (x1, y1) = mapper.data_to_pixel(a.0, a.1, None, None);
+            let (x2, y2) = mapper.data_to_pixel(b.0, b.1, None, None);
+            format!("M{x1:.1} {y1:.1}L{x2:.1} {y2:.1}")
+        })
+        .collect()
+}
+
+fn points_to_svg_path(points: &[(f32, f32)], mapper: &PlotMapper) -> String {
+    points
+        .iter()
+        .map(|(x, y)| {
+            let (px, py) = mapper.data_to_pixel(*x, *y, None, None);
+            let (left, top) = (
+                px - BACKGATE_POINT_SIZE / 2.0,
+                py - BACKGATE_POINT_SIZE / 2.0,
+            );
+            format!("M{left:.1} {top:.1}h{s}v{s}h-{s}z", s = BACKGATE_POINT_SIZE)
+        })
+        .collect()
+}
+
+pub fn get_bounds(data: &[(f32, f32)]) -> Option<((f32, f32), (f32, f32))> {
     if data.is_empty() { return None; }
 
     let initial = (
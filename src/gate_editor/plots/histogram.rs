@@ -0,0 +1,234 @@
+use std::{path::PathBuf, sync::Arc};
+
+use crate::gate_move::kde::{kde_1d, silverman_bandwidth};
+
+pub const DEFAULT_BINS: usize = 256;
+// kde is evaluated at every grid point for every event - larger populations are thinned
+const MAX_KDE_EVENTS: usize = 20_000;
+
+// overlays are coloured in order, the plot's own sample first
+pub const SERIES_COLOURS: [&str; 8] = [
+    "#333333", "#e6194b", "#3cb44b", "#4363d8", "#f58231", "#911eb4", "#42d4f4", "#f032e6",
+];
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum PlotMode {
+    #[default]
+    Density,
+    Histogram,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum HistogramScale {
+    #[default]
+    Count,
+    PercentMax,
+}
+
+impl HistogramScale {
+    pub fn label(&self) -> &'static str {
+        match self {
+            HistogramScale::Count => "Count",
+            HistogramScale::PercentMax => "% Max",
+        }
+    }
+
+    /// The value of the top y tick, below the axis headroom
+    pub fn max_tick(&self, y_top: f32) -> f32 {
+        match self {
+            HistogramScale::Count => y_top / 1.05,
+            HistogramScale::PercentMax => 100.0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct HistogramOptions {
+    pub smooth: bool,
+    pub scale: HistogramScale,
+    pub bins: usize,
+}
+
+impl Default for HistogramOptions {
+    fn default() -> Self {
+        Self {
+            smooth: false,
+            scale: HistogramScale::default(),
+            bins: DEFAULT_BINS,
+        }
+    }
+}
+
+/// Files pooled into one overlaid curve - a single sample, or every sample in a group
+#[derive(Clone, PartialEq, Debug)]
+pub struct HistogramOverlay {
+    pub name: String,
+    pub paths: Vec<PathBuf>,
+}
+
+/// The events of one overlaid population on the plot's x parameter
+#[derive(Clone, PartialEq, Debug)]
+pub struct HistogramSeries {
+    pub name: String,
+    pub values: Arc<Vec<f32>>,
+}
+
+/// Event counts at each bin centre across `range`. Smoothed curves are a kde scaled
+/// to the same expected count per bin, so both read off the same axis.
+pub fn histogram_curve(
+    values: &[f32],
+    range: (f32, f32),
+    options: &HistogramOptions,
+) -> Vec<(f32, f32)> {
+    let bins = options.bins.max(2);
+    let (lower, upper) = range;
+    let bin_width = (upper - lower) / bins as f32;
+    if values.is_empty() || bin_width <= 0.0 || !bin_width.is_finite() {
+        return vec![];
+    }
+    let centres = (0..bins).map(|i| lower + (i as f32 + 0.5) * bin_width);
+
+    let counts: Vec<f32> = if options.smooth {
+        // NaN/inf events can't be drawn, so they're neither sampled nor counted
+        let finite: Vec<f64> = values
+            .iter()
+            .filter(|v| v.is_finite())
+            .map(|v| *v as f64)
+            .collect();
+        let step = finite.len().div_ceil(MAX_KDE_EVENTS).max(1);
+        let sample: Vec<f64> = finite.iter().step_by(step).copied().collect();
+        if sample.is_empty() {
+            return vec![];
+        }
+        // a degenerate population still gets a visible peak
+        let bandwidth = silverman_bandwidth(&sample).max(bin_width as f64);
+        let (_, density) = kde_1d(
+            &sample,
+            (
+                (lower + bin_width / 2.0) as f64,
+                (upper - bin_width / 2.0) as f64,
+            ),
+            bins,
+            bandwidth,
+        );
+        density
+            .into_iter()
+            .map(|d| (d * finite.len() as f64 * bin_width as f64) as f32)
+            .collect()
+    } else {
+        let mut counts = vec![0f32; bins];
+        for v in values {
+            let bin = ((v - lower) / bin_width).floor();
+            if bin >= 0.0 && (bin as usize) < bins {
+                counts[bin as usize] += 1.0;
+            }
+        }
+        counts
+    };
+
+    let curve = centres.zip(counts).collect();
+    match options.scale {
+        HistogramScale::Count => curve,
+        HistogramScale::PercentMax => percent_of_max(curve),
+    }
+}
+
+fn percent_of_max(curve: Vec<(f32, f32)>) -> Vec<(f32, f32)> {
+    let max = curve.iter().map(|(_, y)| *y).fold(0f32, f32::max);
+    if max <= 0.0 {
+        return curve;
+    }
+    curve
+        .into_iter()
+        .map(|(x, y)| (x, y / max * 100.0))
+        .collect()
+}
+
+/// The top of the y axis for a set of curves, with a little headroom
+pub fn y_axis_top<'a>(
+    curves: impl IntoIterator<Item = &'a [(f32, f32)]>,
+    scale: HistogramScale,
+) -> f32 {
+    match scale {
+        HistogramScale::PercentMax => 105.0,
+        HistogramScale::Count => {
+            let max = curves
+                .into_iter()
+                .flatten()
+                .map(|(_, y)| *y)
+                .fold(0f32, f32::max);
+            if max > 0.0 { max * 1.05 } else { 1.0 }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_histogram_curve_counts() {
+        let options = HistogramOptions {
+            bins: 4,
+            ..Default::default()
+        };
+        // out of range events aren't drawn
+        let values = [0.5, 1.5, 1.6, 3.9, -1.0, 4.0];
+        let curve = histogram_curve(&values, (0.0, 4.0), &options);
+        assert_eq!(curve, vec![(0.5, 1.0), (1.5, 2.0), (2.5, 0.0), (3.5, 1.0)]);
+
+        let options = HistogramOptions {
+            scale: HistogramScale::PercentMax,
+            ..options
+        };
+        let curve = histogram_curve(&values, (0.0, 4.0), &options);
+        assert_eq!(curve[1], (1.5, 100.0));
+        assert_eq!(curve[0], (0.5, 50.0));
+
+        assert!(histogram_curve(&[], (0.0, 4.0), &options).is_empty());
+        assert!(histogram_curve(&values, (4.0, 4.0), &options).is_empty());
+    }
+
+    #[test]
+    fn test_smoothed_curve_keeps_counts() {
+        let values: Vec<f32> = (0..1000).map(|i| 50.0 + (i % 20) as f32 - 10.0).collect();
+        let options = HistogramOptions {
+            smooth: true,
+            bins: 100,
+            ..Default::default()
+        };
+        let curve = histogram_curve(&values, (0.0, 100.0), &options);
+        assert_eq!(curve.len(), 100);
+
+        // roughly every event is under the curve, and the peak is where the events are
+        let total: f32 = curve.iter().map(|(_, y)| y).sum();
+        assert!((total - 1000.0).abs() < 20.0, "{total}");
+        let peak = curve
+            .iter()
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(x, _)| *x)
+            .unwrap();
+        assert!((40.0..60.0).contains(&peak), "{peak}");
+
+        // events that can't be drawn don't inflate the curve
+        let mut with_invalid = values.clone();
+        with_invalid.extend([f32::NAN; 500]);
+        with_invalid.extend([f32::INFINITY; 500]);
+        let total: f32 = histogram_curve(&with_invalid, (0.0, 100.0), &options)
+            .iter()
+            .map(|(_, y)| y)
+            .sum();
+        assert!((total - 1000.0).abs() < 20.0, "{total}");
+        assert!(histogram_curve(&[f32::NAN; 10], (0.0, 100.0), &options).is_empty());
+
+        // subsampling every other event of this would only have picked the NaNs
+        let interleaved: Vec<f32> = (0..2 * MAX_KDE_EVENTS)
+            .map(|i| if i % 2 == 0 { f32::NAN } else { 50.0 })
+            .collect();
+        let curve = histogram_curve(&interleaved, (0.0, 100.0), &options);
+        assert!(curve.iter().all(|(_, y)| y.is_finite()));
+        let total: f32 = curve.iter().map(|(_, y)| y).sum();
+        let expected = MAX_KDE_EVENTS as f32;
+        assert!((total - expected).abs() < expected * 0.02, "{total}");
+    }
+}
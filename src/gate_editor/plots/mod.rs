@@ -1,5 +1,9 @@
 pub mod axis_store;
+pub mod backgate;
 pub mod data_helpers;
+pub mod draw_histogram;
 pub mod draw_plot;
+pub mod histogram;
 pub mod plot_store;
+pub mod plot_style;
 pub mod plot_window;
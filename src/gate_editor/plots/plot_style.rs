@@ -0,0 +1,316 @@
+use std::sync::Arc;
+
+use flow_plots::{ColorMaps, PlotType};
+use serde::{Deserialize, Serialize};
+
+use crate::gate_move::density_grid::{DensityGrid, gaussian_blur};
+
+// contours are traced on a blurred grid of this many bins per axis
+const CONTOUR_BINS: usize = 128;
+const CONTOUR_BLUR_SIGMA: f32 = 1.5;
+
+/// Styles are kept per (x, y) fluoro pair
+pub type PlotStyleKey = (Arc<str>, Arc<str>);
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+pub enum PlotStyle {
+    #[default]
+    Density,
+    Dot,
+    Contour,
+    Zebra,
+}
+
+impl PlotStyle {
+    pub const ALL: [PlotStyle; 4] = [
+        PlotStyle::Density,
+        PlotStyle::Dot,
+        PlotStyle::Contour,
+        PlotStyle::Zebra,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            PlotStyle::Density => "Density",
+            PlotStyle::Dot => "Dot",
+            PlotStyle::Contour => "Contour",
+            PlotStyle::Zebra => "Zebra",
+        }
+    }
+
+    pub fn has_contours(&self) -> bool {
+        matches!(self, PlotStyle::Contour | PlotStyle::Zebra)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+pub enum PlotColourMap {
+    #[default]
+    Jet,
+    Viridis,
+    Plasma,
+    Inferno,
+    Magma,
+    Greys,
+}
+
+impl PlotColourMap {
+    pub const ALL: [PlotColourMap; 6] = [
+        PlotColourMap::Jet,
+        PlotColourMap::Viridis,
+        PlotColourMap::Plasma,
+        PlotColourMap::Inferno,
+        PlotColourMap::Magma,
+        PlotColourMap::Greys,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            PlotColourMap::Jet => "Jet",
+            PlotColourMap::Viridis => "Viridis",
+            PlotColourMap::Plasma => "Plasma",
+            PlotColourMap::Inferno => "Inferno",
+            PlotColourMap::Magma => "Magma",
+            PlotColourMap::Greys => "Greys",
+        }
+    }
+
+    pub fn colormap(&self) -> ColorMaps {
+        match self {
+            PlotColourMap::Jet => ColorMaps::Jet,
+            PlotColourMap::Viridis => ColorMaps::Viridis,
+            PlotColourMap::Plasma => ColorMaps::Plasma,
+            PlotColourMap::Inferno => ColorMaps::Inferno,
+            PlotColourMap::Magma => ColorMaps::Magma,
+            PlotColourMap::Greys => ColorMaps::Greys,
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlotStyleSettings {
+    pub style: PlotStyle,
+    pub colour_map: PlotColourMap,
+    pub point_size: f32,
+    // probability contours - each encloses all but this percentage of the events
+    pub contour_levels: Vec<f32>,
+    // zebra plots draw the events in the sparsest this percent as dots
+    pub outlier_threshold: f32,
+}
+
+impl Default for PlotStyleSettings {
+    fn default() -> Self {
+        Self {
+            style: PlotStyle::default(),
+            colour_map: PlotColourMap::default(),
+            point_size: 0.5,
+            contour_levels: vec![10.0, 25.0, 50.0, 75.0, 90.0],
+            outlier_threshold: 2.0,
+        }
+    }
+}
+
+impl PlotStyleSettings {
+    /// What flow_plots renders underneath - contour styles only draw their outliers
+    pub fn plot_type(&self) -> PlotType {
+        match self.style {
+            PlotStyle::Density => PlotType::Density,
+            PlotStyle::Dot | PlotStyle::Contour | PlotStyle::Zebra => PlotType::Dot,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PlotStyleRecord {
+    pub x: Arc<str>,
+    pub y: Arc<str>,
+    pub settings: PlotStyleSettings,
+}
+
+/// Contour lines as data-space segments, one set per level, and the events drawn as
+/// outliers beneath them
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct Contours {
+    pub levels: Vec<Vec<((f32, f32), (f32, f32))>>,
+    pub outliers: Vec<(f32, f32)>,
+}
+
+pub fn contours(
+    points: &[(f32, f32)],
+    x_range: (f32, f32),
+    y_range: (f32, f32),
+    settings: &PlotStyleSettings,
+) -> Contours {
+    let mut grid = DensityGrid::from_points(
+        points,
+        CONTOUR_BINS,
+        (x_range.0 as f64, x_range.1 as f64),
+        (y_range.0 as f64, y_range.1 as f64),
+    );
+    gaussian_blur(&mut grid, CONTOUR_BLUR_SIGMA);
+
+    let levels = probability_levels(&grid, &settings.contour_levels)
+        .into_iter()
+        .map(|level| contour_segments(&grid, level))
+        .collect();
+
+    let outliers = match settings.style {
+        PlotStyle::Zebra => {
+            let threshold = probability_levels(&grid, &[settings.outlier_threshold])[0];
+            points
+                .iter()
+                .filter(|(x, y)| {
+                    grid.bin_index(*x as f64, *y as f64)
+                        .is_some_and(|idx| grid.counts[idx] < threshold)
+                })
+                .copied()
+                .collect()
+        }
+        _ => vec![],
+    };
+
+    Contours { levels, outliers }
+}
+
+/// The density at which `percentile` percent of the events lie in sparser bins
+pub fn probability_levels(grid: &DensityGrid, percentiles: &[f32]) -> Vec<f32> {
+    let mut densities: Vec<f32> = grid.counts.iter().copied().filter(|v| *v > 0.0).collect();
+    densities.sort_by(f32::total_cmp);
+    let total: f32 = densities.iter().sum();
+
+    percentiles
+        .iter()
+        .map(|p| {
+            let target = total * p / 100.0;
+            let mut cumulative = 0.0;
+            densities
+                .iter()
+                .find(|d| {
+                    cumulative += **d;
+                    cumulative >= target
+                })
+                .or(densities.last())
+                .copied()
+                .unwrap_or(0.0)
+        })
+        .collect()
+}
+
+/// Marching squares between bin centres. The grid is treated as empty beyond its
+/// edges, so every contour closes.
+pub fn contour_segments(grid: &DensityGrid, level: f32) -> Vec<((f32, f32), (f32, f32))> {
+    let n = grid.n_bins as isize;
+    let value = |r: isize, c: isize| {
+        if r < 0 || c < 0 || r >= n || c >= n {
+            0.0
+        } else {
+            grid.counts[r as usize * grid.n_bins + c as usize]
+        }
+    };
+    let centre = |(r, c): (isize, isize)| {
+        (
+            (grid.x_range.0 + (c as f64 + 0.5) * grid.bin_width_x()) as f32,
+            (grid.y_range.0 + (r as f64 + 0.5) * grid.bin_width_y()) as f32,
+        )
+    };
+
+    let mut segments = vec![];
+    for r in -1..n {
+        for c in -1..n {
+            let corners = [(r, c), (r, c + 1), (r + 1, c + 1), (r + 1, c)];
+            let values = corners.map(|(r, c)| value(r, c));
+            let above = values.map(|v| v >= level);
+            if above.iter().all(|a| *a) || !above.iter().any(|a| *a) {
+                continue;
+            }
+            // edge i runs from corner i to corner i + 1
+            let crossing = |i: usize| {
+                let j = (i + 1) % 4;
+                let t = (level - values[i]) / (values[j] - values[i]);
+                let (a, b) = (centre(corners[i]), centre(corners[j]));
+                (a.0 + t * (b.0 - a.0), a.1 + t * (b.1 - a.1))
+            };
+            let crossed: Vec<usize> = (0..4).filter(|&i| above[i] != above[(i + 1) % 4]).collect();
+            if crossed.len() == 2 {
+                segments.push((crossing(crossed[0]), crossing(crossed[1])));
+            } else {
+                // a saddle - cut each corner that's above the level off on its own
+                for k in (0..4).filter(|&k| above[k]) {
+                    segments.push((crossing((k + 3) % 4), crossing(k)));
+                }
+            }
+        }
+    }
+    segments
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grid(counts: Vec<f32>) -> DensityGrid {
+        let n_bins = (counts.len() as f64).sqrt() as usize;
+        DensityGrid {
+            counts,
+            n_bins,
+            x_range: (0.0, n_bins as f64),
+            y_range: (0.0, n_bins as f64),
+        }
+    }
+
+    #[test]
+    fn test_probability_levels() {
+        let g = grid(vec![0.0, 1.0, 2.0, 7.0]);
+        // 10% of 10 events - the sparsest bin alone covers it
+        assert_eq!(
+            probability_levels(&g, &[10.0, 30.0, 50.0, 100.0]),
+            vec![1.0, 2.0, 7.0, 7.0]
+        );
+    }
+
+    #[test]
+    fn test_contour_closes_around_a_peak() {
+        // a single dense bin in the middle of a 3x3 grid
+        let g = grid(vec![0.0, 0.0, 0.0, 0.0, 4.0, 0.0, 0.0, 0.0, 0.0]);
+        let segments = contour_segments(&g, 2.0);
+        assert_eq!(segments.len(), 4);
+
+        // halfway between the peak's centre (1.5, 1.5) and its neighbours'
+        for (a, b) in &segments {
+            for (x, y) in [a, b] {
+                assert!(
+                    (1.0..=2.0).contains(x) && (1.0..=2.0).contains(y),
+                    "{x} {y}"
+                );
+                assert!(*x == 1.0 || *x == 2.0 || *y == 1.0 || *y == 2.0);
+            }
+        }
+        assert!(contour_segments(&g, 5.0).is_empty());
+    }
+
+    #[test]
+    fn test_zebra_outliers() {
+        let mut points: Vec<(f32, f32)> = (0..500).map(|i| (50.0 + (i % 5) as f32, 50.0)).collect();
+        points.push((5.0, 95.0));
+        let settings = PlotStyleSettings {
+            style: PlotStyle::Zebra,
+            ..Default::default()
+        };
+        let c = contours(&points, (0.0, 100.0), (0.0, 100.0), &settings);
+        assert_eq!(c.outliers, vec![(5.0, 95.0)]);
+        assert_eq!(c.levels.len(), settings.contour_levels.len());
+        assert!(c.levels.iter().all(|segments| !segments.is_empty()));
+
+        let settings = PlotStyleSettings {
+            style: PlotStyle::Contour,
+            ..settings
+        };
+        assert!(
+            contours(&points, (0.0, 100.0), (0.0, 100.0), &settings)
+                .outliers
+                .is_empty()
+        );
+    }
+}
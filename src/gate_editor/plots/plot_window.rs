@@ -1,20 +1,34 @@
 use crate::file_load::FcsSampleStub;
+use crate::gate_editor::compensation::{CompensationImplExt, CompensationStore};
 use crate::gate_editor::gates::gate_store::GateOverrideResolver;
+use crate::gate_editor::plots::backgate::{BackgateSeries, Backgates, thin_events};
 use crate::gate_editor::plots::data_helpers::{
-    get_event_mask_from_scaled_df, get_filtered_dataframe, get_flow_data, zip_cols_from_filtered_df,
+    ScaledData, get_event_mask_from_scaled_df, get_filtered_dataframe, get_flow_data,
+    population_points, population_values, scale_fcs_data, zip_cols_from_filtered_df,
 };
+use crate::gate_editor::plots::draw_histogram::HistogramPlot;
 use crate::gate_editor::plots::draw_plot::PseudoColourPlot;
+use crate::gate_editor::plots::histogram::{
+    HistogramOptions, HistogramOverlay, HistogramSeries, PlotMode,
+};
 use crate::omiq::metadata::MetaDataStoreStoreExt;
 
 use crate::gate_editor::plots::plot_store::{EventIndexMapped, PlotStore, PlotStoreStoreExt};
 use crate::gate_editor::{
     AxisInfo,
-    gates::{GateState, gate_store::GateStateImplExt},
+    gates::{
+        GateId, GateState,
+        gate_filtering::{PopulationMasks, population_dependency_closure},
+        gate_stats::{PopulationCounts, get_population_counts},
+        gate_store::{FileId, GateStateImplExt, GateStateStoreExt, GateSubStoreStoreExt, ROOTGATE},
+        mask_cache::{GateMaskCache, next_data_generation},
+    },
     plots::axis_store::{AxisStore, AxisStoreImplExt, AxisStoreStoreExt, Param},
 };
 use crate::omiq::metadata::MetaDataStore;
 use dioxus::{CapturedError, prelude::*};
 use polars::frame::DataFrame;
+use rustc_hash::FxHashSet;
 
 use std::sync::Arc;
 
@@ -24,6 +38,9 @@ pub fn PlotWindow(
     x_axis_marker: ReadSignal<Param>,
     y_axis_marker: ReadSignal<Param>,
     parental_gate: ReadSignal<Option<Arc<str>>>,
+    plot_mode: ReadSignal<PlotMode>,
+    histogram_options: ReadSignal<HistogramOptions>,
+    histogram_overlays: ReadSignal<Vec<HistogramOverlay>>,
 ) -> Element {
     let mut gate_store = use_context::<Store<GateState, CopyValue<GateState, SyncStorage>>>();
     let mut gate_resolver_store: Signal<Option<Arc<GateOverrideResolver>>> = use_signal(|| None);
@@ -37,6 +54,9 @@ pub fn PlotWindow(
 
     let mut axis_store = use_context::<Store<AxisStore, CopyValue<AxisStore, SyncStorage>>>();
 
+    let comp_store =
+        use_context::<Store<CompensationStore, CopyValue<CompensationStore, SyncStorage>>>();
+
     // RESOURCE 1: Load FCS File
     let mut fcs_file: SyncSignal<Option<flow_fcs::Fcs>> = use_signal_sync(|| None);
     let _ = use_resource(move || async move {
@@ -100,23 +120,35 @@ pub fn PlotWindow(
 
     // this is currently scaling the data but filtering is done elsewhere!
     let scaled_data = use_resource(move || async move {
-        let mut params: Vec<(Arc<str>, f32)> = Vec::new();
-        for (k, v) in axis_store.settings().read().iter() {
-            if v.is_arcsinh() {
-                params.push((k.clone(), v.get_cofactor().unwrap()))
-            }
-        }
+        // cheap im clone
+        let axis_settings = axis_store.settings().read().clone();
 
         if fcs_file.read().is_none() {return Err(anyhow::anyhow!("No data to scale"))};
 
+        // re-scaled whenever the sample's compensation is reassigned or its matrix edited
+        let file_id = plot_store.current_file_id()();
+        let groups = metadata_store.metadata()()
+            .get(&file_id)
+            .cloned()
+            .unwrap_or_default();
+        let compensation =
+            comp_store.active_matrix(&file_id, &groups, &sample_stub.read().metadata)?;
+
         let result =
-            tokio::task::spawn_blocking(move || -> Result<Arc<DataFrame>, anyhow::Error> {
-                let param_refs: Vec<(&str, f32)> =
-                    params.iter().map(|(k, v)| (k.as_ref(), *v)).collect();
-                let scaled_df = &*fcs_file.read().as_ref().unwrap().apply_arcsinh_transforms(param_refs.as_slice())?;
+            tokio::task::spawn_blocking(move || -> Result<ScaledData, anyhow::Error> {
+                let scaled_df = scale_fcs_data(
+                    fcs_file.read().as_ref().unwrap(),
+                    compensation.as_deref(),
+                    &axis_settings,
+                )?;
                 let df_with_index = scaled_df.with_row_index("original_index".into(), None)?;
 
-                Ok(Arc::new(df_with_index))
+                Ok(ScaledData {
+                    df: Arc::new(df_with_index),
+                    generation: next_data_generation(),
+                    axis_settings,
+                    compensation,
+                })
             })
             .await;
 
@@ -161,6 +193,68 @@ pub fn PlotWindow(
         }
     });
 
+    // counts for every population in the sidebar, booleans included
+    let mut population_counts = use_context::<Signal<PopulationCounts>>();
+    let mask_cache = use_context::<GateMaskCache>();
+    let _ = use_resource(move || {
+        let mask_cache = mask_cache.clone();
+        let hierarchy = gate_store.hierarchy().read().clone();
+        // the plot's own population, the gates drawn on it, and what those are computed from
+        let parent = parental_gate().unwrap_or_else(|| ROOTGATE.clone());
+        let plotted = {
+            let registry_binding = gate_store.gate_store().primary_and_subgate_registry();
+            let registry = registry_binding.read();
+            let shown = hierarchy.get_children(&parent).into_iter().cloned();
+            population_dependency_closure(&hierarchy, &registry, shown.chain([parent]))
+        };
+        let resolver = resolver();
+        let file_id = plot_store.current_file_id()();
+        let scaled = scaled_data
+            .read()
+            .as_ref()
+            .and_then(|res| res.as_ref().ok())
+            .cloned();
+        async move {
+            let (Ok(resolver), Some(scaled)) = (resolver, scaled) else {
+                population_counts.set(PopulationCounts::default());
+                return;
+            };
+            let inputs = Arc::new((scaled, hierarchy, resolver));
+            let count = move |only: Option<FxHashSet<GateId>>| {
+                let (inputs, mask_cache, file_id) =
+                    (inputs.clone(), mask_cache.clone(), file_id.clone());
+                tokio::task::spawn_blocking(move || {
+                    let (scaled, hierarchy, resolver) = &*inputs;
+                    get_population_counts(
+                        PopulationMasks::new(&scaled.df, hierarchy, resolver).with_cache(
+                            &mask_cache,
+                            file_id,
+                            scaled.generation,
+                            &scaled.axis_settings,
+                            scaled.compensation.as_ref(),
+                        ),
+                        only.as_ref(),
+                    )
+                })
+            };
+
+            // a drag only re-evaluates what this plot shows...
+            match count(Some(plotted)).await {
+                Ok(counts) => population_counts.write().extend(counts),
+                Err(e) => {
+                    println!("{e}");
+                    return;
+                }
+            }
+            // ...and the rest of the sidebar once edits settle - a new edit restarts the resource
+            tokio::time::sleep(std::time::Duration::from_millis(300)).await;
+            match count(None).await {
+                Ok(counts) => population_counts.set(counts),
+                Err(e) => println!("{e}"),
+            }
+        }
+    });
+
     let mut plot_data_signal = use_signal(Vec::new);
 
     let filtered_dataframe: Resource<std::result::Result<Arc<DataFrame>, anyhow::Error>> =
@@ -168,7 +262,7 @@ pub fn PlotWindow(
             let x_fluoro = x_axis_marker.read().fluoro.clone();
             let y_fluoro = y_axis_marker.read().fluoro.clone();
             let parental = parental_gate();
-            plot_store.current_file_id()();
+            let file_id = plot_store.current_file_id()();
             async move {
                 let Ok(resolver) = resolver.peek().clone() else {
                     return Err(anyhow::anyhow!("No resolver"));
@@ -183,7 +277,7 @@ pub fn PlotWindow(
                     return Err(anyhow::anyhow!("No data yet"))
                 };
                     let filtered_data =
-                        match get_filtered_dataframe(d.clone(), parental, resolver).await {
+                        match get_filtered_dataframe(d.clone(), parental, resolver, file_id).await {
                             Ok(d) => d.clone(),
                             Err(e) => {
                                 plot_data_signal.set(vec![]);
@@ -262,6 +356,195 @@ pub fn PlotWindow(
         *plot_store.event_index_map().write() = data;
     });
 
+    // samples overlaid on the histogram - loaded and scaled once, then gated like this one
+    let overlay_data = use_resource(move || {
+        let overlays = histogram_overlays();
+        let is_histogram = plot_mode() == PlotMode::Histogram;
+        let axis_settings = axis_store.settings().read().clone();
+        let name_to_id = metadata_store.file_name_to_gating_id().peek().clone();
+        let metadata = metadata_store.metadata().peek().clone();
+        // each overlay is compensated the way it would be on its own plot
+        let compensation = comp_store.read().clone();
+        async move {
+            if !is_histogram || overlays.is_empty() {
+                return vec![];
+            }
+            let loaded = tokio::task::spawn_blocking(move || {
+                overlays
+                    .into_iter()
+                    .map(|overlay| {
+                        let files: Vec<(FileId, ScaledData)> = overlay
+                            .paths
+                            .iter()
+                            .filter_map(|path| {
+                                let file_name: Arc<str> = path.file_name()?.to_str()?.into();
+                                let file_id =
+                                    name_to_id.get(&file_name).cloned().unwrap_or(file_name);
+                                let groups = metadata.get(&file_id).cloned().unwrap_or_default();
+                                let (scaled, compensation) = flow_fcs::Fcs::open(path.to_str()?)
+                                    .and_then(|fcs| {
+                                        let compensation = compensation.active_matrix(
+                                            &file_id,
+                                            &groups,
+                                            &fcs.metadata,
+                                        )?;
+                                        let scaled = scale_fcs_data(
+                                            &fcs,
+                                            compensation.as_deref(),
+                                            &axis_settings,
+                                        )?;
+                                        Ok((scaled, compensation))
+                                    })
+                                    .inspect_err(|e| println!("{}: {e}", path.display()))
+                                    .ok()?;
+                                Some((
+                                    file_id,
+                                    ScaledData {
+                                        df: Arc::new(scaled),
+                                        generation: next_data_generation(),
+                                        axis_settings: axis_settings.clone(),
+                                        compensation,
+                                    },
+                                ))
+                            })
+                            .collect();
+                        (overlay.name, files)
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .await;
+            loaded.unwrap_or_else(|e| {
+                println!("{e}");
+                vec![]
+            })
+        }
+    });
+
+    let overlay_mask_cache = use_context::<GateMaskCache>();
+    let overlay_series = use_resource(move || {
+        let hierarchy = gate_store.hierarchy().read().clone();
+        let population = parental_gate().unwrap_or_else(|| ROOTGATE.clone());
+        let channel = x_axis_marker.read().fluoro.clone();
+        let mask_cache = overlay_mask_cache.clone();
+        let metadata = metadata_store.metadata().peek().clone();
+        // every file is gated with its own overrides
+        let overlays: Vec<_> = overlay_data
+            .read()
+            .iter()
+            .flatten()
+            .map(|(name, files)| {
+                let files: Vec<_> = files
+                    .iter()
+                    .filter_map(|(file_id, scaled)| {
+                        let groups = metadata.get(file_id).cloned().unwrap_or_default();
+                        let resolver = gate_store
+                            .get_current_sample(file_id.clone(), &groups)
+                            .ok()?;
+                        Some((file_id.clone(), scaled.clone(), resolver))
+                    })
+                    .collect();
+                (name.clone(), files)
+            })
+            .collect();
+        async move {
+            let series = tokio::task::spawn_blocking(move || {
+                overlays
+                    .into_iter()
+                    .map(|(name, files)| {
+                        let values: Vec<f32> = files
+                            .iter()
+                            .flat_map(|(file_id, scaled, resolver)| {
+                                population_values(
+                                    scaled,
+                                    file_id.clone(),
+                                    &hierarchy,
+                                    resolver,
+                                    &population,
+                                    &channel,
+                                    &mask_cache,
+                                )
+                                .inspect_err(|e| println!("{file_id}: {e}"))
+                                .unwrap_or_default()
+                            })
+                            .collect();
+                        HistogramSeries {
+                            name,
+                            values: Arc::new(values),
+                        }
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .await;
+            series.unwrap_or_default()
+        }
+    });
+
+    // descendant populations coloured over this plot, gated with this sample's resolver
+    let backgates = use_context::<Signal<Backgates>>();
+    let backgate_mask_cache = use_context::<GateMaskCache>();
+    let backgate_series = use_resource(move || {
+        let hierarchy = gate_store.hierarchy().read().clone();
+        let parent = parental_gate().unwrap_or_else(|| ROOTGATE.clone());
+        let selected = backgates.read().below(&hierarchy, &parent);
+        let names: Vec<String> = {
+            let store = gate_store.gate_store().read();
+            selected
+                .iter()
+                .map(|b| {
+                    store
+                        .primary_and_subgate_registry
+                        .display_name(&b.gate_id)
+                        .unwrap_or_else(|| b.gate_id.to_string())
+                })
+                .collect()
+        };
+        let x_fluoro = x_axis_marker.read().fluoro.clone();
+        let y_fluoro = y_axis_marker.read().fluoro.clone();
+        let file_id = plot_store.current_file_id()();
+        let resolver = resolver();
+        let scaled = scaled_data
+            .read()
+            .as_ref()
+            .and_then(|res| res.as_ref().ok())
+            .cloned();
+        let mask_cache = backgate_mask_cache.clone();
+        async move {
+            let (Ok(resolver), Some(scaled)) = (resolver, scaled) else {
+                return vec![];
+            };
+            if selected.is_empty() {
+                return vec![];
+            }
+            let series = tokio::task::spawn_blocking(move || {
+                selected
+                    .into_iter()
+                    .zip(names)
+                    .filter_map(|(backgate, name)| {
+                        let points = population_points(
+                            &scaled,
+                            file_id.clone(),
+                            &hierarchy,
+                            &resolver,
+                            &backgate.gate_id,
+                            (&x_fluoro, &y_fluoro),
+                            &mask_cache,
+                        )
+                        .inspect_err(|e| println!("{}: {e}", backgate.gate_id))
+                        .ok()?;
+                        Some(BackgateSeries {
+                            gate_id: backgate.gate_id,
+                            name,
+                            colour: backgate.colour,
+                            points: Arc::new(thin_events(points)),
+                        })
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .await;
+            series.unwrap_or_default()
+        }
+    });
+
     match &*event_index.read() {
         Some(Ok(_)) => {}
         Some(Err(e)) => {
@@ -294,11 +577,30 @@ pub fn PlotWindow(
             }
             {
                 let show_plot = resolver.read().is_ok();
-                if show_plot {
+                if show_plot && plot_mode() == PlotMode::Histogram {
+                    let sample_name = sample_stub
+                        .read()
+                        .get_fil_keyword()
+                        .map(|n| n.to_string())
+                        .unwrap_or_else(|_| sample_stub.read().get_filepath().display().to_string());
+                    rsx! {
+                        HistogramPlot {
+                            size: (600, 600),
+                            data: plot_data_signal,
+                            sample_name,
+                            overlays: overlay_series.read().clone().unwrap_or_default(),
+                            options: histogram_options,
+                            x_axis_info: x_axis_limits.read().clone(),
+                            y_axis_info: y_axis_limits.read().clone(),
+                            parental_gate_id: parental_gate,
+                        }
+                    }
+                } else if show_plot {
                     rsx! {
                         PseudoColourPlot {
                             size: (600, 600),
                             data: plot_data_signal,
+                            backgates: backgate_series.read().clone().unwrap_or_default(),
                             x_axis_info: x_axis_limits.read().clone(),
                             y_axis_info: y_axis_limits.read().clone(),
                             parental_gate_id: parental_gate,
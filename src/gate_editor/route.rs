@@ -1,4 +1,6 @@
+use crate::gate_editor::comp_window::CompWindow;
 use crate::gate_editor::main_window::MainWindow;
+use crate::gate_editor::session::use_session_provider;
 use dioxus::prelude::*;
 
 #[derive(Routable, Clone, PartialEq)]
@@ -9,8 +11,8 @@ pub enum Route {
     // #[route("/scale")]
     // ScaleWindow,
 
-    // #[route("/comp")]
-    // CompWindow,
+    #[route("/comp")]
+    CompWindow,
 
     // #[route("/options")]
     // OptionsWindow,
@@ -22,6 +24,8 @@ pub enum Route {
 #[component]
 pub fn NavBar() -> Element {
     // let mut nav_burger_menu_open = use_signal(|| "".to_string());
+    // the stores live here, above the windows, so moving between them keeps the session
+    use_session_provider();
 
     rsx! {
         div { class: "route-outlet", Outlet::<Route> {} }
@@ -39,6 +43,16 @@ pub fn NavBar() -> Element {
                         div { class: "nav_bar-item", "|" }
                     }
 
+                    div {
+                        Link { to: Route::CompWindow,
+                            div { class: "nav_bar-item", title: "Compensation", "🎛" }
+                        }
+                    }
+
+                    div {
+                        div { class: "nav_bar-item", "|" }
+                    }
+
                 // div {
                 //     if geolocation::check_geolocation_permission() == PermissionResult::GRANTED {
                 //         Link { to: route::Route::LocationMap,
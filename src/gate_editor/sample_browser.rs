@@ -0,0 +1,453 @@
+use std::cmp::Ordering;
+
+use dioxus::prelude::*;
+use dioxus::stores::SyncStore;
+use rustc_hash::FxHashSet;
+
+use crate::components::sheet::{Sheet, SheetContent, SheetHeader, SheetSide, SheetTitle};
+use crate::file_load::keyword_string_value;
+use crate::gate_editor::gates::{
+    GateState,
+    gate_store::{FileId, GateStateStoreExt, GateSubStoreStoreExt, GroupId},
+};
+use crate::gate_editor::session::Session;
+use crate::omiq::metadata::{MetaDataParameter, MetaDataStore, MetaDataStoreStoreExt};
+
+const MONTHS: [&str; 12] = [
+    "JAN", "FEB", "MAR", "APR", "MAY", "JUN", "JUL", "AUG", "SEP", "OCT", "NOV", "DEC",
+];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum SortColumn {
+    Name,
+    Events,
+    Date,
+    // index into the metadata parameters
+    Metadata(usize),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct SampleRow {
+    // into FcsFiles::file_list, what the plots are driven by
+    index: usize,
+    name: String,
+    events: Option<u64>,
+    date: String,
+    // one per metadata parameter, None where the sample isn't in a group
+    groups: Vec<Option<GroupId>>,
+    sample_overrides: bool,
+    group_overrides: bool,
+}
+
+// FCS $DATE is dd-mmm-yyyy, which doesn't sort as text
+fn date_key(date: &str) -> Option<(i32, usize, u32)> {
+    let mut parts = date.trim().split('-');
+    let day = parts.next()?.parse().ok()?;
+    let month_name = parts.next()?;
+    let month = MONTHS
+        .iter()
+        .position(|m| m.eq_ignore_ascii_case(month_name))?;
+    let year = parts.next()?.parse().ok()?;
+    Some((year, month, day))
+}
+
+fn compare_rows(a: &SampleRow, b: &SampleRow, column: SortColumn) -> Ordering {
+    match column {
+        SortColumn::Name => a.name.to_lowercase().cmp(&b.name.to_lowercase()),
+        SortColumn::Events => a.events.cmp(&b.events),
+        SortColumn::Date => date_key(&a.date)
+            .cmp(&date_key(&b.date))
+            .then_with(|| a.date.cmp(&b.date)),
+        SortColumn::Metadata(i) => group_of(a, i).cmp(&group_of(b, i)),
+    }
+}
+
+fn group_of(row: &SampleRow, parameter: usize) -> Option<&GroupId> {
+    row.groups.get(parameter).and_then(|g| g.as_ref())
+}
+
+// the rows to show, in order, split into runs sharing a value of `group_by` - a single run
+// when there's no grouping. Samples outside every group come last.
+fn arrange_rows(
+    rows: &[SampleRow],
+    search: &str,
+    filter: Option<&(usize, GroupId)>,
+    (sort, ascending): (SortColumn, bool),
+    group_by: Option<usize>,
+) -> Vec<(Option<GroupId>, Vec<SampleRow>)> {
+    let search = search.trim().to_lowercase();
+    let mut visible: Vec<SampleRow> = rows
+        .iter()
+        .filter(|r| search.is_empty() || r.name.to_lowercase().contains(&search))
+        .filter(|r| filter.is_none_or(|(i, group)| group_of(r, *i) == Some(group)))
+        .cloned()
+        .collect();
+    visible.sort_by(|a, b| {
+        let grouped = match group_by {
+            Some(i) => match (group_of(a, i), group_of(b, i)) {
+                (Some(x), Some(y)) => x.cmp(y),
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (None, None) => Ordering::Equal,
+            },
+            None => Ordering::Equal,
+        };
+        let order = compare_rows(a, b, sort);
+        grouped.then(if ascending { order } else { order.reverse() })
+    });
+
+    let mut runs: Vec<(Option<GroupId>, Vec<SampleRow>)> = vec![];
+    for row in visible {
+        let key = group_by.and_then(|i| group_of(&row, i).cloned());
+        match runs.last_mut() {
+            Some((last, run)) if *last == key => run.push(row),
+            _ => runs.push((key, vec![row])),
+        }
+    }
+    runs
+}
+
+// every loaded sample with its keywords and metadata - sortable, filterable and grouped by
+// metadata. Clicking a row, or arrowing through them, loads it into the plots.
+#[component]
+pub fn SampleBrowser(open: Signal<bool>) -> Element {
+    let session = use_context::<Session>();
+    let gate_store = use_context::<SyncStore<GateState>>();
+    let metadata_store = use_context::<SyncStore<MetaDataStore>>();
+    let mut sample_index = session.sample_index;
+
+    let mut sort: Signal<(SortColumn, bool)> = use_signal(|| (SortColumn::Name, true));
+    let mut search = use_signal(String::new);
+    let mut filter: Signal<Option<(usize, GroupId)>> = use_signal(|| None);
+    let mut group_by: Signal<Option<usize>> = use_signal(|| None);
+
+    let parameters = use_memo(move || {
+        let mut p: Vec<MetaDataParameter> = metadata_store
+            .metadata()
+            .read()
+            .values()
+            .flat_map(|groups| groups.keys().cloned())
+            .collect();
+        p.sort();
+        p.dedup();
+        p
+    });
+
+    // a filter or grouping is only valid for the parameters it was chosen from
+    use_effect(move || {
+        let _ = parameters.read();
+        filter.set(None);
+        group_by.set(None);
+    });
+
+    let rows = use_memo(move || {
+        let files = session.files.read();
+        let Some(files) = files.as_ref() else {
+            return vec![];
+        };
+        let parameters = parameters.read();
+        let metadata = metadata_store.metadata().read();
+        let name_to_id = metadata_store.file_name_to_gating_id().read();
+        let overrides = gate_store.gate_store();
+        let sample_overrides = overrides.sample_position_overrides();
+        let sample_overridden: FxHashSet<FileId> = sample_overrides
+            .read()
+            .keys()
+            .map(|(_, file_id)| file_id.clone())
+            .collect();
+        let group_overrides = overrides.group_position_overrides();
+        let group_overrides = group_overrides.read();
+
+        files
+            .file_list()
+            .iter()
+            .enumerate()
+            .map(|(index, stub)| {
+                let file_name: std::sync::Arc<str> = stub
+                    .get_filepath()
+                    .file_name()
+                    .and_then(|n| n.to_str())
+                    .unwrap_or_default()
+                    .into();
+                let file_id = name_to_id.get(&file_name).cloned().unwrap_or(file_name);
+                let file_groups = metadata.get(&file_id);
+                let keyword = |k: &str| {
+                    keyword_string_value(&stub.metadata, k)
+                        .map(|v| v.trim().to_string())
+                        .ok()
+                };
+                SampleRow {
+                    index,
+                    name: stub
+                        .get_fil_keyword()
+                        .map(|n| n.to_string())
+                        .unwrap_or_else(|_| file_id.to_string()),
+                    events: keyword("$TOT").and_then(|t| t.parse().ok()),
+                    date: keyword("$DATE").unwrap_or_default(),
+                    groups: parameters
+                        .iter()
+                        .map(|p| file_groups.and_then(|g| g.get(p)).cloned())
+                        .collect(),
+                    sample_overrides: sample_overridden.contains(&file_id),
+                    group_overrides: group_overrides.keys().any(|(_, key)| {
+                        file_groups.and_then(|g| g.get(&key.parameter)) == Some(&key.group)
+                    }),
+                }
+            })
+            .collect::<Vec<_>>()
+    });
+
+    let runs = use_memo(move || {
+        arrange_rows(
+            &rows.read(),
+            &search.read(),
+            filter.read().as_ref(),
+            sort(),
+            group_by(),
+        )
+    });
+
+    // up and down step through the rows as shown
+    let on_key = move |e: KeyboardEvent| {
+        let step: isize = match e.key() {
+            Key::ArrowDown => 1,
+            Key::ArrowUp => -1,
+            _ => return,
+        };
+        e.prevent_default();
+        let order: Vec<usize> = runs
+            .read()
+            .iter()
+            .flat_map(|(_, run)| run.iter().map(|r| r.index))
+            .collect();
+        if order.is_empty() {
+            return;
+        }
+        let current = order.iter().position(|i| *i == sample_index());
+        let next = match current {
+            Some(p) => (p as isize + step).clamp(0, order.len() as isize - 1) as usize,
+            None => 0,
+        };
+        sample_index.set(order[next]);
+    };
+
+    let mut sort_by = move |column: SortColumn| {
+        let (current, ascending) = sort();
+        sort.set((column, if current == column { !ascending } else { true }));
+    };
+    let arrow = move |column: SortColumn| match sort() {
+        (current, true) if current == column => " ▲",
+        (current, false) if current == column => " ▼",
+        _ => "",
+    };
+
+    let filter_options: Vec<(String, String)> = {
+        let rows = rows.read();
+        let mut options = vec![];
+        for (i, parameter) in parameters.read().iter().enumerate() {
+            let mut values: Vec<&GroupId> = rows.iter().filter_map(|r| group_of(r, i)).collect();
+            values.sort();
+            values.dedup();
+            for value in values {
+                options.push((format!("{i}:{value}"), format!("{parameter} = {value}")));
+            }
+        }
+        options
+    };
+    let filter_value = filter
+        .read()
+        .as_ref()
+        .map(|(i, value)| format!("{i}:{value}"))
+        .unwrap_or_default();
+    let column_count = 4 + parameters.read().len();
+
+    rsx! {
+        Sheet { open: open(), on_open_change: move |v| open.set(v),
+            SheetContent { side: SheetSide::Right, class: "stats-panel",
+                SheetHeader {
+                    SheetTitle { "Samples" }
+                }
+                div { class: "stats-panel_actions",
+                    input {
+                        r#type: "search",
+                        placeholder: "Search names",
+                        value: "{search}",
+                        oninput: move |e| search.set(e.value()),
+                    }
+                    select {
+                        onchange: move |e| {
+                            let value = e.value();
+                            filter
+                                .set(
+                                    value
+                                        .split_once(':')
+                                        .and_then(|(i, group)| Some((i.parse().ok()?, group.into()))),
+                                );
+                        },
+                        option { value: "", selected: filter_value.is_empty(), "All samples" }
+                        for (value , label) in filter_options {
+                            option {
+                                key: "{value}",
+                                value: "{value}",
+                                selected: value == filter_value,
+                                "{label}"
+                            }
+                        }
+                    }
+                    select {
+                        onchange: move |e| group_by.set(e.value().parse().ok()),
+                        option { value: "", selected: group_by().is_none(), "No grouping" }
+                        for (i , parameter) in parameters.read().iter().enumerate() {
+                            option {
+                                key: "{parameter}",
+                                value: "{i}",
+                                selected: group_by() == Some(i),
+                                "Group by {parameter}"
+                            }
+                        }
+                    }
+                }
+                div {
+                    class: "stats-panel_table sample-browser",
+                    tabindex: 0,
+                    onkeydown: on_key,
+                    table {
+                        thead {
+                            tr {
+                                th { onclick: move |_| sort_by(SortColumn::Name),
+                                    "$FIL{arrow(SortColumn::Name)}"
+                                }
+                                th { onclick: move |_| sort_by(SortColumn::Events),
+                                    "Events{arrow(SortColumn::Events)}"
+                                }
+                                th { onclick: move |_| sort_by(SortColumn::Date),
+                                    "Date{arrow(SortColumn::Date)}"
+                                }
+                                th { "" }
+                                for (i , parameter) in parameters.read().iter().enumerate() {
+                                    th {
+                                        key: "{parameter}",
+                                        onclick: move |_| sort_by(SortColumn::Metadata(i)),
+                                        "{parameter}{arrow(SortColumn::Metadata(i))}"
+                                    }
+                                }
+                            }
+                        }
+                        tbody {
+                            for (group , run) in runs.read().iter().cloned() {
+                                if let Some(i) = group_by() {
+                                    tr { class: "sample-browser_group",
+                                        td { colspan: "{column_count}",
+                                            {
+                                                let parameter = parameters.read().get(i).cloned().unwrap_or_default();
+                                                let value = group
+                                                    .as_ref()
+                                                    .map(|g| g.to_string())
+                                                    .unwrap_or_else(|| "(none)".to_string());
+                                                rsx! { "{parameter}: {value} ({run.len()})" }
+                                            }
+                                        }
+                                    }
+                                }
+                                for row in run {
+                                    tr {
+                                        key: "{row.index}",
+                                        class: if row.index == sample_index() { "sample-browser_current" },
+                                        onclick: move |_| sample_index.set(row.index),
+                                        td { "{row.name}" }
+                                        td {
+                                            {row.events.map(|e| e.to_string()).unwrap_or_default()}
+                                        }
+                                        td { "{row.date}" }
+                                        td {
+                                            if row.group_overrides {
+                                                span {
+                                                    class: "gate-origin gate-origin_group",
+                                                    title: "Has group specific gate positions",
+                                                    "G"
+                                                }
+                                            }
+                                            if row.sample_overrides {
+                                                span {
+                                                    class: "gate-origin gate-origin_sample",
+                                                    title: "Has sample specific gate positions",
+                                                    "S"
+                                                }
+                                            }
+                                        }
+                                        for (i , group) in row.groups.iter().enumerate() {
+                                            td { key: "{i}",
+                                                {group.as_ref().map(|g| g.to_string()).unwrap_or_default()}
+                                            }
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    fn row(index: usize, name: &str, date: &str, group: Option<&str>) -> SampleRow {
+        SampleRow {
+            index,
+            name: name.to_string(),
+            events: Some(index as u64 * 100),
+            date: date.to_string(),
+            groups: vec![group.map(Arc::from)],
+            sample_overrides: false,
+            group_overrides: false,
+        }
+    }
+
+    fn indices(runs: &[(Option<GroupId>, Vec<SampleRow>)]) -> Vec<Vec<usize>> {
+        runs.iter()
+            .map(|(_, run)| run.iter().map(|r| r.index).collect())
+            .collect()
+    }
+
+    #[test]
+    fn test_date_key() {
+        assert_eq!(date_key("05-Jan-2024"), Some((2024, 0, 5)));
+        assert!(date_key("01-DEC-2023") < date_key("05-jan-2024"));
+        assert_eq!(date_key("2024/01/05"), None);
+    }
+
+    #[test]
+    fn test_arrange_rows() {
+        let rows = vec![
+            row(0, "b", "02-Feb-2024", Some("Stim")),
+            row(1, "a", "01-Mar-2023", None),
+            row(2, "c", "03-Jan-2024", Some("Ctrl")),
+            row(3, "d", "01-Jan-2024", Some("Stim")),
+        ];
+        let by = |sort, group_by| arrange_rows(&rows, "", None, sort, group_by);
+
+        assert_eq!(
+            indices(&by((SortColumn::Name, true), None)),
+            vec![vec![1, 0, 2, 3]]
+        );
+        assert_eq!(
+            indices(&by((SortColumn::Date, false), None)),
+            vec![vec![0, 2, 3, 1]]
+        );
+        // groups in order, ungrouped samples last, sorted within each group
+        let grouped = by((SortColumn::Events, false), Some(0));
+        assert_eq!(indices(&grouped), vec![vec![2], vec![3, 0], vec![1]]);
+        assert_eq!(grouped[2].0, None);
+
+        let stim = (0, Arc::from("Stim"));
+        let filtered = arrange_rows(&rows, "", Some(&stim), (SortColumn::Name, true), None);
+        assert_eq!(indices(&filtered), vec![vec![0, 3]]);
+        let searched = arrange_rows(&rows, " C ", None, (SortColumn::Name, true), None);
+        assert_eq!(indices(&searched), vec![vec![2]]);
+    }
+}
@@ -0,0 +1,220 @@
+use std::path::PathBuf;
+
+use dioxus::prelude::*;
+use dioxus::stores::use_store_sync;
+
+use crate::file_load::FcsFiles;
+use crate::gate_editor::compensation::{CompensationImplExt, CompensationStore};
+use crate::gate_editor::file_menu::SessionInput;
+use crate::gate_editor::gates::{
+    GateState,
+    gate_store::{FileId, GateStateImplExt},
+    mask_cache::GateMaskCache,
+};
+use crate::gate_editor::plots::axis_store::{
+    AxisStore, AxisStoreImplExt, AxisStoreStoreExt, ScalingInfoSource,
+};
+use crate::gate_editor::plots::backgate::Backgates;
+use crate::omiq::metadata::{
+    MetaDataImplExt, MetaDataOrigin, MetaDataStore, MetaDataStoreStoreExt,
+};
+
+// Everything loaded for a gating session - the stores, the fcs files and where each input
+// came from. Provided by the route layout so switching windows doesn't drop or reload it.
+#[derive(Clone, Copy)]
+pub struct Session {
+    pub files: Signal<Option<FcsFiles>>,
+    pub sample_index: Signal<usize>,
+    pub fcs_dir_path: Signal<Option<PathBuf>>,
+    pub metadata_path: Signal<Option<PathBuf>>,
+    pub gates_path: Signal<Option<PathBuf>>,
+    pub scaling_path: Signal<Option<PathBuf>>,
+    pub comp_path: Signal<Option<PathBuf>>,
+    pub file_result: Resource<anyhow::Result<()>>,
+    pub meta_result: Resource<anyhow::Result<()>>,
+    pub gate_result: Resource<anyhow::Result<()>>,
+    pub axis_result: Resource<anyhow::Result<()>>,
+    pub comp_result: Resource<anyhow::Result<()>>,
+    // the sample in the main window's first plot
+    pub current_file: Memo<Option<FileId>>,
+}
+
+impl Session {
+    pub fn path(&self, input: SessionInput) -> Signal<Option<PathBuf>> {
+        match input {
+            SessionInput::FcsDirectory => self.fcs_dir_path,
+            SessionInput::Metadata => self.metadata_path,
+            SessionInput::Gates => self.gates_path,
+            SessionInput::Scaling => self.scaling_path,
+            SessionInput::Compensation => self.comp_path,
+        }
+    }
+
+    pub fn result(&self, input: SessionInput) -> Resource<anyhow::Result<()>> {
+        match input {
+            SessionInput::FcsDirectory => self.file_result,
+            SessionInput::Metadata => self.meta_result,
+            SessionInput::Gates => self.gate_result,
+            SessionInput::Scaling => self.axis_result,
+            SessionInput::Compensation => self.comp_result,
+        }
+    }
+}
+
+// creates the session stores and provides them, and the Session, as context
+pub fn use_session_provider() -> Session {
+    let mut files: Signal<Option<FcsFiles>> = use_signal(|| None);
+    let mut sample_index = use_signal(|| 0);
+
+    // each input is chosen and reloaded independently from the file menu
+    let fcs_dir_path: Signal<Option<PathBuf>> = use_signal(|| None);
+    let metadata_path: Signal<Option<PathBuf>> = use_signal(|| None);
+    let gates_path: Signal<Option<PathBuf>> = use_signal(|| None);
+    let scaling_path: Signal<Option<PathBuf>> = use_signal(|| None);
+    let comp_path: Signal<Option<PathBuf>> = use_signal(|| None);
+
+    let mut metadata_store = use_store_sync(MetaDataStore::default);
+    use_context_provider(|| metadata_store);
+
+    let meta_result = use_resource(move || async move {
+        let Some(path) = metadata_path() else {
+            return Ok(());
+        };
+        let result = tokio::task::spawn_blocking(move || -> Result<(), anyhow::Error> {
+            metadata_store.set_metadata_from_file(path, "OmiqID", "Filename", MetaDataOrigin::Omiq)
+        })
+        .await;
+
+        match result {
+            Ok(r) => r,
+            Err(e) => Err(anyhow::anyhow!("Failed to load metadata from file {}", e)),
+        }
+    });
+
+    let mut gate_store = use_store_sync(GateState::default);
+    use_context_provider(|| gate_store);
+
+    let mut axis_store = use_store_sync(AxisStore::default);
+    use_context_provider(|| axis_store);
+
+    let mut comp_store = use_store_sync(CompensationStore::default);
+    use_context_provider(|| comp_store);
+
+    use_context_provider(GateMaskCache::default);
+
+    let backgates = use_signal(Backgates::default);
+    use_context_provider(|| backgates);
+
+    let axis_result = use_resource(move || async move {
+        let Some(path) = scaling_path() else {
+            return Ok(());
+        };
+        let result = tokio::task::spawn_blocking(move || -> Result<(), anyhow::Error> {
+            axis_store.set_axes_from_file(path, ScalingInfoSource::Omiq)
+        })
+        .await;
+
+        match result {
+            Ok(r) => r,
+            Err(e) => Err(anyhow::anyhow!(
+                "Failed to load axis settings from file {}",
+                e
+            )),
+        }
+    });
+
+    let comp_result = use_resource(move || async move {
+        let Some(path) = comp_path() else {
+            return Ok(());
+        };
+        // samples already on the new matrix's target are flagged against the current metadata
+        let metadata = metadata_store.metadata().peek().clone();
+        let result = tokio::task::spawn_blocking(move || -> Result<(), anyhow::Error> {
+            comp_store.import_omiq_matrix(&path, &metadata)
+        })
+        .await;
+
+        match result {
+            Ok(r) => r,
+            Err(e) => Err(anyhow::anyhow!("Failed to load compensation {}", e)),
+        }
+    });
+
+    let file_result = use_resource(move || async move {
+        let Some(path) = fcs_dir_path() else {
+            return Ok(());
+        };
+        let result = tokio::task::spawn_blocking(move || -> anyhow::Result<FcsFiles> {
+            FcsFiles::create(&path.to_string_lossy())
+        })
+        .await;
+
+        match result {
+            Ok(Ok(f)) => {
+                sample_index.set(0);
+                files.set(Some(f));
+                Ok(())
+            }
+            Ok(Err(e)) => Err(e),
+            Err(e) => Err(anyhow::anyhow!("Failed to load files from path {}", e)),
+        }
+    });
+
+    let gate_result = use_resource(move || async move {
+        let Some(path) = gates_path() else {
+            return Ok(());
+        };
+        // gates are only reloaded when asked to - cheap im clones
+        let metadata = metadata_store.metadata().peek().clone();
+        let axis_settings = axis_store.settings().peek().clone();
+
+        if metadata.is_empty() || axis_settings.is_empty() {
+            return Err(anyhow::anyhow!(
+                "Load metadata and scaling before loading gates"
+            ));
+        }
+
+        let result = tokio::task::spawn_blocking(move || {
+            gate_store
+                .upload_gates_from_file(path, &metadata, axis_settings)
+                .map_err(|e| anyhow::anyhow!("Upload failed: {}", e))
+        })
+        .await;
+
+        match result {
+            Ok(Ok(_)) => Ok(()),
+            Ok(Err(e)) => {
+                println!("{e}");
+                Err(e)
+            }
+            Err(e) => Err(anyhow::anyhow!("Thread joined with error: {}", e)),
+        }
+    });
+
+    let current_file = use_memo(move || {
+        let files = files.read();
+        let stub = files.as_ref()?.file_list().get(sample_index())?;
+        let file_name = stub.get_filepath().file_name()?.to_str()?;
+        metadata_store
+            .file_name_to_gating_id()
+            .read()
+            .get(file_name)
+            .cloned()
+    });
+
+    use_context_provider(|| Session {
+        files,
+        sample_index,
+        fcs_dir_path,
+        metadata_path,
+        gates_path,
+        scaling_path,
+        comp_path,
+        file_result,
+        meta_result,
+        gate_result,
+        axis_result,
+        comp_result,
+        current_file,
+    })
+}
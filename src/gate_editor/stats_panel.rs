@@ -0,0 +1,240 @@
+use std::sync::Arc;
+
+use dioxus::prelude::*;
+use dioxus::stores::SyncStore;
+use rustc_hash::FxHashMap;
+
+use crate::components::sheet::{Sheet, SheetContent, SheetHeader, SheetSide, SheetTitle};
+use crate::file_load::FcsFiles;
+use crate::gate_editor::compensation::{CompensationImplExt, CompensationStore};
+use crate::gate_editor::gates::{
+    GateState,
+    batch_stats::{
+        StatsFile, StatsLayout, StatsTable, collect_stats_gates, compute_batch_stats,
+    },
+    gate_store::{GateStateImplExt, GateStateStoreExt, GateSubStoreStoreExt},
+};
+use crate::gate_editor::plots::axis_store::{AxisStore, AxisStoreStoreExt};
+use crate::omiq::metadata::{MetaDataStore, MetaDataStoreStoreExt};
+
+// each file in the fcs directory with the gates resolved for it
+pub fn collect_stats_files(
+    fcs_files: &FcsFiles,
+    mut gate_store: SyncStore<GateState>,
+    metadata_store: SyncStore<MetaDataStore>,
+    comp_store: SyncStore<CompensationStore>,
+) -> (Vec<StatsFile>, Vec<String>) {
+    let name_to_id = metadata_store.file_name_to_gating_id().peek().clone();
+    let metadata = metadata_store.metadata().peek().clone();
+    let mut warnings = vec![];
+    let mut stats_files = vec![];
+    for stub in fcs_files.file_list() {
+        let path = stub.get_filepath().to_path_buf();
+        let file_name: Arc<str> = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or_default()
+            .into();
+        let file_id = match name_to_id.get(&file_name) {
+            Some(id) => id.clone(),
+            None => {
+                warnings.push(format!(
+                    "{file_name}: not found in metadata, sample and group overrides ignored"
+                ));
+                file_name.clone()
+            }
+        };
+        let groups = metadata.get(&file_id).cloned().unwrap_or_else(FxHashMap::default);
+        let resolver = match gate_store.get_current_sample(file_id.clone(), &groups) {
+            Ok(r) => r,
+            Err(e) => {
+                warnings.push(format!("{file_name}: {e}"));
+                continue;
+            }
+        };
+        let compensation = match comp_store.active_matrix(&file_id, &groups, &stub.metadata) {
+            Ok(m) => m,
+            Err(e) => {
+                warnings.push(format!("{file_name}: {e}"));
+                continue;
+            }
+        };
+        let name = stub
+            .get_fil_keyword()
+            .map(|n| n.to_string())
+            .unwrap_or_else(|_| file_name.to_string());
+        stats_files.push(StatsFile {
+            file_id,
+            name,
+            path,
+            resolver,
+            compensation,
+        });
+    }
+    (stats_files, warnings)
+}
+
+// population statistics for every gate across every file in the fcs directory
+#[component]
+pub fn StatsPanel(open: Signal<bool>, files: ReadSignal<Option<FcsFiles>>) -> Element {
+    let gate_store = use_context::<SyncStore<GateState>>();
+    let axis_store = use_context::<SyncStore<AxisStore>>();
+    let metadata_store = use_context::<SyncStore<MetaDataStore>>();
+    let comp_store = use_context::<SyncStore<CompensationStore>>();
+
+    let mut selected_channels: Signal<Vec<Arc<str>>> = use_signal(Vec::new);
+    let mut table: Signal<Option<StatsTable>> = use_signal(|| None);
+    let mut running = use_signal(|| false);
+    let mut message = use_signal(|| None::<String>);
+
+    let run = move |_| {
+        let Some(fcs_files) = files.peek().clone() else {
+            message.set(Some("No FCS directory loaded".to_string()));
+            return;
+        };
+
+        // snapshot everything needed on the ui thread, then crunch the numbers off it
+        let (stats_files, mut warnings) =
+            collect_stats_files(&fcs_files, gate_store, metadata_store, comp_store);
+
+        let hierarchy = gate_store.hierarchy().peek().clone();
+        let gates = {
+            let registry_binding = gate_store.gate_store().primary_and_subgate_registry();
+            let registry = registry_binding.peek();
+            collect_stats_gates(&hierarchy, &registry)
+        };
+        let channels = selected_channels.peek().clone();
+        let axis_settings = axis_store.settings().peek().clone();
+
+        running.set(true);
+        message.set(None);
+        spawn(async move {
+            let result = tokio::task::spawn_blocking(move || {
+                compute_batch_stats(&stats_files, &gates, &hierarchy, &channels, &axis_settings)
+            })
+            .await;
+            match result {
+                Ok(mut t) => {
+                    warnings.append(&mut t.errors);
+                    t.errors = warnings;
+                    table.set(Some(t));
+                }
+                Err(e) => message.set(Some(format!("Statistics failed: {e}"))),
+            }
+            running.set(false);
+        });
+    };
+
+    let export = move |layout: StatsLayout| {
+        spawn(async move {
+            let Some(handle) = rfd::AsyncFileDialog::new()
+                .set_title("Export statistics")
+                .add_filter("CSV", &["csv"])
+                .add_filter("TSV", &["tsv"])
+                .save_file()
+                .await
+            else {
+                return;
+            };
+            let result = match &*table.peek() {
+                Some(t) => t.write(handle.path(), layout),
+                None => Err(anyhow::anyhow!("No statistics to export")),
+            };
+            match result {
+                Ok(_) => message.set(Some(format!("Saved {}", handle.path().display()))),
+                Err(e) => message.set(Some(format!("Export failed: {e}"))),
+            }
+        });
+    };
+
+    rsx! {
+        Sheet { open: open(), on_open_change: move |v| open.set(v),
+            SheetContent { side: SheetSide::Right, class: "stats-panel",
+                SheetHeader {
+                    SheetTitle { "Population statistics" }
+                }
+                div { class: "stats-panel_channels",
+                    for param in axis_store.sorted_settings().read().iter().cloned() {
+                        label { key: "{param.fluoro}",
+                            input {
+                                r#type: "checkbox",
+                                checked: selected_channels.read().contains(&param.fluoro),
+                                onchange: {
+                                    let fluoro = param.fluoro.clone();
+                                    move |e: FormEvent| {
+                                        let mut channels = selected_channels.write();
+                                        if e.checked() {
+                                            if !channels.contains(&fluoro) {
+                                                channels.push(fluoro.clone());
+                                            }
+                                        } else {
+                                            channels.retain(|c| *c != fluoro);
+                                        }
+                                    }
+                                },
+                            }
+                            "{param.marker} ({param.fluoro})"
+                        }
+                    }
+                }
+                div { class: "stats-panel_actions",
+                    button { disabled: running(), onclick: run,
+                        if running() {
+                            "Calculating..."
+                        } else {
+                            "Calculate"
+                        }
+                    }
+                    button {
+                        disabled: table.read().is_none(),
+                        onclick: move |_| export(StatsLayout::Long),
+                        "Export (long)"
+                    }
+                    button {
+                        disabled: table.read().is_none(),
+                        onclick: move |_| export(StatsLayout::Wide),
+                        "Export (wide)"
+                    }
+                }
+                if let Some(m) = message() {
+                    div { class: "stats-panel_message", "{m}" }
+                }
+                if let Some(t) = &*table.read() {
+                    for e in t.errors.iter() {
+                        div { class: "error-message", "{e}" }
+                    }
+                    div { class: "stats-panel_table",
+                        table {
+                            thead {
+                                tr {
+                                    th { "Sample" }
+                                    th { "Gate" }
+                                    th { "Count" }
+                                    th { "%Parent" }
+                                    th { "%Total" }
+                                    for channel in t.channels.iter() {
+                                        th { "Median {channel}" }
+                                    }
+                                }
+                            }
+                            tbody {
+                                for row in t.rows.iter() {
+                                    tr {
+                                        td { "{row.file_name}" }
+                                        td { "{row.gate_path}" }
+                                        td { "{row.count}" }
+                                        td { "{row.percent_parent:.2}" }
+                                        td { "{row.percent_total:.2}" }
+                                        for summary in row.channels.iter() {
+                                            td { "{summary.median:.1}" }
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
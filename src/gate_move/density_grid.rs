@@ -48,6 +48,38 @@ impl DensityGrid {
         }
     }
 
+    pub fn from_points(
+        points: &[(f32, f32)],
+        n_bins: usize,
+        x_range: (f64, f64),
+        y_range: (f64, f64),
+    ) -> Self {
+        let mut grid = Self {
+            counts: vec![0.0f32; n_bins * n_bins],
+            n_bins,
+            x_range,
+            y_range,
+        };
+        for (x, y) in points {
+            if let Some(idx) = grid.bin_index(*x as f64, *y as f64) {
+                grid.counts[idx] += 1.0;
+            }
+        }
+        grid
+    }
+
+    /// Row-major index of the bin holding (x, y), if it's on the grid
+    pub fn bin_index(&self, x: f64, y: f64) -> Option<usize> {
+        let col = ((x - self.x_range.0) / self.bin_width_x()).floor();
+        let row = ((y - self.y_range.0) / self.bin_width_y()).floor();
+        let n = self.n_bins as f64;
+        if col >= 0.0 && col < n && row >= 0.0 && row < n {
+            Some(row as usize * self.n_bins + col as usize)
+        } else {
+            None
+        }
+    }
+
     /// Bin width in data-space units — used to convert peak offset back to data coords
     pub fn bin_width_x(&self) -> f64 {
         (self.x_range.1 - self.x_range.0) / self.n_bins as f64
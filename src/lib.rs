@@ -5,5 +5,6 @@ pub mod components;
 pub mod file_load;
 pub mod gate_editor;
 pub mod omiq;
+pub mod project;
 pub mod searchable_select;
 pub type FxIndexMap<K, V> = IndexMap<K, V, FxBuildHasher>;
@@ -6,7 +6,7 @@ use flow_gates::{
 };
 use itertools::Itertools;
 use rustc_hash::{FxBuildHasher, FxHashMap};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::collections::hash_map::Entry;
 use std::ops::RangeInclusive;
@@ -27,21 +27,21 @@ use crate::gate_editor::gates::gate_store::{FileId, GateSource};
 use crate::gate_editor::gates::gate_traits::DrawableGate;
 use crate::omiq::metadata::{MetaDataFileMap, MetaDataKey, MetaDataParameter};
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Serialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct ExperimentJson {
     pub tree: GatingTree,
 }
 
 // Gating Tree and Node will be made into the Gating Hierarchy
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Serialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct GatingTree {
     pub nodes: HashMap<Arc<str>, GatingNode>,
     pub filter_containers: HashMap<Arc<str>, FilterContainer>,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Serialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct GatingNode {
     pub id: Arc<str>,
@@ -52,7 +52,7 @@ pub struct GatingNode {
 }
 
 //FilterContainer is the actual gate info
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Deserialize, Serialize, Debug, Clone)]
 #[serde(tag = "containerType")]
 pub enum FilterContainer {
     #[serde(rename = "AtomicFilterContainer")]
@@ -62,7 +62,7 @@ pub enum FilterContainer {
     Compound(CompoundContainer),
 }
 
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Deserialize, Serialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct CompoundContainer {
     pub id: GateId,
@@ -72,7 +72,7 @@ pub struct CompoundContainer {
     pub filter_container_ids: Vec<GateId>,
 }
 
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Deserialize, Serialize, Debug, Clone)]
 #[serde(rename_all = "UPPERCASE")]
 pub enum BooleanOpType {
     And,
@@ -80,7 +80,7 @@ pub enum BooleanOpType {
     Not,
 }
 
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Deserialize, Serialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct AtomicContainer {
     pub id: GateId,
@@ -207,7 +207,7 @@ impl AtomicContainer {
     }
 }
 
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Deserialize, Serialize, Debug, Clone)]
 #[serde(tag = "type")]
 pub enum GateSerialized {
     #[serde(rename = "RectangleGate")]
@@ -436,7 +436,7 @@ impl GateSerialized {
     }
 }
 
-#[derive(Deserialize, Debug, Copy, Clone, PartialEq, PartialOrd)]
+#[derive(Deserialize, Serialize, Debug, Copy, Clone, PartialEq, PartialOrd)]
 pub struct Point {
     #[serde(rename = "f1Val", default)]
     pub x: f64,
@@ -969,7 +969,7 @@ pub fn extract_axis_range_from_axis_settings(
     let y_axis_range = y_axis.axis_lower..=y_axis.axis_upper;
 
     let x_trans = x_axis.transform.clone();
-    let y_trans = x_axis.transform.clone();
+    let y_trans = y_axis.transform.clone();
 
     Ok((x_axis_range, y_axis_range, x_trans, y_trans))
 }
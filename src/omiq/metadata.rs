@@ -3,6 +3,7 @@ use std::{collections::HashMap, path::PathBuf};
 use dioxus::prelude::*;
 use polars::prelude::*;
 use rustc_hash::{FxBuildHasher, FxHashMap};
+use serde::{Deserialize, Serialize};
 
 use crate::gate_editor::gates::gate_store::{FileId, GroupId};
 
@@ -31,8 +32,48 @@ pub struct MetaDataStore {
     gating_id_to_actual_id_override_map: HashMap<FileId, String, FxBuildHasher>,
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct MetaDataRecord {
+    pub metadata: FxHashMap<FileId, FxHashMap<MetaDataParameter, GroupId>>,
+    pub file_name_to_gating_id: FxHashMap<Arc<str>, FileId>,
+    pub gating_id_to_actual_id_override_map: FxHashMap<FileId, String>,
+}
+
 #[store(pub name = MetaDataImplExt)]
 impl<Lens> Store<MetaDataStore, Lens> {
+    fn save_to_record(&self) -> MetaDataRecord {
+        let store = self.peek();
+        MetaDataRecord {
+            metadata: store
+                .metadata
+                .iter()
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .collect(),
+            file_name_to_gating_id: store
+                .file_name_to_gating_id
+                .iter()
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .collect(),
+            gating_id_to_actual_id_override_map: store
+                .gating_id_to_actual_id_override_map
+                .iter()
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .collect(),
+        }
+    }
+
+    fn load_from_record(&mut self, record: MetaDataRecord) {
+        self.with_mut(|s| {
+            s.metadata = record.metadata.into_iter().collect();
+            s.file_name_to_gating_id = record.file_name_to_gating_id.into_iter().collect();
+            s.gating_id_to_actual_id_override_map = record
+                .gating_id_to_actual_id_override_map
+                .into_iter()
+                .collect();
+        });
+    }
+
     fn set_metadata_from_file(
         &mut self,
         path: PathBuf,
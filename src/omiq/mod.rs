@@ -1,2 +1,3 @@
 pub mod deserialise;
 pub mod metadata;
+pub mod serialise;
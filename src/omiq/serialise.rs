@@ -0,0 +1,796 @@
+use anyhow::anyhow;
+use flow_gates::{BooleanOperation, GateGeometry};
+use rustc_hash::{FxHashMap, FxHashSet};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::gate_editor::gates::GateId;
+use crate::gate_editor::gates::gate_composite::bisector_gate::BisectorGate;
+use crate::gate_editor::gates::gate_composite::quadrant_gate::QuadrantGate;
+use crate::gate_editor::gates::gate_composite::skewed_quadrant_gate::{
+    DataPoints, SkewedQuadrantGate,
+};
+use crate::gate_editor::gates::gate_hierarchy::GateHierarchy;
+use crate::gate_editor::gates::gate_single::boolean_gates::BooleanGate;
+use crate::gate_editor::gates::gate_single::ellipse_gate::calculate_ellipse_nodes;
+use crate::gate_editor::gates::gate_single::line_gate::LineGate;
+use crate::gate_editor::gates::gate_store::{
+    FileId, GateMap, GroupGateMap, ROOTGATE, SampleGateMap,
+};
+use crate::gate_editor::gates::gate_traits::DrawableGate;
+use crate::omiq::deserialise::{
+    AtomicContainer, BooleanOpType, CompositeType, CompoundContainer, ExperimentJson,
+    FilterContainer, GateSerialized, GatingNode, GatingTree, Point,
+};
+use crate::omiq::metadata::{MetaDataFileMap, MetaDataParameter};
+
+// The inverse of GateState::load_experiment - walks the hierarchy and writes
+// every reachable gate back out as an OMIQ filter container. Linked gates are written once,
+// under the ids in `canonical_ids`, and shared by the nodes of every link.
+pub fn build_experiment_json(
+    hierarchy: &GateHierarchy,
+    registry: &GateMap,
+    sample_overrides: &SampleGateMap,
+    group_overrides: &GroupGateMap,
+    canonical_ids: &FxHashMap<GateId, GateId>,
+    metadata: &MetaDataFileMap,
+) -> anyhow::Result<ExperimentJson> {
+    let canonical = |id: &GateId| canonical_ids.get(id).unwrap_or(id).clone();
+    let mut nodes = HashMap::new();
+    let mut to_visit: Vec<GateId> = vec![];
+
+    for gate_id in hierarchy.iter_dfs(&ROOTGATE) {
+        if gate_id == *ROOTGATE {
+            continue;
+        }
+        // omiq uses an empty parent id for top level nodes
+        let parent_id = match hierarchy.get_parent(&gate_id) {
+            Some(parent) if *parent != *ROOTGATE => parent.clone(),
+            _ => Arc::from(""),
+        };
+        // we don't keep separate node ids - the gate id is unique per node
+        let filter_container_id = canonical(&gate_id);
+        nodes.insert(
+            gate_id.clone(),
+            GatingNode {
+                id: gate_id.clone(),
+                parent_id,
+                filter_container_id: filter_container_id.clone(),
+                ord: hierarchy.get_order(&gate_id).unwrap_or(0),
+                collapsed: false,
+            },
+        );
+        to_visit.push(filter_container_id);
+    }
+
+    let mut filter_containers = HashMap::new();
+    let mut visited: FxHashSet<GateId> = FxHashSet::default();
+
+    while let Some(gate_id) = to_visit.pop() {
+        if !visited.insert(gate_id.clone()) {
+            continue;
+        }
+        let gate = registry
+            .get(&gate_id)
+            .ok_or_else(|| anyhow!("Gate {} is in the hierarchy but not the registry", gate_id))?;
+
+        if let Some(boolean_gate) = gate.as_any().downcast_ref::<BooleanGate>() {
+            // operands may live outside the hierarchy, so make sure they get written too -
+            // an operand that is a linked gate can only be written as the shared container
+            let mut container = boolean_gate_to_container(boolean_gate);
+            container.filter_container_ids = container
+                .filter_container_ids
+                .iter()
+                .map(canonical)
+                .collect();
+            to_visit.extend(container.filter_container_ids.iter().cloned());
+            filter_containers.insert(gate_id.clone(), FilterContainer::Compound(container));
+        } else if gate.is_composite() {
+            // all subgates are written in one go - they share a groupId
+            for container in composite_gate_to_containers(
+                gate.as_ref(),
+                sample_overrides,
+                group_overrides,
+                metadata,
+            )? {
+                visited.insert(container.id.clone());
+                filter_containers.insert(container.id.clone(), FilterContainer::Atomic(container));
+            }
+        } else {
+            let container = primary_gate_to_container(
+                gate.as_ref(),
+                sample_overrides,
+                group_overrides,
+                metadata,
+            )?;
+            filter_containers.insert(gate_id.clone(), FilterContainer::Atomic(container));
+        }
+    }
+
+    Ok(ExperimentJson {
+        tree: GatingTree {
+            nodes,
+            filter_containers,
+        },
+    })
+}
+
+fn boolean_gate_to_container(gate: &BooleanGate) -> CompoundContainer {
+    let operation = match gate.get_operation() {
+        BooleanOperation::And => BooleanOpType::And,
+        BooleanOperation::Or => BooleanOpType::Or,
+        BooleanOperation::Not => BooleanOpType::Not,
+    };
+    CompoundContainer {
+        id: gate.get_id(),
+        name: Arc::from(gate.get_name()),
+        operation,
+        filter_container_ids: gate.get_operands().to_vec(),
+    }
+}
+
+fn primary_gate_to_container(
+    gate: &dyn DrawableGate,
+    sample_overrides: &SampleGateMap,
+    group_overrides: &GroupGateMap,
+    metadata: &MetaDataFileMap,
+) -> anyhow::Result<AtomicContainer> {
+    let gate_id = gate.get_id();
+    let (md, overrides) =
+        collect_per_file_overrides(&gate_id, sample_overrides, group_overrides, metadata);
+
+    let per_file_filters = overrides
+        .iter()
+        .map(|(file_id, ovr)| Ok((file_id.clone(), primary_gate_to_serialized(ovr.as_ref())?)))
+        .collect::<anyhow::Result<FxHashMap<_, _>>>()?;
+
+    Ok(AtomicContainer {
+        id: gate_id,
+        name: Arc::from(gate.get_name()),
+        default_filter: primary_gate_to_serialized(gate)?,
+        group_id: None,
+        md,
+        per_file_filters,
+    })
+}
+
+fn composite_gate_to_containers(
+    gate: &dyn DrawableGate,
+    sample_overrides: &SampleGateMap,
+    group_overrides: &GroupGateMap,
+    metadata: &MetaDataFileMap,
+) -> anyhow::Result<Vec<AtomicContainer>> {
+    let composite_id = gate.get_id();
+    let subgate_ids = gate.get_inner_gate_ids();
+
+    let composite_type = composite_type_for_gate(gate)
+        .ok_or_else(|| anyhow!("Unknown composite gate type for {}", composite_id))?;
+
+    let default_filters = composite_gate_to_serialized(gate)?;
+
+    let (md, overrides) =
+        collect_per_file_overrides(&composite_id, sample_overrides, group_overrides, metadata);
+    let override_filters = overrides
+        .iter()
+        .map(|(file_id, ovr)| Ok((file_id.clone(), composite_gate_to_serialized(ovr.as_ref())?)))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    let n = subgate_ids.len();
+    let mut containers = Vec::with_capacity(n);
+    for (i, subgate_id) in subgate_ids.into_iter().enumerate() {
+        let (_, group_id) = composite_group_id(&composite_type, i, n);
+        let name = gate
+            .get_gate_ref(Some(&subgate_id))
+            .map(|g| g.name.clone())
+            .unwrap_or_else(|| subgate_id.to_string());
+
+        let per_file_filters = override_filters
+            .iter()
+            .map(|(file_id, filters)| (file_id.clone(), filters[i].clone()))
+            .collect();
+
+        containers.push(AtomicContainer {
+            id: subgate_id,
+            name: Arc::from(name),
+            default_filter: default_filters[i].clone(),
+            group_id: Some(group_id),
+            md: md.clone(),
+            per_file_filters,
+        });
+    }
+
+    Ok(containers)
+}
+
+pub fn composite_type_for_gate(gate: &dyn DrawableGate) -> Option<CompositeType> {
+    let any = gate.as_any();
+    let id = gate.get_id().to_string();
+    if any.is::<BisectorGate>() {
+        Some(CompositeType::Bisector(id))
+    } else if any.is::<SkewedQuadrantGate>() {
+        Some(CompositeType::SkewedQuadrant(id))
+    } else if any.is::<QuadrantGate>() {
+        Some(CompositeType::Quadrant(id))
+    } else {
+        None
+    }
+}
+
+// The group id suffix tells the importer which composite to rebuild.
+// The importer takes subgates in descending position order, so bisector subgates
+// (left, right) and quadrant subgates (bl, br, tr, tl) are the reverse of SPLIT0..SPLIT1
+// and QUAD0..QUAD3
+pub fn composite_group_id(
+    composite_type: &CompositeType,
+    subgate_index: usize,
+    subgate_count: usize,
+) -> (u32, String) {
+    let (id, suffix) = match composite_type {
+        CompositeType::Bisector(id) => (id, "SPLIT"),
+        CompositeType::Quadrant(id) => (id, "QUAD"),
+        CompositeType::SkewedQuadrant(id) => (id, "SKEWEDQUAD"),
+    };
+    let position = subgate_count - 1 - subgate_index;
+    (position as u32, format!("{id}_{suffix}{position}"))
+}
+
+// An omiq container can either be group specific (md is set and every file in an
+// overridden group gets an entry) or file specific - not both. As in the app, a file's own
+// position beats its group's, so sample overrides replace their file's group entry.
+fn collect_per_file_overrides(
+    gate_id: &GateId,
+    sample_overrides: &SampleGateMap,
+    group_overrides: &GroupGateMap,
+    metadata: &MetaDataFileMap,
+) -> (
+    Option<MetaDataParameter>,
+    Vec<(FileId, Arc<dyn DrawableGate>)>,
+) {
+    let mut groups: Vec<_> = group_overrides
+        .iter()
+        .filter(|((id, _), _)| id == gate_id)
+        .map(|((_, key), gate)| (key, gate))
+        .collect();
+    groups.sort_by(|a, b| {
+        (&a.0.parameter, &a.0.group).cmp(&(&b.0.parameter, &b.0.group))
+    });
+
+    let md = groups.first().map(|(key, _)| key.parameter.clone());
+    let mut per_file: Vec<(FileId, Arc<dyn DrawableGate>)> = vec![];
+    if let Some(md) = &md {
+        for (key, gate) in groups.iter() {
+            if key.parameter != *md {
+                println!(
+                    "Gate {} is grouped by {} and {} - only {} will be exported",
+                    gate_id, md, key.parameter, md
+                );
+                continue;
+            }
+            for (file_id, file_metadata) in metadata.iter() {
+                if file_metadata.get(md) == Some(&key.group) {
+                    per_file.push((file_id.clone(), (*gate).clone()));
+                }
+            }
+        }
+    }
+
+    for ((_, file_id), gate) in sample_overrides.iter().filter(|((id, _), _)| id == gate_id) {
+        match per_file.iter_mut().find(|(f, _)| f == file_id) {
+            Some(entry) => entry.1 = gate.clone(),
+            None => per_file.push((file_id.clone(), gate.clone())),
+        }
+    }
+
+    (md, per_file)
+}
+
+fn label_point(gate: &flow_gates::Gate) -> Option<Point> {
+    gate.label_position.as_ref().map(|l| Point {
+        x: l.offset_x as f64,
+        y: l.offset_y as f64,
+    })
+}
+
+fn rectangle_bounds(gate: &flow_gates::Gate) -> anyhow::Result<((f32, f32), (f32, f32))> {
+    let GateGeometry::Rectangle { min, max } = &gate.geometry else {
+        return Err(anyhow!("Gate {} does not have a rectangle geometry", gate.id));
+    };
+    let (x_param, y_param) = &gate.parameters;
+    match (
+        min.get_coordinate(x_param),
+        min.get_coordinate(y_param),
+        max.get_coordinate(x_param),
+        max.get_coordinate(y_param),
+    ) {
+        (Some(x1), Some(y1), Some(x2), Some(y2)) => Ok(((x1, y1), (x2, y2))),
+        _ => Err(anyhow!("Invalid rectangle coordinates for gate {}", gate.id)),
+    }
+}
+
+pub fn primary_gate_to_serialized(gate: &dyn DrawableGate) -> anyhow::Result<GateSerialized> {
+    let inner = gate
+        .get_gate_ref(None)
+        .ok_or_else(|| anyhow!("Gate {} has no internal data", gate.get_id()))?;
+    let (x_param, y_param) = inner.parameters.clone();
+    let label_position = label_point(inner);
+
+    match &inner.geometry {
+        GateGeometry::Rectangle { .. } => {
+            let (min, max) = rectangle_bounds(inner)?;
+            if gate.as_any().is::<LineGate>() {
+                Ok(GateSerialized::Line {
+                    x_param,
+                    y_param,
+                    f1min: min.0 as f64,
+                    f1max: max.0 as f64,
+                    label_position,
+                })
+            } else {
+                Ok(GateSerialized::Rectangle {
+                    x_param,
+                    y_param,
+                    min: min.into(),
+                    max: max.into(),
+                    label_position,
+                })
+            }
+        }
+        GateGeometry::Polygon { nodes, .. } => {
+            let points = nodes
+                .iter()
+                .filter_map(|n| {
+                    Some(Point::from((
+                        n.get_coordinate(&x_param)?,
+                        n.get_coordinate(&y_param)?,
+                    )))
+                })
+                .collect();
+            Ok(GateSerialized::Polygon {
+                x_param,
+                y_param,
+                points,
+                label_position,
+            })
+        }
+        GateGeometry::Ellipse {
+            center,
+            radius_x,
+            radius_y,
+            angle,
+        } => {
+            let (Some(cx), Some(cy)) = (
+                center.get_coordinate(&x_param),
+                center.get_coordinate(&y_param),
+            ) else {
+                return Err(anyhow!("Invalid ellipse center for gate {}", inner.id));
+            };
+            // center, right, top, left, bottom
+            let nodes = calculate_ellipse_nodes(cx, cy, *radius_x, *radius_y, *angle);
+            Ok(GateSerialized::Ellipse {
+                x_param,
+                y_param,
+                left: nodes[3].into(),
+                top: nodes[2].into(),
+                right: nodes[1].into(),
+                bottom: nodes[4].into(),
+                label_position,
+            })
+        }
+        GateGeometry::Boolean { .. } => Err(anyhow!(
+            "Boolean gate {} should be exported as a compound container",
+            inner.id
+        )),
+    }
+}
+
+// returns one filter per subgate, in the same order as get_inner_gate_ids
+pub fn composite_gate_to_serialized(gate: &dyn DrawableGate) -> anyhow::Result<Vec<GateSerialized>> {
+    let (x_param, y_param) = gate.get_params();
+    let subgate_ids = gate.get_inner_gate_ids();
+    let label_for = |id: &GateId| gate.get_gate_ref(Some(id)).and_then(label_point);
+
+    let any = gate.as_any();
+    if any.is::<BisectorGate>() {
+        subgate_ids
+            .iter()
+            .map(|id| {
+                let subgate = gate
+                    .get_gate_ref(Some(id))
+                    .ok_or_else(|| anyhow!("Missing bisector subgate {}", id))?;
+                let (min, max) = rectangle_bounds(subgate)?;
+                Ok(GateSerialized::Line {
+                    x_param: x_param.clone(),
+                    y_param: y_param.clone(),
+                    f1min: min.0 as f64,
+                    f1max: max.0 as f64,
+                    label_position: label_point(subgate),
+                })
+            })
+            .collect()
+    } else if let Some(quadrant) = any.downcast_ref::<QuadrantGate>() {
+        let p = quadrant.get_data_points();
+        let (cx, cy) = p.center;
+        let (x_lo, x_hi, y_lo, y_hi) = (p.left.0, p.right.0, p.bottom.1, p.top.1);
+        // bl, br, tr, tl - the importer takes the center from the min of the top right
+        let bounds = [
+            ((x_lo, y_lo), (cx, cy)),
+            ((cx, y_lo), (x_hi, cy)),
+            ((cx, cy), (x_hi, y_hi)),
+            ((x_lo, cy), (cx, y_hi)),
+        ];
+        Ok(subgate_ids
+            .iter()
+            .zip(bounds)
+            .map(|(id, (min, max))| GateSerialized::Rectangle {
+                x_param: x_param.clone(),
+                y_param: y_param.clone(),
+                min: min.into(),
+                max: max.into(),
+                label_position: label_for(id),
+            })
+            .collect())
+    } else if let Some(quadrant) = any.downcast_ref::<SkewedQuadrantGate>() {
+        let DataPoints {
+            center,
+            left,
+            bottom,
+            right,
+            top,
+        } = quadrant.get_data_points().clone();
+        // each quadrant is the angle between its two arms: bl, br, tr, tl
+        let arms = [(bottom, left), (bottom, right), (top, right), (top, left)];
+        Ok(subgate_ids
+            .iter()
+            .zip(arms)
+            .map(|(id, (v1, v2))| GateSerialized::Angle {
+                x_param: x_param.clone(),
+                y_param: y_param.clone(),
+                center: center.into(),
+                v1: v1.into(),
+                v2: v2.into(),
+                label_position: label_for(id),
+            })
+            .collect())
+    } else {
+        Err(anyhow!("Unknown composite gate type for {}", gate.get_id()))
+    }
+}
+
+// the fixture is shared with the project record tests in gate_store
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::gate_editor::AxisInfo;
+    use crate::gate_editor::GateState;
+    use crate::gate_editor::plots::axis_store::Param;
+    use flow_fcs::TransformType;
+    use rustc_hash::FxBuildHasher;
+
+    pub(crate) const EXPERIMENT: &str = r#"{
+        "tree": {
+            "nodes": {
+                "n1": {"id": "n1", "parentId": "", "filterContainerId": "rect", "ord": 0, "collapsed": false},
+                "n2": {"id": "n2", "parentId": "n1", "filterContainerId": "poly", "ord": 0, "collapsed": false},
+                "n3": {"id": "n3", "parentId": "n1", "filterContainerId": "ell", "ord": 1, "collapsed": true},
+                "n4": {"id": "n4", "parentId": "n2", "filterContainerId": "q0", "ord": 0, "collapsed": false},
+                "n5": {"id": "n5", "parentId": "n2", "filterContainerId": "q1", "ord": 1, "collapsed": false},
+                "n6": {"id": "n6", "parentId": "n2", "filterContainerId": "q2", "ord": 2, "collapsed": false},
+                "n7": {"id": "n7", "parentId": "n2", "filterContainerId": "q3", "ord": 3, "collapsed": false},
+                "n8": {"id": "n8", "parentId": "n3", "filterContainerId": "s0", "ord": 0, "collapsed": false},
+                "n9": {"id": "n9", "parentId": "n3", "filterContainerId": "s1", "ord": 1, "collapsed": false},
+                "n10": {"id": "n10", "parentId": "n1", "filterContainerId": "k0", "ord": 2, "collapsed": false},
+                "n11": {"id": "n11", "parentId": "n1", "filterContainerId": "k1", "ord": 3, "collapsed": false},
+                "n12": {"id": "n12", "parentId": "n1", "filterContainerId": "k2", "ord": 4, "collapsed": false},
+                "n13": {"id": "n13", "parentId": "n1", "filterContainerId": "k3", "ord": 5, "collapsed": false},
+                "n14": {"id": "n14", "parentId": "n1", "filterContainerId": "range", "ord": 6, "collapsed": false},
+                "n15": {"id": "n15", "parentId": "n1", "filterContainerId": "both", "ord": 7, "collapsed": false}
+            },
+            "filterContainers": {
+                "rect": {"containerType": "AtomicFilterContainer", "id": "rect", "name": "Cells",
+                    "defaultFilter": {"type": "RectangleGate", "f1": "FSC-A", "f2": "SSC-A",
+                        "min": {"f1Val": 10.0, "f2Val": 20.0}, "max": {"f1Val": 100.0, "f2Val": 200.0}},
+                    "perFileFilters": {
+                        "file1": {"type": "RectangleGate", "f1": "FSC-A", "f2": "SSC-A",
+                            "min": {"f1Val": 12.0, "f2Val": 22.0}, "max": {"f1Val": 110.0, "f2Val": 210.0}}
+                    }},
+                "poly": {"containerType": "AtomicFilterContainer", "id": "poly", "name": "Live",
+                    "defaultFilter": {"type": "PolygonGate", "f1": "FSC-A", "f2": "SSC-A",
+                        "vertices": [{"f1Val": 1.0, "f2Val": 1.0}, {"f1Val": 5.0, "f2Val": 1.0}, {"f1Val": 3.0, "f2Val": 4.0}],
+                        "labelLoc": {"f1Val": 0.5, "f2Val": 0.25}},
+                    "md": "Donor",
+                    "perFileFilters": {
+                        "file1": {"type": "PolygonGate", "f1": "FSC-A", "f2": "SSC-A",
+                            "vertices": [{"f1Val": 2.0, "f2Val": 1.0}, {"f1Val": 6.0, "f2Val": 1.0}, {"f1Val": 4.0, "f2Val": 4.0}]},
+                        "file2": {"type": "PolygonGate", "f1": "FSC-A", "f2": "SSC-A",
+                            "vertices": [{"f1Val": 2.0, "f2Val": 1.0}, {"f1Val": 6.0, "f2Val": 1.0}, {"f1Val": 4.0, "f2Val": 4.0}]}
+                    }},
+                "ell": {"containerType": "AtomicFilterContainer", "id": "ell", "name": "Lymphs",
+                    "defaultFilter": {"type": "EllipseGate", "f1": "FSC-A", "f2": "SSC-A",
+                        "left": {"f1Val": 0.0, "f2Val": 0.0}, "right": {"f1Val": 4.0, "f2Val": 0.0},
+                        "top": {"f1Val": 2.0, "f2Val": 1.0}, "bottom": {"f1Val": 2.0, "f2Val": -1.0}}},
+                "q0": {"containerType": "AtomicFilterContainer", "id": "q0", "name": "CD4+CD8-", "groupId": "quad_QUAD0",
+                    "defaultFilter": {"type": "RectangleGate", "f1": "CD4", "f2": "CD8",
+                        "min": {"f1Val": -1.0, "f2Val": 2.0}, "max": {"f1Val": 2.0, "f2Val": 5.0}}},
+                "q1": {"containerType": "AtomicFilterContainer", "id": "q1", "name": "CD4+CD8+", "groupId": "quad_QUAD1",
+                    "defaultFilter": {"type": "RectangleGate", "f1": "CD4", "f2": "CD8",
+                        "min": {"f1Val": 2.0, "f2Val": 2.0}, "max": {"f1Val": 5.0, "f2Val": 5.0}}},
+                "q2": {"containerType": "AtomicFilterContainer", "id": "q2", "name": "CD4-CD8+", "groupId": "quad_QUAD2",
+                    "defaultFilter": {"type": "RectangleGate", "f1": "CD4", "f2": "CD8",
+                        "min": {"f1Val": 2.0, "f2Val": -1.0}, "max": {"f1Val": 5.0, "f2Val": 2.0}}},
+                "q3": {"containerType": "AtomicFilterContainer", "id": "q3", "name": "CD4-CD8-", "groupId": "quad_QUAD3",
+                    "defaultFilter": {"type": "RectangleGate", "f1": "CD4", "f2": "CD8",
+                        "min": {"f1Val": -1.0, "f2Val": -1.0}, "max": {"f1Val": 2.0, "f2Val": 2.0}}},
+                "s0": {"containerType": "AtomicFilterContainer", "id": "s0", "name": "CD3-", "groupId": "split_SPLIT0",
+                    "defaultFilter": {"type": "RangeGate", "f1": "CD3", "f2": "CD19", "f1Min": -1.0, "f1Max": 1.5}},
+                "s1": {"containerType": "AtomicFilterContainer", "id": "s1", "name": "CD3+", "groupId": "split_SPLIT1",
+                    "defaultFilter": {"type": "RangeGate", "f1": "CD3", "f2": "CD19", "f1Min": 1.5, "f1Max": 5.0}},
+                "k0": {"containerType": "AtomicFilterContainer", "id": "k0", "name": "TL", "groupId": "skew_SKEWEDQUAD0",
+                    "defaultFilter": {"type": "AngleGate", "f1": "CD4", "f2": "CD8",
+                        "c": {"f1Val": 2.0, "f2Val": 2.0}, "v1": {"f1Val": 2.5, "f2Val": 5.0}, "v2": {"f1Val": -1.0, "f2Val": 1.5}}},
+                "k1": {"containerType": "AtomicFilterContainer", "id": "k1", "name": "TR", "groupId": "skew_SKEWEDQUAD1",
+                    "defaultFilter": {"type": "AngleGate", "f1": "CD4", "f2": "CD8",
+                        "c": {"f1Val": 2.0, "f2Val": 2.0}, "v1": {"f1Val": 2.5, "f2Val": 5.0}, "v2": {"f1Val": 5.0, "f2Val": 2.5}}},
+                "k2": {"containerType": "AtomicFilterContainer", "id": "k2", "name": "BR", "groupId": "skew_SKEWEDQUAD2",
+                    "defaultFilter": {"type": "AngleGate", "f1": "CD4", "f2": "CD8",
+                        "c": {"f1Val": 2.0, "f2Val": 2.0}, "v1": {"f1Val": 1.5, "f2Val": -1.0}, "v2": {"f1Val": 5.0, "f2Val": 2.5}}},
+                "k3": {"containerType": "AtomicFilterContainer", "id": "k3", "name": "BL", "groupId": "skew_SKEWEDQUAD3",
+                    "defaultFilter": {"type": "AngleGate", "f1": "CD4", "f2": "CD8",
+                        "c": {"f1Val": 2.0, "f2Val": 2.0}, "v1": {"f1Val": 1.5, "f2Val": -1.0}, "v2": {"f1Val": -1.0, "f2Val": 1.5}}},
+                "range": {"containerType": "AtomicFilterContainer", "id": "range", "name": "CD19+",
+                    "defaultFilter": {"type": "RangeGate", "f1": "CD19", "f2": "CD3", "f1Min": 1.0, "f1Max": 3.0}},
+                "both": {"containerType": "CompoundFilterContainer", "id": "both", "name": "Live or Lymphs",
+                    "type": "OR", "filterContainerIds": ["poly", "ell"]}
+            }
+        }
+    }"#;
+
+    pub(crate) fn test_metadata() -> MetaDataFileMap {
+        let mut metadata: MetaDataFileMap = im::HashMap::with_hasher(FxBuildHasher);
+        for (file, donor) in [("file1", "A"), ("file2", "A"), ("file3", "B")] {
+            let mut groups = FxHashMap::default();
+            groups.insert(Arc::from("Donor"), Arc::from(donor));
+            metadata.insert(Arc::from(file), groups);
+        }
+        metadata
+    }
+
+    pub(crate) fn test_axis_settings() -> im::HashMap<Arc<str>, AxisInfo, FxBuildHasher> {
+        let mut settings = im::HashMap::with_hasher(FxBuildHasher);
+        for marker in ["FSC-A", "SSC-A", "CD3", "CD4", "CD8", "CD19"] {
+            settings.insert(
+                Arc::from(marker),
+                AxisInfo {
+                    param: Param {
+                        marker: Arc::from(marker),
+                        fluoro: Arc::from(marker),
+                    },
+                    axis_lower: -1.0,
+                    axis_upper: 5.0,
+                    transform: TransformType::Linear,
+                },
+            );
+        }
+        settings
+    }
+
+    fn round_trip(experiment: ExperimentJson) -> ExperimentJson {
+        let mut state = GateState::default();
+        state
+            .load_experiment(experiment, &test_metadata(), test_axis_settings())
+            .expect("import failed");
+        let exported = state.to_experiment_json(&test_metadata()).expect("export failed");
+        // go through text so we know the json itself is readable
+        let text = serde_json::to_string(&exported).expect("serialise failed");
+        serde_json::from_str(&text).expect("deserialise failed")
+    }
+
+    fn assert_close(a: f64, b: f64, context: &str) {
+        assert!((a - b).abs() < 1e-3, "{context}: {a} != {b}");
+    }
+
+    fn assert_points_close(a: &Point, b: &Point, context: &str) {
+        assert_close(a.x, b.x, context);
+        assert_close(a.y, b.y, context);
+    }
+
+    fn assert_filters_close(a: &GateSerialized, b: &GateSerialized, context: &str) {
+        assert_eq!(a.get_params(), b.get_params(), "{context}");
+        match (a, b) {
+            (
+                GateSerialized::Rectangle { min: a_min, max: a_max, .. },
+                GateSerialized::Rectangle { min: b_min, max: b_max, .. },
+            ) => {
+                assert_points_close(a_min, b_min, context);
+                assert_points_close(a_max, b_max, context);
+            }
+            (
+                GateSerialized::Polygon { points: a_points, label_position: a_label, .. },
+                GateSerialized::Polygon { points: b_points, label_position: b_label, .. },
+            ) => {
+                assert_eq!(a_points.len(), b_points.len(), "{context}");
+                for (pa, pb) in a_points.iter().zip(b_points) {
+                    assert_points_close(pa, pb, context);
+                }
+                assert_eq!(a_label.is_some(), b_label.is_some(), "{context}");
+            }
+            (
+                GateSerialized::Ellipse { left: al, right: ar, top: at, .. },
+                GateSerialized::Ellipse { left: bl, right: br, top: bt, .. },
+            ) => {
+                assert_points_close(al, bl, context);
+                assert_points_close(ar, br, context);
+                assert_points_close(at, bt, context);
+            }
+            (
+                GateSerialized::Line { f1min: a_min, f1max: a_max, .. },
+                GateSerialized::Line { f1min: b_min, f1max: b_max, .. },
+            ) => {
+                assert_close(*a_min, *b_min, context);
+                assert_close(*a_max, *b_max, context);
+            }
+            (
+                GateSerialized::Angle { center: ac, v1: a1, v2: a2, .. },
+                GateSerialized::Angle { center: bc, v1: b1, v2: b2, .. },
+            ) => {
+                assert_points_close(ac, bc, context);
+                // arm order is not significant
+                let close =
+                    |p: &Point, q: &Point| (p.x - q.x).abs() < 1e-3 && (p.y - q.y).abs() < 1e-3;
+                assert!(
+                    (close(a1, b1) && close(a2, b2)) || (close(a1, b2) && close(a2, b1)),
+                    "{context}: angle arms differ"
+                );
+            }
+            _ => panic!("{context}: gate types differ"),
+        }
+    }
+
+    pub(crate) fn assert_experiments_match(a: &ExperimentJson, b: &ExperimentJson) {
+        let parent_fc = |e: &ExperimentJson, node: &GatingNode| -> Arc<str> {
+            e.tree
+                .nodes
+                .get(&node.parent_id)
+                .map(|p| p.filter_container_id.clone())
+                .unwrap_or_else(|| Arc::from(""))
+        };
+        let by_container = |e: &ExperimentJson| -> FxHashMap<Arc<str>, (Arc<str>, u64)> {
+            e.tree
+                .nodes
+                .values()
+                .map(|n| (n.filter_container_id.clone(), (parent_fc(e, n), n.ord)))
+                .collect()
+        };
+        assert_eq!(by_container(a), by_container(b));
+
+        assert_eq!(a.tree.filter_containers.len(), b.tree.filter_containers.len());
+        for (id, fc_a) in &a.tree.filter_containers {
+            let fc_b = b
+                .tree
+                .filter_containers
+                .get(id)
+                .unwrap_or_else(|| panic!("container {id} missing after round trip"));
+            match (fc_a, fc_b) {
+                (FilterContainer::Atomic(a), FilterContainer::Atomic(b)) => {
+                    assert_eq!(a.name, b.name, "{id}");
+                    assert_eq!(a.group_id, b.group_id, "{id}");
+                    assert_eq!(a.md, b.md, "{id}");
+                    assert_filters_close(&a.default_filter, &b.default_filter, id);
+                    assert_eq!(
+                        a.per_file_filters.keys().collect::<FxHashSet<_>>(),
+                        b.per_file_filters.keys().collect::<FxHashSet<_>>(),
+                        "{id}"
+                    );
+                    for (file_id, filter) in &a.per_file_filters {
+                        assert_filters_close(filter, &b.per_file_filters[file_id], id);
+                    }
+                }
+                (FilterContainer::Compound(a), FilterContainer::Compound(b)) => {
+                    assert_eq!(a.name, b.name, "{id}");
+                    assert_eq!(
+                        std::mem::discriminant(&a.operation),
+                        std::mem::discriminant(&b.operation),
+                        "{id}"
+                    );
+                    assert_eq!(a.filter_container_ids, b.filter_container_ids, "{id}");
+                }
+                _ => panic!("container {id} changed type"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_sample_override_beats_group_override() {
+        use crate::gate_editor::gates::auto_adjust::translate_gate;
+        use crate::omiq::metadata::MetaDataKey;
+
+        let experiment: ExperimentJson = serde_json::from_str(EXPERIMENT).unwrap();
+        let Some(FilterContainer::Atomic(poly)) = experiment.tree.filter_containers.get("poly")
+        else {
+            panic!("poly missing");
+        };
+        let gate_id: GateId = Arc::from("poly");
+        let group_gate = poly
+            .default_filter
+            .to_drawable(
+                gate_id.clone(),
+                poly.name.clone(),
+                flow_gates::GateMode::Global,
+                false,
+            )
+            .unwrap();
+        let sample_gate = translate_gate(&group_gate, 1.0, 0.0).unwrap();
+
+        let mut group_overrides = GroupGateMap::default();
+        group_overrides.insert(
+            (
+                gate_id.clone(),
+                MetaDataKey {
+                    parameter: Arc::from("Donor"),
+                    group: Arc::from("A"),
+                },
+            ),
+            group_gate.clone(),
+        );
+        let mut sample_overrides = SampleGateMap::default();
+        sample_overrides.insert((gate_id.clone(), Arc::from("file1")), sample_gate.clone());
+
+        let (md, mut per_file) = collect_per_file_overrides(
+            &gate_id,
+            &sample_overrides,
+            &group_overrides,
+            &test_metadata(),
+        );
+        assert_eq!(md.as_deref(), Some("Donor"));
+        per_file.sort_by(|a, b| a.0.cmp(&b.0));
+        let files: Vec<&str> = per_file.iter().map(|(f, _)| f.as_ref()).collect();
+        assert_eq!(files, vec!["file1", "file2"]);
+        // file1 keeps its own position, file2 still follows its group
+        assert!(Arc::ptr_eq(&per_file[0].1, &sample_gate));
+        assert!(Arc::ptr_eq(&per_file[1].1, &group_gate));
+    }
+
+    #[test]
+    fn test_round_trip_preserves_gates() {
+        let original: ExperimentJson = serde_json::from_str(EXPERIMENT).unwrap();
+        let original_ids: FxHashSet<Arc<str>> =
+            original.tree.filter_containers.keys().cloned().collect();
+
+        let first = round_trip(original);
+        let first_ids: FxHashSet<Arc<str>> = first.tree.filter_containers.keys().cloned().collect();
+        assert_eq!(original_ids, first_ids);
+
+        let expected_group_ids = [
+            ("q0", "quad_QUAD0"),
+            ("q3", "quad_QUAD3"),
+            ("s0", "split_SPLIT0"),
+            ("s1", "split_SPLIT1"),
+            ("k2", "skew_SKEWEDQUAD2"),
+        ];
+        for (id, group_id) in expected_group_ids {
+            let Some(FilterContainer::Atomic(fc)) = first.tree.filter_containers.get(id) else {
+                panic!("{id} missing");
+            };
+            assert_eq!(fc.group_id.as_deref(), Some(group_id));
+        }
+
+        // group specific - every file in the overridden group gets a filter
+        let Some(FilterContainer::Atomic(poly)) = first.tree.filter_containers.get("poly") else {
+            panic!("poly missing");
+        };
+        assert_eq!(poly.md.as_deref(), Some("Donor"));
+        let mut files: Vec<_> = poly.per_file_filters.keys().map(|f| f.to_string()).collect();
+        files.sort();
+        assert_eq!(files, vec!["file1", "file2"]);
+
+        // file specific
+        let Some(FilterContainer::Atomic(rect)) = first.tree.filter_containers.get("rect") else {
+            panic!("rect missing");
+        };
+        assert!(rect.md.is_none());
+        assert_eq!(rect.per_file_filters.len(), 1);
+
+        let second = round_trip(first);
+        let first = round_trip(serde_json::from_str(EXPERIMENT).unwrap());
+        assert_experiments_match(&first, &second);
+    }
+}
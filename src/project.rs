@@ -0,0 +1,150 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{Result, anyhow};
+use dioxus::prelude::*;
+use dioxus::stores::SyncStore;
+use serde::{Deserialize, Serialize};
+
+use crate::gate_editor::compensation::{
+    CompensationImplExt, CompensationRecord, CompensationStore,
+};
+use crate::gate_editor::gates::{
+    GateId, GateState, gate_record::GateStateRecord, gate_store::GateStateImplExt,
+};
+use crate::gate_editor::plots::axis_store::{
+    AxisStore, AxisStoreImplExt, AxisStoreRecord, AxisStoreStoreExt, Param,
+};
+use crate::omiq::metadata::{MetaDataImplExt, MetaDataRecord, MetaDataStore};
+
+// Native project file - the whole gating session in one json document.
+// Bump PROJECT_SCHEMA_VERSION whenever the layout changes and add a step to `migrate`
+// so projects saved by older versions can still be opened.
+pub const PROJECT_SCHEMA_VERSION: u32 = 1;
+
+const SCHEMA_VERSION_KEY: &str = "schemaVersion";
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ProjectFile {
+    pub schema_version: u32,
+    pub fcs_directory: Option<PathBuf>,
+    #[serde(default)]
+    pub selection: ProjectSelection,
+    pub axes: AxisStoreRecord,
+    pub metadata: MetaDataRecord,
+    pub gates: GateStateRecord,
+    #[serde(default)]
+    pub compensation: CompensationRecord,
+}
+
+// what the user was looking at when the project was saved
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ProjectSelection {
+    pub x_axis: Option<Param>,
+    pub y_axis: Option<Param>,
+    pub parental_gate: Option<GateId>,
+}
+
+impl ProjectFile {
+    pub fn read(path: &Path) -> Result<Self> {
+        let file = std::fs::File::open(path)
+            .map_err(|e| anyhow!("Failed to open project {}: {}", path.display(), e))?;
+        let reader = std::io::BufReader::new(file);
+        let mut value: serde_json::Value = serde_json::from_reader(reader)?;
+
+        let version = value
+            .get(SCHEMA_VERSION_KEY)
+            .and_then(|v| v.as_u64())
+            .ok_or_else(|| anyhow!("Project file has no schema version"))? as u32;
+
+        if version > PROJECT_SCHEMA_VERSION {
+            return Err(anyhow!(
+                "Project was saved with schema version {} but this version only supports up to {}",
+                version,
+                PROJECT_SCHEMA_VERSION
+            ));
+        }
+
+        let mut current = version;
+        while current < PROJECT_SCHEMA_VERSION {
+            value = migrate(value, current)?;
+            current += 1;
+            value[SCHEMA_VERSION_KEY] = serde_json::Value::from(current);
+        }
+
+        Ok(serde_json::from_value(value)?)
+    }
+
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let file = std::fs::File::create(path)
+            .map_err(|e| anyhow!("Failed to create project {}: {}", path.display(), e))?;
+        let writer = std::io::BufWriter::new(file);
+        serde_json::to_writer_pretty(writer, self)?;
+        Ok(())
+    }
+}
+
+// upgrades a project document from `from_version` to `from_version + 1`.
+// There are no migrations yet - version 1 is the first release of the format.
+fn migrate(_value: serde_json::Value, from_version: u32) -> Result<serde_json::Value> {
+    Err(anyhow!(
+        "No migration available from project schema version {}",
+        from_version
+    ))
+}
+
+pub fn save_project(
+    path: &Path,
+    fcs_directory: Option<PathBuf>,
+    selection: ProjectSelection,
+    gate_store: SyncStore<GateState>,
+    axis_store: SyncStore<AxisStore>,
+    metadata_store: SyncStore<MetaDataStore>,
+    comp_store: SyncStore<CompensationStore>,
+) -> Result<()> {
+    let project = ProjectFile {
+        schema_version: PROJECT_SCHEMA_VERSION,
+        fcs_directory,
+        selection,
+        axes: axis_store.save_to_record(),
+        metadata: metadata_store.save_to_record(),
+        gates: gate_store.save_to_record()?,
+        compensation: comp_store.save_to_record(),
+    };
+    project.write(path)
+}
+
+// loads the stores in dependency order - gates need the axis settings to rebuild composites.
+// Returns the fcs directory and selection so the caller can restore its signals.
+pub fn load_project(
+    path: &Path,
+    mut gate_store: SyncStore<GateState>,
+    mut axis_store: SyncStore<AxisStore>,
+    mut metadata_store: SyncStore<MetaDataStore>,
+    mut comp_store: SyncStore<CompensationStore>,
+) -> Result<(Option<PathBuf>, ProjectSelection)> {
+    let project = ProjectFile::read(path)?;
+
+    axis_store.load_from_record(project.axes);
+    metadata_store.load_from_record(project.metadata);
+    let axis_settings = axis_store.settings().peek().clone();
+    gate_store.load_from_record(project.gates, &axis_settings)?;
+    comp_store.load_from_record(project.compensation);
+
+    Ok((project.fcs_directory, project.selection))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_migrate_rejects_unknown_schema_version() {
+        let value = serde_json::json!({ SCHEMA_VERSION_KEY: 0 });
+        for version in [0, PROJECT_SCHEMA_VERSION, PROJECT_SCHEMA_VERSION + 1] {
+            let err = migrate(value.clone(), version).expect_err("no migration exists yet");
+            assert!(err.to_string().contains(&version.to_string()));
+        }
+    }
+}